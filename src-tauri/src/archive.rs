@@ -0,0 +1,736 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, Statement, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    attachments::{self, to_hex, ATTACHMENTS_DIR_NAME},
+    audit,
+    cases::{CaseManager, CaseModel, CasePayload},
+    connect_database_from_path, init_schema, migrations, partial_path,
+    timestamps::unix_now,
+};
+
+const ARCHIVE_FORMAT: &str = "cyberweaver-case";
+const ARCHIVE_FORMAT_VERSION: u32 = 1;
+const MANIFEST_PATH: &str = "manifest.json";
+const DATABASE_PATH: &str = "case.db";
+/// Manifests are small; anything bigger is not one of ours.
+const MANIFEST_LIMIT: u64 = 16 * 1024 * 1024;
+
+/// First entry of a case archive, describing everything after it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ArchiveManifest {
+    pub(crate) format: String,
+    pub(crate) format_version: u32,
+    /// Migration version of the bundled database.
+    pub(crate) schema_version: i64,
+    pub(crate) exported_at: i64,
+    pub(crate) case: ArchivedCase,
+    pub(crate) files: Vec<ArchivedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ArchivedCase {
+    pub(crate) name: String,
+    pub(crate) case_number: Option<String>,
+    pub(crate) analyst: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ArchivedFile {
+    pub(crate) path: String,
+    pub(crate) sha256: String,
+    pub(crate) size: u64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ArchiveSummary {
+    pub(crate) path: String,
+    pub(crate) schema_version: i64,
+    pub(crate) files: usize,
+    pub(crate) bytes: u64,
+}
+
+/// `case.db` or `attachments/<first two hex digits>/<sha256>`.
+fn is_archived_path(path: &str) -> bool {
+    if path == DATABASE_PATH {
+        return true;
+    }
+
+    let parts = path.split('/').collect::<Vec<_>>();
+    match parts.as_slice() {
+        [dir, prefix, sha256] => {
+            *dir == ATTACHMENTS_DIR_NAME
+                && sha256.len() == 64
+                && sha256
+                    .bytes()
+                    .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+                && sha256.starts_with(prefix)
+                && prefix.len() == 2
+        }
+        _ => false,
+    }
+}
+
+fn hash_file(path: &Path) -> std::io::Result<(String, u64)> {
+    let mut input = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut input, &mut hasher)?;
+    Ok((to_hex(&hasher.finalize()), size))
+}
+
+/// Appends one regular file. Reads exactly `size` bytes of `input`, so a
+/// file that changes while it is archived fails the export.
+fn append_entry(
+    archive: &mut tar::Builder<impl Write>,
+    path: &str,
+    size: u64,
+    mtime: i64,
+    input: impl Read,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_ustar();
+    header.set_path(path)?;
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(mtime.max(0) as u64);
+    header.set_cksum();
+
+    let mut input = input.take(size);
+    archive.append(&header, &mut input)?;
+    if input.limit() != 0 {
+        return Err(std::io::Error::other(format!(
+            "{path} changed while archived"
+        )));
+    }
+    Ok(())
+}
+
+/// The entry's path and size. Case archives hold regular files only.
+fn entry_info(entry: &tar::Entry<impl Read>) -> Result<(String, u64), String> {
+    if !entry.header().entry_type().is_file() {
+        return Err("case archive may only contain regular files".to_owned());
+    }
+    let path = String::from_utf8(entry.path_bytes().into_owned())
+        .map_err(|_| "case archive has an entry with an invalid name".to_owned())?;
+
+    Ok((path, entry.size()))
+}
+
+/// Hashes the snapshot and every attachment, then writes the gzip-compressed
+/// archive, manifest first.
+fn pack(
+    output: &Path,
+    manifest_base: ArchiveManifest,
+    files: Vec<(String, PathBuf)>,
+) -> Result<ArchiveManifest, String> {
+    let mut manifest = manifest_base;
+    for (path, source) in &files {
+        let (sha256, size) = hash_file(source)
+            .map_err(|err| format!("failed to read {}: {err}", source.display()))?;
+        if let Some(expected) = path.strip_prefix(&format!("{ATTACHMENTS_DIR_NAME}/")) {
+            if !expected.ends_with(&sha256) {
+                return Err(format!(
+                    "attachment {} does not match its hash; run verify_attachments",
+                    &expected[3..]
+                ));
+            }
+        }
+        manifest.files.push(ArchivedFile {
+            path: path.clone(),
+            sha256,
+            size,
+        });
+    }
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|err| err.to_string())?;
+
+    let partial = partial_path(output);
+    let written = (|| -> std::io::Result<()> {
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            BufWriter::new(File::create(&partial)?),
+            Compression::default(),
+        ));
+        append_entry(
+            &mut archive,
+            MANIFEST_PATH,
+            manifest_json.len() as u64,
+            manifest.exported_at,
+            manifest_json.as_slice(),
+        )?;
+        for (file, (_, source)) in manifest.files.iter().zip(&files) {
+            append_entry(
+                &mut archive,
+                &file.path,
+                file.size,
+                manifest.exported_at,
+                File::open(source)?,
+            )?;
+        }
+        archive
+            .into_inner()?
+            .finish()?
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .sync_all()?;
+        std::fs::rename(&partial, output)
+    })();
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(format!(
+            "failed to write case archive {}: {err}",
+            output.display()
+        ));
+    }
+
+    Ok(manifest)
+}
+
+/// Writes the open case, its database snapshot and attachments to one
+/// archive at `output_path`. The export itself is audited first, so the
+/// snapshot carries the record of it.
+pub(crate) async fn export(
+    db: &DatabaseConnection,
+    case: &CaseModel,
+    case_dir: &Path,
+    actor: &str,
+    output_path: &str,
+) -> Result<ArchiveSummary, String> {
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    audit::append(
+        &txn,
+        actor,
+        "case.export",
+        &json!({ "caseId": case.id, "outputPath": output_path }),
+    )
+    .await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    let staging = case_dir.join(format!(".export-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::create_dir_all(&staging).map_err(|err| err.to_string())?;
+    let packed = export_staged(db, case, case_dir, &staging, output_path).await;
+    let _ = std::fs::remove_dir_all(&staging);
+    packed
+}
+
+async fn export_staged(
+    db: &DatabaseConnection,
+    case: &CaseModel,
+    case_dir: &Path,
+    staging: &Path,
+    output_path: &str,
+) -> Result<ArchiveSummary, String> {
+    let snapshot = staging.join(DATABASE_PATH);
+    db.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "VACUUM INTO ?;".to_owned(),
+        vec![snapshot.to_string_lossy().into_owned().into()],
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+
+    let store = case_dir.join(ATTACHMENTS_DIR_NAME);
+    let mut files = vec![(DATABASE_PATH.to_owned(), snapshot)];
+    for sha256 in attachments::known_hashes(db, None).await? {
+        files.push((
+            format!("{ATTACHMENTS_DIR_NAME}/{}/{sha256}", &sha256[..2]),
+            attachments::blob_path(&store, &sha256),
+        ));
+    }
+
+    let manifest = ArchiveManifest {
+        format: ARCHIVE_FORMAT.to_owned(),
+        format_version: ARCHIVE_FORMAT_VERSION,
+        schema_version: migrations::current_version(db)
+            .await
+            .map_err(|err| err.to_string())?,
+        exported_at: unix_now(),
+        case: ArchivedCase {
+            name: case.name.clone(),
+            case_number: case.case_number.clone(),
+            analyst: case.analyst.clone(),
+        },
+        files: Vec::new(),
+    };
+    let output = PathBuf::from(output_path);
+    let manifest = tauri::async_runtime::spawn_blocking(move || pack(&output, manifest, files))
+        .await
+        .map_err(|err| err.to_string())??;
+
+    Ok(ArchiveSummary {
+        path: output_path.to_owned(),
+        schema_version: manifest.schema_version,
+        files: manifest.files.len(),
+        bytes: manifest.files.iter().map(|file| file.size).sum(),
+    })
+}
+
+/// Extracts an archive into `staging`, checking every entry against the
+/// manifest. Returns the manifest and the SHA-256 of its raw bytes.
+fn unpack(archive: &Path, staging: &Path) -> Result<(ArchiveManifest, String), String> {
+    let file = File::open(archive)
+        .map_err(|err| format!("failed to open case archive {}: {err}", archive.display()))?;
+    let mut input = tar::Archive::new(GzDecoder::new(BufReader::new(file)));
+    let read_failed = |err: std::io::Error| format!("failed to read case archive: {err}");
+    let mut entries = input.entries().map_err(read_failed)?;
+
+    let mut first = entries
+        .next()
+        .ok_or_else(|| "case archive is empty".to_owned())?
+        .map_err(read_failed)?;
+    let (path, size) = entry_info(&first)?;
+    if path != MANIFEST_PATH || size > MANIFEST_LIMIT {
+        return Err(format!("case archive must start with {MANIFEST_PATH}"));
+    }
+    let mut raw = Vec::new();
+    first.read_to_end(&mut raw).map_err(read_failed)?;
+    let manifest = serde_json::from_slice::<ArchiveManifest>(&raw)
+        .map_err(|err| format!("invalid case archive manifest: {err}"))?;
+    validate_manifest(&manifest)?;
+
+    let expected = manifest
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file))
+        .collect::<BTreeMap<_, _>>();
+    let mut seen = BTreeSet::new();
+
+    for entry in entries {
+        let mut entry = entry.map_err(read_failed)?;
+        let (path, size) = entry_info(&entry)?;
+        let file = expected
+            .get(path.as_str())
+            .ok_or_else(|| format!("case archive contains {path}, which is not in its manifest"))?;
+        if !seen.insert(path.clone()) {
+            return Err(format!("case archive contains {path} twice"));
+        }
+
+        let target = staging.join(&path);
+        let mut hasher = Sha256::new();
+        let copied = (|| -> std::io::Result<u64> {
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut output = File::create(&target)?;
+            let mut buffer = vec![0u8; 64 * 1024];
+            let mut copied = 0u64;
+            loop {
+                let read = entry.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                output.write_all(&buffer[..read])?;
+                copied += read as u64;
+            }
+            output.sync_all()?;
+            Ok(copied)
+        })()
+        .map_err(|err| format!("failed to extract {path}: {err}"))?;
+        if copied != size {
+            return Err(format!("case archive is truncated inside {path}"));
+        }
+
+        if size != file.size || to_hex(&hasher.finalize()) != file.sha256 {
+            return Err(format!("{path} does not match its manifest hash"));
+        }
+    }
+
+    if let Some(missing) = expected.keys().find(|path| !seen.contains(**path)) {
+        return Err(format!("case archive is missing {missing}"));
+    }
+
+    Ok((manifest, to_hex(&Sha256::digest(&raw))))
+}
+
+fn validate_manifest(manifest: &ArchiveManifest) -> Result<(), String> {
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(format!("not a case archive: format {}", manifest.format));
+    }
+    if manifest.format_version != ARCHIVE_FORMAT_VERSION {
+        return Err(format!(
+            "unsupported case archive format version {}",
+            manifest.format_version
+        ));
+    }
+    if manifest.schema_version > migrations::latest_version() {
+        return Err(format!(
+            "case archive has schema version {}, newer than this app supports ({})",
+            manifest.schema_version,
+            migrations::latest_version()
+        ));
+    }
+    if !manifest.files.iter().any(|file| file.path == DATABASE_PATH) {
+        return Err(format!(
+            "case archive manifest does not list {DATABASE_PATH}"
+        ));
+    }
+    for file in &manifest.files {
+        if !is_archived_path(&file.path) {
+            return Err(format!(
+                "case archive manifest lists unexpected path {}",
+                file.path
+            ));
+        }
+        if let Some(sha256) = file.path.strip_prefix(&format!("{ATTACHMENTS_DIR_NAME}/")) {
+            if sha256[3..] != file.sha256 {
+                return Err(format!("{} does not match its manifest hash", file.path));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the extracted database against the manifest, migrates it, makes
+/// sure its audit chain holds and records the import in it.
+async fn prepare_database(
+    staging: &Path,
+    manifest: &ArchiveManifest,
+    manifest_sha256: &str,
+    actor: &str,
+    archive_path: &str,
+) -> Result<(), String> {
+    let db = connect_database_from_path(&staging.join(DATABASE_PATH))
+        .await
+        .map_err(|err| err.to_string())?;
+    let prepared = async {
+        let version = migrations::current_version(&db)
+            .await
+            .map_err(|err| err.to_string())?;
+        if version != manifest.schema_version {
+            return Err(format!(
+                "case archive manifest claims schema version {}, but its database has {version}",
+                manifest.schema_version
+            ));
+        }
+
+        init_schema(&db).await.map_err(|err| err.to_string())?;
+        let chain = audit::verify(&db).await?;
+        if let Some(broken) = chain.broken {
+            return Err(format!(
+                "audit log of the archived case is broken at entry {}: {}",
+                broken.seq, broken.reason
+            ));
+        }
+
+        let txn = db.begin().await.map_err(|err| err.to_string())?;
+        audit::append(
+            &txn,
+            actor,
+            "case.import",
+            &json!({
+                "archivePath": archive_path,
+                "manifestSha256": manifest_sha256,
+                "schemaVersion": manifest.schema_version,
+            }),
+        )
+        .await?;
+        txn.commit().await.map_err(|err| err.to_string())
+    }
+    .await;
+    db.close().await.map_err(|err| err.to_string())?;
+    prepared
+}
+
+/// Adds the archived case as a new case. Nothing is registered unless every
+/// file matches the manifest and the database migrates cleanly.
+pub(crate) async fn import(
+    cases: &CaseManager,
+    actor: &str,
+    archive_path: &str,
+    name: Option<String>,
+) -> Result<CaseModel, String> {
+    let staging = cases.staging_dir();
+    std::fs::create_dir_all(&staging).map_err(|err| err.to_string())?;
+
+    let imported = async {
+        let archive = PathBuf::from(archive_path);
+        let target = staging.clone();
+        let (manifest, manifest_sha256) =
+            tauri::async_runtime::spawn_blocking(move || unpack(&archive, &target))
+                .await
+                .map_err(|err| err.to_string())??;
+        prepare_database(&staging, &manifest, &manifest_sha256, actor, archive_path).await?;
+
+        cases
+            .adopt(
+                CasePayload {
+                    name: name.unwrap_or(manifest.case.name),
+                    case_number: manifest.case.case_number,
+                    analyst: manifest.case.analyst,
+                },
+                &staging,
+            )
+            .await
+    }
+    .await;
+
+    if imported.is_err() {
+        let _ = std::fs::remove_dir_all(&staging);
+    }
+    imported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        list_edges_internal, list_nodes_internal,
+        test_support::{note, temp_dir},
+        upsert_edges_internal, upsert_nodes_internal, EdgePayload,
+    };
+
+    fn base_manifest(schema_version: i64) -> ArchiveManifest {
+        ArchiveManifest {
+            format: ARCHIVE_FORMAT.to_owned(),
+            format_version: ARCHIVE_FORMAT_VERSION,
+            schema_version,
+            exported_at: 0,
+            case: ArchivedCase {
+                name: "Handed over".to_owned(),
+                case_number: Some("IR-7".to_owned()),
+                analyst: None,
+            },
+            files: Vec::new(),
+        }
+    }
+
+    fn staged_children(root: &Path) -> usize {
+        std::fs::read_dir(root.join("cases"))
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(".staging-")
+            })
+            .count()
+    }
+
+    #[test]
+    fn writes_and_reads_archive_entries() {
+        let mut archive = tar::Builder::new(Vec::new());
+        append_entry(
+            &mut archive,
+            "attachments/ab/abcd",
+            4,
+            1_700_000_000,
+            b"evidence".as_slice(),
+        )
+        .unwrap();
+        assert!(append_entry(&mut archive, "case.db", 4, 0, b"ev".as_slice()).is_err());
+        assert!(append_entry(&mut archive, &"a".repeat(101), 1, 0, b"a".as_slice()).is_err());
+        let bytes = archive.into_inner().unwrap();
+
+        let mut archive = tar::Archive::new(bytes.as_slice());
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(
+            entry_info(&entry).unwrap(),
+            ("attachments/ab/abcd".to_owned(), 4)
+        );
+        assert_eq!(&entry.header().as_ustar().unwrap().magic, b"ustar\0");
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "evid");
+
+        let mut links = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        links
+            .append_link(&mut header, "case.db", "/etc/passwd")
+            .unwrap();
+        let bytes = links.into_inner().unwrap();
+        let mut archive = tar::Archive::new(bytes.as_slice());
+        let entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert_eq!(
+            entry_info(&entry).unwrap_err(),
+            "case archive may only contain regular files"
+        );
+
+        assert!(is_archived_path("case.db"));
+        assert!(is_archived_path(&format!(
+            "attachments/ab/ab{}",
+            "0".repeat(62)
+        )));
+        assert!(!is_archived_path(&format!(
+            "attachments/cd/ab{}",
+            "0".repeat(62)
+        )));
+        assert!(!is_archived_path("attachments/../../etc/passwd"));
+        assert!(!is_archived_path("/case.db"));
+    }
+
+    #[tokio::test]
+    async fn round_trips_a_case_with_its_attachments() {
+        let root = temp_dir("archive-roundtrip");
+        let cases = CaseManager::load(&root).await.unwrap();
+        let case = cases.bootstrap(None).await.unwrap();
+        let db = cases.active_db().await.unwrap();
+        upsert_nodes_internal(&db, "test", vec![note("a", "loader"), note("b", "c2")])
+            .await
+            .unwrap();
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![EdgePayload {
+                id: "e1".to_owned(),
+                source: "a".to_owned(),
+                target: "b".to_owned(),
+                kind: "connected_to".to_owned(),
+                label: None,
+            }],
+        )
+        .await
+        .unwrap();
+        let evidence = root.join("sample.bin");
+        std::fs::write(&evidence, b"\x7fELF evidence").unwrap();
+        let store = cases.case_dir(&case.id).join(ATTACHMENTS_DIR_NAME);
+        attachments::attach(&db, store, "test", "a", evidence.to_str().unwrap())
+            .await
+            .unwrap();
+
+        let output = root.join("handover.tar.gz");
+        let summary = export(
+            &db,
+            &case,
+            &cases.case_dir(&case.id),
+            "test",
+            output.to_str().unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(summary.files, 2);
+        assert_eq!(summary.schema_version, migrations::latest_version());
+
+        let nodes = list_nodes_internal(&db).await.unwrap();
+        let edges = list_edges_internal(&db).await.unwrap();
+        let attached = attachments::list(&db, None).await.unwrap();
+
+        let imported = import(&cases, "test", output.to_str().unwrap(), None)
+            .await
+            .unwrap();
+        assert_ne!(imported.id, case.id);
+        assert_eq!(imported.name, case.name);
+        cases.open(&imported.id).await.unwrap();
+        let copy = cases.active_db().await.unwrap();
+        assert_eq!(list_nodes_internal(&copy).await.unwrap(), nodes);
+        assert_eq!(list_edges_internal(&copy).await.unwrap(), edges);
+        assert_eq!(attachments::list(&copy, None).await.unwrap(), attached);
+        let store = cases.case_dir(&imported.id).join(ATTACHMENTS_DIR_NAME);
+        let checks = attachments::verify(&copy, store, None).await.unwrap();
+        assert_eq!(checks[0].status, attachments::IntegrityStatus::Intact);
+
+        let log = audit::list(&copy, None, 100).await.unwrap();
+        let operations = log
+            .iter()
+            .rev()
+            .take(2)
+            .map(|entry| entry.operation.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(operations, vec!["case.import", "case.export"]);
+        assert!(audit::verify(&copy).await.unwrap().valid);
+        assert_eq!(staged_children(&root), 0);
+    }
+
+    #[tokio::test]
+    async fn migrates_older_archives_and_refuses_bad_ones() {
+        let root = temp_dir("archive-validation");
+        let cases = CaseManager::load(&root).await.unwrap();
+        let legacy = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/legacy-cyberweaver.db");
+        let pack_legacy = |name: &str, manifest: ArchiveManifest| {
+            let output = root.join(name);
+            pack(
+                &output,
+                manifest,
+                vec![(DATABASE_PATH.to_owned(), legacy.clone())],
+            )
+            .unwrap();
+            output.to_string_lossy().into_owned()
+        };
+
+        let older = pack_legacy("older.tar.gz", base_manifest(0));
+        let imported = import(&cases, "test", &older, Some("Legacy".to_owned()))
+            .await
+            .unwrap();
+        assert_eq!(imported.name, "Legacy");
+        assert_eq!(imported.case_number.as_deref(), Some("IR-7"));
+        cases.open(&imported.id).await.unwrap();
+        let db = cases.active_db().await.unwrap();
+        assert_eq!(
+            migrations::current_version(&db).await.unwrap(),
+            migrations::latest_version()
+        );
+        assert!(!list_nodes_internal(&db).await.unwrap().is_empty());
+
+        let newer = pack_legacy(
+            "newer.tar.gz",
+            base_manifest(migrations::latest_version() + 1),
+        );
+        assert!(import(&cases, "test", &newer, None)
+            .await
+            .unwrap_err()
+            .contains("newer than this app supports"));
+
+        let mislabeled = pack_legacy("mislabeled.tar.gz", base_manifest(3));
+        assert!(import(&cases, "test", &mislabeled, None)
+            .await
+            .unwrap_err()
+            .contains("its database has 0"));
+
+        // Rewrite the archive with a manifest whose hash no longer matches.
+        let mut manifest = base_manifest(0);
+        manifest.files.push(ArchivedFile {
+            path: DATABASE_PATH.to_owned(),
+            sha256: "0".repeat(64),
+            size: std::fs::metadata(&legacy).unwrap().len(),
+        });
+        let tampered = root.join("tampered.tar.gz");
+        let manifest_json = serde_json::to_vec(&manifest).unwrap();
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            File::create(&tampered).unwrap(),
+            Compression::default(),
+        ));
+        append_entry(
+            &mut archive,
+            MANIFEST_PATH,
+            manifest_json.len() as u64,
+            0,
+            manifest_json.as_slice(),
+        )
+        .unwrap();
+        append_entry(
+            &mut archive,
+            DATABASE_PATH,
+            manifest.files[0].size,
+            0,
+            File::open(&legacy).unwrap(),
+        )
+        .unwrap();
+        archive.into_inner().unwrap().finish().unwrap();
+        assert_eq!(
+            import(&cases, "test", tampered.to_str().unwrap(), None)
+                .await
+                .unwrap_err(),
+            "case.db does not match its manifest hash"
+        );
+
+        assert_eq!(cases.list(true).await.unwrap().len(), 1);
+        assert_eq!(staged_children(&root), 0);
+        cases.close().await.unwrap();
+    }
+}
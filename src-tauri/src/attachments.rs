@@ -0,0 +1,769 @@
+use md5::Md5;
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, QueryResult, Statement, TransactionTrait,
+};
+use serde::Serialize;
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{audit, existing_node_ids, id_placeholders, normalize_shape_id, partial_path};
+
+/// Directory inside a case holding the content-addressed blobs.
+pub(crate) const ATTACHMENTS_DIR_NAME: &str = "attachments";
+
+/// Bytes looked at for magic numbers.
+const HEAD_LEN: usize = 512;
+
+/// An evidence file linked to a node. The same content linked to several
+/// nodes is stored once, under its SHA-256.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Attachment {
+    pub(crate) node_id: String,
+    pub(crate) file_name: String,
+    pub(crate) attached_at: i64,
+    pub(crate) sha256: String,
+    pub(crate) sha1: String,
+    pub(crate) md5: String,
+    pub(crate) size: i64,
+    pub(crate) mime: String,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum IntegrityStatus {
+    Intact,
+    Missing,
+    Modified,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct IntegrityCheck {
+    pub(crate) sha256: String,
+    pub(crate) status: IntegrityStatus,
+    /// What the stored blob hashes to now, when it differs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) actual_sha256: Option<String>,
+}
+
+impl Attachment {
+    fn from_row(row: QueryResult) -> Self {
+        Self {
+            node_id: row.try_get("", "node_id").unwrap_or_default(),
+            file_name: row.try_get("", "file_name").unwrap_or_default(),
+            attached_at: row.try_get("", "attached_at").unwrap_or(0),
+            sha256: row.try_get("", "sha256").unwrap_or_default(),
+            sha1: row.try_get("", "sha1").unwrap_or_default(),
+            md5: row.try_get("", "md5").unwrap_or_default(),
+            size: row.try_get("", "size").unwrap_or(0),
+            mime: row.try_get("", "mime").unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Digests {
+    sha256: String,
+    sha1: String,
+    md5: String,
+    size: u64,
+    mime: &'static str,
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// MIME type from the leading bytes of a file. Covers the formats evidence
+/// usually comes in; anything else is text when it decodes as UTF-8.
+pub(crate) fn detect_mime(head: &[u8]) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"MZ", "application/vnd.microsoft.portable-executable"),
+        (b"\x7fELF", "application/x-elf"),
+        (b"\xfe\xed\xfa\xce", "application/x-mach-binary"),
+        (b"\xfe\xed\xfa\xcf", "application/x-mach-binary"),
+        (b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+        (b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"MSCF", "application/vnd.ms-cab-compressed"),
+        (b"\xd4\xc3\xb2\xa1", "application/vnd.tcpdump.pcap"),
+        (b"\xa1\xb2\xc3\xd4", "application/vnd.tcpdump.pcap"),
+        (b"\x4d\x3c\xb2\xa1", "application/vnd.tcpdump.pcap"),
+        (b"\xa1\xb2\x3c\x4d", "application/vnd.tcpdump.pcap"),
+        (b"\x0a\x0d\x0d\x0a", "application/x-pcapng"),
+        (b"ElfFile\x00", "application/x-ms-evtx"),
+        (b"regf", "application/x-ms-registry-hive"),
+        (b"SQLite format 3\x00", "application/vnd.sqlite3"),
+        (
+            b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1",
+            "application/x-ole-storage",
+        ),
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+    ];
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return mime;
+    }
+    if head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+
+    // The head may end inside a multi-byte character.
+    let text = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    };
+    if text && !head.contains(&0) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+pub(crate) fn blob_path(store: &Path, sha256: &str) -> PathBuf {
+    store.join(&sha256[..2]).join(sha256)
+}
+
+/// Streams `source` into `store` while hashing it, then moves it to its
+/// content address. Content already in the store is not written twice.
+fn ingest_file(store: &Path, source: &Path) -> Result<Digests, String> {
+    let mut input = File::open(source)
+        .map_err(|err| format!("failed to open attachment {}: {err}", source.display()))?;
+    std::fs::create_dir_all(store).map_err(|err| err.to_string())?;
+    let temp = store.join(format!(".incoming-{}", uuid::Uuid::new_v4()));
+    let copied = copy_hashing(&mut input, &temp);
+    let digests = match copied {
+        Ok(digests) => digests,
+        Err(err) => {
+            let _ = std::fs::remove_file(&temp);
+            return Err(format!(
+                "failed to store attachment {}: {err}",
+                source.display()
+            ));
+        }
+    };
+
+    let target = blob_path(store, &digests.sha256);
+    if target.is_file() {
+        let _ = std::fs::remove_file(&temp);
+    } else {
+        let placed = target
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::rename(&temp, &target));
+        if let Err(err) = placed {
+            let _ = std::fs::remove_file(&temp);
+            return Err(format!("failed to store attachment: {err}"));
+        }
+        // Blobs are evidence; nothing should write to them again.
+        if let Ok(metadata) = std::fs::metadata(&target) {
+            let mut permissions = metadata.permissions();
+            permissions.set_readonly(true);
+            let _ = std::fs::set_permissions(&target, permissions);
+        }
+    }
+
+    Ok(digests)
+}
+
+fn copy_hashing(input: &mut impl Read, temp: &Path) -> std::io::Result<Digests> {
+    let mut output = File::create(temp)?;
+    let (mut sha256, mut sha1, mut md5) = (Sha256::new(), Sha1::new(), Md5::new());
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    let mut size = 0u64;
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = input.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        let chunk = &buffer[..read];
+        sha256.update(chunk);
+        sha1.update(chunk);
+        md5.update(chunk);
+        let wanted = HEAD_LEN.saturating_sub(head.len()).min(read);
+        head.extend_from_slice(&chunk[..wanted]);
+        output.write_all(chunk)?;
+        size += read as u64;
+    }
+    output.sync_all()?;
+
+    Ok(Digests {
+        sha256: to_hex(&sha256.finalize()),
+        sha1: to_hex(&sha1.finalize()),
+        md5: to_hex(&md5.finalize()),
+        size,
+        mime: detect_mime(&head),
+    })
+}
+
+fn sha256_of(path: &Path) -> std::io::Result<String> {
+    let mut input = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut input, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+const ATTACHMENT_COLUMNS: &str = "node_attachments.node_id, node_attachments.file_name,
+     node_attachments.attached_at, attachments.sha256, attachments.sha1, attachments.md5,
+     attachments.size, attachments.mime";
+
+async fn query<C: ConnectionTrait>(
+    conn: &C,
+    filter: &str,
+    values: Vec<sea_orm::Value>,
+) -> Result<Vec<Attachment>, String> {
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {ATTACHMENT_COLUMNS}
+                 FROM node_attachments
+                 JOIN attachments ON attachments.sha256 = node_attachments.sha256
+                 JOIN nodes ON nodes.id = node_attachments.node_id
+                 WHERE nodes.deleted_at IS NULL {filter}
+                 ORDER BY node_attachments.attached_at ASC, node_attachments.node_id ASC,
+                          node_attachments.file_name ASC;"
+            ),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(Attachment::from_row).collect())
+}
+
+/// Copies the file at `path` into the case store and links it to a node.
+pub(crate) async fn attach(
+    db: &DatabaseConnection,
+    store: PathBuf,
+    actor: &str,
+    node_id: &str,
+    path: &str,
+) -> Result<Attachment, String> {
+    let node_id = existing_node_ids(db, vec![node_id.to_owned()])
+        .await?
+        .pop()
+        .ok_or_else(|| "node id must not be empty".to_owned())?;
+    let source = PathBuf::from(path);
+    let file_name = source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("attachment path has no file name: {path}"))?;
+    let digests = tauri::async_runtime::spawn_blocking(move || ingest_file(&store, &source))
+        .await
+        .map_err(|err| err.to_string())??;
+
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT INTO attachments (sha256, sha1, md5, size, mime, created_at)
+         VALUES (?, ?, ?, ?, ?, unixepoch())
+         ON CONFLICT(sha256) DO NOTHING;"
+            .to_owned(),
+        vec![
+            digests.sha256.clone().into(),
+            digests.sha1.clone().into(),
+            digests.md5.clone().into(),
+            (digests.size as i64).into(),
+            digests.mime.into(),
+        ],
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT INTO node_attachments (node_id, sha256, file_name, attached_at)
+         VALUES (?, ?, ?, unixepoch())
+         ON CONFLICT(node_id, sha256) DO UPDATE SET file_name = excluded.file_name;"
+            .to_owned(),
+        vec![
+            node_id.clone().into(),
+            digests.sha256.clone().into(),
+            file_name.clone().into(),
+        ],
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+    audit::append(
+        &txn,
+        actor,
+        "attachments.attach",
+        &json!({ "nodeId": node_id, "sha256": digests.sha256, "fileName": file_name }),
+    )
+    .await?;
+
+    let attachment = query(
+        &txn,
+        "AND node_attachments.node_id = ? AND node_attachments.sha256 = ?",
+        vec![node_id.into(), digests.sha256.into()],
+    )
+    .await?
+    .pop()
+    .ok_or_else(|| "attachment was not stored".to_owned())?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(attachment)
+}
+
+/// Attachments of live nodes, or of one node.
+pub(crate) async fn list(
+    db: &DatabaseConnection,
+    node_id: Option<&str>,
+) -> Result<Vec<Attachment>, String> {
+    match node_id {
+        Some(node_id) => {
+            query(
+                db,
+                "AND node_attachments.node_id = ?",
+                vec![normalize_shape_id(node_id).into()],
+            )
+            .await
+        }
+        None => query(db, "", Vec::new()).await,
+    }
+}
+
+pub(crate) async fn known_hashes(
+    db: &DatabaseConnection,
+    sha256: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let (filter, values) = match sha256 {
+        Some(sha256) => ("WHERE sha256 = ?", vec![sha256.to_ascii_lowercase().into()]),
+        None => ("", Vec::new()),
+    };
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!("SELECT sha256 FROM attachments {filter} ORDER BY sha256 ASC;"),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| row.try_get::<String>("", "sha256").ok())
+        .collect())
+}
+
+/// Drops the records of blobs no node links to any more and returns their
+/// hashes. The caller removes the blobs once the transaction commits.
+pub(crate) async fn forget_unreferenced<C: ConnectionTrait>(
+    conn: &C,
+) -> Result<Vec<String>, String> {
+    let hashes = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT sha256 FROM attachments
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM node_attachments
+                 WHERE node_attachments.sha256 = attachments.sha256
+             )
+             ORDER BY sha256 ASC;"
+                .to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .filter_map(|row| row.try_get::<String>("", "sha256").ok())
+        .collect::<Vec<_>>();
+
+    if !hashes.is_empty() {
+        let (placeholders, values) = id_placeholders(&hashes);
+        conn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!("DELETE FROM attachments WHERE sha256 IN ({placeholders});"),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+
+    Ok(hashes)
+}
+
+/// Deletes the blobs of forgotten attachments. A blob that cannot be removed
+/// is left behind; nothing refers to it any more.
+pub(crate) fn remove_blobs(store: &Path, hashes: &[String]) {
+    for sha256 in hashes {
+        let blob = blob_path(store, sha256);
+        // Blobs are stored read-only, which Windows also applies to deletes.
+        if let Ok(metadata) = std::fs::metadata(&blob) {
+            let mut permissions = metadata.permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            let _ = std::fs::set_permissions(&blob, permissions);
+        }
+        let _ = std::fs::remove_file(&blob);
+        if let Some(prefix) = blob.parent() {
+            // Only succeeds once the prefix directory is empty.
+            let _ = std::fs::remove_dir(prefix);
+        }
+    }
+}
+
+/// Copies a stored attachment to `output_path`, refusing to hand out a blob
+/// that no longer matches its hash. An existing file at `output_path` is only
+/// replaced with `overwrite`.
+pub(crate) async fn export(
+    db: &DatabaseConnection,
+    store: PathBuf,
+    actor: &str,
+    sha256: &str,
+    output_path: &str,
+    overwrite: bool,
+) -> Result<(), String> {
+    let sha256 = known_hashes(db, Some(sha256.trim()))
+        .await?
+        .pop()
+        .ok_or_else(|| format!("unknown attachment: {sha256}"))?;
+    let blob = blob_path(&store, &sha256);
+    let output = PathBuf::from(output_path);
+    let expected = sha256.clone();
+    let refuse_existing = move |output: &Path| {
+        if !overwrite && output.exists() {
+            Err(format!("{} already exists", output.display()))
+        } else {
+            Ok(())
+        }
+    };
+
+    tauri::async_runtime::spawn_blocking(move || {
+        refuse_existing(&output)?;
+        let mut input =
+            File::open(&blob).map_err(|err| format!("attachment blob missing: {err}"))?;
+        let temp = partial_path(&output);
+        let copied = copy_hashing(&mut input, &temp)
+            .map_err(|err| format!("failed to export attachment: {err}"))
+            .and_then(|digests| {
+                if digests.sha256 == expected {
+                    // The target may have appeared while the blob was copied.
+                    refuse_existing(&output)?;
+                    std::fs::rename(&temp, &output)
+                        .map_err(|err| format!("failed to export attachment: {err}"))
+                } else {
+                    Err(format!(
+                        "attachment {expected} was modified in the store; not exported"
+                    ))
+                }
+            });
+        if copied.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        copied
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    audit::append(
+        &txn,
+        actor,
+        "attachments.export",
+        &json!({ "sha256": sha256, "outputPath": output_path }),
+    )
+    .await?;
+    txn.commit().await.map_err(|err| err.to_string())
+}
+
+/// Re-hashes stored blobs, every one or just `sha256`.
+pub(crate) async fn verify(
+    db: &DatabaseConnection,
+    store: PathBuf,
+    sha256: Option<&str>,
+) -> Result<Vec<IntegrityCheck>, String> {
+    let hashes = known_hashes(db, sha256.map(str::trim)).await?;
+    if let (Some(sha256), true) = (sha256, hashes.is_empty()) {
+        return Err(format!("unknown attachment: {sha256}"));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        hashes
+            .into_iter()
+            .map(|sha256| {
+                let blob = blob_path(&store, &sha256);
+                let (status, actual_sha256) = if !blob.is_file() {
+                    (IntegrityStatus::Missing, None)
+                } else {
+                    match sha256_of(&blob) {
+                        Ok(actual) if actual == sha256 => (IntegrityStatus::Intact, None),
+                        Ok(actual) => (IntegrityStatus::Modified, Some(actual)),
+                        Err(_) => (IntegrityStatus::Missing, None),
+                    }
+                };
+                IntegrityCheck {
+                    sha256,
+                    status,
+                    actual_sha256,
+                }
+            })
+            .collect()
+    })
+    .await
+    .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        delete_nodes_internal,
+        test_support::{create_test_db, note, temp_dir},
+        trash, upsert_nodes_internal,
+    };
+
+    #[test]
+    fn detects_mime_from_magic_bytes() {
+        assert_eq!(
+            detect_mime(b"MZ\x90\x00\x03"),
+            "application/vnd.microsoft.portable-executable"
+        );
+        assert_eq!(
+            detect_mime(b"\xd4\xc3\xb2\xa1\x02\x00"),
+            "application/vnd.tcpdump.pcap"
+        );
+        assert_eq!(detect_mime(b"\x89PNG\r\n\x1a\n\x00\x00"), "image/png");
+        assert_eq!(detect_mime(b"RIFF\x10\x00\x00\x00WEBPVP8 "), "image/webp");
+        assert_eq!(
+            detect_mime("whoami /priv\nnet user é".as_bytes()),
+            "text/plain"
+        );
+        // A head cut inside a multi-byte character is still text.
+        assert_eq!(detect_mime(&"é".as_bytes()[..1]), "text/plain");
+        assert_eq!(detect_mime(b"\x00\x01\x02"), "application/octet-stream");
+        assert_eq!(detect_mime(b""), "text/plain");
+    }
+
+    #[tokio::test]
+    async fn stores_links_exports_and_verifies_attachments() {
+        let dir = temp_dir("attachments-roundtrip");
+        let store = dir.join(ATTACHMENTS_DIR_NAME);
+        let db = create_test_db().await;
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![note("dropper", "dropper"), note("host", "host")],
+        )
+        .await
+        .unwrap();
+
+        let source = dir.join("payload.exe");
+        std::fs::write(&source, b"MZ\x90\x00fake dropper").unwrap();
+        let attached = attach(
+            &db,
+            store.clone(),
+            "test",
+            "dropper",
+            source.to_str().unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(attached.node_id, "shape:dropper");
+        assert_eq!(attached.file_name, "payload.exe");
+        assert_eq!(attached.size, 16);
+        assert_eq!(
+            attached.mime,
+            "application/vnd.microsoft.portable-executable"
+        );
+        assert_eq!(
+            attached.md5,
+            to_hex(&Md5::digest(b"MZ\x90\x00fake dropper"))
+        );
+        assert_eq!(
+            attached.sha1,
+            to_hex(&Sha1::digest(b"MZ\x90\x00fake dropper"))
+        );
+        assert!(blob_path(&store, &attached.sha256).is_file());
+
+        // Same content under another name is linked, not stored again.
+        let copy = dir.join("copy.bin");
+        std::fs::copy(&source, &copy).unwrap();
+        attach(&db, store.clone(), "test", "host", copy.to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(list(&db, None).await.unwrap().len(), 2);
+        assert_eq!(
+            list(&db, Some("host")).await.unwrap()[0].file_name,
+            "copy.bin"
+        );
+        assert_eq!(
+            std::fs::read_dir(store.join(&attached.sha256[..2]))
+                .unwrap()
+                .count(),
+            1
+        );
+        assert!(attach(
+            &db,
+            store.clone(),
+            "test",
+            "missing",
+            copy.to_str().unwrap()
+        )
+        .await
+        .is_err());
+
+        let exported = dir.join("exported.exe");
+        export(
+            &db,
+            store.clone(),
+            "test",
+            &attached.sha256,
+            exported.to_str().unwrap(),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read(&exported).unwrap(),
+            std::fs::read(&source).unwrap()
+        );
+        let error = export(
+            &db,
+            store.clone(),
+            "test",
+            &attached.sha256,
+            exported.to_str().unwrap(),
+            false,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.ends_with("already exists"), "{error}");
+        export(
+            &db,
+            store.clone(),
+            "test",
+            &attached.sha256,
+            exported.to_str().unwrap(),
+            true,
+        )
+        .await
+        .unwrap();
+        let log = audit::list(&db, None, 10).await.unwrap();
+        assert_eq!(log.last().unwrap().operation, "attachments.export");
+
+        let checks = verify(&db, store.clone(), None).await.unwrap();
+        assert_eq!(checks[0].status, IntegrityStatus::Intact);
+
+        let blob = blob_path(&store, &attached.sha256);
+        let mut permissions = std::fs::metadata(&blob).unwrap().permissions();
+        assert!(permissions.readonly());
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(&blob, permissions).unwrap();
+        std::fs::write(&blob, b"tampered").unwrap();
+        let check = verify(&db, store.clone(), Some(&attached.sha256))
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(check.status, IntegrityStatus::Modified);
+        assert_eq!(
+            check.actual_sha256,
+            Some(to_hex(&Sha256::digest(b"tampered")))
+        );
+        assert!(export(
+            &db,
+            store.clone(),
+            "test",
+            &attached.sha256,
+            exported.to_str().unwrap(),
+            true,
+        )
+        .await
+        .is_err());
+        // A refused export leaves the earlier copy alone and no partial file.
+        assert_eq!(
+            std::fs::read(&exported).unwrap(),
+            std::fs::read(&source).unwrap()
+        );
+        assert!(std::fs::read_dir(&dir).unwrap().all(|entry| !entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .ends_with(".partial")));
+
+        std::fs::remove_file(&blob).unwrap();
+        let check = verify(&db, store.clone(), None).await.unwrap().remove(0);
+        assert_eq!(check.status, IntegrityStatus::Missing);
+        assert!(verify(&db, store.clone(), Some("feed")).await.is_err());
+
+        // Attachments follow their node into the trash and out of the case.
+        delete_nodes_internal(&db, "test", vec!["host".to_owned()])
+            .await
+            .unwrap();
+        assert_eq!(list(&db, None).await.unwrap().len(), 1);
+        let preview = trash::preview_purge(&db, vec!["host".to_owned()], Vec::new())
+            .await
+            .unwrap();
+        let report = trash::purge(
+            &db,
+            &store,
+            "test",
+            vec!["host".to_owned()],
+            Vec::new(),
+            &preview.token,
+        )
+        .await
+        .unwrap();
+        // The dropper still links to the same content.
+        assert!(report.attachments.is_empty());
+        let links = db
+            .query_one(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "SELECT COUNT(*) AS count FROM node_attachments;".to_owned(),
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(links.try_get::<i64>("", "count").unwrap(), 1);
+        assert!(!blob.is_file());
+
+        // Attaching the same content again puts the missing blob back; purging
+        // the last node that links to it removes it from the store.
+        attach(
+            &db,
+            store.clone(),
+            "test",
+            "dropper",
+            source.to_str().unwrap(),
+        )
+        .await
+        .unwrap();
+        assert!(blob.is_file());
+        delete_nodes_internal(&db, "test", vec!["dropper".to_owned()])
+            .await
+            .unwrap();
+        let preview = trash::preview_purge(&db, vec!["dropper".to_owned()], Vec::new())
+            .await
+            .unwrap();
+        let report = trash::purge(
+            &db,
+            &store,
+            "test",
+            vec!["dropper".to_owned()],
+            Vec::new(),
+            &preview.token,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.attachments, vec![attached.sha256.clone()]);
+        assert!(known_hashes(&db, None).await.unwrap().is_empty());
+        assert!(!blob.is_file());
+        assert!(!store.join(&attached.sha256[..2]).exists());
+    }
+}
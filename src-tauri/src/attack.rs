@@ -0,0 +1,979 @@
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, DatabaseTransaction, DbErr, Statement,
+    TransactionTrait, Value,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+
+use crate::{
+    attack_paths::tactic_rank, audit, changes::ChangeOrigin, existing_node_ids, id_placeholders,
+    list_nodes_by_ids, normalize_shape_id, search::plain_text_from_content, timestamps::unix_now,
+    NodeModel,
+};
+
+/// Offline subset of Enterprise ATT&CK used until a full dataset is loaded.
+const BUNDLED_DATASET: &str = include_str!("../data/attack-enterprise-subset.json");
+pub(crate) const BUNDLED_SOURCE: &str = "bundled";
+const DEFAULT_SUGGESTIONS: usize = 5;
+const MAX_SUGGESTIONS: usize = 50;
+
+/// Extra phrases that point at a technique beyond its own name. Entries for
+/// techniques missing from the loaded dataset are ignored.
+const KEYWORDS: &[(&str, &[&str])] = &[
+    ("T1595", &["nmap", "masscan", "port scan"]),
+    ("T1583.001", &["typosquat", "newly registered domain"]),
+    ("T1566.001", &["attachment", ".docm", ".xlsm"]),
+    ("T1566.002", &["phishing link", "credential harvesting"]),
+    ("T1190", &["webshell", "web shell", "w3wp.exe", "exploit"]),
+    ("T1133", &["vpn", "citrix"]),
+    (
+        "T1059.001",
+        &[
+            "powershell",
+            "pwsh",
+            "-enc",
+            "-encodedcommand",
+            "iex",
+            "invoke-expression",
+            "downloadstring",
+        ],
+    ),
+    ("T1059.003", &["cmd.exe", "cmd /c"]),
+    (
+        "T1059.005",
+        &["vbscript", "wscript", "cscript", ".vbs", "macro"],
+    ),
+    ("T1204.002", &["opened the attachment", ".lnk", ".iso"]),
+    ("T1047", &["wmic", "wmiprvse.exe", "win32_process"]),
+    ("T1053.005", &["schtasks", "scheduled task", "4698"]),
+    (
+        "T1547.001",
+        &["currentversion\\run", "runonce", "startup folder"],
+    ),
+    (
+        "T1543.003",
+        &["sc.exe create", "sc create", "new service", "7045"],
+    ),
+    (
+        "T1055",
+        &["createremotethread", "process hollowing", "injected"],
+    ),
+    ("T1027", &["base64", "frombase64string", "obfuscated"]),
+    ("T1070.001", &["wevtutil cl", "clear-eventlog", "1102"]),
+    ("T1218.011", &["rundll32", "rundll32.exe"]),
+    (
+        "T1003.001",
+        &["lsass", "mimikatz", "sekurlsa", "procdump", "comsvcs.dll"],
+    ),
+    ("T1110", &["password spray", "brute force", "4625"]),
+    (
+        "T1558.003",
+        &["kerberoast", "kerberoasting", "rubeus", "4769"],
+    ),
+    (
+        "T1087.002",
+        &["net user /domain", "net group", "adfind", "get-aduser"],
+    ),
+    ("T1018", &["net view", "nltest"]),
+    ("T1082", &["systeminfo"]),
+    ("T1021.001", &["rdp", "mstsc", "3389"]),
+    ("T1021.002", &["admin$", "c$", "psexec", "smb"]),
+    (
+        "T1021.006",
+        &["winrm", "wsmprovhost", "enter-pssession", "5985"],
+    ),
+    ("T1560.001", &["7z", "7z.exe", "rar.exe", "winrar"]),
+    ("T1071.001", &["beacon", "user-agent", "https"]),
+    ("T1071.004", &["dns tunnel", "txt record"]),
+    (
+        "T1105",
+        &["certutil", "bitsadmin", "invoke-webrequest", "wget", "curl"],
+    ),
+    ("T1572", &["ngrok", "plink", "chisel"]),
+    ("T1567.002", &["rclone", "mega.nz", "dropbox"]),
+    ("T1486", &["ransom note", "ransomware", "encrypted files"]),
+    (
+        "T1490",
+        &["vssadmin", "delete shadows", "wbadmin", "bcdedit"],
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct DatasetTactic {
+    id: String,
+    shortname: String,
+    name: String,
+    position: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DatasetTechnique {
+    id: String,
+    name: String,
+    description: String,
+    is_subtechnique: bool,
+    tactics: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+struct Dataset {
+    tactics: Vec<DatasetTactic>,
+    techniques: Vec<DatasetTechnique>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AttackDatasetSummary {
+    pub(crate) source: String,
+    pub(crate) tactics: usize,
+    pub(crate) techniques: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Technique {
+    pub(crate) id: String,
+    /// Sub-techniques are shown as `Parent: Child`, as on attack.mitre.org.
+    pub(crate) name: String,
+    pub(crate) tactics: Vec<String>,
+    pub(crate) is_subtechnique: bool,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NodeTechnique {
+    pub(crate) node_id: String,
+    pub(crate) technique_id: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TechniqueSuggestion {
+    pub(crate) technique: Technique,
+    pub(crate) score: usize,
+    pub(crate) matched: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CoveredTechnique {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) node_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TacticCoverage {
+    pub(crate) id: String,
+    pub(crate) shortname: String,
+    pub(crate) name: String,
+    pub(crate) techniques: Vec<CoveredTechnique>,
+}
+
+fn attack_external_id(object: &serde_json::Value) -> Option<String> {
+    object
+        .get("external_references")?
+        .as_array()?
+        .iter()
+        .find(|reference| {
+            reference.get("source_name").and_then(|name| name.as_str()) == Some("mitre-attack")
+        })?
+        .get("external_id")?
+        .as_str()
+        .map(|id| id.trim().to_ascii_uppercase())
+}
+
+fn is_withdrawn(object: &serde_json::Value) -> bool {
+    ["revoked", "x_mitre_deprecated"]
+        .iter()
+        .any(|key| object.get(key).and_then(|flag| flag.as_bool()) == Some(true))
+}
+
+fn text_field(object: &serde_json::Value, key: &str) -> String {
+    object
+        .get(key)
+        .and_then(|value| value.as_str())
+        .unwrap_or_default()
+        .trim()
+        .to_owned()
+}
+
+/// Reads tactics and techniques out of an ATT&CK STIX 2.x bundle, skipping
+/// revoked and deprecated objects. Tactic order comes from the matrix
+/// object when present and falls back to the kill-chain order.
+fn parse_bundle(raw: &str) -> Result<Dataset, String> {
+    let bundle = serde_json::from_str::<serde_json::Value>(raw)
+        .map_err(|err| format!("invalid ATT&CK bundle: {err}"))?;
+    let objects = bundle
+        .get("objects")
+        .and_then(|objects| objects.as_array())
+        .ok_or_else(|| "ATT&CK bundle has no objects".to_owned())?;
+
+    let matrix_order = objects
+        .iter()
+        .find(|object| object.get("type").and_then(|kind| kind.as_str()) == Some("x-mitre-matrix"))
+        .and_then(|matrix| matrix.get("tactic_refs")?.as_array())
+        .map(|refs| {
+            refs.iter()
+                .filter_map(|id| id.as_str())
+                .enumerate()
+                .map(|(position, id)| (id.to_owned(), position as i64))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap_or_default();
+
+    let mut dataset = Dataset::default();
+
+    for object in objects.iter().filter(|object| !is_withdrawn(object)) {
+        let Some(id) = attack_external_id(object) else {
+            continue;
+        };
+
+        match object.get("type").and_then(|kind| kind.as_str()) {
+            Some("x-mitre-tactic") => {
+                let shortname = text_field(object, "x_mitre_shortname");
+
+                if shortname.is_empty() {
+                    continue;
+                }
+
+                let stix_id = text_field(object, "id");
+                let position = matrix_order
+                    .get(&stix_id)
+                    .copied()
+                    .or_else(|| tactic_rank(&shortname).map(|rank| rank as i64))
+                    .unwrap_or(i64::MAX);
+
+                dataset.tactics.push(DatasetTactic {
+                    id,
+                    name: text_field(object, "name"),
+                    shortname,
+                    position,
+                });
+            }
+            Some("attack-pattern") => {
+                let tactics = object
+                    .get("kill_chain_phases")
+                    .and_then(|phases| phases.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter(|phase| {
+                        phase.get("kill_chain_name").and_then(|name| name.as_str())
+                            == Some("mitre-attack")
+                    })
+                    .filter_map(|phase| phase.get("phase_name")?.as_str())
+                    .map(str::to_owned)
+                    .collect::<BTreeSet<_>>();
+
+                dataset.techniques.push(DatasetTechnique {
+                    is_subtechnique: object
+                        .get("x_mitre_is_subtechnique")
+                        .and_then(|flag| flag.as_bool())
+                        .unwrap_or(id.contains('.')),
+                    id,
+                    name: text_field(object, "name"),
+                    description: text_field(object, "description"),
+                    tactics: tactics.into_iter().collect(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if dataset.tactics.is_empty() || dataset.techniques.is_empty() {
+        return Err("ATT&CK bundle contains no tactics or techniques".to_owned());
+    }
+
+    dataset.tactics.sort_by(|left, right| {
+        (left.position, &left.shortname).cmp(&(right.position, &right.shortname))
+    });
+    for (position, tactic) in dataset.tactics.iter_mut().enumerate() {
+        tactic.position = position as i64;
+    }
+
+    Ok(dataset)
+}
+
+/// Replaces the case's ATT&CK tables with `dataset`. Node tags are kept;
+/// tags whose technique is gone simply drop out of listings and coverage.
+async fn import(
+    db: &DatabaseConnection,
+    actor: &str,
+    dataset: &Dataset,
+    summary: &AttackDatasetSummary,
+) -> Result<(), String> {
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+
+    for table in [
+        "attack_technique_tactics",
+        "attack_techniques",
+        "attack_tactics",
+    ] {
+        txn.execute(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            format!("DELETE FROM {table};"),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+
+    for tactic in &dataset.tactics {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "INSERT OR REPLACE INTO attack_tactics (id, shortname, name, position)
+             VALUES (?, ?, ?, ?);"
+                .to_owned(),
+            vec![
+                tactic.id.clone().into(),
+                tactic.shortname.clone().into(),
+                tactic.name.clone().into(),
+                tactic.position.into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+
+    for technique in &dataset.techniques {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "INSERT OR REPLACE INTO attack_techniques (id, name, description, is_subtechnique)
+             VALUES (?, ?, ?, ?);"
+                .to_owned(),
+            vec![
+                technique.id.clone().into(),
+                technique.name.clone().into(),
+                technique.description.clone().into(),
+                technique.is_subtechnique.into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+        for tactic in &technique.tactics {
+            txn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "INSERT OR IGNORE INTO attack_technique_tactics (technique_id, tactic)
+                 VALUES (?, ?);"
+                    .to_owned(),
+                vec![technique.id.clone().into(), tactic.clone().into()],
+            ))
+            .await
+            .map_err(|err| err.to_string())?;
+        }
+    }
+
+    audit::append(&txn, actor, "attack.dataset.load", summary).await?;
+    txn.commit().await.map_err(|err| err.to_string())
+}
+
+/// Loads a STIX bundle from `path`, or the bundled subset without one.
+pub(crate) async fn load(
+    db: &DatabaseConnection,
+    actor: &str,
+    path: Option<&Path>,
+) -> Result<AttackDatasetSummary, String> {
+    let (source, raw) = match path {
+        Some(path) => (
+            path.display().to_string(),
+            std::fs::read_to_string(path).map_err(|err| {
+                format!("failed to read ATT&CK dataset {}: {err}", path.display())
+            })?,
+        ),
+        None => (BUNDLED_SOURCE.to_owned(), BUNDLED_DATASET.to_owned()),
+    };
+
+    let dataset = parse_bundle(&raw)?;
+    let summary = AttackDatasetSummary {
+        source,
+        tactics: dataset.tactics.len(),
+        techniques: dataset.techniques.len(),
+    };
+    import(db, actor, &dataset, &summary).await?;
+
+    Ok(summary)
+}
+
+/// Falls back to the bundled subset for cases that never loaded a dataset.
+pub(crate) async fn ensure_loaded(db: &DatabaseConnection) -> Result<(), String> {
+    let row = db
+        .query_one(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT COUNT(*) AS count FROM attack_techniques;".to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    let count = row
+        .and_then(|row| row.try_get::<i64>("", "count").ok())
+        .unwrap_or(0);
+
+    if count == 0 {
+        load(db, &audit::actor(ChangeOrigin::Backend), None).await?;
+    }
+
+    Ok(())
+}
+
+async fn load_techniques<C: ConnectionTrait>(conn: &C) -> Result<Vec<Technique>, String> {
+    let rows = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, name, is_subtechnique FROM attack_techniques ORDER BY id ASC;".to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    let tactic_rows = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT technique_tactics.technique_id, technique_tactics.tactic
+             FROM attack_technique_tactics AS technique_tactics
+             LEFT JOIN attack_tactics AS tactics ON tactics.shortname = technique_tactics.tactic
+             ORDER BY COALESCE(tactics.position, 1000000) ASC, technique_tactics.tactic ASC;"
+                .to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut tactics = HashMap::<String, Vec<String>>::new();
+    for row in tactic_rows {
+        let technique_id = row
+            .try_get::<String>("", "technique_id")
+            .unwrap_or_default();
+        let tactic = row.try_get::<String>("", "tactic").unwrap_or_default();
+        tactics.entry(technique_id).or_default().push(tactic);
+    }
+
+    let names = rows
+        .iter()
+        .map(|row| {
+            (
+                row.try_get::<String>("", "id").unwrap_or_default(),
+                row.try_get::<String>("", "name").unwrap_or_default(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let id = row.try_get::<String>("", "id").unwrap_or_default();
+            let is_subtechnique = row.try_get::<bool>("", "is_subtechnique").unwrap_or(false);
+            let own_name = names.get(&id).cloned().unwrap_or_default();
+            let name = id
+                .split_once('.')
+                .filter(|_| is_subtechnique)
+                .and_then(|(parent, _)| names.get(parent))
+                .filter(|parent| !own_name.starts_with(parent.as_str()))
+                .map(|parent| format!("{parent}: {own_name}"))
+                .unwrap_or(own_name);
+
+            Technique {
+                tactics: tactics.remove(&id).unwrap_or_default(),
+                id,
+                name,
+                is_subtechnique,
+            }
+        })
+        .collect())
+}
+
+/// Techniques whose id or name contains `query`, case-insensitively.
+pub(crate) async fn list_techniques(
+    db: &DatabaseConnection,
+    query: Option<&str>,
+) -> Result<Vec<Technique>, String> {
+    ensure_loaded(db).await?;
+
+    let query = query.map(str::trim).unwrap_or_default().to_lowercase();
+    let mut techniques = load_techniques(db).await?;
+    techniques.retain(|technique| {
+        query.is_empty()
+            || technique.id.to_lowercase().contains(&query)
+            || technique.name.to_lowercase().contains(&query)
+    });
+
+    Ok(techniques)
+}
+
+pub(crate) async fn node_techniques<C: ConnectionTrait>(
+    conn: &C,
+    ids: Option<&[String]>,
+) -> Result<Vec<NodeTechnique>, DbErr> {
+    // Tags of trashed nodes are kept for a restore but not reported.
+    let (filter, values) = match ids {
+        Some([]) => return Ok(Vec::new()),
+        Some(ids) => {
+            let (placeholders, values) = id_placeholders(ids);
+            (format!("AND node_id IN ({placeholders})"), values)
+        }
+        None => (String::new(), Vec::new()),
+    };
+
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT node_id, technique_id FROM node_techniques
+                 WHERE node_id IN (SELECT id FROM nodes WHERE deleted_at IS NULL) {filter}
+                 ORDER BY node_id ASC, technique_id ASC;"
+            ),
+            values,
+        ))
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| NodeTechnique {
+            node_id: row.try_get("", "node_id").unwrap_or_default(),
+            technique_id: row.try_get("", "technique_id").unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Tactic shortnames of the techniques tagged on each live node.
+pub(crate) async fn node_tactics<C: ConnectionTrait>(
+    conn: &C,
+) -> Result<HashMap<String, Vec<String>>, String> {
+    let rows = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT DISTINCT node_techniques.node_id, technique_tactics.tactic
+             FROM node_techniques
+             JOIN attack_technique_tactics AS technique_tactics
+               ON technique_tactics.technique_id = node_techniques.technique_id
+             WHERE node_techniques.node_id IN (SELECT id FROM nodes WHERE deleted_at IS NULL)
+             ORDER BY node_techniques.node_id ASC, technique_tactics.tactic ASC;"
+                .to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut tactics = HashMap::<String, Vec<String>>::new();
+    for row in rows {
+        let node_id = row.try_get::<String>("", "node_id").unwrap_or_default();
+        let tactic = row.try_get::<String>("", "tactic").unwrap_or_default();
+        tactics.entry(node_id).or_default().push(tactic);
+    }
+
+    Ok(tactics)
+}
+
+/// Replaces the techniques tagged on a node. Every id must exist in the
+/// loaded dataset.
+pub(crate) async fn set_node_techniques(
+    db: &DatabaseConnection,
+    actor: &str,
+    node_id: &str,
+    technique_ids: Vec<String>,
+) -> Result<Vec<NodeTechnique>, String> {
+    ensure_loaded(db).await?;
+
+    let node_id = existing_node_ids(db, vec![node_id.to_owned()])
+        .await?
+        .pop()
+        .ok_or_else(|| "node id must not be empty".to_owned())?;
+    let known = known_technique_ids(db).await?;
+    let technique_ids = technique_ids
+        .iter()
+        .map(|id| id.trim().to_ascii_uppercase())
+        .filter(|id| !id.is_empty())
+        .collect::<BTreeSet<_>>();
+
+    if let Some(unknown) = technique_ids.iter().find(|id| !known.contains(*id)) {
+        return Err(format!("unknown ATT&CK technique: {unknown}"));
+    }
+
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    write_node_techniques(&txn, actor, &node_id, &technique_ids).await?;
+    let tags = node_techniques(&txn, Some(&[node_id]))
+        .await
+        .map_err(|err| err.to_string())?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(tags)
+}
+
+/// Ids of every technique in the loaded dataset.
+pub(crate) async fn known_technique_ids<C: ConnectionTrait>(
+    conn: &C,
+) -> Result<BTreeSet<String>, String> {
+    Ok(load_techniques(conn)
+        .await?
+        .into_iter()
+        .map(|technique| technique.id)
+        .collect())
+}
+
+/// Replaces the tags of an existing node inside `txn`. The ids must already
+/// be normalized and known.
+pub(crate) async fn write_node_techniques(
+    txn: &DatabaseTransaction,
+    actor: &str,
+    node_id: &str,
+    technique_ids: &BTreeSet<String>,
+) -> Result<(), String> {
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "DELETE FROM node_techniques WHERE node_id = ?;".to_owned(),
+        vec![node_id.into()],
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+
+    for technique_id in technique_ids {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "INSERT INTO node_techniques (node_id, technique_id, created_at) VALUES (?, ?, ?);"
+                .to_owned(),
+            vec![
+                node_id.into(),
+                technique_id.clone().into(),
+                unix_now().into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+
+    audit::append(
+        txn,
+        actor,
+        "nodes.techniques",
+        &serde_json::json!({ "nodeId": node_id, "techniqueIds": technique_ids }),
+    )
+    .await
+}
+
+pub(crate) async fn remove_nodes<C: ConnectionTrait>(
+    conn: &C,
+    ids: &[String],
+) -> Result<(), DbErr> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    conn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        format!(
+            "DELETE FROM node_techniques WHERE node_id IN ({});",
+            vec!["?"; ids.len()].join(", ")
+        ),
+        ids.iter().cloned().map(Into::into).collect::<Vec<Value>>(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+fn node_text(node: &NodeModel) -> String {
+    let mut text = plain_text_from_content(&node.content);
+
+    if let Some(serde_json::Value::Object(attributes)) = &node.attributes {
+        for value in attributes.values().filter_map(|value| value.as_str()) {
+            text.push('\n');
+            text.push_str(value);
+        }
+    }
+
+    text.to_lowercase()
+}
+
+/// Whether `phrase` occurs in `text` without being part of a longer word.
+fn contains_phrase(text: &str, phrase: &str) -> bool {
+    let is_word = |ch: char| ch.is_alphanumeric();
+
+    text.match_indices(phrase).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + phrase.len()..].chars().next();
+        let starts_clean = !phrase.starts_with(is_word) || !before.is_some_and(is_word);
+        let ends_clean = !phrase.ends_with(is_word) || !after.is_some_and(is_word);
+
+        starts_clean && ends_clean
+    })
+}
+
+fn suggest_for_text(
+    text: &str,
+    techniques: &[Technique],
+    exclude: &BTreeSet<String>,
+) -> Vec<TechniqueSuggestion> {
+    let keywords = KEYWORDS.iter().copied().collect::<HashMap<_, _>>();
+    let mut suggestions = Vec::new();
+
+    for technique in techniques
+        .iter()
+        .filter(|technique| !exclude.contains(&technique.id))
+    {
+        let own_name = technique
+            .name
+            .rsplit(": ")
+            .next()
+            .unwrap_or(&technique.name)
+            .to_lowercase();
+        let mut matched = Vec::new();
+        let mut score = 0;
+
+        // Names count double: "Scheduled Task" is stronger evidence than a
+        // stray "4698".
+        if own_name.len() >= 4 && contains_phrase(text, &own_name) {
+            matched.push(own_name);
+            score += 2;
+        }
+
+        for keyword in keywords
+            .get(technique.id.as_str())
+            .copied()
+            .unwrap_or_default()
+        {
+            if !matched.iter().any(|phrase| phrase == keyword) && contains_phrase(text, keyword) {
+                matched.push((*keyword).to_owned());
+                score += 1;
+            }
+        }
+
+        if score > 0 {
+            suggestions.push(TechniqueSuggestion {
+                technique: technique.clone(),
+                score,
+                matched,
+            });
+        }
+    }
+
+    suggestions.sort_by(|left, right| {
+        right
+            .score
+            .cmp(&left.score)
+            .then(
+                right
+                    .technique
+                    .is_subtechnique
+                    .cmp(&left.technique.is_subtechnique),
+            )
+            .then(left.technique.id.cmp(&right.technique.id))
+    });
+    suggestions
+}
+
+/// Techniques hinted at by a node's text and attributes, best first,
+/// leaving out the ones it is already tagged with.
+pub(crate) async fn suggest(
+    db: &DatabaseConnection,
+    node_id: &str,
+    limit: Option<usize>,
+) -> Result<Vec<TechniqueSuggestion>, String> {
+    ensure_loaded(db).await?;
+
+    let node_id = normalize_shape_id(node_id);
+    let node = list_nodes_by_ids(db, std::slice::from_ref(&node_id))
+        .await?
+        .pop()
+        .ok_or_else(|| format!("missing node: {node_id}"))?;
+    let tagged = node_techniques(db, Some(std::slice::from_ref(&node_id)))
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|tag| tag.technique_id)
+        .collect::<BTreeSet<_>>();
+
+    let mut suggestions = suggest_for_text(&node_text(&node), &load_techniques(db).await?, &tagged);
+    suggestions.truncate(
+        limit
+            .unwrap_or(DEFAULT_SUGGESTIONS)
+            .clamp(1, MAX_SUGGESTIONS),
+    );
+    Ok(suggestions)
+}
+
+/// Tagged techniques grouped under every tactic of the loaded dataset, in
+/// matrix order. Tactics nothing was tagged with are kept with an empty list.
+pub(crate) async fn coverage<C: ConnectionTrait>(conn: &C) -> Result<Vec<TacticCoverage>, String> {
+    let tactic_rows = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, shortname, name FROM attack_tactics ORDER BY position ASC, shortname ASC;"
+                .to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    let techniques = load_techniques(conn)
+        .await?
+        .into_iter()
+        .map(|technique| (technique.id.clone(), technique))
+        .collect::<HashMap<_, _>>();
+
+    let mut tagged = BTreeMap::<String, Vec<String>>::new();
+    for tag in node_techniques(conn, None)
+        .await
+        .map_err(|err| err.to_string())?
+    {
+        tagged
+            .entry(tag.technique_id)
+            .or_default()
+            .push(tag.node_id);
+    }
+
+    Ok(tactic_rows
+        .into_iter()
+        .map(|row| {
+            let shortname = row.try_get::<String>("", "shortname").unwrap_or_default();
+            let covered = tagged
+                .iter()
+                .filter_map(|(technique_id, node_ids)| {
+                    let technique = techniques.get(technique_id)?;
+
+                    technique
+                        .tactics
+                        .contains(&shortname)
+                        .then(|| CoveredTechnique {
+                            id: technique.id.clone(),
+                            name: technique.name.clone(),
+                            node_ids: node_ids.clone(),
+                        })
+                })
+                .collect();
+
+            TacticCoverage {
+                id: row.try_get("", "id").unwrap_or_default(),
+                name: row.try_get("", "name").unwrap_or_default(),
+                shortname,
+                techniques: covered,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        delete_nodes_internal, test_support::create_test_db, upsert_nodes_internal, NodePayload,
+    };
+    use serde_json::json;
+
+    fn process(id: &str, content: &str, command_line: &str) -> NodePayload {
+        NodePayload {
+            id: id.to_owned(),
+            node_type: "geo".to_owned(),
+            x: 0.0,
+            y: 0.0,
+            content: content.to_owned(),
+            width: None,
+            height: None,
+            kind: Some("process".to_owned()),
+            attributes: Some(json!({ "image": content, "commandLine": command_line })),
+        }
+    }
+
+    #[test]
+    fn parses_bundled_dataset_in_matrix_order() {
+        let dataset = parse_bundle(BUNDLED_DATASET).unwrap();
+        let shortnames = dataset
+            .tactics
+            .iter()
+            .map(|tactic| tactic.shortname.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(shortnames.len(), 14);
+        assert_eq!(shortnames[0], "reconnaissance");
+        assert_eq!(shortnames[13], "impact");
+
+        let powershell = dataset
+            .techniques
+            .iter()
+            .find(|technique| technique.id == "T1059.001")
+            .unwrap();
+        assert!(powershell.is_subtechnique);
+        assert_eq!(powershell.tactics, vec!["execution"]);
+        assert!(parse_bundle(r#"{ "objects": [] }"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn tags_suggests_and_summarizes_coverage() {
+        let db = create_test_db().await;
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                process(
+                    "ps",
+                    "powershell.exe",
+                    "powershell.exe -nop -enc SQBFAFgA; schtasks /create /tn updater",
+                ),
+                process("dump", "procdump.exe", "procdump.exe -ma lsass.exe out.dmp"),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let suggestions = suggest(&db, "ps", None).await.unwrap();
+        assert_eq!(suggestions[0].technique.id, "T1059.001");
+        assert_eq!(
+            suggestions[0].technique.name,
+            "Command and Scripting Interpreter: PowerShell"
+        );
+        assert!(suggestions
+            .iter()
+            .any(|suggestion| suggestion.technique.id == "T1053.005"));
+
+        let tags = set_node_techniques(
+            &db,
+            "test",
+            "ps",
+            vec!["t1059.001".to_owned(), "T1053.005".to_owned()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(tags.len(), 2);
+        assert!(suggest(&db, "ps", None)
+            .await
+            .unwrap()
+            .iter()
+            .all(|suggestion| suggestion.technique.id != "T1059.001"));
+        set_node_techniques(&db, "test", "dump", vec!["T1003.001".to_owned()])
+            .await
+            .unwrap();
+        assert_eq!(
+            set_node_techniques(&db, "test", "dump", vec!["T9999".to_owned()])
+                .await
+                .unwrap_err(),
+            "unknown ATT&CK technique: T9999"
+        );
+        assert_eq!(
+            set_node_techniques(&db, "test", "  ", vec!["T1003.001".to_owned()])
+                .await
+                .unwrap_err(),
+            "node id must not be empty"
+        );
+
+        let summary = coverage(&db).await.unwrap();
+        let covered = |shortname: &str| {
+            summary
+                .iter()
+                .find(|tactic| tactic.shortname == shortname)
+                .unwrap()
+                .techniques
+                .iter()
+                .map(|technique| technique.id.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary.len(), 14);
+        assert_eq!(covered("execution"), vec!["T1053.005", "T1059.001"]);
+        assert_eq!(covered("persistence"), vec!["T1053.005"]);
+        assert_eq!(covered("credential-access"), vec!["T1003.001"]);
+        assert!(covered("impact").is_empty());
+
+        delete_nodes_internal(&db, "test", vec!["dump".to_owned()])
+            .await
+            .unwrap();
+        assert!(node_techniques(&db, None)
+            .await
+            .unwrap()
+            .iter()
+            .all(|tag| tag.node_id != "shape:dump"));
+    }
+}
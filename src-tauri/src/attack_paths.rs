@@ -0,0 +1,610 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+use crate::{normalize_shape_id, EdgeModel, NodeModel};
+
+const DEFAULT_MAX_DEPTH: usize = 8;
+const MAX_DEPTH_LIMIT: usize = 32;
+const DEFAULT_MAX_PATHS: usize = 20;
+const MAX_PATHS_LIMIT: usize = 500;
+// Depth-first steps per all-simple-paths query; bounds the cost on dense
+// graphs. Ranking only sees the paths found within the budget.
+const SEARCH_BUDGET: usize = 1_000_000;
+
+/// ATT&CK enterprise tactics in kill-chain order, with their ids.
+const TACTICS: &[(&str, &str)] = &[
+    ("reconnaissance", "TA0043"),
+    ("resource-development", "TA0042"),
+    ("initial-access", "TA0001"),
+    ("execution", "TA0002"),
+    ("persistence", "TA0003"),
+    ("privilege-escalation", "TA0004"),
+    ("defense-evasion", "TA0005"),
+    ("credential-access", "TA0006"),
+    ("discovery", "TA0007"),
+    ("lateral-movement", "TA0008"),
+    ("collection", "TA0009"),
+    ("command-and-control", "TA0011"),
+    ("exfiltration", "TA0010"),
+    ("impact", "TA0040"),
+];
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PathMode {
+    /// The single shortest path from `from` to `to`.
+    #[default]
+    Shortest,
+    /// Every simple path from `from` to `to` up to `maxDepth` relations.
+    AllSimple,
+    /// The shortest path from `from` to every node it can reach; `to` is
+    /// ignored.
+    Reachable,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PathRanking {
+    /// Highest accumulated threat score first.
+    #[default]
+    Score,
+    /// Paths whose tactic sequence follows the ATT&CK kill chain first, then
+    /// by score. A node's tactic comes from `attributes.tactic` and from the
+    /// ATT&CK techniques tagged on it, the earliest in the chain winning.
+    Tactic,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AttackPathQuery {
+    pub(crate) from: String,
+    #[serde(default)]
+    pub(crate) to: Option<String>,
+    #[serde(default)]
+    pub(crate) mode: PathMode,
+    #[serde(default)]
+    pub(crate) rank_by: PathRanking,
+    /// Follow relations in both directions instead of source to target.
+    #[serde(default)]
+    pub(crate) undirected: bool,
+    #[serde(default)]
+    pub(crate) max_depth: Option<usize>,
+    #[serde(default)]
+    pub(crate) max_paths: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AttackPath {
+    pub(crate) node_ids: Vec<String>,
+    pub(crate) edge_ids: Vec<String>,
+    /// Sum of the stored threat scores of the nodes on the path.
+    pub(crate) score: f64,
+    /// Recognised tactics of the nodes on the path, in path order.
+    pub(crate) tactics: Vec<String>,
+    /// Steps where the path moves backwards in the kill chain.
+    pub(crate) tactic_inversions: usize,
+}
+
+/// Position of a tactic name or id in the kill chain. Accepts the forms
+/// analysts tend to type: `Lateral Movement`, `lateral_movement`, `TA0008`.
+pub(crate) fn tactic_rank(raw: &str) -> Option<usize> {
+    let normalized = raw.trim().to_ascii_lowercase().replace([' ', '_'], "-");
+
+    TACTICS
+        .iter()
+        .position(|(name, id)| *name == normalized || id.eq_ignore_ascii_case(&normalized))
+}
+
+fn attribute_tactic(node: &NodeModel) -> Option<usize> {
+    match node.attributes.as_ref()?.get("tactic")? {
+        serde_json::Value::String(tactic) => tactic_rank(tactic),
+        serde_json::Value::Array(tactics) => tactics
+            .iter()
+            .filter_map(|tactic| tactic_rank(tactic.as_str()?))
+            .min(),
+        _ => None,
+    }
+}
+
+fn node_tactic(node: &NodeModel, tagged_tactics: &HashMap<String, Vec<String>>) -> Option<usize> {
+    let tagged = tagged_tactics
+        .get(&node.id)
+        .into_iter()
+        .flatten()
+        .filter_map(|tactic| tactic_rank(tactic));
+
+    attribute_tactic(node).into_iter().chain(tagged).min()
+}
+
+struct Graph<'a> {
+    nodes: &'a [NodeModel],
+    index: HashMap<&'a str, usize>,
+    /// Outgoing `(neighbour, edge)` pairs per node, ordered by edge id.
+    adjacency: Vec<Vec<(usize, usize)>>,
+}
+
+impl<'a> Graph<'a> {
+    fn new(nodes: &'a [NodeModel], edges: &'a [EdgeModel], undirected: bool) -> Self {
+        let index = nodes
+            .iter()
+            .enumerate()
+            .map(|(position, node)| (node.id.as_str(), position))
+            .collect::<HashMap<_, _>>();
+        let mut adjacency = vec![Vec::new(); nodes.len()];
+        let mut ordered = (0..edges.len()).collect::<Vec<_>>();
+        ordered.sort_by(|left, right| edges[*left].id.cmp(&edges[*right].id));
+
+        for edge in ordered {
+            let (Some(&source), Some(&target)) = (
+                index.get(edges[edge].source.as_str()),
+                index.get(edges[edge].target.as_str()),
+            ) else {
+                continue;
+            };
+
+            adjacency[source].push((target, edge));
+
+            if undirected && source != target {
+                adjacency[target].push((source, edge));
+            }
+        }
+
+        Self {
+            nodes,
+            index,
+            adjacency,
+        }
+    }
+
+    fn node(&self, raw: &str) -> Result<usize, String> {
+        let id = normalize_shape_id(raw);
+
+        self.index
+            .get(id.as_str())
+            .copied()
+            .ok_or_else(|| format!("missing node: {id}"))
+    }
+
+    /// Breadth-first tree from `start`: the `(parent, edge)` each node was
+    /// first reached through, within `max_depth` relations.
+    fn bfs(&self, start: usize, max_depth: usize) -> Vec<Option<(usize, usize)>> {
+        let mut parents = vec![None; self.nodes.len()];
+        let mut depth = vec![usize::MAX; self.nodes.len()];
+        let mut queue = VecDeque::from([start]);
+        depth[start] = 0;
+
+        while let Some(node) = queue.pop_front() {
+            if depth[node] == max_depth {
+                continue;
+            }
+
+            for &(next, edge) in &self.adjacency[node] {
+                if depth[next] == usize::MAX {
+                    depth[next] = depth[node] + 1;
+                    parents[next] = Some((node, edge));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        parents
+    }
+
+    fn all_simple(
+        &self,
+        start: usize,
+        goal: usize,
+        max_depth: usize,
+    ) -> Vec<(Vec<usize>, Vec<usize>)> {
+        let mut found = Vec::new();
+        let mut on_path = HashSet::from([start]);
+        let mut nodes = vec![start];
+        let mut edges = Vec::new();
+        // Each frame is the node being expanded and the next adjacency slot.
+        let mut stack = vec![(start, 0)];
+        let mut steps = 0;
+
+        while let Some((node, slot)) = stack.last_mut() {
+            steps += 1;
+
+            if steps > SEARCH_BUDGET {
+                break;
+            }
+
+            let Some(&(next, edge)) = self.adjacency[*node].get(*slot) else {
+                let (node, _) = stack.pop().expect("stack is not empty");
+                on_path.remove(&node);
+                nodes.pop();
+                edges.pop();
+                continue;
+            };
+            *slot += 1;
+
+            if on_path.contains(&next) {
+                continue;
+            }
+
+            if next == goal {
+                let mut path_nodes = nodes.clone();
+                let mut path_edges = edges.clone();
+                path_nodes.push(next);
+                path_edges.push(edge);
+                found.push((path_nodes, path_edges));
+            } else if edges.len() + 1 < max_depth {
+                on_path.insert(next);
+                nodes.push(next);
+                edges.push(edge);
+                stack.push((next, 0));
+            }
+        }
+
+        found
+    }
+}
+
+fn unwind(parents: &[Option<(usize, usize)>], goal: usize) -> (Vec<usize>, Vec<usize>) {
+    let mut nodes = vec![goal];
+    let mut edges = Vec::new();
+    let mut current = goal;
+
+    while let Some((parent, edge)) = parents[current] {
+        nodes.push(parent);
+        edges.push(edge);
+        current = parent;
+    }
+
+    nodes.reverse();
+    edges.reverse();
+    (nodes, edges)
+}
+
+/// Finds and ranks paths through the relation graph. `scores` maps node ids
+/// to their stored threat score; unscored nodes count as zero.
+pub(crate) fn find(
+    nodes: &[NodeModel],
+    edges: &[EdgeModel],
+    scores: &HashMap<String, f64>,
+    tagged_tactics: &HashMap<String, Vec<String>>,
+    query: &AttackPathQuery,
+) -> Result<Vec<AttackPath>, String> {
+    let graph = Graph::new(nodes, edges, query.undirected);
+    let start = graph.node(&query.from)?;
+    let max_depth = query
+        .max_depth
+        .unwrap_or(DEFAULT_MAX_DEPTH)
+        .clamp(1, MAX_DEPTH_LIMIT);
+    let max_paths = query
+        .max_paths
+        .unwrap_or(DEFAULT_MAX_PATHS)
+        .clamp(1, MAX_PATHS_LIMIT);
+    let goal = match (query.mode, query.to.as_deref()) {
+        (PathMode::Reachable, _) => None,
+        (_, Some(to)) => Some(graph.node(to)?),
+        (_, None) => return Err("attack path query needs a target node".to_owned()),
+    };
+
+    let raw_paths = match (query.mode, goal) {
+        (PathMode::AllSimple, Some(goal)) if goal != start => {
+            graph.all_simple(start, goal, max_depth)
+        }
+        (_, Some(goal)) => {
+            let parents = graph.bfs(start, max_depth);
+
+            if goal == start || parents[goal].is_some() {
+                vec![unwind(&parents, goal)]
+            } else {
+                Vec::new()
+            }
+        }
+        (_, None) => {
+            let parents = graph.bfs(start, max_depth);
+
+            (0..nodes.len())
+                .filter(|&node| parents[node].is_some())
+                .map(|node| unwind(&parents, node))
+                .collect()
+        }
+    };
+
+    let mut paths = raw_paths
+        .into_iter()
+        .map(|(path_nodes, path_edges)| {
+            let ranks = path_nodes
+                .iter()
+                .filter_map(|&node| node_tactic(&nodes[node], tagged_tactics))
+                .collect::<Vec<_>>();
+
+            AttackPath {
+                score: path_nodes
+                    .iter()
+                    .map(|&node| scores.get(&nodes[node].id).copied().unwrap_or(0.0))
+                    .sum(),
+                tactics: ranks
+                    .iter()
+                    .map(|&rank| TACTICS[rank].0.to_owned())
+                    .collect(),
+                tactic_inversions: ranks.windows(2).filter(|pair| pair[1] < pair[0]).count(),
+                node_ids: path_nodes
+                    .into_iter()
+                    .map(|node| nodes[node].id.clone())
+                    .collect(),
+                edge_ids: path_edges
+                    .into_iter()
+                    .map(|edge| edges[edge].id.clone())
+                    .collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    paths.sort_by(|left, right| {
+        let primary = match query.rank_by {
+            PathRanking::Score => Ordering::Equal,
+            PathRanking::Tactic => left
+                .tactic_inversions
+                .cmp(&right.tactic_inversions)
+                .then_with(|| distinct(&right.tactics).cmp(&distinct(&left.tactics))),
+        };
+
+        primary
+            .then_with(|| right.score.total_cmp(&left.score))
+            .then_with(|| left.edge_ids.len().cmp(&right.edge_ids.len()))
+            .then_with(|| left.node_ids.cmp(&right.node_ids))
+    });
+    paths.truncate(max_paths);
+
+    Ok(paths)
+}
+
+fn distinct(tactics: &[String]) -> usize {
+    tactics.iter().collect::<HashSet<_>>().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        attack, list_edges_internal, list_nodes_internal,
+        test_support::{self, create_test_db, note},
+        upsert_edges_internal, upsert_nodes_internal, NodePayload,
+    };
+    use serde_json::json;
+
+    fn node(id: &str, tactic: Option<&str>) -> NodeModel {
+        NodeModel {
+            id: format!("shape:{id}"),
+            node_type: "geo".to_owned(),
+            x: 0.0,
+            y: 0.0,
+            content: id.to_owned(),
+            width: None,
+            height: None,
+            kind: Some("event".to_owned()),
+            attributes: tactic.map(|tactic| json!({ "tactic": tactic })),
+        }
+    }
+
+    fn edge(source: &str, target: &str) -> EdgeModel {
+        EdgeModel {
+            id: format!("{source}->{target}"),
+            source: format!("shape:{source}"),
+            target: format!("shape:{target}"),
+            kind: "related_to".to_owned(),
+            label: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    // mail -> macro -> dump -> dc, with a noisier detour through a beacon
+    // that jumps back to execution before reaching the domain controller.
+    fn graph() -> (Vec<NodeModel>, Vec<EdgeModel>, HashMap<String, f64>) {
+        let nodes = vec![
+            node("mail", Some("Initial Access")),
+            node("macro", Some("execution")),
+            node("dump", Some("credential_access")),
+            node("beacon", Some("TA0011")),
+            node("rerun", Some("execution")),
+            node("dc", Some("lateral-movement")),
+            node("printer", None),
+        ];
+        let edges = vec![
+            edge("mail", "macro"),
+            edge("macro", "dump"),
+            edge("dump", "dc"),
+            edge("macro", "beacon"),
+            edge("beacon", "rerun"),
+            edge("rerun", "dc"),
+            edge("printer", "mail"),
+        ];
+        let scores = [("beacon", 90.0), ("rerun", 40.0), ("dump", 60.0)]
+            .into_iter()
+            .map(|(id, score)| (format!("shape:{id}"), score))
+            .collect();
+
+        (nodes, edges, scores)
+    }
+
+    fn ids(path: &AttackPath) -> Vec<&str> {
+        path.node_ids
+            .iter()
+            .map(|id| id.trim_start_matches("shape:"))
+            .collect()
+    }
+
+    fn query(mode: PathMode, rank_by: PathRanking) -> AttackPathQuery {
+        AttackPathQuery {
+            from: "mail".to_owned(),
+            to: Some("shape:dc".to_owned()),
+            mode,
+            rank_by,
+            ..AttackPathQuery::default()
+        }
+    }
+
+    #[test]
+    fn finds_shortest_and_all_simple_paths() {
+        let (nodes, edges, scores) = graph();
+
+        let shortest = find(
+            &nodes,
+            &edges,
+            &scores,
+            &HashMap::new(),
+            &query(PathMode::Shortest, PathRanking::Score),
+        )
+        .unwrap();
+        assert_eq!(shortest.len(), 1);
+        assert_eq!(ids(&shortest[0]), vec!["mail", "macro", "dump", "dc"]);
+        assert_eq!(
+            shortest[0].edge_ids,
+            vec!["mail->macro", "macro->dump", "dump->dc"]
+        );
+
+        let by_score = find(
+            &nodes,
+            &edges,
+            &scores,
+            &HashMap::new(),
+            &query(PathMode::AllSimple, PathRanking::Score),
+        )
+        .unwrap();
+        assert_eq!(by_score.len(), 2);
+        assert_eq!(
+            ids(&by_score[0]),
+            vec!["mail", "macro", "beacon", "rerun", "dc"]
+        );
+        assert_eq!(by_score[0].score, 130.0);
+
+        let by_tactic = find(
+            &nodes,
+            &edges,
+            &scores,
+            &HashMap::new(),
+            &query(PathMode::AllSimple, PathRanking::Tactic),
+        )
+        .unwrap();
+        assert_eq!(ids(&by_tactic[0]), vec!["mail", "macro", "dump", "dc"]);
+        assert_eq!(
+            by_tactic[0].tactics,
+            vec![
+                "initial-access",
+                "execution",
+                "credential-access",
+                "lateral-movement"
+            ]
+        );
+        assert_eq!(by_tactic[0].tactic_inversions, 0);
+        assert_eq!(by_tactic[1].tactic_inversions, 1);
+
+        let shallow = AttackPathQuery {
+            max_depth: Some(2),
+            ..query(PathMode::AllSimple, PathRanking::Score)
+        };
+        assert!(find(&nodes, &edges, &scores, &HashMap::new(), &shallow)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn reachability_respects_direction() {
+        let (nodes, edges, scores) = graph();
+        let reachable = |undirected| {
+            let query = AttackPathQuery {
+                undirected,
+                ..query(PathMode::Reachable, PathRanking::Score)
+            };
+            let mut targets = find(&nodes, &edges, &scores, &HashMap::new(), &query)
+                .unwrap()
+                .iter()
+                .map(|path| ids(path).last().unwrap().to_string())
+                .collect::<Vec<_>>();
+            targets.sort();
+            targets
+        };
+
+        assert_eq!(
+            reachable(false),
+            vec!["beacon", "dc", "dump", "macro", "rerun"]
+        );
+        assert!(reachable(true).contains(&"printer".to_owned()));
+
+        let missing = AttackPathQuery {
+            to: Some("nowhere".to_owned()),
+            ..query(PathMode::Shortest, PathRanking::Score)
+        };
+        assert_eq!(
+            find(&nodes, &edges, &scores, &HashMap::new(), &missing).unwrap_err(),
+            "missing node: shape:nowhere"
+        );
+    }
+
+    #[tokio::test]
+    async fn tagged_techniques_count_towards_tactic_ranking() {
+        let db = create_test_db().await;
+        let event = |id: &str, tactic: Option<&str>| NodePayload {
+            kind: Some("event".to_owned()),
+            attributes: tactic.map(|tactic| json!({ "tactic": tactic })),
+            ..note(id, id)
+        };
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                event("mail", Some("initial-access")),
+                event("macro", Some("execution")),
+                event("dump", None),
+                event("dc", Some("lateral-movement")),
+            ],
+        )
+        .await
+        .unwrap();
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![
+                test_support::edge("e1", "mail", "macro"),
+                test_support::edge("e2", "macro", "dc"),
+                test_support::edge("e3", "mail", "dump"),
+                test_support::edge("e4", "dump", "dc"),
+            ],
+        )
+        .await
+        .unwrap();
+        let nodes = list_nodes_internal(&db).await.unwrap();
+        let edges = list_edges_internal(&db).await.unwrap();
+        let scores = HashMap::from([("shape:dump".to_owned(), 10.0)]);
+        let query = AttackPathQuery {
+            from: "mail".to_owned(),
+            to: Some("dc".to_owned()),
+            mode: PathMode::AllSimple,
+            rank_by: PathRanking::Tactic,
+            ..AttackPathQuery::default()
+        };
+        let best = |tagged_tactics: &HashMap<String, Vec<String>>| {
+            find(&nodes, &edges, &scores, tagged_tactics, &query)
+                .unwrap()
+                .remove(0)
+        };
+
+        // Untagged, the dump step has no tactic and the macro path covers more
+        // of the kill chain.
+        let untagged = attack::node_tactics(&db).await.unwrap();
+        assert_eq!(ids(&best(&untagged)), vec!["mail", "macro", "dc"]);
+
+        attack::set_node_techniques(&db, "test", "dump", vec!["T1003.001".to_owned()])
+            .await
+            .unwrap();
+        let tagged = attack::node_tactics(&db).await.unwrap();
+        assert_eq!(tagged["shape:dump"], vec!["credential-access"]);
+        let path = best(&tagged);
+        assert_eq!(ids(&path), vec!["mail", "dump", "dc"]);
+        assert_eq!(
+            path.tactics,
+            vec!["initial-access", "credential-access", "lateral-movement"]
+        );
+    }
+}
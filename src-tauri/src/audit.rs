@@ -0,0 +1,397 @@
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, QueryResult, Statement};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{changes::ChangeOrigin, timestamps::unix_now};
+
+/// `prev_hash` of the first entry.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One link of the chain-of-custody log.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditEntry {
+    pub(crate) seq: i64,
+    pub(crate) actor: String,
+    pub(crate) created_at: i64,
+    pub(crate) operation: String,
+    /// SHA-256 of the JSON payload the operation wrote.
+    pub(crate) payload_digest: String,
+    pub(crate) prev_hash: String,
+    pub(crate) hash: String,
+}
+
+/// The first link that does not hold, by `seq`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditBreak {
+    pub(crate) seq: i64,
+    pub(crate) reason: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AuditVerification {
+    pub(crate) entries: i64,
+    pub(crate) valid: bool,
+    /// Hash of the last entry. Recording it elsewhere also covers entries
+    /// cut off the end of the log, which no link can reveal.
+    pub(crate) head: Option<String>,
+    pub(crate) broken: Option<AuditBreak>,
+}
+
+impl AuditEntry {
+    fn from_row(row: QueryResult) -> Self {
+        Self {
+            seq: row.try_get("", "seq").unwrap_or(0),
+            actor: row.try_get("", "actor").unwrap_or_default(),
+            created_at: row.try_get("", "created_at").unwrap_or(0),
+            operation: row.try_get("", "operation").unwrap_or_default(),
+            payload_digest: row.try_get("", "payload_digest").unwrap_or_default(),
+            prev_hash: row.try_get("", "prev_hash").unwrap_or_default(),
+            hash: row.try_get("", "hash").unwrap_or_default(),
+        }
+    }
+
+    /// Covers every other column, so editing any of them breaks the entry.
+    fn compute_hash(&self) -> String {
+        sha256_hex(
+            format!(
+                "{}\n{}\n{}\n{}\n{}\n{}",
+                self.seq,
+                self.actor,
+                self.created_at,
+                self.operation,
+                self.payload_digest,
+                self.prev_hash
+            )
+            .as_bytes(),
+        )
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The local account that made a write and the channel it came through,
+/// e.g. `alice (webview)`.
+pub(crate) fn actor(origin: ChangeOrigin) -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_owned());
+    let channel = match origin {
+        ChangeOrigin::Webview => "webview",
+        ChangeOrigin::SyncClient => "sync-client",
+        ChangeOrigin::Backend => "backend",
+    };
+
+    format!("{user} ({channel})")
+}
+
+/// Appends an entry for `operation` to the chain. Call it inside the
+/// transaction of the write so the entry commits or rolls back with it.
+pub(crate) async fn append<C: ConnectionTrait, T: Serialize>(
+    conn: &C,
+    actor: &str,
+    operation: &str,
+    payload: &T,
+) -> Result<(), String> {
+    let payload = serde_json::to_vec(payload).map_err(|err| err.to_string())?;
+    let last = conn
+        .query_one(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT seq, hash FROM audit_log ORDER BY seq DESC LIMIT 1;".to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    let (seq, prev_hash) = match last {
+        Some(row) => (
+            row.try_get::<i64>("", "seq")
+                .map_err(|err| err.to_string())?
+                + 1,
+            row.try_get::<String>("", "hash")
+                .map_err(|err| err.to_string())?,
+        ),
+        None => (1, GENESIS_HASH.to_owned()),
+    };
+
+    let mut entry = AuditEntry {
+        seq,
+        actor: actor.to_owned(),
+        created_at: unix_now(),
+        operation: operation.to_owned(),
+        payload_digest: sha256_hex(&payload),
+        prev_hash,
+        hash: String::new(),
+    };
+    entry.hash = entry.compute_hash();
+
+    conn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT INTO audit_log (seq, actor, created_at, operation, payload_digest, prev_hash, hash)
+         VALUES (?, ?, ?, ?, ?, ?, ?);"
+            .to_owned(),
+        vec![
+            entry.seq.into(),
+            entry.actor.into(),
+            entry.created_at.into(),
+            entry.operation.into(),
+            entry.payload_digest.into(),
+            entry.prev_hash.into(),
+            entry.hash.into(),
+        ],
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+
+    Ok(())
+}
+
+async fn entries(
+    db: &DatabaseConnection,
+    after: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Vec<AuditEntry>, String> {
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "SELECT seq, actor, created_at, operation, payload_digest, prev_hash, hash
+             FROM audit_log
+             WHERE seq > ?
+             ORDER BY seq ASC
+             LIMIT ?;"
+                .to_owned(),
+            vec![
+                after.unwrap_or(0).into(),
+                limit.map_or(-1, i64::from).into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(AuditEntry::from_row).collect())
+}
+
+/// Oldest first; pass the last `seq` seen as `after` to page on.
+pub(crate) async fn list(
+    db: &DatabaseConnection,
+    after: Option<i64>,
+    limit: u32,
+) -> Result<Vec<AuditEntry>, String> {
+    entries(db, after, Some(limit)).await
+}
+
+/// Walks the whole chain and stops at the first entry that was edited,
+/// removed or inserted out of line.
+pub(crate) async fn verify(db: &DatabaseConnection) -> Result<AuditVerification, String> {
+    let entries = entries(db, None, None).await?;
+    let mut prev_hash = GENESIS_HASH.to_owned();
+    let mut broken = None;
+
+    for (expected_seq, entry) in (1..).zip(&entries) {
+        let reason = if entry.seq != expected_seq {
+            Some(format!(
+                "expected entry {expected_seq}, found {}",
+                entry.seq
+            ))
+        } else if entry.prev_hash != prev_hash {
+            Some("previous hash does not match the entry before it".to_owned())
+        } else if entry.hash != entry.compute_hash() {
+            Some("entry contents do not match its hash".to_owned())
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            broken = Some(AuditBreak {
+                seq: entry.seq,
+                reason,
+            });
+            break;
+        }
+        prev_hash = entry.hash.clone();
+    }
+
+    Ok(AuditVerification {
+        entries: entries.len() as i64,
+        valid: broken.is_none(),
+        head: entries.last().map(|entry| entry.hash.clone()),
+        broken,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        delete_nodes_internal,
+        test_support::{create_test_db, note},
+        upsert_edges_internal, upsert_nodes_internal, EdgePayload,
+    };
+
+    async fn execute(db: &DatabaseConnection, sql: &str) -> Result<(), String> {
+        db.execute(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            sql.to_owned(),
+        ))
+        .await
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+    }
+
+    #[tokio::test]
+    async fn chains_every_write_and_pinpoints_tampering() {
+        let db = create_test_db().await;
+        assert_eq!(
+            verify(&db).await.unwrap(),
+            AuditVerification {
+                entries: 0,
+                valid: true,
+                head: None,
+                broken: None,
+            }
+        );
+
+        upsert_nodes_internal(
+            &db,
+            "alice (webview)",
+            vec![note("a", "loader"), note("b", "c2")],
+        )
+        .await
+        .unwrap();
+        upsert_edges_internal(
+            &db,
+            "alice (webview)",
+            vec![EdgePayload {
+                id: "e1".to_owned(),
+                source: "a".to_owned(),
+                target: "b".to_owned(),
+                kind: "connected_to".to_owned(),
+                label: None,
+            }],
+        )
+        .await
+        .unwrap();
+        delete_nodes_internal(&db, "bob (sync-client)", vec!["b".to_owned()])
+            .await
+            .unwrap();
+        // A failed write leaves no entry behind.
+        assert!(
+            upsert_nodes_internal(&db, "alice (webview)", vec![note("", "x")])
+                .await
+                .is_err()
+        );
+
+        let log = list(&db, None, 10).await.unwrap();
+        let operations = log
+            .iter()
+            .map(|entry| (entry.operation.as_str(), entry.actor.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            operations,
+            vec![
+                ("nodes.upsert", "alice (webview)"),
+                ("edges.upsert", "alice (webview)"),
+                ("nodes.delete", "bob (sync-client)"),
+            ]
+        );
+        assert_eq!(log[0].prev_hash, GENESIS_HASH);
+        assert_eq!(log[1].prev_hash, log[0].hash);
+        assert_eq!(log[2].payload_digest, sha256_hex(br#"["shape:b"]"#));
+        assert_eq!(list(&db, Some(2), 10).await.unwrap(), log[2..].to_vec());
+
+        let verification = verify(&db).await.unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.head.as_deref(), Some(log[2].hash.as_str()));
+
+        assert!(
+            execute(&db, "UPDATE audit_log SET actor = 'mallory' WHERE seq = 2;")
+                .await
+                .unwrap_err()
+                .contains("append-only")
+        );
+
+        execute(&db, "DROP TRIGGER audit_log_no_update;")
+            .await
+            .unwrap();
+        execute(&db, "DROP TRIGGER audit_log_no_delete;")
+            .await
+            .unwrap();
+        execute(&db, "UPDATE audit_log SET actor = 'mallory' WHERE seq = 2;")
+            .await
+            .unwrap();
+        let broken = verify(&db).await.unwrap().broken.unwrap();
+        assert_eq!(broken.seq, 2);
+        assert_eq!(broken.reason, "entry contents do not match its hash");
+
+        execute(
+            &db,
+            "UPDATE audit_log SET actor = 'alice (webview)' WHERE seq = 2;",
+        )
+        .await
+        .unwrap();
+        assert!(verify(&db).await.unwrap().valid);
+        execute(&db, "DELETE FROM audit_log WHERE seq = 2;")
+            .await
+            .unwrap();
+        let verification = verify(&db).await.unwrap();
+        assert!(!verification.valid);
+        assert_eq!(
+            verification.broken.unwrap(),
+            AuditBreak {
+                seq: 3,
+                reason: "expected entry 2, found 3".to_owned(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn records_tags_scores_and_dataset_loads() {
+        let db = create_test_db().await;
+        upsert_nodes_internal(&db, "alice (webview)", vec![note("ps", "powershell -enc")])
+            .await
+            .unwrap();
+
+        // Tagging falls back to the bundled dataset, which the backend loads.
+        crate::attack::set_node_techniques(
+            &db,
+            "alice (webview)",
+            "ps",
+            vec!["T1059.001".to_owned()],
+        )
+        .await
+        .unwrap();
+        crate::scoring::score_case(
+            &db,
+            "alice (webview)",
+            &crate::scoring::ScoringConfig::default(),
+        )
+        .await
+        .unwrap();
+
+        let log = list(&db, None, 10).await.unwrap();
+        let operations = log
+            .iter()
+            .map(|entry| entry.operation.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            operations,
+            vec![
+                "nodes.upsert",
+                "attack.dataset.load",
+                "nodes.techniques",
+                "scores.update",
+            ]
+        );
+        assert_eq!(log[1].actor, actor(ChangeOrigin::Backend));
+        assert_eq!(
+            log[2].payload_digest,
+            sha256_hex(br#"{"nodeId":"shape:ps","techniqueIds":["T1059.001"]}"#)
+        );
+        assert!(verify(&db).await.unwrap().valid);
+    }
+}
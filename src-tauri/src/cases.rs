@@ -0,0 +1,558 @@
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, QueryResult, Statement};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::RwLock;
+
+use crate::{connect_database_from_path, init_schema};
+
+const REGISTRY_FILE_NAME: &str = "cases.db";
+const CASES_DIR_NAME: &str = "cases";
+const CASE_DB_FILE_NAME: &str = "case.db";
+const DEFAULT_CASE_NAME: &str = "Default case";
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CasePayload {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) case_number: Option<String>,
+    #[serde(default)]
+    pub(crate) analyst: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CaseModel {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) case_number: Option<String>,
+    pub(crate) analyst: Option<String>,
+    pub(crate) status: String,
+    pub(crate) created_at: i64,
+    pub(crate) updated_at: i64,
+    pub(crate) last_opened_at: Option<i64>,
+}
+
+impl CaseModel {
+    fn from_row(row: QueryResult) -> Self {
+        Self {
+            id: row.try_get("", "id").unwrap_or_default(),
+            name: row.try_get("", "name").unwrap_or_default(),
+            case_number: row.try_get("", "case_number").ok(),
+            analyst: row.try_get("", "analyst").ok(),
+            status: row.try_get("", "status").unwrap_or_default(),
+            created_at: row.try_get("", "created_at").unwrap_or(0),
+            updated_at: row.try_get("", "updated_at").unwrap_or(0),
+            last_opened_at: row.try_get("", "last_opened_at").ok(),
+        }
+    }
+}
+
+struct ActiveCase {
+    id: String,
+    db: DatabaseConnection,
+}
+
+fn normalize_optional_text(raw: Option<String>) -> Option<String> {
+    raw.map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+fn validate_case_name(raw: &str) -> Result<String, String> {
+    let trimmed = raw.trim();
+
+    if trimmed.is_empty() {
+        return Err("case.name must not be empty".to_owned());
+    }
+
+    Ok(trimmed.to_owned())
+}
+
+/// Registry of investigation cases. Every case lives in its own directory
+/// holding a separate SQLite database; at most one case is open at a time.
+pub(crate) struct CaseManager {
+    root: PathBuf,
+    registry: DatabaseConnection,
+    active: RwLock<Option<ActiveCase>>,
+}
+
+impl CaseManager {
+    pub(crate) async fn load(root: &Path) -> Result<Self, String> {
+        std::fs::create_dir_all(root.join(CASES_DIR_NAME)).map_err(|err| err.to_string())?;
+
+        let registry = connect_database_from_path(&root.join(REGISTRY_FILE_NAME))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        registry
+            .execute(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "CREATE TABLE IF NOT EXISTS cases (
+                    id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    case_number TEXT,
+                    analyst TEXT,
+                    status TEXT NOT NULL DEFAULT 'open',
+                    created_at INTEGER NOT NULL DEFAULT 0,
+                    updated_at INTEGER NOT NULL DEFAULT 0,
+                    last_opened_at INTEGER
+                );"
+                .to_owned(),
+            ))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            registry,
+            active: RwLock::new(None),
+        })
+    }
+
+    /// Makes sure there is a case to work on right after startup: the first
+    /// launch adopts the pre-registry single database as the default case, and
+    /// later launches reopen the most recently used case.
+    pub(crate) async fn bootstrap(&self, legacy_db: Option<&Path>) -> Result<CaseModel, String> {
+        if self.list(true).await?.is_empty() {
+            let created = self
+                .create_with_seed(
+                    CasePayload {
+                        name: DEFAULT_CASE_NAME.to_owned(),
+                        case_number: None,
+                        analyst: None,
+                    },
+                    legacy_db.filter(|path| path.is_file()),
+                )
+                .await?;
+
+            return self.open(&created.id).await;
+        }
+
+        let candidates = self.list(false).await?;
+        let last_used = candidates
+            .iter()
+            .max_by_key(|case| (case.last_opened_at.unwrap_or(0), case.created_at))
+            .cloned();
+
+        match last_used {
+            Some(case) => self.open(&case.id).await,
+            None => {
+                let created = self
+                    .create(CasePayload {
+                        name: DEFAULT_CASE_NAME.to_owned(),
+                        case_number: None,
+                        analyst: None,
+                    })
+                    .await?;
+                self.open(&created.id).await
+            }
+        }
+    }
+
+    /// App data directory holding the registry and all cases.
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub(crate) fn case_dir(&self, id: &str) -> PathBuf {
+        self.root.join(CASES_DIR_NAME).join(id)
+    }
+
+    pub(crate) async fn active_case_dir(&self) -> Result<PathBuf, String> {
+        self.active_case_id()
+            .await
+            .map(|id| self.case_dir(&id))
+            .ok_or_else(|| "no case is open".to_owned())
+    }
+
+    fn case_db_path(&self, id: &str) -> PathBuf {
+        self.case_dir(id).join(CASE_DB_FILE_NAME)
+    }
+
+    /// Connection to the currently open case database.
+    pub(crate) async fn active_db(&self) -> Result<DatabaseConnection, String> {
+        self.active
+            .read()
+            .await
+            .as_ref()
+            .map(|active| active.db.clone())
+            .ok_or_else(|| "no case is open".to_owned())
+    }
+
+    pub(crate) async fn active_case_id(&self) -> Option<String> {
+        self.active
+            .read()
+            .await
+            .as_ref()
+            .map(|active| active.id.clone())
+    }
+
+    pub(crate) async fn active_case(&self) -> Result<Option<CaseModel>, String> {
+        match self.active_case_id().await {
+            Some(id) => self.get(&id).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) async fn list(&self, include_archived: bool) -> Result<Vec<CaseModel>, String> {
+        let sql = if include_archived {
+            "SELECT * FROM cases ORDER BY created_at ASC, id ASC;"
+        } else {
+            "SELECT * FROM cases WHERE status != 'archived' ORDER BY created_at ASC, id ASC;"
+        };
+
+        let rows = self
+            .registry
+            .query_all(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                sql.to_owned(),
+            ))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(rows.into_iter().map(CaseModel::from_row).collect())
+    }
+
+    pub(crate) async fn get(&self, id: &str) -> Result<CaseModel, String> {
+        self.registry
+            .query_one(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "SELECT * FROM cases WHERE id = ?;".to_owned(),
+                vec![id.trim().into()],
+            ))
+            .await
+            .map_err(|err| err.to_string())?
+            .map(CaseModel::from_row)
+            .ok_or_else(|| format!("case not found: {}", id.trim()))
+    }
+
+    pub(crate) async fn create(&self, payload: CasePayload) -> Result<CaseModel, String> {
+        self.create_with_seed(payload, None).await
+    }
+
+    async fn create_with_seed(
+        &self,
+        payload: CasePayload,
+        seed_db: Option<&Path>,
+    ) -> Result<CaseModel, String> {
+        let name = validate_case_name(&payload.name)?;
+        let id = uuid::Uuid::new_v4().simple().to_string();
+
+        let case_dir = self.case_dir(&id);
+        std::fs::create_dir_all(&case_dir).map_err(|err| err.to_string())?;
+
+        if let Some(seed) = seed_db {
+            if let Err(err) = std::fs::copy(seed, self.case_db_path(&id)) {
+                let _ = std::fs::remove_dir_all(&case_dir);
+                return Err(err.to_string());
+            }
+        }
+
+        self.register_or_discard(id, name, payload).await
+    }
+
+    /// A fresh directory next to the cases, for assembling a case before
+    /// `adopt` takes it over.
+    pub(crate) fn staging_dir(&self) -> PathBuf {
+        self.root
+            .join(CASES_DIR_NAME)
+            .join(format!(".staging-{}", uuid::Uuid::new_v4().simple()))
+    }
+
+    /// Registers a prepared case directory (a `case.db` plus whatever the
+    /// case stores beside it) as a new case, migrating its database.
+    pub(crate) async fn adopt(
+        &self,
+        payload: CasePayload,
+        staged: &Path,
+    ) -> Result<CaseModel, String> {
+        let name = validate_case_name(&payload.name)?;
+        let id = uuid::Uuid::new_v4().simple().to_string();
+
+        std::fs::rename(staged, self.case_dir(&id)).map_err(|err| err.to_string())?;
+
+        self.register_or_discard(id, name, payload).await
+    }
+
+    /// Registers the case directory `id`, removing it again when that fails;
+    /// unregistered, it would sit among the cases unseen.
+    async fn register_or_discard(
+        &self,
+        id: String,
+        name: String,
+        payload: CasePayload,
+    ) -> Result<CaseModel, String> {
+        let case_dir = self.case_dir(&id);
+        let registered = self.register(id, name, payload).await;
+        if registered.is_err() {
+            let _ = std::fs::remove_dir_all(&case_dir);
+        }
+        registered
+    }
+
+    async fn register(
+        &self,
+        id: String,
+        name: String,
+        payload: CasePayload,
+    ) -> Result<CaseModel, String> {
+        let db = connect_database_from_path(&self.case_db_path(&id))
+            .await
+            .map_err(|err| err.to_string())?;
+        init_schema(&db).await.map_err(|err| err.to_string())?;
+        db.close().await.map_err(|err| err.to_string())?;
+
+        self.registry
+            .execute(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "INSERT INTO cases (id, name, case_number, analyst, status, created_at, updated_at)
+                 VALUES (?, ?, ?, ?, 'open', unixepoch(), unixepoch());"
+                    .to_owned(),
+                vec![
+                    id.clone().into(),
+                    name.into(),
+                    normalize_optional_text(payload.case_number).into(),
+                    normalize_optional_text(payload.analyst).into(),
+                ],
+            ))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        self.get(&id).await
+    }
+
+    pub(crate) async fn rename(&self, id: &str, name: &str) -> Result<CaseModel, String> {
+        let name = validate_case_name(name)?;
+        let case = self.get(id).await?;
+
+        self.registry
+            .execute(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "UPDATE cases SET name = ?, updated_at = unixepoch() WHERE id = ?;".to_owned(),
+                vec![name.into(), case.id.clone().into()],
+            ))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        self.get(&case.id).await
+    }
+
+    pub(crate) async fn archive(&self, id: &str) -> Result<CaseModel, String> {
+        let case = self.get(id).await?;
+
+        if self.active_case_id().await.as_deref() == Some(case.id.as_str()) {
+            self.close().await?;
+        }
+
+        self.registry
+            .execute(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "UPDATE cases SET status = 'archived', updated_at = unixepoch() WHERE id = ?;"
+                    .to_owned(),
+                vec![case.id.clone().into()],
+            ))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        self.get(&case.id).await
+    }
+
+    /// Puts an archived case back into the list of cases that can be opened.
+    pub(crate) async fn unarchive(&self, id: &str) -> Result<CaseModel, String> {
+        let case = self.get(id).await?;
+
+        self.registry
+            .execute(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "UPDATE cases SET status = 'open', updated_at = unixepoch() WHERE id = ?;"
+                    .to_owned(),
+                vec![case.id.clone().into()],
+            ))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        self.get(&case.id).await
+    }
+
+    /// Switches the active connection to the given case, migrating its
+    /// database first. The previously open case is closed afterwards.
+    /// Archived cases have to be unarchived before they can be opened.
+    pub(crate) async fn open(&self, id: &str) -> Result<CaseModel, String> {
+        let case = self.get(id).await?;
+        if case.status == "archived" {
+            return Err(format!(
+                "case {} is archived; unarchive it before opening it",
+                case.name
+            ));
+        }
+        let db = connect_database_from_path(&self.case_db_path(&case.id))
+            .await
+            .map_err(|err| err.to_string())?;
+        init_schema(&db).await.map_err(|err| err.to_string())?;
+
+        let previous = self.active.write().await.replace(ActiveCase {
+            id: case.id.clone(),
+            db,
+        });
+
+        if let Some(previous) = previous {
+            if previous.id != case.id {
+                previous.db.close().await.map_err(|err| err.to_string())?;
+            }
+        }
+
+        self.registry
+            .execute(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "UPDATE cases SET last_opened_at = unixepoch() WHERE id = ?;".to_owned(),
+                vec![case.id.clone().into()],
+            ))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        self.get(&case.id).await
+    }
+
+    pub(crate) async fn close(&self) -> Result<(), String> {
+        let previous = self.active.write().await.take();
+
+        if let Some(previous) = previous {
+            previous.db.close().await.map_err(|err| err.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        list_nodes_internal,
+        test_support::{note, temp_dir, test_state},
+        upsert_nodes_internal,
+    };
+
+    #[tokio::test]
+    async fn create_rename_and_archive_cases() {
+        let state = test_state("cases-registry").await;
+        let manager = &state.cases;
+
+        let case = manager
+            .create(CasePayload {
+                name: "  Phishing wave  ".to_owned(),
+                case_number: Some("IR-2026-041".to_owned()),
+                analyst: Some(" ".to_owned()),
+            })
+            .await
+            .expect("create case");
+
+        assert_eq!(case.name, "Phishing wave");
+        assert_eq!(case.case_number.as_deref(), Some("IR-2026-041"));
+        assert_eq!(case.analyst, None);
+        assert_eq!(case.status, "open");
+        assert!(manager.case_dir(&case.id).join(CASE_DB_FILE_NAME).is_file());
+
+        let renamed = manager.rename(&case.id, "Phishing wave #2").await.unwrap();
+        assert_eq!(renamed.name, "Phishing wave #2");
+        assert!(manager.rename(&case.id, "   ").await.is_err());
+
+        manager.archive(&case.id).await.unwrap();
+        assert_eq!(manager.list(false).await.unwrap().len(), 1);
+        assert_eq!(manager.list(true).await.unwrap().len(), 2);
+
+        // An archived case stays closed until it is unarchived.
+        assert_eq!(
+            manager.open(&case.id).await.unwrap_err(),
+            "case Phishing wave #2 is archived; unarchive it before opening it"
+        );
+        assert_ne!(manager.active_case_id().await, Some(case.id.clone()));
+        let unarchived = manager.unarchive(&case.id).await.unwrap();
+        assert_eq!(unarchived.status, "open");
+        assert_eq!(manager.list(false).await.unwrap().len(), 2);
+        manager.open(&case.id).await.unwrap();
+        assert_eq!(manager.active_case_id().await, Some(case.id.clone()));
+    }
+
+    #[tokio::test]
+    async fn switching_cases_isolates_their_data() {
+        let state = test_state("cases-switch").await;
+        let manager = &state.cases;
+        let first = manager.active_case().await.unwrap().unwrap();
+        let second = manager
+            .create(CasePayload {
+                name: "Ransomware".to_owned(),
+                case_number: None,
+                analyst: None,
+            })
+            .await
+            .unwrap();
+
+        upsert_nodes_internal(
+            &manager.active_db().await.unwrap(),
+            "test",
+            vec![note("first", "first")],
+        )
+        .await
+        .unwrap();
+
+        manager.open(&second.id).await.unwrap();
+        let db = manager.active_db().await.unwrap();
+        assert!(list_nodes_internal(&db).await.unwrap().is_empty());
+
+        manager.open(&first.id).await.unwrap();
+        let db = manager.active_db().await.unwrap();
+        assert_eq!(list_nodes_internal(&db).await.unwrap().len(), 1);
+
+        manager.close().await.unwrap();
+        assert!(manager.active_db().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn bootstrap_adopts_legacy_database() {
+        let root = temp_dir("cases-legacy");
+        let legacy = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/legacy-cyberweaver.db");
+        let manager = CaseManager::load(&root).await.expect("load registry");
+
+        let case = manager.bootstrap(Some(&legacy)).await.expect("bootstrap");
+        assert_eq!(case.name, DEFAULT_CASE_NAME);
+        assert_eq!(manager.active_case_id().await, Some(case.id.clone()));
+
+        let db = manager.active_db().await.unwrap();
+        assert_eq!(list_nodes_internal(&db).await.unwrap().len(), 2);
+
+        manager.close().await.unwrap();
+        let reopened = manager.bootstrap(Some(&legacy)).await.unwrap();
+        assert_eq!(reopened.id, case.id);
+    }
+
+    #[tokio::test]
+    async fn failed_registration_leaves_no_directory_behind() {
+        let state = test_state("cases-adopt").await;
+        let manager = &state.cases;
+        let staged = manager.staging_dir();
+        std::fs::create_dir_all(&staged).unwrap();
+        std::fs::write(staged.join(CASE_DB_FILE_NAME), b"not a database").unwrap();
+
+        let payload = CasePayload {
+            name: "Handed over".to_owned(),
+            case_number: None,
+            analyst: None,
+        };
+        assert!(manager.adopt(payload.clone(), &staged).await.is_err());
+        let seed = state.root().join("seed.db");
+        std::fs::write(&seed, b"not a database").unwrap();
+        assert!(manager
+            .create_with_seed(payload, Some(&seed))
+            .await
+            .is_err());
+        // Only the bootstrapped case is left, in the registry and on disk.
+        assert_eq!(manager.list(true).await.unwrap().len(), 1);
+        assert_eq!(
+            std::fs::read_dir(manager.root().join(CASES_DIR_NAME))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+}
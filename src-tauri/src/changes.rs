@@ -0,0 +1,175 @@
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{EdgeModel, NodeModel};
+
+const CHANGE_FEED_CAPACITY: usize = 1024;
+
+/// Emitted instead of the dropped events when the webview falls behind.
+pub(crate) const RESYNC_EVENT: &str = "canvas-resync";
+
+/// Who made a change. The webview ignores events tagged `webview`, which are
+/// echoes of its own writes.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ChangeOrigin {
+    Webview,
+    SyncClient,
+    /// Plugins and other work started on the backend.
+    Backend,
+}
+
+/// A committed change to the open case, fanned out to every live consumer.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub(crate) enum ChangeEvent {
+    NodesUpserted {
+        nodes: Vec<NodeModel>,
+    },
+    /// Edges attached to deleted nodes are removed with them and are not
+    /// reported separately.
+    NodesDeleted {
+        ids: Vec<String>,
+    },
+    EdgesUpserted {
+        edges: Vec<EdgeModel>,
+    },
+    EdgesDeleted {
+        ids: Vec<String>,
+    },
+    /// The active case was switched or closed; consumers should reload.
+    CaseChanged {
+        #[serde(rename = "caseId")]
+        case_id: Option<String>,
+    },
+    // Canvas directives change no data, only what the analyst is shown.
+    FocusNode {
+        id: String,
+    },
+    SelectNodes {
+        ids: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    HighlightPath {
+        node_ids: Vec<String>,
+        edge_ids: Vec<String>,
+    },
+}
+
+impl ChangeEvent {
+    /// Name of the Tauri event this change is emitted as.
+    pub(crate) fn event_name(&self) -> &'static str {
+        match self {
+            Self::NodesUpserted { .. } => "nodes-upserted",
+            Self::NodesDeleted { .. } => "nodes-deleted",
+            Self::EdgesUpserted { .. } => "edges-upserted",
+            Self::EdgesDeleted { .. } => "edges-deleted",
+            Self::CaseChanged { .. } => "case-changed",
+            Self::FocusNode { .. } => "focus-node",
+            Self::SelectNodes { .. } => "select-nodes",
+            Self::HighlightPath { .. } => "highlight-path",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub(crate) struct Change {
+    pub(crate) origin: ChangeOrigin,
+    #[serde(flatten)]
+    pub(crate) event: ChangeEvent,
+}
+
+#[derive(Clone)]
+pub(crate) struct ChangeFeed {
+    sender: broadcast::Sender<Change>,
+}
+
+impl ChangeFeed {
+    pub(crate) fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANGE_FEED_CAPACITY);
+        Self { sender }
+    }
+
+    pub(crate) fn publish(&self, origin: ChangeOrigin, event: ChangeEvent) {
+        // Having no subscribers is the normal state when sync is disabled.
+        let _ = self.sender.send(Change { origin, event });
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+}
+
+/// Re-emits every change to the webview as a typed Tauri event.
+pub(crate) fn forward_to_webview(app: AppHandle, feed: &ChangeFeed) {
+    let mut receiver = feed.subscribe();
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let emitted = match receiver.recv().await {
+                Ok(change) => app.emit(change.event.event_name(), &change),
+                Err(RecvError::Lagged(_)) => app.emit(RESYNC_EVENT, ()),
+                Err(RecvError::Closed) => break,
+            };
+
+            if let Err(err) = emitted {
+                eprintln!("failed to emit canvas event: {err}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{note, test_state};
+
+    #[tokio::test]
+    async fn tags_changes_with_origin_and_validates_directives() {
+        let state = test_state("changes").await;
+        let mut receiver = state.changes.subscribe();
+
+        state
+            .upsert_nodes(ChangeOrigin::Webview, vec![note("a", "a"), note("b", "b")])
+            .await
+            .unwrap();
+        let change = receiver.recv().await.unwrap();
+        assert_eq!(change.origin, ChangeOrigin::Webview);
+        assert_eq!(change.event.event_name(), "nodes-upserted");
+
+        let payload = serde_json::to_value(&change).unwrap();
+        assert_eq!(payload["origin"], "webview");
+        assert_eq!(payload["kind"], "nodesUpserted");
+        assert_eq!(payload["nodes"].as_array().unwrap().len(), 2);
+
+        state
+            .highlight_path(
+                ChangeOrigin::SyncClient,
+                vec!["a".into(), "b".into()],
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        let change = receiver.recv().await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&change).unwrap(),
+            serde_json::json!({
+                "origin": "syncClient",
+                "kind": "highlightPath",
+                "nodeIds": ["shape:a", "shape:b"],
+                "edgeIds": []
+            })
+        );
+
+        assert!(state
+            .focus_node(ChangeOrigin::SyncClient, "missing".into())
+            .await
+            .is_err());
+        assert!(state
+            .highlight_path(ChangeOrigin::SyncClient, Vec::new(), vec!["missing".into()])
+            .await
+            .is_err());
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,414 @@
+use serde_json::{Map, Value};
+use std::net::IpAddr;
+
+pub(crate) const ENTITY_KINDS: &[&str] = &[
+    "ip",
+    "domain",
+    "url",
+    "process",
+    "file",
+    "hash",
+    "user",
+    "host",
+    "registry_key",
+    "event",
+    "email",
+    "indicator",
+    "malware",
+    "attack_pattern",
+    "connection",
+    "certificate",
+    "alert",
+];
+
+const REGISTRY_HIVES: &[&str] = &[
+    "HKEY_LOCAL_MACHINE",
+    "HKEY_CURRENT_USER",
+    "HKEY_USERS",
+    "HKEY_CLASSES_ROOT",
+    "HKEY_CURRENT_CONFIG",
+    "HKLM",
+    "HKCU",
+    "HKU",
+    "HKCR",
+    "HKCC",
+];
+
+pub(crate) fn normalize_entity_kind(raw: &str) -> Option<&'static str> {
+    let trimmed = raw.trim();
+    ENTITY_KINDS.iter().copied().find(|kind| *kind == trimmed)
+}
+
+fn optional_str<'a>(
+    attributes: &'a Map<String, Value>,
+    kind: &str,
+    key: &str,
+) -> Result<Option<&'a str>, String> {
+    match attributes.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value.as_str())),
+        Some(_) => Err(format!("{kind}.{key} must be a string")),
+    }
+}
+
+fn required_str<'a>(
+    attributes: &'a Map<String, Value>,
+    kind: &str,
+    key: &str,
+) -> Result<&'a str, String> {
+    optional_str(attributes, kind, key)?
+        .filter(|value| !value.trim().is_empty())
+        .ok_or_else(|| format!("{kind}.{key} is required"))
+}
+
+fn optional_u64(attributes: &Map<String, Value>, kind: &str, key: &str) -> Result<(), String> {
+    match attributes.get(key) {
+        None | Some(Value::Null) => Ok(()),
+        Some(value) if value.as_u64().is_some() => Ok(()),
+        Some(_) => Err(format!("{kind}.{key} must be a non-negative integer")),
+    }
+}
+
+pub(crate) fn is_hex_digest(value: &str, length: usize) -> bool {
+    value.len() == length && value.chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+fn digest_length(algorithm: &str) -> Option<usize> {
+    match algorithm {
+        "md5" => Some(32),
+        "sha1" => Some(40),
+        "sha256" => Some(64),
+        _ => None,
+    }
+}
+
+/// `T1234` or `T1234.001`.
+pub(crate) fn is_technique_id(value: &str) -> bool {
+    let Some(digits) = value.strip_prefix('T') else {
+        return false;
+    };
+    let (technique, sub) = digits.split_once('.').unwrap_or((digits, "000"));
+
+    [(technique, 4), (sub, 3)]
+        .iter()
+        .all(|(part, length)| part.len() == *length && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+pub(crate) fn is_domain_name(value: &str) -> bool {
+    let trimmed = value.trim_end_matches('.');
+    let labels = trimmed.split('.').collect::<Vec<_>>();
+
+    trimmed.len() <= 253
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+        })
+        && labels
+            .last()
+            .is_some_and(|tld| tld.chars().all(|ch| ch.is_ascii_alphabetic()))
+}
+
+pub(crate) fn is_email_address(value: &str) -> bool {
+    match value.rsplit_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty() && !local.contains(char::is_whitespace) && is_domain_name(domain)
+        }
+        None => false,
+    }
+}
+
+fn validate_hashes(attributes: &Map<String, Value>, kind: &str) -> Result<(), String> {
+    for algorithm in ["md5", "sha1", "sha256"] {
+        if let Some(value) = optional_str(attributes, kind, algorithm)? {
+            let length = digest_length(algorithm).unwrap_or_default();
+
+            if !is_hex_digest(value, length) {
+                return Err(format!(
+                    "{kind}.{algorithm} must be {length} hexadecimal characters"
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_email_field(attributes: &Map<String, Value>, key: &str) -> Result<(), String> {
+    let addresses = match attributes.get(key) {
+        None | Some(Value::Null) => return Ok(()),
+        Some(Value::String(value)) => vec![value.as_str()],
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .ok_or_else(|| format!("email.{key} must contain strings"))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => return Err(format!("email.{key} must be a string or a list of strings")),
+    };
+
+    match addresses.into_iter().find(|value| !is_email_address(value)) {
+        Some(invalid) => Err(format!("email.{key} is not a valid address: {invalid}")),
+        None => Ok(()),
+    }
+}
+
+/// Checks the kind-specific shape of a node's `attributes` object. Unknown keys
+/// are kept as-is so analysts can record extra context.
+pub(crate) fn validate_entity_attributes(kind: &str, attributes: &Value) -> Result<(), String> {
+    let attributes = attributes
+        .as_object()
+        .ok_or_else(|| "node.attributes must be a JSON object".to_owned())?;
+
+    match kind {
+        "ip" => {
+            let address = required_str(attributes, kind, "address")?;
+            address
+                .trim()
+                .parse::<IpAddr>()
+                .map_err(|_| format!("ip.address is not a valid IP address: {address}"))?;
+        }
+        "domain" => {
+            let name = required_str(attributes, kind, "name")?;
+
+            if !is_domain_name(name) {
+                return Err(format!("domain.name is not a valid domain: {name}"));
+            }
+        }
+        "url" => {
+            let url = required_str(attributes, kind, "url")?;
+            let valid = url.split_once("://").is_some_and(|(scheme, rest)| {
+                !scheme.is_empty()
+                    && scheme
+                        .chars()
+                        .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '-' | '.'))
+                    && !rest.is_empty()
+            });
+
+            if !valid {
+                return Err(format!("url.url is not an absolute URL: {url}"));
+            }
+        }
+        "process" => {
+            optional_u64(attributes, kind, "pid")?;
+            optional_u64(attributes, kind, "parentPid")?;
+            optional_str(attributes, kind, "image")?;
+            optional_str(attributes, kind, "commandLine")?;
+            optional_str(attributes, kind, "guid")?;
+        }
+        "file" => {
+            optional_str(attributes, kind, "path")?;
+            optional_str(attributes, kind, "name")?;
+            optional_u64(attributes, kind, "size")?;
+            validate_hashes(attributes, kind)?;
+        }
+        "hash" => {
+            let algorithm = required_str(attributes, kind, "algorithm")?;
+            let value = required_str(attributes, kind, "value")?;
+            let length = digest_length(&algorithm.to_ascii_lowercase())
+                .ok_or_else(|| format!("unsupported hash algorithm: {algorithm}"))?;
+
+            if !is_hex_digest(value, length) {
+                return Err(format!(
+                    "{algorithm} hash must be {length} hexadecimal characters"
+                ));
+            }
+        }
+        "user" => {
+            required_str(attributes, kind, "name")?;
+            optional_str(attributes, kind, "domain")?;
+            optional_str(attributes, kind, "sid")?;
+        }
+        "host" => {
+            required_str(attributes, kind, "hostname")?;
+
+            if let Some(ip) = optional_str(attributes, kind, "ip")? {
+                ip.trim()
+                    .parse::<IpAddr>()
+                    .map_err(|_| format!("host.ip is not a valid IP address: {ip}"))?;
+            }
+        }
+        "registry_key" => {
+            let path = required_str(attributes, kind, "path")?;
+            let hive = path.split('\\').next().unwrap_or_default();
+
+            if !REGISTRY_HIVES
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(hive))
+            {
+                return Err(format!(
+                    "registry_key.path must start with a registry hive: {path}"
+                ));
+            }
+        }
+        "event" => match attributes.get("timestamp") {
+            None | Some(Value::Null) | Some(Value::String(_)) => {}
+            Some(value) if value.as_i64().is_some() => {}
+            Some(_) => {
+                return Err("event.timestamp must be a string or unix timestamp".to_owned());
+            }
+        },
+        "email" => {
+            validate_email_field(attributes, "from")?;
+            validate_email_field(attributes, "to")?;
+            optional_str(attributes, kind, "subject")?;
+        }
+        "indicator" => {
+            required_str(attributes, kind, "pattern")?;
+            optional_str(attributes, kind, "patternType")?;
+            optional_str(attributes, kind, "name")?;
+            optional_str(attributes, kind, "validFrom")?;
+        }
+        "malware" => {
+            required_str(attributes, kind, "name")?;
+        }
+        "attack_pattern" => {
+            required_str(attributes, kind, "name")?;
+
+            if let Some(id) = optional_str(attributes, kind, "externalId")? {
+                if !is_technique_id(id) {
+                    return Err(format!(
+                        "attack_pattern.externalId is not an ATT&CK technique id: {id}"
+                    ));
+                }
+            }
+        }
+        "connection" => {
+            for key in ["srcIp", "dstIp"] {
+                let address = required_str(attributes, kind, key)?;
+                address.trim().parse::<IpAddr>().map_err(|_| {
+                    format!("connection.{key} is not a valid IP address: {address}")
+                })?;
+            }
+
+            for key in ["srcPort", "dstPort", "count", "bytes"] {
+                optional_u64(attributes, kind, key)?;
+            }
+            optional_str(attributes, kind, "proto")?;
+            optional_str(attributes, kind, "service")?;
+        }
+        "certificate" => {
+            let fingerprint = optional_str(attributes, kind, "fingerprint")?;
+            let subject = optional_str(attributes, kind, "subject")?;
+
+            if fingerprint.is_none() && subject.is_none() {
+                return Err("certificate requires a fingerprint or subject".to_owned());
+            }
+            if let Some(fingerprint) = fingerprint {
+                let digits = fingerprint.replace(':', "");
+                if digits.is_empty() || !is_hex_digest(&digits, digits.len()) {
+                    return Err(format!(
+                        "certificate.fingerprint must be hexadecimal: {fingerprint}"
+                    ));
+                }
+            }
+            optional_str(attributes, kind, "issuer")?;
+        }
+        "alert" => {
+            required_str(attributes, kind, "signature")?;
+            optional_u64(attributes, kind, "signatureId")?;
+            optional_u64(attributes, kind, "severity")?;
+            optional_u64(attributes, kind, "count")?;
+            optional_str(attributes, kind, "category")?;
+        }
+        _ => return Err(format!("unsupported entity kind: {kind}")),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn accepts_well_formed_entities() {
+        let cases = [
+            ("ip", json!({ "address": "2001:db8::1" })),
+            ("domain", json!({ "name": "update.evil-cdn.com" })),
+            ("url", json!({ "url": "https://example.org/a.ps1" })),
+            ("process", json!({ "pid": 4242, "image": "powershell.exe" })),
+            (
+                "hash",
+                json!({ "algorithm": "SHA256", "value": "a".repeat(64) }),
+            ),
+            (
+                "registry_key",
+                json!({ "path": "HKLM\\Software\\Microsoft\\Windows\\CurrentVersion\\Run" }),
+            ),
+            (
+                "email",
+                json!({ "from": "hr@corp.example", "to": ["a@b.io"] }),
+            ),
+            ("event", json!({})),
+            (
+                "indicator",
+                json!({ "pattern": "[ipv4-addr:value = '45.33.32.156']", "patternType": "stix" }),
+            ),
+            ("malware", json!({ "name": "Cobalt Strike" })),
+            (
+                "attack_pattern",
+                json!({ "name": "PowerShell", "externalId": "T1059.001" }),
+            ),
+            (
+                "connection",
+                json!({ "srcIp": "10.0.0.5", "dstIp": "198.51.100.7", "dstPort": 443, "proto": "tcp" }),
+            ),
+            (
+                "certificate",
+                json!({ "fingerprint": "ab:cd:ef:01", "subject": "CN=cdn.example" }),
+            ),
+            (
+                "alert",
+                json!({ "signature": "ET MALWARE Beacon", "signatureId": 2030000, "severity": 1 }),
+            ),
+        ];
+
+        for (kind, attributes) in cases {
+            assert_eq!(
+                validate_entity_attributes(kind, &attributes),
+                Ok(()),
+                "{kind} should be valid"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_entities() {
+        let cases = [
+            ("ip", json!({ "address": "999.1.1.1" })),
+            ("ip", json!({})),
+            ("domain", json!({ "name": "localhost" })),
+            ("hash", json!({ "algorithm": "sha256", "value": "abc" })),
+            ("file", json!({ "md5": "zz" })),
+            ("process", json!({ "pid": -1 })),
+            ("registry_key", json!({ "path": "Software\\Run" })),
+            ("email", json!({ "from": "not-an-address" })),
+            ("user", json!("alice")),
+            ("indicator", json!({ "name": "no pattern" })),
+            (
+                "attack_pattern",
+                json!({ "name": "x", "externalId": "T12" }),
+            ),
+            ("connection", json!({ "srcIp": "10.0.0.5", "dstIp": "-" })),
+            ("certificate", json!({ "issuer": "CN=Let's Encrypt" })),
+            ("certificate", json!({ "fingerprint": "not hex" })),
+            ("alert", json!({ "signature": "x", "severity": "high" })),
+        ];
+
+        for (kind, attributes) in cases {
+            assert!(
+                validate_entity_attributes(kind, &attributes).is_err(),
+                "{kind} {attributes} should be rejected"
+            );
+        }
+    }
+}
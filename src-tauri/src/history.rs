@@ -0,0 +1,648 @@
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, DatabaseTransaction, QueryResult,
+    Statement, TransactionTrait,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    audit, delete_edge_rows, delete_node_rows, id_placeholders, list_edges_touching,
+    list_nodes_by_ids, write_edges, write_nodes, EdgeModel, EdgePayload, NodeModel, NodePayload,
+};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum HistoryAction {
+    Create,
+    Update,
+    Delete,
+    /// Taken back out of the trash.
+    Restore,
+    /// Removed from the trash for good.
+    Purge,
+}
+
+impl HistoryAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Restore => "restore",
+            Self::Purge => "purge",
+        }
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "create" => Some(Self::Create),
+            "update" => Some(Self::Update),
+            "delete" => Some(Self::Delete),
+            "restore" => Some(Self::Restore),
+            "purge" => Some(Self::Purge),
+            _ => None,
+        }
+    }
+}
+
+/// One recorded change to a node.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistoryEntry {
+    pub(crate) seq: i64,
+    pub(crate) batch_id: String,
+    pub(crate) node_id: String,
+    pub(crate) action: HistoryAction,
+    pub(crate) before: Option<NodeModel>,
+    pub(crate) after: Option<NodeModel>,
+    /// Edges removed, restored or purged together with the node.
+    pub(crate) edges: Vec<EdgeModel>,
+    pub(crate) created_at: i64,
+}
+
+impl HistoryEntry {
+    fn from_row(row: QueryResult) -> Option<Self> {
+        let json = |column: &str| {
+            row.try_get::<String>("", column)
+                .ok()
+                .and_then(|raw| serde_json::from_str::<serde_json::Value>(&raw).ok())
+        };
+        let node = |column: &str| json(column).and_then(|value| serde_json::from_value(value).ok());
+
+        Some(Self {
+            seq: row.try_get("", "seq").ok()?,
+            batch_id: row.try_get("", "batch_id").ok()?,
+            node_id: row.try_get("", "node_id").ok()?,
+            action: HistoryAction::parse(&row.try_get::<String>("", "action").ok()?)?,
+            before: node("before"),
+            after: node("after"),
+            edges: json("edges")
+                .and_then(|value| serde_json::from_value(value).ok())
+                .unwrap_or_default(),
+            created_at: row.try_get("", "created_at").unwrap_or(0),
+        })
+    }
+}
+
+/// The changes one write made, newest batches first in listings.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistoryBatch {
+    pub(crate) batch_id: String,
+    pub(crate) created_at: i64,
+    pub(crate) created: i64,
+    pub(crate) updated: i64,
+    pub(crate) deleted: i64,
+    pub(crate) restored: i64,
+    pub(crate) purged: i64,
+    /// Relations deleted or restored on their own.
+    pub(crate) deleted_edges: i64,
+    pub(crate) restored_edges: i64,
+}
+
+/// What a restore or revert wrote, recorded as a batch of its own so it can
+/// be reverted in turn.
+#[derive(Debug, Default, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistoryRevert {
+    pub(crate) batch_id: String,
+    pub(crate) upserted: Vec<String>,
+    pub(crate) deleted: Vec<String>,
+    pub(crate) edges: Vec<String>,
+    /// Relations deleted on their own, not along with a node.
+    pub(crate) deleted_edges: Vec<String>,
+}
+
+/// A relation deleted or restored on its own, as it was at that point.
+struct EdgeEntry {
+    action: HistoryAction,
+    edge: EdgeModel,
+}
+
+pub(crate) fn new_batch_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|err| err.to_string())
+}
+
+/// Appends one entry per node whose state differs between `before` and
+/// `after`. A node missing from `after` was deleted, taking the edges in
+/// `removed_edges` that touch it along.
+pub(crate) async fn record(
+    txn: &DatabaseTransaction,
+    batch_id: &str,
+    before: Vec<NodeModel>,
+    after: Vec<NodeModel>,
+    removed_edges: Vec<EdgeModel>,
+) -> Result<(), String> {
+    let mut states = BTreeMap::<String, (Option<NodeModel>, Option<NodeModel>)>::new();
+    for node in before {
+        let id = node.id.clone();
+        states.entry(id).or_default().0 = Some(node);
+    }
+    for node in after {
+        let id = node.id.clone();
+        states.entry(id).or_default().1 = Some(node);
+    }
+
+    for (node_id, (before, after)) in states {
+        let action = match (&before, &after) {
+            (None, Some(_)) => HistoryAction::Create,
+            (Some(before), Some(after)) if before != after => HistoryAction::Update,
+            (Some(_), None) => HistoryAction::Delete,
+            _ => continue,
+        };
+        let edges = removed_edges
+            .iter()
+            .filter(|edge| {
+                action == HistoryAction::Delete
+                    && (edge.source == node_id || edge.target == node_id)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        append(
+            txn,
+            batch_id,
+            action,
+            &node_id,
+            before.as_ref(),
+            after.as_ref(),
+            &edges,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Node and edge entries share one `seq` so batches keep a single order.
+const NEXT_SEQ: &str = "(SELECT COALESCE(MAX(seq), 0) + 1 FROM (
+        SELECT MAX(seq) AS seq FROM node_history
+        UNION ALL SELECT MAX(seq) FROM edge_history
+    ))";
+
+pub(crate) async fn append(
+    txn: &DatabaseTransaction,
+    batch_id: &str,
+    action: HistoryAction,
+    node_id: &str,
+    before: Option<&NodeModel>,
+    after: Option<&NodeModel>,
+    edges: &[EdgeModel],
+) -> Result<(), String> {
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        format!(
+            "INSERT INTO node_history
+                 (seq, batch_id, node_id, action, before, after, edges, created_at)
+             VALUES ({NEXT_SEQ}, ?, ?, ?, ?, ?, ?, unixepoch());"
+        ),
+        vec![
+            batch_id.into(),
+            node_id.into(),
+            action.as_str().into(),
+            before.map(to_json).transpose()?.into(),
+            after.map(to_json).transpose()?.into(),
+            (!edges.is_empty())
+                .then(|| to_json(&edges))
+                .transpose()?
+                .into(),
+        ],
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+
+    Ok(())
+}
+
+/// Records relations deleted or restored on their own. Only `Delete` and
+/// `Restore` apply to them.
+pub(crate) async fn append_edges(
+    txn: &DatabaseTransaction,
+    batch_id: &str,
+    action: HistoryAction,
+    edges: &[EdgeModel],
+) -> Result<(), String> {
+    for edge in edges {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "INSERT INTO edge_history (seq, batch_id, edge_id, action, edge, created_at)
+                 VALUES ({NEXT_SEQ}, ?, ?, ?, ?, unixepoch());"
+            ),
+            vec![
+                batch_id.into(),
+                edge.id.clone().into(),
+                action.as_str().into(),
+                to_json(edge)?.into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+
+    Ok(())
+}
+
+async fn edge_entries(txn: &DatabaseTransaction, batch_id: &str) -> Result<Vec<EdgeEntry>, String> {
+    let rows = txn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "SELECT action, edge FROM edge_history WHERE batch_id = ? ORDER BY seq ASC;".to_owned(),
+            vec![batch_id.into()],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(EdgeEntry {
+                action: HistoryAction::parse(&row.try_get::<String>("", "action").ok()?)?,
+                edge: serde_json::from_str(&row.try_get::<String>("", "edge").ok()?).ok()?,
+            })
+        })
+        .collect())
+}
+
+/// Edges by id whether live or in the trash, with whether they are live.
+async fn stored_edges(
+    txn: &DatabaseTransaction,
+    ids: &[String],
+) -> Result<BTreeMap<String, (EdgeModel, bool)>, String> {
+    if ids.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let (placeholders, values) = id_placeholders(ids);
+    let rows = txn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT id, source, target, kind, label, created_at, updated_at,
+                        deleted_at IS NULL AS live
+                 FROM edges WHERE id IN ({placeholders});"
+            ),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let live = row.try_get::<bool>("", "live").unwrap_or(false);
+            let edge = EdgeModel::from_row(row);
+            (edge.id.clone(), (edge, live))
+        })
+        .collect())
+}
+
+fn same_relation(left: &EdgeModel, right: &EdgeModel) -> bool {
+    (&left.source, &left.target, &left.kind, &left.label)
+        == (&right.source, &right.target, &right.kind, &right.label)
+}
+
+const ENTRY_COLUMNS: &str = "seq, batch_id, node_id, action, before, after, edges, created_at";
+
+async fn query_entries<C: ConnectionTrait>(
+    conn: &C,
+    filter: &str,
+    value: &str,
+) -> Result<Vec<HistoryEntry>, String> {
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {ENTRY_COLUMNS} FROM node_history WHERE {filter} = ? ORDER BY seq ASC;"
+            ),
+            vec![value.into()],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(HistoryEntry::from_row)
+        .collect())
+}
+
+/// Every recorded change to one node, oldest first.
+pub(crate) async fn node_history(
+    db: &DatabaseConnection,
+    node_id: &str,
+) -> Result<Vec<HistoryEntry>, String> {
+    query_entries(db, "node_id", node_id).await
+}
+
+pub(crate) async fn list_batches(
+    db: &DatabaseConnection,
+    limit: u32,
+) -> Result<Vec<HistoryBatch>, String> {
+    let rows = db
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "SELECT batch_id,
+                    MIN(created_at) AS created_at,
+                    SUM(node AND action = 'create') AS created,
+                    SUM(node AND action = 'update') AS updated,
+                    SUM(node AND action = 'delete') AS deleted,
+                    SUM(node AND action = 'restore') AS restored,
+                    SUM(node AND action = 'purge') AS purged,
+                    SUM(NOT node AND action = 'delete') AS deleted_edges,
+                    SUM(NOT node AND action = 'restore') AS restored_edges
+             FROM (
+                 SELECT seq, batch_id, action, created_at, 1 AS node FROM node_history
+                 UNION ALL
+                 SELECT seq, batch_id, action, created_at, 0 AS node FROM edge_history
+             )
+             GROUP BY batch_id
+             ORDER BY MAX(seq) DESC
+             LIMIT ?;"
+                .to_owned(),
+            vec![limit.into()],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| HistoryBatch {
+            batch_id: row.try_get("", "batch_id").unwrap_or_default(),
+            created_at: row.try_get("", "created_at").unwrap_or(0),
+            created: row.try_get("", "created").unwrap_or(0),
+            updated: row.try_get("", "updated").unwrap_or(0),
+            deleted: row.try_get("", "deleted").unwrap_or(0),
+            restored: row.try_get("", "restored").unwrap_or(0),
+            purged: row.try_get("", "purged").unwrap_or(0),
+            deleted_edges: row.try_get("", "deleted_edges").unwrap_or(0),
+            restored_edges: row.try_get("", "restored_edges").unwrap_or(0),
+        })
+        .collect())
+}
+
+pub(crate) fn payload(node: &NodeModel) -> NodePayload {
+    NodePayload {
+        id: node.id.clone(),
+        node_type: node.node_type.clone(),
+        x: node.x,
+        y: node.y,
+        content: node.content.clone(),
+        width: node.width,
+        height: node.height,
+        kind: node.kind.clone(),
+        attributes: node.attributes.clone(),
+    }
+}
+
+/// Puts each node into its target state (`None` removes it) and re-adds the
+/// edges whose endpoints both exist afterwards, as one new batch.
+pub(crate) async fn apply_states(
+    txn: &DatabaseTransaction,
+    targets: BTreeMap<String, Option<NodeModel>>,
+    edges: Vec<EdgeModel>,
+) -> Result<HistoryRevert, String> {
+    let batch_id = new_batch_id();
+    let ids = targets.keys().cloned().collect::<Vec<_>>();
+    let before = list_nodes_by_ids(txn, &ids).await?;
+
+    let removed = targets
+        .iter()
+        .filter(|(id, target)| target.is_none() && before.iter().any(|node| &node.id == *id))
+        .map(|(id, _)| id.clone())
+        .collect::<Vec<_>>();
+    let removed_edges = list_edges_touching(txn, &removed).await?;
+    if !removed.is_empty() {
+        delete_node_rows(txn, &batch_id, &removed).await?;
+    }
+
+    let restored = targets.into_values().flatten().collect::<Vec<_>>();
+    let upserted = write_nodes(txn, restored.iter().map(payload).collect()).await?;
+    // Upserts keep a kind or attributes the payload leaves out; a restore has
+    // to clear them too.
+    for node in &restored {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "UPDATE nodes SET kind = ?, attributes = ? WHERE id = ?;".to_owned(),
+            vec![
+                node.kind.clone().into(),
+                node.attributes
+                    .as_ref()
+                    .map(|attributes| attributes.to_string())
+                    .into(),
+                node.id.clone().into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+
+    let endpoints = edges
+        .iter()
+        .flat_map(|edge| [edge.source.clone(), edge.target.clone()])
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let present = list_nodes_by_ids(txn, &endpoints)
+        .await?
+        .into_iter()
+        .map(|node| node.id)
+        .collect::<BTreeSet<_>>();
+    let edges = edges
+        .into_iter()
+        .filter(|edge| present.contains(&edge.source) && present.contains(&edge.target))
+        .map(|edge| (edge.id.clone(), edge))
+        .collect::<BTreeMap<_, _>>()
+        .into_values()
+        .map(|edge| EdgePayload {
+            id: edge.id,
+            source: edge.source,
+            target: edge.target,
+            kind: edge.kind,
+            label: edge.label,
+        })
+        .collect();
+    let edges = write_edges(txn, edges).await?;
+
+    let after = list_nodes_by_ids(txn, &ids).await?;
+    record(txn, &batch_id, before, after, removed_edges).await?;
+
+    Ok(HistoryRevert {
+        batch_id,
+        upserted,
+        deleted: removed,
+        edges,
+        deleted_edges: Vec::new(),
+    })
+}
+
+async fn purged_since(txn: &DatabaseTransaction, entry: &HistoryEntry) -> Result<bool, String> {
+    let row = txn
+        .query_one(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "SELECT 1 FROM node_history WHERE node_id = ? AND action = 'purge' AND seq > ?;"
+                .to_owned(),
+            vec![entry.node_id.clone().into(), entry.seq.into()],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(row.is_some())
+}
+
+/// Undoes every change of a batch. Fails without writing anything when a
+/// node or relation has changed since, so later edits are never silently lost.
+pub(crate) async fn revert_batch(
+    db: &DatabaseConnection,
+    actor: &str,
+    batch_id: &str,
+) -> Result<HistoryRevert, String> {
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let entries = query_entries(&txn, "batch_id", batch_id).await?;
+    let edge_entries = edge_entries(&txn, batch_id).await?;
+
+    if entries.is_empty() && edge_entries.is_empty() {
+        return Err(format!("unknown history batch: {batch_id}"));
+    }
+
+    let ids = entries
+        .iter()
+        .map(|entry| entry.node_id.clone())
+        .collect::<Vec<_>>();
+    let current = list_nodes_by_ids(&txn, &ids).await?;
+    let mut targets = BTreeMap::new();
+    let mut edges = Vec::new();
+
+    for entry in entries {
+        if entry.action == HistoryAction::Purge {
+            return Err(format!(
+                "batch {batch_id} purged nodes and cannot be reverted"
+            ));
+        }
+
+        let now = current.iter().find(|node| node.id == entry.node_id);
+        if now.is_none() && entry.before.is_some() && purged_since(&txn, &entry).await? {
+            return Err(format!("node {} was purged", entry.node_id));
+        }
+        if now != entry.after.as_ref() {
+            return Err(format!(
+                "node {} changed after batch {batch_id}",
+                entry.node_id
+            ));
+        }
+
+        edges.extend(entry.edges);
+        targets.insert(entry.node_id, entry.before);
+    }
+
+    let edge_ids = edge_entries
+        .iter()
+        .map(|entry| entry.edge.id.clone())
+        .collect::<Vec<_>>();
+    let stored = stored_edges(&txn, &edge_ids).await?;
+    let mut readded = Vec::new();
+    let mut redeleted = Vec::new();
+
+    for entry in edge_entries {
+        let id = entry.edge.id.clone();
+        match (entry.action, stored.get(&id)) {
+            (_, None) => return Err(format!("edge {id} was purged")),
+            (HistoryAction::Delete, Some((_, false))) => readded.push(entry.edge),
+            (HistoryAction::Restore, Some((edge, true))) if same_relation(edge, &entry.edge) => {
+                redeleted.push(id)
+            }
+            _ => return Err(format!("edge {id} changed after batch {batch_id}")),
+        }
+    }
+    edges.extend(readded.iter().cloned());
+
+    let mut revert = apply_states(&txn, targets, edges).await?;
+    if let Some(edge) = readded.iter().find(|edge| !revert.edges.contains(&edge.id)) {
+        return Err(format!(
+            "edge {} connects a node that no longer exists",
+            edge.id
+        ));
+    }
+    append_edges(&txn, &revert.batch_id, HistoryAction::Restore, &readded).await?;
+    // Some may already have gone along with a node deleted above.
+    revert.deleted_edges = delete_edge_rows(&txn, &revert.batch_id, &redeleted).await?;
+
+    audit::append(&txn, actor, "history.revert", &revert).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(revert)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        list_edges_internal, list_nodes_internal,
+        test_support::{create_test_db, edge, note},
+        upsert_edges_internal, upsert_nodes_internal,
+    };
+
+    async fn contents(db: &DatabaseConnection) -> Vec<String> {
+        let mut contents = list_nodes_internal(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|node| node.content)
+            .collect::<Vec<_>>();
+        contents.sort();
+        contents
+    }
+
+    #[tokio::test]
+    async fn records_batches_and_reverts_them() {
+        let db = create_test_db().await;
+        upsert_nodes_internal(&db, "test", vec![note("a", "first"), note("b", "second")])
+            .await
+            .unwrap();
+        upsert_edges_internal(&db, "test", vec![edge("e1", "a", "b")])
+            .await
+            .unwrap();
+        upsert_nodes_internal(&db, "test", vec![note("a", "edited"), note("b", "second")])
+            .await
+            .unwrap();
+
+        let history = node_history(&db, "shape:a").await.unwrap();
+        let actions = history.iter().map(|entry| entry.action).collect::<Vec<_>>();
+        assert_eq!(actions, vec![HistoryAction::Create, HistoryAction::Update]);
+        assert_eq!(history[1].before.as_ref().unwrap().content, "first");
+        assert_eq!(history[1].after.as_ref().unwrap().content, "edited");
+        // The unchanged `b` is not part of the second batch.
+        let batches = list_batches(&db, 10).await.unwrap();
+        assert_eq!((batches[0].updated, batches[0].created), (1, 0));
+        assert_eq!((batches[1].created, batches[1].updated), (2, 0));
+
+        let revert = revert_batch(&db, "test", &batches[0].batch_id)
+            .await
+            .unwrap();
+        assert_eq!(revert.upserted, vec!["shape:a"]);
+        assert_eq!(contents(&db).await, vec!["first", "second"]);
+
+        upsert_nodes_internal(&db, "test", vec![note("b", "later edit")])
+            .await
+            .unwrap();
+        assert_eq!(
+            revert_batch(&db, "test", &batches[1].batch_id)
+                .await
+                .unwrap_err(),
+            format!("node shape:b changed after batch {}", batches[1].batch_id)
+        );
+
+        let latest = list_batches(&db, 1).await.unwrap().remove(0);
+        revert_batch(&db, "test", &latest.batch_id).await.unwrap();
+        let revert = revert_batch(&db, "test", &batches[1].batch_id)
+            .await
+            .unwrap();
+        assert_eq!(revert.deleted, vec!["shape:a", "shape:b"]);
+        assert!(contents(&db).await.is_empty());
+        assert!(list_edges_internal(&db).await.unwrap().is_empty());
+        assert!(revert_batch(&db, "test", "missing").await.is_err());
+    }
+}
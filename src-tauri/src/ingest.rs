@@ -0,0 +1,508 @@
+use sea_orm::{DatabaseConnection, TransactionTrait};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+
+use crate::{
+    attack, audit,
+    changes::{ChangeEvent, ChangeOrigin},
+    layout::{self, LayoutAlgorithm},
+    list_edges_by_ids, list_nodes_by_ids, list_nodes_internal, normalize_shape_id,
+    stix::CYBERWEAVER_NAMESPACE,
+    upsert_edge_rows, upsert_node_rows, validate_node_payload, AppState, EdgeModel, EdgePayload,
+    NodeModel, NodePayload,
+};
+
+const PLACEMENT_GAP: f64 = 200.0;
+const DEFAULT_NODE_WIDTH: f64 = 200.0;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ImportReport {
+    pub(crate) nodes: usize,
+    pub(crate) edges: usize,
+    pub(crate) techniques_tagged: usize,
+    /// `<id>: <reason>` for every record that was not imported.
+    pub(crate) skipped: Vec<String>,
+}
+
+/// Validated nodes and edges parsed from an external format. Positions are
+/// filled in by [`apply`].
+#[derive(Debug, Default)]
+pub(crate) struct ImportPlan {
+    pub(crate) nodes: Vec<NodePayload>,
+    pub(crate) edges: Vec<EdgePayload>,
+    /// `(node id, technique id)` pairs to tag once the nodes exist.
+    pub(crate) techniques: Vec<(String, String)>,
+    pub(crate) skipped: Vec<String>,
+}
+
+/// Keys that identify an entity across records and sources. The first key
+/// names new nodes; any of them matches an entity seen before.
+fn entity_keys(kind: &str, attributes: &Map<String, Value>) -> Vec<String> {
+    let value = |key: &str| {
+        attributes
+            .get(key)
+            .and_then(|value| match value {
+                Value::Number(number) => Some(number.to_string()),
+                value => value.as_str().map(str::to_owned),
+            })
+            .map(|value| value.to_ascii_lowercase())
+    };
+
+    let keys = match kind {
+        "process" => vec![value("guid")],
+        "file" => vec![
+            value("sha256").map(|hash| format!("sha256:{hash}")),
+            value("path").map(|path| format!("path:{path}")),
+            value("sha1").map(|hash| format!("sha1:{hash}")),
+            value("md5").map(|hash| format!("md5:{hash}")),
+        ],
+        "ip" => vec![value("address")],
+        "domain" => vec![value("name")],
+        "registry_key" => vec![value("path")],
+        "connection" => vec![(|| {
+            Some(format!(
+                "{}|{}|{}|{}",
+                value("proto").unwrap_or_default(),
+                value("srcIp")?,
+                value("dstIp")?,
+                value("dstPort").unwrap_or_default()
+            ))
+        })()],
+        "certificate" => vec![
+            value("fingerprint").map(|fingerprint| fingerprint.replace(':', "")),
+            value("subject"),
+        ],
+        "alert" => vec![value("signatureId"), value("signature")],
+        _ => Vec::new(),
+    };
+
+    keys.into_iter()
+        .flatten()
+        .map(|key| format!("{kind}|{key}"))
+        .collect()
+}
+
+/// Folds `incoming` into `attributes`. Sighting counters add up within one
+/// import; against what the case already holds they keep the larger value,
+/// so importing the same log twice does not double them.
+fn merge_attributes(
+    attributes: &mut Map<String, Value>,
+    incoming: Map<String, Value>,
+    accumulate: bool,
+) {
+    for (key, value) in incoming {
+        let Some(current) = attributes.get_mut(&key) else {
+            attributes.insert(key, value);
+            continue;
+        };
+
+        match key.as_str() {
+            "count" | "bytes" => {
+                if let (Some(left), Some(right)) = (current.as_u64(), value.as_u64()) {
+                    *current = if accumulate {
+                        left + right
+                    } else {
+                        left.max(right)
+                    }
+                    .into();
+                }
+            }
+            "firstSeen" if value.as_str() < current.as_str() => *current = value,
+            "lastSeen" if value.as_str() > current.as_str() => *current = value,
+            _ => {}
+        }
+    }
+}
+
+fn entity_payload(
+    id: &str,
+    kind: &str,
+    attributes: &Map<String, Value>,
+    content: &str,
+) -> NodePayload {
+    NodePayload {
+        id: id.to_owned(),
+        node_type: "geo".to_owned(),
+        x: 0.0,
+        y: 0.0,
+        content: content.to_owned(),
+        width: None,
+        height: None,
+        kind: Some(kind.to_owned()),
+        attributes: Some(Value::Object(attributes.clone())),
+    }
+}
+
+struct PendingNode {
+    kind: &'static str,
+    attributes: Map<String, Value>,
+    content: String,
+}
+
+type PendingEdge = (String, &'static str, String, Option<String>);
+
+/// Entities and relations read from a log, deduplicated by [`entity_keys`]
+/// against each other and against the entities already in the case.
+pub(crate) struct EntityGraph {
+    nodes: BTreeMap<String, PendingNode>,
+    edges: BTreeMap<String, EdgePayload>,
+    aliases: HashMap<String, String>,
+    existing: HashMap<String, NodeModel>,
+}
+
+/// Entities and relations of one record, merged into the graph only when the
+/// whole record maps cleanly.
+pub(crate) struct RecordBatch<'a> {
+    graph: &'a EntityGraph,
+    nodes: Vec<(String, PendingNode)>,
+    edges: Vec<PendingEdge>,
+}
+
+impl RecordBatch<'_> {
+    /// Validates an entity and returns the id of the node it maps to.
+    pub(crate) fn entity(
+        &mut self,
+        kind: &'static str,
+        attributes: Map<String, Value>,
+        content: String,
+    ) -> Result<String, String> {
+        let keys = entity_keys(kind, &attributes);
+        let first = keys
+            .first()
+            .ok_or_else(|| format!("{kind} has nothing to identify it by"))?;
+        let id = keys
+            .iter()
+            .find_map(|key| {
+                self.graph.aliases.get(key).cloned().or_else(|| {
+                    self.nodes
+                        .iter()
+                        .find(|(_, node)| entity_keys(node.kind, &node.attributes).contains(key))
+                        .map(|(id, _)| id.clone())
+                })
+            })
+            .unwrap_or_else(|| {
+                format!(
+                    "shape:{}",
+                    Uuid::new_v5(&CYBERWEAVER_NAMESPACE, format!("entity|{first}").as_bytes())
+                )
+            });
+
+        validate_node_payload(&entity_payload(&id, kind, &attributes, &content))?;
+        self.nodes.push((
+            id.clone(),
+            PendingNode {
+                kind,
+                attributes,
+                content,
+            },
+        ));
+        Ok(id)
+    }
+
+    pub(crate) fn relate(
+        &mut self,
+        source: &str,
+        kind: &'static str,
+        target: &str,
+        label: Option<String>,
+    ) {
+        self.edges
+            .push((source.to_owned(), kind, target.to_owned(), label));
+    }
+}
+
+impl EntityGraph {
+    pub(crate) fn new(existing: Vec<NodeModel>) -> Self {
+        let mut aliases = HashMap::new();
+
+        for node in &existing {
+            let (Some(kind), Some(Value::Object(attributes))) = (&node.kind, &node.attributes)
+            else {
+                continue;
+            };
+
+            for key in entity_keys(kind, attributes) {
+                aliases.entry(key).or_insert_with(|| node.id.clone());
+            }
+        }
+
+        Self {
+            nodes: BTreeMap::new(),
+            edges: BTreeMap::new(),
+            aliases,
+            existing: existing
+                .into_iter()
+                .map(|node| (node.id.clone(), node))
+                .collect(),
+        }
+    }
+
+    /// Maps one record through `map`. Nothing from the record is kept when
+    /// it fails.
+    pub(crate) fn record(
+        &mut self,
+        map: impl FnOnce(&mut RecordBatch) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut batch = RecordBatch {
+            graph: self,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        map(&mut batch)?;
+
+        let RecordBatch { nodes, edges, .. } = batch;
+        self.merge(nodes);
+        self.merge_edges(edges);
+        Ok(())
+    }
+
+    fn merge(&mut self, batch: Vec<(String, PendingNode)>) {
+        for (id, node) in batch {
+            for key in entity_keys(node.kind, &node.attributes) {
+                self.aliases.entry(key).or_insert_with(|| id.clone());
+            }
+
+            match self.nodes.get_mut(&id) {
+                Some(existing) => merge_attributes(&mut existing.attributes, node.attributes, true),
+                None => {
+                    self.nodes.insert(id, node);
+                }
+            }
+        }
+    }
+
+    /// Repeated relations between the same pair collapse into one edge that
+    /// lists every distinct label.
+    fn merge_edges(&mut self, edges: Vec<PendingEdge>) {
+        for (source, kind, target, label) in edges {
+            let id = Uuid::new_v5(
+                &CYBERWEAVER_NAMESPACE,
+                format!("entity|{source}|{kind}|{target}").as_bytes(),
+            )
+            .to_string();
+
+            match self.edges.get_mut(&id) {
+                Some(edge) => {
+                    if let Some(label) = label {
+                        let labels = edge.label.get_or_insert_with(String::new);
+                        if !labels.split(", ").any(|existing| existing == label) {
+                            if !labels.is_empty() {
+                                labels.push_str(", ");
+                            }
+                            labels.push_str(&label);
+                        }
+                    }
+                }
+                None => {
+                    self.edges.insert(
+                        id.clone(),
+                        EdgePayload {
+                            id,
+                            source,
+                            target,
+                            kind: kind.to_owned(),
+                            label,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Distinct entities and relations collected so far.
+    pub(crate) fn len(&self) -> usize {
+        self.nodes.len() + self.edges.len()
+    }
+
+    /// Turns the graph into a plan. Entities already in the case keep their
+    /// content and attributes, with what the log adds on top.
+    pub(crate) fn into_plan(mut self, skipped: Vec<String>) -> ImportPlan {
+        let nodes = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(|(id, mut node)| {
+                let mut content = node.content;
+
+                if let Some(current) = self.existing.remove(&id) {
+                    if let Some(Value::Object(attributes)) = current.attributes {
+                        let incoming = std::mem::replace(&mut node.attributes, attributes);
+                        merge_attributes(&mut node.attributes, incoming, false);
+                    }
+                    content = current.content;
+                }
+
+                entity_payload(&id, node.kind, &node.attributes, &content)
+            })
+            .collect();
+
+        ImportPlan {
+            nodes,
+            edges: self.edges.into_values().collect(),
+            techniques: Vec::new(),
+            skipped,
+        }
+    }
+}
+
+/// Places nodes that are new to the case to the right of everything already
+/// on the canvas, arranged in layers along their relations. Nodes that
+/// already exist keep their position.
+async fn place_nodes(db: &DatabaseConnection, plan: &mut ImportPlan) -> Result<(), String> {
+    let ids = plan
+        .nodes
+        .iter()
+        .map(|node| node.id.clone())
+        .collect::<Vec<_>>();
+    let existing = list_nodes_by_ids(db, &ids)
+        .await?
+        .into_iter()
+        .map(|node| (node.id.clone(), node))
+        .collect::<HashMap<_, _>>();
+    let others = list_nodes_internal(db)
+        .await?
+        .into_iter()
+        .filter(|node| !existing.contains_key(&node.id))
+        .collect::<Vec<_>>();
+
+    let origin_x = others
+        .iter()
+        .map(|node| node.x + node.width.unwrap_or(DEFAULT_NODE_WIDTH))
+        .fold(None, |max: Option<f64>, right| {
+            Some(max.map_or(right, |max| max.max(right)))
+        })
+        .map_or(0.0, |right| right + PLACEMENT_GAP);
+    let origin_y = others
+        .iter()
+        .map(|node| node.y)
+        .fold(None, |min: Option<f64>, top| {
+            Some(min.map_or(top, |min| min.min(top)))
+        })
+        .unwrap_or(0.0);
+
+    let fresh = plan
+        .nodes
+        .iter()
+        .filter(|node| !existing.contains_key(&node.id))
+        .map(|node| NodeModel {
+            id: node.id.clone(),
+            node_type: node.node_type.clone(),
+            x: origin_x,
+            y: origin_y,
+            content: node.content.clone(),
+            width: None,
+            height: None,
+            kind: node.kind.clone(),
+            attributes: None,
+        })
+        .collect::<Vec<_>>();
+    let relations = plan
+        .edges
+        .iter()
+        .map(|edge| EdgeModel {
+            id: edge.id.clone(),
+            source: edge.source.clone(),
+            target: edge.target.clone(),
+            kind: edge.kind.clone(),
+            label: None,
+            created_at: 0,
+            updated_at: 0,
+        })
+        .collect::<Vec<_>>();
+    let positions = layout::compute(LayoutAlgorithm::Layered, &fresh, &relations)
+        .into_iter()
+        .map(|position| (position.id, (position.x, position.y)))
+        .collect::<HashMap<_, _>>();
+
+    for node in &mut plan.nodes {
+        if let Some(current) = existing.get(&node.id) {
+            node.x = current.x;
+            node.y = current.y;
+            node.width = current.width;
+            node.height = current.height;
+        } else if let Some(&(x, y)) = positions.get(&node.id) {
+            node.x = x;
+            node.y = y;
+        }
+    }
+
+    Ok(())
+}
+
+/// Lays out and writes a plan into the open case through the regular upsert
+/// path, then adds its technique tags to whatever the nodes already carry.
+/// Everything is written in one transaction, so a failed import leaves the
+/// case as it was.
+pub(crate) async fn apply(state: &AppState, mut plan: ImportPlan) -> Result<ImportReport, String> {
+    let db = state.db().await?;
+    place_nodes(&db, &mut plan).await?;
+
+    let nodes = plan.nodes.len();
+    let edges = plan.edges.len();
+    let mut skipped = plan.skipped;
+    let known = if plan.techniques.is_empty() {
+        BTreeSet::new()
+    } else {
+        attack::ensure_loaded(&db).await?;
+        attack::known_technique_ids(&db).await?
+    };
+
+    let actor = audit::actor(ChangeOrigin::Backend);
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let node_ids = upsert_node_rows(&txn, &actor, plan.nodes).await?;
+    let edge_ids = upsert_edge_rows(&txn, &actor, plan.edges).await?;
+    let mut techniques_tagged = 0;
+
+    for (node_id, technique) in plan.techniques {
+        let node_id = normalize_shape_id(&node_id);
+        let technique = technique.trim().to_ascii_uppercase();
+
+        if !known.contains(&technique) {
+            skipped.push(format!("{node_id}: unknown ATT&CK technique: {technique}"));
+            continue;
+        }
+        if list_nodes_by_ids(&txn, std::slice::from_ref(&node_id))
+            .await?
+            .is_empty()
+        {
+            skipped.push(format!("{node_id}: missing node: {node_id}"));
+            continue;
+        }
+
+        let mut tagged = attack::node_techniques(&txn, Some(std::slice::from_ref(&node_id)))
+            .await
+            .map_err(|err| err.to_string())?
+            .into_iter()
+            .map(|tag| tag.technique_id)
+            .collect::<BTreeSet<_>>();
+        if !tagged.insert(technique) {
+            continue;
+        }
+
+        attack::write_node_techniques(&txn, &actor, &node_id, &tagged).await?;
+        techniques_tagged += 1;
+    }
+
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    if !node_ids.is_empty() {
+        let nodes = list_nodes_by_ids(&db, &node_ids).await?;
+        state
+            .changes
+            .publish(ChangeOrigin::Backend, ChangeEvent::NodesUpserted { nodes });
+    }
+    if !edge_ids.is_empty() {
+        let edges = list_edges_by_ids(&db, &edge_ids).await?;
+        state
+            .changes
+            .publish(ChangeOrigin::Backend, ChangeEvent::EdgesUpserted { edges });
+    }
+
+    Ok(ImportReport {
+        nodes,
+        edges,
+        techniques_tagged,
+        skipped,
+    })
+}
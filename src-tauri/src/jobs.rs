@@ -0,0 +1,101 @@
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::ingest::ImportReport;
+
+/// Tauri event carrying every `JobUpdate`.
+pub(crate) const JOB_EVENT: &str = "job-progress";
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Progress of a background job; the last update of a job is never
+/// `Running`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JobUpdate {
+    pub(crate) job_id: String,
+    pub(crate) kind: &'static str,
+    pub(crate) status: JobStatus,
+    /// Records read so far.
+    pub(crate) processed: u64,
+    pub(crate) bytes_read: u64,
+    pub(crate) total_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) report: Option<ImportReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+}
+
+/// Cancellation flags of the jobs currently running.
+#[derive(Default)]
+pub(crate) struct JobRegistry {
+    running: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl JobRegistry {
+    pub(crate) fn start(self: &Arc<Self>, job_id: &str) -> Result<JobGuard, String> {
+        let mut running = self.running.lock().map_err(|err| err.to_string())?;
+
+        if running.contains_key(job_id) {
+            return Err(format!("job {job_id} is already running"));
+        }
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        running.insert(job_id.to_owned(), cancelled.clone());
+
+        Ok(JobGuard {
+            registry: self.clone(),
+            job_id: job_id.to_owned(),
+            cancelled,
+        })
+    }
+
+    pub(crate) fn cancel(&self, job_id: &str) -> Result<(), String> {
+        self.running
+            .lock()
+            .map_err(|err| err.to_string())?
+            .get(job_id)
+            .ok_or_else(|| format!("no running job with id {job_id}"))?
+            .store(true, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+/// Unregisters a job from `JobRegistry::running` however the job ends.
+pub(crate) struct JobGuard {
+    registry: Arc<JobRegistry>,
+    job_id: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobGuard {
+    pub(crate) fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    pub(crate) fn cancelled(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.registry.running.lock() {
+            running.remove(&self.job_id);
+        }
+    }
+}
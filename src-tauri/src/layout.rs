@@ -0,0 +1,567 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{timestamps::parse_timestamp, EdgeModel, NodeModel};
+
+const DEFAULT_NODE_WIDTH: f64 = 200.0;
+const DEFAULT_NODE_HEIGHT: f64 = 120.0;
+const HORIZONTAL_GAP: f64 = 80.0;
+const VERTICAL_GAP: f64 = 120.0;
+const ORDERING_SWEEPS: usize = 8;
+// Pairwise force evaluations per layout; bounds the cost on large graphs.
+const FORCE_BUDGET: usize = 40_000_000;
+const FORCE_ITERATIONS: (usize, usize) = (30, 300);
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum LayoutAlgorithm {
+    /// Sugiyama-style layers following edge direction, for process trees.
+    Layered,
+    /// Spring embedding, for infrastructure graphs without a natural order.
+    ForceDirected,
+    /// Left-to-right by `attributes.timestamp`, one lane per entity kind.
+    TimeAxis,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NodePosition {
+    pub(crate) id: String,
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
+/// Lays out `nodes` using the edges between them. The result keeps the
+/// top-left corner of the nodes' current bounding box so the arranged graph
+/// stays where the analyst was looking.
+pub(crate) fn compute(
+    algorithm: LayoutAlgorithm,
+    nodes: &[NodeModel],
+    edges: &[EdgeModel],
+) -> Vec<NodePosition> {
+    if nodes.is_empty() {
+        return Vec::new();
+    }
+
+    let graph = Graph::new(nodes, edges);
+    let mut points = match algorithm {
+        LayoutAlgorithm::Layered => layered(&graph),
+        LayoutAlgorithm::ForceDirected => force_directed(&graph),
+        LayoutAlgorithm::TimeAxis => time_axis(&graph, nodes),
+    };
+
+    let origin_x = nodes
+        .iter()
+        .map(|node| node.x)
+        .fold(f64::INFINITY, f64::min);
+    let origin_y = nodes
+        .iter()
+        .map(|node| node.y)
+        .fold(f64::INFINITY, f64::min);
+    let min_x = points
+        .iter()
+        .map(|point| point.0)
+        .fold(f64::INFINITY, f64::min);
+    let min_y = points
+        .iter()
+        .map(|point| point.1)
+        .fold(f64::INFINITY, f64::min);
+
+    for point in &mut points {
+        point.0 += origin_x - min_x;
+        point.1 += origin_y - min_y;
+    }
+
+    nodes
+        .iter()
+        .zip(points)
+        .map(|(node, (x, y))| NodePosition {
+            id: node.id.clone(),
+            x: x.round(),
+            y: y.round(),
+        })
+        .collect()
+}
+
+/// Nodes by input index with edges restricted to those between them.
+struct Graph {
+    len: usize,
+    outgoing: Vec<Vec<usize>>,
+    incoming: Vec<Vec<usize>>,
+    column_width: f64,
+    row_height: f64,
+}
+
+impl Graph {
+    fn new(nodes: &[NodeModel], edges: &[EdgeModel]) -> Self {
+        let index = nodes
+            .iter()
+            .enumerate()
+            .map(|(position, node)| (node.id.as_str(), position))
+            .collect::<HashMap<_, _>>();
+        let mut outgoing = vec![Vec::new(); nodes.len()];
+        let mut incoming = vec![Vec::new(); nodes.len()];
+
+        for edge in edges {
+            if let (Some(&source), Some(&target)) = (
+                index.get(edge.source.as_str()),
+                index.get(edge.target.as_str()),
+            ) {
+                if source != target && !outgoing[source].contains(&target) {
+                    outgoing[source].push(target);
+                    incoming[target].push(source);
+                }
+            }
+        }
+
+        let widest = nodes
+            .iter()
+            .map(|node| node.width.unwrap_or(DEFAULT_NODE_WIDTH))
+            .fold(DEFAULT_NODE_WIDTH, f64::max);
+        let tallest = nodes
+            .iter()
+            .map(|node| node.height.unwrap_or(DEFAULT_NODE_HEIGHT))
+            .fold(DEFAULT_NODE_HEIGHT, f64::max);
+
+        Self {
+            len: nodes.len(),
+            outgoing,
+            incoming,
+            column_width: widest + HORIZONTAL_GAP,
+            row_height: tallest + VERTICAL_GAP,
+        }
+    }
+
+    fn neighbours(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        self.outgoing[node]
+            .iter()
+            .chain(self.incoming[node].iter())
+            .copied()
+    }
+}
+
+/// Edges that close a cycle in a depth-first walk, which the layering ignores.
+fn back_edges(graph: &Graph) -> Vec<(usize, usize)> {
+    // 0 = unvisited, 1 = on the current path, 2 = finished.
+    let mut state = vec![0u8; graph.len];
+    let mut reversed = Vec::new();
+
+    for root in 0..graph.len {
+        if state[root] != 0 {
+            continue;
+        }
+
+        let mut stack = vec![(root, 0usize)];
+        state[root] = 1;
+
+        while let Some((node, next)) = stack.pop() {
+            match graph.outgoing[node].get(next) {
+                Some(&target) => {
+                    stack.push((node, next + 1));
+
+                    match state[target] {
+                        0 => {
+                            state[target] = 1;
+                            stack.push((target, 0));
+                        }
+                        1 => reversed.push((node, target)),
+                        _ => {}
+                    }
+                }
+                None => state[node] = 2,
+            }
+        }
+    }
+
+    reversed
+}
+
+fn layered(graph: &Graph) -> Vec<(f64, f64)> {
+    let ignored = back_edges(graph).into_iter().collect::<HashSet<_>>();
+    let is_forward = |source: usize, target: usize| !ignored.contains(&(source, target));
+
+    // Longest-path layering over the acyclic part, in topological order.
+    let mut pending = (0..graph.len)
+        .map(|node| {
+            graph.incoming[node]
+                .iter()
+                .filter(|&&source| is_forward(source, node))
+                .count()
+        })
+        .collect::<Vec<_>>();
+    let mut ready = (0..graph.len)
+        .filter(|&node| pending[node] == 0)
+        .collect::<Vec<_>>();
+    let mut layer = vec![0usize; graph.len];
+    let mut cursor = 0;
+
+    while cursor < ready.len() {
+        let node = ready[cursor];
+        cursor += 1;
+
+        for &target in &graph.outgoing[node] {
+            if !is_forward(node, target) {
+                continue;
+            }
+
+            layer[target] = layer[target].max(layer[node] + 1);
+            pending[target] -= 1;
+
+            if pending[target] == 0 {
+                ready.push(target);
+            }
+        }
+    }
+
+    let depth = layer.iter().copied().max().unwrap_or(0) + 1;
+    let mut rows = vec![Vec::new(); depth];
+
+    for node in 0..graph.len {
+        rows[layer[node]].push(node);
+    }
+
+    // Barycenter sweeps, alternating down and up, to reduce crossings.
+    let mut order = vec![0f64; graph.len];
+
+    for row in &rows {
+        for (position, &node) in row.iter().enumerate() {
+            order[node] = position as f64;
+        }
+    }
+
+    for sweep in 0..ORDERING_SWEEPS {
+        let downward = sweep % 2 == 0;
+        let row_indices = if downward {
+            (1..depth).collect::<Vec<_>>()
+        } else {
+            (0..depth.saturating_sub(1)).rev().collect()
+        };
+
+        for row_index in row_indices {
+            let row = &mut rows[row_index];
+            let barycenter = |node: usize| {
+                let adjacent = graph
+                    .neighbours(node)
+                    .filter(|&other| {
+                        let other_layer = layer[other];
+                        if downward {
+                            other_layer + 1 == row_index
+                        } else {
+                            other_layer == row_index + 1
+                        }
+                    })
+                    .map(|other| order[other])
+                    .collect::<Vec<_>>();
+
+                if adjacent.is_empty() {
+                    order[node]
+                } else {
+                    adjacent.iter().sum::<f64>() / adjacent.len() as f64
+                }
+            };
+
+            let mut keyed = row
+                .iter()
+                .map(|&node| (barycenter(node), node))
+                .collect::<Vec<_>>();
+            keyed.sort_by(|left, right| left.0.total_cmp(&right.0));
+            *row = keyed.into_iter().map(|(_, node)| node).collect();
+
+            for (position, &node) in row.iter().enumerate() {
+                order[node] = position as f64;
+            }
+        }
+    }
+
+    let mut points = vec![(0.0, 0.0); graph.len];
+
+    for (row_index, row) in rows.iter().enumerate() {
+        let offset = (row.len() as f64 - 1.0) / 2.0;
+
+        for (position, &node) in row.iter().enumerate() {
+            points[node] = (
+                (position as f64 - offset) * graph.column_width,
+                row_index as f64 * graph.row_height,
+            );
+        }
+    }
+
+    points
+}
+
+/// Fruchterman-Reingold with a weak pull towards the centre so disconnected
+/// components stay close. Starts from a circle, so results are deterministic.
+fn force_directed(graph: &Graph) -> Vec<(f64, f64)> {
+    let count = graph.len;
+    let ideal = graph.column_width.max(graph.row_height);
+
+    if count == 1 {
+        return vec![(0.0, 0.0)];
+    }
+
+    let radius = ideal * (count as f64).sqrt();
+    let mut points = (0..count)
+        .map(|index| {
+            let angle = index as f64 / count as f64 * std::f64::consts::TAU;
+            (radius * angle.cos(), radius * angle.sin())
+        })
+        .collect::<Vec<_>>();
+
+    let iterations = (FORCE_BUDGET / (count * count)).clamp(FORCE_ITERATIONS.0, FORCE_ITERATIONS.1);
+    let mut temperature = radius / 2.0;
+    let cooling = temperature / iterations as f64;
+
+    for _ in 0..iterations {
+        let mut moves = vec![(0.0, 0.0); count];
+
+        for left in 0..count {
+            for right in left + 1..count {
+                let (dx, dy, distance) = separation(points[left], points[right], left, right);
+                let force = ideal * ideal / distance;
+                moves[left].0 += dx / distance * force;
+                moves[left].1 += dy / distance * force;
+                moves[right].0 -= dx / distance * force;
+                moves[right].1 -= dy / distance * force;
+            }
+        }
+
+        for source in 0..count {
+            for &target in &graph.outgoing[source] {
+                let (dx, dy, distance) = separation(points[source], points[target], source, target);
+                let force = distance * distance / ideal;
+                moves[source].0 -= dx / distance * force;
+                moves[source].1 -= dy / distance * force;
+                moves[target].0 += dx / distance * force;
+                moves[target].1 += dy / distance * force;
+            }
+        }
+
+        for (point, movement) in points.iter_mut().zip(&mut moves) {
+            movement.0 -= point.0 * 0.05;
+            movement.1 -= point.1 * 0.05;
+
+            let length = movement.0.hypot(movement.1);
+
+            if length > 0.0 {
+                let step = length.min(temperature);
+                point.0 += movement.0 / length * step;
+                point.1 += movement.1 / length * step;
+            }
+        }
+
+        temperature = (temperature - cooling).max(1.0);
+    }
+
+    points
+}
+
+/// Vector from `right` to `left` and its length, nudged apart when the two
+/// points coincide.
+fn separation(
+    left: (f64, f64),
+    right: (f64, f64),
+    left_index: usize,
+    right_index: usize,
+) -> (f64, f64, f64) {
+    let dx = left.0 - right.0;
+    let dy = left.1 - right.1;
+    let distance = dx.hypot(dy);
+
+    if distance < 0.01 {
+        let angle = (left_index * 31 + right_index * 17) as f64;
+        return (angle.cos() * 0.01, angle.sin() * 0.01, 0.01);
+    }
+
+    (dx, dy, distance)
+}
+
+fn time_axis(graph: &Graph, nodes: &[NodeModel]) -> Vec<(f64, f64)> {
+    let mut times = nodes
+        .iter()
+        .map(|node| {
+            node.attributes
+                .as_ref()
+                .and_then(|attributes| attributes.get("timestamp"))
+                .and_then(parse_timestamp)
+        })
+        .collect::<Vec<_>>();
+
+    // Untimed nodes (a process next to its events) take the earliest time
+    // among their timed neighbours.
+    let inherited = (0..graph.len)
+        .map(|node| {
+            times[node].or_else(|| {
+                graph
+                    .neighbours(node)
+                    .filter_map(|other| times[other])
+                    .reduce(f64::min)
+            })
+        })
+        .collect::<Vec<_>>();
+    times = inherited;
+
+    let mut timed = (0..graph.len)
+        .filter_map(|node| times[node].map(|time| (time, node)))
+        .collect::<Vec<_>>();
+    timed.sort_by(|left, right| left.0.total_cmp(&right.0).then(left.1.cmp(&right.1)));
+
+    let mut lanes = BTreeMap::new();
+    let mut lane_order = Vec::new();
+
+    for &(_, node) in &timed {
+        let lane = nodes[node].kind.clone().unwrap_or_default();
+
+        if !lanes.contains_key(&lane) {
+            lanes.insert(lane.clone(), lane_order.len());
+            lane_order.push(lane);
+        }
+    }
+
+    let first = timed.first().map(|entry| entry.0).unwrap_or(0.0);
+    let last = timed.last().map(|entry| entry.0).unwrap_or(0.0);
+    let distinct = timed
+        .windows(2)
+        .filter(|pair| pair[0].0 != pair[1].0)
+        .count();
+    let scale = if last > first {
+        distinct as f64 * graph.column_width / (last - first)
+    } else {
+        0.0
+    };
+
+    let mut points = vec![(0.0, 0.0); graph.len];
+    let mut lane_ends = vec![f64::NEG_INFINITY; lane_order.len()];
+
+    for &(time, node) in &timed {
+        let lane = lanes[&nodes[node].kind.clone().unwrap_or_default()];
+        let x = ((time - first) * scale).max(lane_ends[lane] + graph.column_width);
+        lane_ends[lane] = x;
+        points[node] = (x, lane as f64 * graph.row_height);
+    }
+
+    // Anything still without a time goes in a row below the timeline.
+    let untimed_row = lane_order.len() as f64 * graph.row_height;
+    let untimed = (0..graph.len).filter(|&node| times[node].is_none());
+
+    for (position, node) in untimed.enumerate() {
+        points[node] = (position as f64 * graph.column_width, untimed_row);
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(id: &str, kind: Option<&str>, attributes: Option<serde_json::Value>) -> NodeModel {
+        NodeModel {
+            id: id.to_owned(),
+            node_type: "geo".to_owned(),
+            x: 1000.0,
+            y: 500.0,
+            content: String::new(),
+            width: None,
+            height: None,
+            kind: kind.map(str::to_owned),
+            attributes,
+        }
+    }
+
+    fn edge(source: &str, target: &str) -> EdgeModel {
+        EdgeModel {
+            id: format!("{source}->{target}"),
+            source: source.to_owned(),
+            target: target.to_owned(),
+            kind: "spawned".to_owned(),
+            label: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn position<'a>(positions: &'a [NodePosition], id: &str) -> &'a NodePosition {
+        positions.iter().find(|position| position.id == id).unwrap()
+    }
+
+    #[test]
+    fn layers_process_trees_top_down() {
+        let nodes = ["explorer", "cmd", "powershell", "whoami", "net"]
+            .map(|id| node(id, Some("process"), None));
+        let edges = [
+            edge("explorer", "cmd"),
+            edge("explorer", "powershell"),
+            edge("cmd", "whoami"),
+            edge("powershell", "net"),
+            // A cycle must not stall the layering.
+            edge("net", "explorer"),
+        ];
+
+        let positions = compute(LayoutAlgorithm::Layered, &nodes, &edges);
+        let y = |id| position(&positions, id).y;
+
+        assert_eq!(y("explorer"), 500.0);
+        assert!(y("cmd") > y("explorer"));
+        assert_eq!(y("cmd"), y("powershell"));
+        assert!(y("whoami") > y("cmd"));
+        assert_eq!(y("whoami"), y("net"));
+        assert!(positions.iter().all(|position| position.x >= 1000.0));
+
+        // Children stay on the same side as their parents.
+        let x = |id| position(&positions, id).x;
+        assert_eq!(x("cmd") < x("powershell"), x("whoami") < x("net"));
+    }
+
+    #[test]
+    fn force_directed_pulls_related_nodes_together() {
+        let nodes = ["a", "b", "c", "x", "y"].map(|id| node(id, None, None));
+        let edges = [edge("a", "b"), edge("b", "c"), edge("x", "y")];
+
+        let first = compute(LayoutAlgorithm::ForceDirected, &nodes, &edges);
+        let second = compute(LayoutAlgorithm::ForceDirected, &nodes, &edges);
+        assert_eq!(first, second);
+
+        let distance = |left: &str, right: &str| {
+            let (left, right) = (position(&first, left), position(&first, right));
+            (left.x - right.x).hypot(left.y - right.y)
+        };
+
+        assert!(distance("a", "b") < distance("a", "y"));
+        assert!(distance("x", "y") < distance("x", "c"));
+        assert!(first
+            .iter()
+            .enumerate()
+            .all(|(index, left)| first[index + 1..]
+                .iter()
+                .all(|right| (left.x - right.x).hypot(left.y - right.y) > 100.0)));
+    }
+
+    #[test]
+    fn time_axis_orders_events_and_lanes_by_kind() {
+        let nodes = [
+            node(
+                "late",
+                Some("event"),
+                Some(json!({ "timestamp": "2024-03-01T10:05:00Z" })),
+            ),
+            node(
+                "early",
+                Some("event"),
+                Some(json!({ "timestamp": "2024-03-01 11:00:00+02:00" })),
+            ),
+            node("proc", Some("process"), None),
+            node("orphan", Some("file"), None),
+        ];
+        let edges = [edge("proc", "late")];
+
+        let positions = compute(LayoutAlgorithm::TimeAxis, &nodes, &edges);
+        let (early, late) = (position(&positions, "early"), position(&positions, "late"));
+
+        assert!(early.x < late.x);
+        assert_eq!(early.y, late.y);
+        assert_eq!(position(&positions, "proc").x, late.x);
+        assert!(position(&positions, "proc").y > late.y);
+        assert!(position(&positions, "orphan").y > position(&positions, "proc").y);
+    }
+}
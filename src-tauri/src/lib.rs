@@ -1,16 +1,278 @@
 use sea_orm::{
-    ConnectionTrait, Database, DatabaseBackend, DatabaseConnection, DbErr, QueryResult, Statement,
-    TransactionTrait,
+    ConnectionTrait, Database, DatabaseBackend, DatabaseConnection, DatabaseTransaction, DbErr,
+    QueryResult, Statement, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeSet, path::Path};
-use tauri::{AppHandle, Manager, State};
-
-const DB_FILE_NAME: &str = "cyberweaver.db";
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+mod archive;
+mod attachments;
+mod attack;
+mod attack_paths;
+mod audit;
+mod cases;
+mod changes;
+mod entities;
+mod history;
+mod ingest;
+mod jobs;
+mod layout;
+mod migrations;
+mod misp;
+mod netlogs;
+mod observables;
+mod plugins;
+mod report;
+mod scoring;
+mod search;
+mod snapshots;
+mod spatial;
+mod stix;
+mod sync_server;
+mod sysmon;
+mod timestamps;
+mod trash;
+
+use archive::ArchiveSummary;
+use attachments::{Attachment, IntegrityCheck};
+use attack::{AttackDatasetSummary, NodeTechnique, TacticCoverage, Technique, TechniqueSuggestion};
+use attack_paths::{AttackPath, AttackPathQuery};
+use audit::{AuditEntry, AuditVerification};
+use cases::{CaseManager, CaseModel, CasePayload};
+use changes::{ChangeEvent, ChangeFeed, ChangeOrigin};
+use history::{HistoryAction, HistoryBatch, HistoryEntry, HistoryRevert};
+use ingest::ImportReport;
+use jobs::JobRegistry;
+use layout::{LayoutAlgorithm, NodePosition};
+use netlogs::NetworkLogImport;
+use observables::ObservableModel;
+use plugins::{PluginHost, PluginManifest, PluginRunReport, PluginRunRequest};
+use report::ReportFormat;
+use scoring::{NodeScore, ScoringConfig};
+use search::SearchHit;
+use snapshots::{Snapshot, SnapshotDiff};
+use spatial::{Bounds, NodePage};
+use trash::{PurgePreview, PurgeReport, Trash};
+
+/// Single database used before cases existed; adopted as the default case.
+const LEGACY_DB_FILE_NAME: &str = "cyberweaver.db";
+
+const NODE_COLUMNS: &str = "id, type, x, y, content, width, height, kind, attributes";
 
 #[derive(Clone)]
 struct AppState {
-    db: DatabaseConnection,
+    cases: Arc<CaseManager>,
+    changes: ChangeFeed,
+    plugins: Arc<PluginHost>,
+    jobs: Arc<JobRegistry>,
+}
+
+impl AppState {
+    fn new(cases: CaseManager) -> Self {
+        let plugins = PluginHost::new(cases.root().join(plugins::PLUGINS_DIR_NAME));
+
+        Self {
+            cases: Arc::new(cases),
+            changes: ChangeFeed::new(),
+            plugins: Arc::new(plugins),
+            jobs: Arc::new(JobRegistry::default()),
+        }
+    }
+
+    async fn db(&self) -> Result<DatabaseConnection, String> {
+        self.cases.active_db().await
+    }
+
+    async fn attachment_store(&self) -> Result<PathBuf, String> {
+        Ok(self
+            .cases
+            .active_case_dir()
+            .await?
+            .join(attachments::ATTACHMENTS_DIR_NAME))
+    }
+
+    // Write paths shared by Tauri commands, the sync server and backend jobs.
+    // Each one publishes the committed change to the change feed, tagged with
+    // the origin of the write.
+
+    async fn upsert_nodes(
+        &self,
+        origin: ChangeOrigin,
+        nodes: Vec<NodePayload>,
+    ) -> Result<(), String> {
+        let db = self.db().await?;
+        let ids = upsert_nodes_internal(&db, &audit::actor(origin), nodes).await?;
+
+        if !ids.is_empty() {
+            let nodes = list_nodes_by_ids(&db, &ids).await?;
+            self.changes
+                .publish(origin, ChangeEvent::NodesUpserted { nodes });
+        }
+
+        Ok(())
+    }
+
+    async fn delete_nodes(&self, origin: ChangeOrigin, ids: Vec<String>) -> Result<(), String> {
+        let ids = delete_nodes_internal(&self.db().await?, &audit::actor(origin), ids).await?;
+
+        if !ids.is_empty() {
+            self.changes
+                .publish(origin, ChangeEvent::NodesDeleted { ids });
+        }
+
+        Ok(())
+    }
+
+    async fn upsert_edges(
+        &self,
+        origin: ChangeOrigin,
+        edges: Vec<EdgePayload>,
+    ) -> Result<(), String> {
+        let db = self.db().await?;
+        let ids = upsert_edges_internal(&db, &audit::actor(origin), edges).await?;
+
+        if !ids.is_empty() {
+            let edges = list_edges_by_ids(&db, &ids).await?;
+            self.changes
+                .publish(origin, ChangeEvent::EdgesUpserted { edges });
+        }
+
+        Ok(())
+    }
+
+    async fn delete_edges(&self, origin: ChangeOrigin, ids: Vec<String>) -> Result<(), String> {
+        let ids = delete_edges_internal(&self.db().await?, &audit::actor(origin), ids).await?;
+
+        if !ids.is_empty() {
+            self.changes
+                .publish(origin, ChangeEvent::EdgesDeleted { ids });
+        }
+
+        Ok(())
+    }
+
+    /// Publishes what a history restore or revert wrote. The backend rebuilt
+    /// these rows and the webview only gets their ids back, so they go out as
+    /// `Backend` even when the webview asked for the restore.
+    async fn publish_revert(
+        &self,
+        origin: ChangeOrigin,
+        revert: &HistoryRevert,
+    ) -> Result<(), String> {
+        let db = self.db().await?;
+
+        if !revert.deleted.is_empty() {
+            self.changes.publish(
+                origin,
+                ChangeEvent::NodesDeleted {
+                    ids: revert.deleted.clone(),
+                },
+            );
+        }
+        if !revert.upserted.is_empty() {
+            let nodes = list_nodes_by_ids(&db, &revert.upserted).await?;
+            self.changes
+                .publish(origin, ChangeEvent::NodesUpserted { nodes });
+        }
+        if !revert.edges.is_empty() {
+            let edges = list_edges_by_ids(&db, &revert.edges).await?;
+            self.changes
+                .publish(origin, ChangeEvent::EdgesUpserted { edges });
+        }
+        if !revert.deleted_edges.is_empty() {
+            self.changes.publish(
+                origin,
+                ChangeEvent::EdgesDeleted {
+                    ids: revert.deleted_edges.clone(),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Moves existing nodes to `positions` through the regular upsert path.
+    async fn move_nodes(
+        &self,
+        origin: ChangeOrigin,
+        positions: &[NodePosition],
+    ) -> Result<(), String> {
+        let ids = positions
+            .iter()
+            .map(|position| position.id.clone())
+            .collect::<Vec<_>>();
+        let nodes = list_nodes_by_ids(&self.db().await?, &ids).await?;
+
+        let moved = nodes
+            .into_iter()
+            .filter_map(|node| {
+                let position = positions.iter().find(|position| position.id == node.id)?;
+
+                Some(NodePayload {
+                    id: node.id,
+                    node_type: node.node_type,
+                    x: position.x,
+                    y: position.y,
+                    content: node.content,
+                    width: node.width,
+                    height: node.height,
+                    kind: None,
+                    attributes: None,
+                })
+            })
+            .collect();
+
+        self.upsert_nodes(origin, moved).await
+    }
+
+    async fn publish_case_changed(&self, origin: ChangeOrigin) {
+        let case_id = self.cases.active_case_id().await;
+        self.changes
+            .publish(origin, ChangeEvent::CaseChanged { case_id });
+    }
+
+    // Canvas directives. Referenced nodes and edges must exist in the open
+    // case so the webview never has to guess at stale ids.
+
+    async fn focus_node(&self, origin: ChangeOrigin, id: String) -> Result<(), String> {
+        let id = existing_node_ids(&self.db().await?, vec![id])
+            .await?
+            .pop()
+            .ok_or_else(|| "node id must not be empty".to_owned())?;
+        self.changes.publish(origin, ChangeEvent::FocusNode { id });
+        Ok(())
+    }
+
+    async fn select_nodes(&self, origin: ChangeOrigin, ids: Vec<String>) -> Result<(), String> {
+        let ids = existing_node_ids(&self.db().await?, ids).await?;
+        self.changes
+            .publish(origin, ChangeEvent::SelectNodes { ids });
+        Ok(())
+    }
+
+    async fn highlight_path(
+        &self,
+        origin: ChangeOrigin,
+        node_ids: Vec<String>,
+        edge_ids: Vec<String>,
+    ) -> Result<(), String> {
+        let db = self.db().await?;
+        let node_ids = existing_node_ids(&db, node_ids).await?;
+        let edge_ids = existing_edge_ids(&db, edge_ids).await?;
+
+        if node_ids.is_empty() && edge_ids.is_empty() {
+            return Err("highlight path must reference at least one node or edge".to_owned());
+        }
+
+        self.changes
+            .publish(origin, ChangeEvent::HighlightPath { node_ids, edge_ids });
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -24,6 +286,10 @@ struct NodePayload {
     content: String,
     width: Option<f64>,
     height: Option<f64>,
+    #[serde(default)]
+    kind: Option<String>,
+    #[serde(default)]
+    attributes: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -37,6 +303,8 @@ struct NodeModel {
     content: String,
     width: Option<f64>,
     height: Option<f64>,
+    kind: Option<String>,
+    attributes: Option<serde_json::Value>,
 }
 
 impl NodeModel {
@@ -49,6 +317,48 @@ impl NodeModel {
             content: row.try_get("", "content").unwrap_or_default(),
             width: row.try_get("", "width").ok(),
             height: row.try_get("", "height").ok(),
+            kind: row.try_get("", "kind").ok(),
+            attributes: row
+                .try_get::<String>("", "attributes")
+                .ok()
+                .and_then(|raw| serde_json::from_str(&raw).ok()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct EdgePayload {
+    id: String,
+    source: String,
+    target: String,
+    kind: String,
+    #[serde(default)]
+    label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct EdgeModel {
+    id: String,
+    source: String,
+    target: String,
+    kind: String,
+    label: Option<String>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+impl EdgeModel {
+    fn from_row(row: QueryResult) -> Self {
+        Self {
+            id: row.try_get("", "id").unwrap_or_default(),
+            source: row.try_get("", "source").unwrap_or_default(),
+            target: row.try_get("", "target").unwrap_or_default(),
+            kind: row.try_get("", "kind").unwrap_or_default(),
+            label: row.try_get("", "label").ok(),
+            created_at: row.try_get("", "created_at").unwrap_or(0),
+            updated_at: row.try_get("", "updated_at").unwrap_or(0),
         }
     }
 }
@@ -99,9 +409,67 @@ fn validate_node_payload(node: &NodePayload) -> Result<(), String> {
         return Err("node.height must be a positive finite number when provided".to_owned());
     }
 
+    match (&node.kind, &node.attributes) {
+        (Some(kind), attributes) => {
+            let kind = entities::normalize_entity_kind(kind)
+                .ok_or_else(|| format!("unsupported entity kind: {kind}"))?;
+
+            if let Some(attributes) = attributes {
+                entities::validate_entity_attributes(kind, attributes)?;
+            }
+        }
+        (None, Some(_)) => {
+            return Err("node.attributes requires node.kind".to_owned());
+        }
+        (None, None) => {}
+    }
+
+    Ok(())
+}
+
+fn normalize_relation_kind(raw: &str) -> Option<&'static str> {
+    match raw.trim() {
+        "spawned" => Some("spawned"),
+        "connected_to" => Some("connected_to"),
+        "wrote" => Some("wrote"),
+        "read" => Some("read"),
+        "resolved_to" => Some("resolved_to"),
+        "downloaded" => Some("downloaded"),
+        "executed" => Some("executed"),
+        "related_to" => Some("related_to"),
+        _ => None,
+    }
+}
+
+fn validate_edge_payload(edge: &EdgePayload) -> Result<(), String> {
+    if edge.id.trim().is_empty() {
+        return Err("edge.id must not be empty".to_owned());
+    }
+
+    if edge.source.trim().is_empty() || edge.target.trim().is_empty() {
+        return Err("edge.source and edge.target must not be empty".to_owned());
+    }
+
+    if normalize_shape_id(&edge.source) == normalize_shape_id(&edge.target) {
+        return Err("edge.source and edge.target must be different nodes".to_owned());
+    }
+
+    if normalize_relation_kind(&edge.kind).is_none() {
+        return Err(format!("unsupported relation kind: {}", edge.kind));
+    }
+
     Ok(())
 }
 
+/// A fresh file name next to `output` to write into before moving the result
+/// into place, so an unfinished export never takes over an existing file.
+pub(crate) fn partial_path(output: &Path) -> PathBuf {
+    let name = output
+        .file_name()
+        .map_or_else(|| "export".into(), |name| name.to_string_lossy());
+    output.with_file_name(format!(".{name}.{}.partial", uuid::Uuid::new_v4().simple()))
+}
+
 fn sqlite_url_from_path(path: &Path) -> String {
     let raw = path.to_string_lossy().replace('\\', "/");
     format!("sqlite://{raw}?mode=rwc")
@@ -111,7 +479,7 @@ async fn connect_database_from_path(path: &Path) -> Result<DatabaseConnection, D
     Database::connect(sqlite_url_from_path(path)).await
 }
 
-async fn connect_database(app_handle: &AppHandle) -> Result<DatabaseConnection, String> {
+fn app_data_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
     let app_data_dir = app_handle
         .path()
         .app_data_dir()
@@ -119,65 +487,48 @@ async fn connect_database(app_handle: &AppHandle) -> Result<DatabaseConnection,
 
     std::fs::create_dir_all(&app_data_dir).map_err(|err| err.to_string())?;
 
-    connect_database_from_path(&app_data_dir.join(DB_FILE_NAME))
-        .await
-        .map_err(|err| err.to_string())
+    Ok(app_data_dir)
 }
 
-async fn ensure_column(
-    db: &DatabaseConnection,
-    column_name: &str,
-    column_definition: &str,
-) -> Result<(), DbErr> {
-    let rows = db
-        .query_all(Statement::from_string(
-            DatabaseBackend::Sqlite,
-            "PRAGMA table_info(nodes);".to_owned(),
-        ))
-        .await?;
-
-    let has_column = rows.into_iter().any(|row| {
-        row.try_get::<String>("", "name")
-            .map(|name| name == column_name)
-            .unwrap_or(false)
-    });
-
-    if has_column {
-        return Ok(());
-    }
-
-    let sql = format!("ALTER TABLE nodes ADD COLUMN {column_name} {column_definition};");
-    db.execute(Statement::from_string(DatabaseBackend::Sqlite, sql))
-        .await?;
-
-    Ok(())
+async fn init_schema(db: &DatabaseConnection) -> Result<(), DbErr> {
+    migrations::migrate(db).await
 }
 
-async fn init_schema(db: &DatabaseConnection) -> Result<(), DbErr> {
-    db.execute(Statement::from_string(
+/// Stable integer key of a node for rowid-based index tables, allocated on
+/// first use.
+async fn node_index_key<C: ConnectionTrait>(conn: &C, node_id: &str) -> Result<i64, DbErr> {
+    conn.execute(Statement::from_sql_and_values(
         DatabaseBackend::Sqlite,
-        "CREATE TABLE IF NOT EXISTS nodes (
-            id TEXT PRIMARY KEY,
-            type TEXT NOT NULL,
-            x REAL NOT NULL,
-            y REAL NOT NULL,
-            content TEXT NOT NULL,
-            width REAL,
-            height REAL,
-            updated_at INTEGER NOT NULL DEFAULT 0
-        );"
-        .to_owned(),
+        "INSERT OR IGNORE INTO node_keys (node_id) VALUES (?);".to_owned(),
+        vec![node_id.into()],
     ))
     .await?;
 
-    ensure_column(db, "width", "REAL").await?;
-    ensure_column(db, "height", "REAL").await?;
-    ensure_column(db, "updated_at", "INTEGER NOT NULL DEFAULT 0").await?;
+    conn.query_one(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "SELECT seq FROM node_keys WHERE node_id = ?;".to_owned(),
+        vec![node_id.into()],
+    ))
+    .await?
+    .ok_or_else(|| DbErr::RecordNotFound(format!("node key for {node_id}")))?
+    .try_get("", "seq")
+}
+
+async fn release_node_keys<C: ConnectionTrait>(conn: &C, ids: &[String]) -> Result<(), DbErr> {
+    if ids.is_empty() {
+        return Ok(());
+    }
 
-    db.execute(Statement::from_string(
+    conn.execute(Statement::from_sql_and_values(
         DatabaseBackend::Sqlite,
-        "CREATE INDEX IF NOT EXISTS idx_nodes_type_updated_at ON nodes(type, updated_at);"
-            .to_owned(),
+        format!(
+            "DELETE FROM node_keys WHERE node_id IN ({});",
+            vec!["?"; ids.len()].join(", ")
+        ),
+        ids.iter()
+            .cloned()
+            .map(Into::into)
+            .collect::<Vec<sea_orm::Value>>(),
     ))
     .await?;
 
@@ -188,11 +539,45 @@ async fn list_nodes_internal(db: &DatabaseConnection) -> Result<Vec<NodeModel>,
     let rows = db
         .query_all(Statement::from_string(
             DatabaseBackend::Sqlite,
-            "SELECT id, type, x, y, content, width, height
-             FROM nodes
-             WHERE type IN ('geo', 'text', 'note')
-             ORDER BY updated_at ASC, id ASC;"
-                .to_owned(),
+            format!(
+                "SELECT {NODE_COLUMNS}
+                 FROM nodes
+                 WHERE type IN ('geo', 'text', 'note') AND deleted_at IS NULL
+                 ORDER BY updated_at ASC, id ASC;"
+            ),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(NodeModel::from_row).collect())
+}
+
+fn id_placeholders(ids: &[String]) -> (String, Vec<sea_orm::Value>) {
+    (
+        vec!["?"; ids.len()].join(", "),
+        ids.iter().cloned().map(Into::into).collect(),
+    )
+}
+
+async fn list_nodes_by_ids<C: ConnectionTrait>(
+    conn: &C,
+    ids: &[String],
+) -> Result<Vec<NodeModel>, String> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (placeholders, values) = id_placeholders(ids);
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {NODE_COLUMNS}
+                 FROM nodes
+                 WHERE id IN ({placeholders}) AND deleted_at IS NULL
+                 ORDER BY updated_at ASC, id ASC;"
+            ),
+            values,
         ))
         .await
         .map_err(|err| err.to_string())?;
@@ -200,28 +585,187 @@ async fn list_nodes_internal(db: &DatabaseConnection) -> Result<Vec<NodeModel>,
     Ok(rows.into_iter().map(NodeModel::from_row).collect())
 }
 
+fn is_attribute_key(raw: &str) -> bool {
+    !raw.is_empty()
+        && raw
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// Lists typed entity nodes of one kind, optionally narrowed to those whose
+/// top-level attribute `key` equals `value`.
+async fn list_entities_internal(
+    db: &DatabaseConnection,
+    kind: &str,
+    attribute: Option<(&str, &str)>,
+) -> Result<Vec<NodeModel>, String> {
+    let kind = entities::normalize_entity_kind(kind)
+        .ok_or_else(|| format!("unsupported entity kind: {kind}"))?;
+
+    let statement = match attribute {
+        Some((key, value)) => {
+            let key = key.trim();
+
+            if !is_attribute_key(key) {
+                return Err(format!("unsupported attribute key: {key}"));
+            }
+
+            Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                format!(
+                    "SELECT {NODE_COLUMNS}
+                     FROM nodes
+                     WHERE kind = ? AND deleted_at IS NULL
+                       AND CAST(json_extract(attributes, '$.{key}') AS TEXT) = ?
+                     ORDER BY updated_at ASC, id ASC;"
+                ),
+                vec![kind.into(), value.into()],
+            )
+        }
+        None => Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {NODE_COLUMNS}
+                 FROM nodes
+                 WHERE kind = ? AND deleted_at IS NULL
+                 ORDER BY updated_at ASC, id ASC;"
+            ),
+            vec![kind.into()],
+        ),
+    };
+
+    let rows = db
+        .query_all(statement)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(NodeModel::from_row).collect())
+}
+
+/// Validates and writes the nodes in one transaction, recorded as one history
+/// batch and one audit entry, returning their normalized ids.
 async fn upsert_nodes_internal(
     db: &DatabaseConnection,
+    actor: &str,
     nodes: Vec<NodePayload>,
-) -> Result<(), String> {
+) -> Result<Vec<String>, String> {
     if nodes.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
 
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let written = upsert_node_rows(&txn, actor, nodes).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(written)
+}
+
+/// Validates and writes nodes inside `txn`, with their audit and history
+/// entries, for writes that span more than nodes.
+async fn upsert_node_rows(
+    txn: &DatabaseTransaction,
+    actor: &str,
+    nodes: Vec<NodePayload>,
+) -> Result<Vec<String>, String> {
     for node in &nodes {
         validate_node_payload(node)?;
     }
 
-    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let ids = nodes
+        .iter()
+        .map(|node| normalize_shape_id(&node.id))
+        .collect::<Vec<_>>();
+    let before = list_nodes_by_ids(txn, &ids).await?;
+    let written = write_nodes(txn, nodes).await?;
+    let after = list_nodes_by_ids(txn, &written).await?;
+    audit::append(txn, actor, "nodes.upsert", &after).await?;
+    history::record(txn, &history::new_batch_id(), before, after, Vec::new()).await?;
+
+    Ok(written)
+}
+
+/// Checks the kind and attributes a node ends up with. An upsert keeps the
+/// stored value of whichever of the two the payload leaves out, so a payload
+/// that passes on its own can still leave an `ip` without an address or old
+/// attributes under a new kind.
+async fn validate_stored_entity(
+    txn: &DatabaseTransaction,
+    node_id: &str,
+    node: &NodePayload,
+) -> Result<(), String> {
+    if node.kind.is_none() && node.attributes.is_none() {
+        return Ok(());
+    }
+
+    let stored = txn
+        .query_one(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "SELECT kind, attributes FROM nodes WHERE id = ?;".to_owned(),
+            vec![node_id.into()],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    let (stored_kind, stored_attributes) = stored
+        .map(|row| {
+            (
+                row.try_get::<String>("", "kind").ok(),
+                row.try_get::<String>("", "attributes")
+                    .ok()
+                    .and_then(|raw| serde_json::from_str(&raw).ok()),
+            )
+        })
+        .unwrap_or_default();
+
+    let Some(kind) = node
+        .kind
+        .as_deref()
+        .and_then(entities::normalize_entity_kind)
+        .or_else(|| {
+            stored_kind
+                .as_deref()
+                .and_then(entities::normalize_entity_kind)
+        })
+    else {
+        return Ok(());
+    };
+    let attributes = node
+        .attributes
+        .clone()
+        .or(stored_attributes)
+        .unwrap_or_else(|| serde_json::json!({}));
+
+    entities::validate_entity_attributes(kind, &attributes)
+        .map_err(|err| format!("node {node_id}: {err}"))
+}
+
+/// Writes already validated nodes and their index entries inside `txn`.
+async fn write_nodes(
+    txn: &DatabaseTransaction,
+    nodes: Vec<NodePayload>,
+) -> Result<Vec<String>, String> {
+    let mut written = Vec::with_capacity(nodes.len());
 
     for node in nodes {
         let normalized_type = normalize_node_type(&node.node_type)
             .ok_or_else(|| format!("unsupported node type: {}", node.node_type))?;
 
+        let node_id = normalize_shape_id(&node.id);
+        validate_stored_entity(txn, &node_id, &node).await?;
+
+        observables::refresh_for_node(txn, &node_id, &node.content)
+            .await
+            .map_err(|err| err.to_string())?;
+        search::index_node(txn, &node_id, &node.content)
+            .await
+            .map_err(|err| err.to_string())?;
+        spatial::index_node(txn, &node_id, node.x, node.y, node.width, node.height)
+            .await
+            .map_err(|err| err.to_string())?;
+
         txn.execute(Statement::from_sql_and_values(
             DatabaseBackend::Sqlite,
-            "INSERT INTO nodes (id, type, x, y, content, width, height, updated_at)
-             VALUES (?, ?, ?, ?, ?, ?, ?, unixepoch())
+            "INSERT INTO nodes (id, type, x, y, content, width, height, kind, attributes, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, unixepoch())
              ON CONFLICT(id) DO UPDATE SET
                type = excluded.type,
                x = excluded.x,
@@ -229,25 +773,37 @@ async fn upsert_nodes_internal(
                content = excluded.content,
                width = excluded.width,
                height = excluded.height,
-               updated_at = unixepoch();"
+               kind = COALESCE(excluded.kind, nodes.kind),
+               attributes = COALESCE(excluded.attributes, nodes.attributes),
+               updated_at = unixepoch(),
+               deleted_at = NULL,
+               deleted_batch = NULL;"
                 .to_owned(),
             vec![
-                normalize_shape_id(&node.id).into(),
+                node_id.clone().into(),
                 normalized_type.into(),
                 node.x.into(),
                 node.y.into(),
                 node.content.into(),
                 node.width.into(),
                 node.height.into(),
+                node.kind
+                    .as_deref()
+                    .and_then(entities::normalize_entity_kind)
+                    .into(),
+                node.attributes
+                    .as_ref()
+                    .map(|attributes| attributes.to_string())
+                    .into(),
             ],
         ))
         .await
         .map_err(|err| err.to_string())?;
-    }
 
-    txn.commit().await.map_err(|err| err.to_string())?;
+        written.push(node_id);
+    }
 
-    Ok(())
+    Ok(written)
 }
 
 fn normalize_delete_ids(ids: Vec<String>) -> Vec<String> {
@@ -264,25 +820,90 @@ fn normalize_delete_ids(ids: Vec<String>) -> Vec<String> {
     deduped.into_iter().collect()
 }
 
-async fn delete_nodes_internal(db: &DatabaseConnection, ids: Vec<String>) -> Result<(), String> {
+/// Moves the nodes and their edges to the trash, recorded as one history
+/// batch, returning the normalized ids that were requested.
+async fn delete_nodes_internal(
+    db: &DatabaseConnection,
+    actor: &str,
+    ids: Vec<String>,
+) -> Result<Vec<String>, String> {
     let normalized_ids = normalize_delete_ids(ids);
 
     if normalized_ids.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
 
-    let placeholders = vec!["?"; normalized_ids.len()].join(", ");
-    let sql = format!("DELETE FROM nodes WHERE id IN ({placeholders});");
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let before = list_nodes_by_ids(&txn, &normalized_ids).await?;
+    let edges = list_edges_touching(&txn, &normalized_ids).await?;
+    let batch_id = history::new_batch_id();
+    delete_node_rows(&txn, &batch_id, &normalized_ids).await?;
+    audit::append(&txn, actor, "nodes.delete", &normalized_ids).await?;
+    history::record(&txn, &batch_id, before, Vec::new(), edges).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
 
-    let values = normalized_ids
-        .into_iter()
-        .map(Into::into)
-        .collect::<Vec<_>>();
+    Ok(normalized_ids)
+}
+
+/// Marks nodes and the edges touching them as deleted by `batch_id` inside
+/// `txn`. Trashed nodes leave the search, spatial and observable indexes;
+/// their technique tags stay for a restore.
+async fn delete_node_rows(
+    txn: &DatabaseTransaction,
+    batch_id: &str,
+    normalized_ids: &[String],
+) -> Result<(), String> {
+    let (placeholders, values) = id_placeholders(normalized_ids);
+
+    search::remove_nodes(txn, normalized_ids)
+        .await
+        .map_err(|err| err.to_string())?;
+    spatial::remove_nodes(txn, normalized_ids)
+        .await
+        .map_err(|err| err.to_string())?;
+    release_node_keys(txn, normalized_ids)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        format!("DELETE FROM observables WHERE node_id IN ({placeholders});"),
+        values.clone(),
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+
+    // `deleted_with` remembers which node took the edge along, so restoring
+    // either end can bring it back.
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        format!(
+            "UPDATE edges
+             SET deleted_at = unixepoch(),
+                 deleted_batch = ?,
+                 deleted_with = CASE WHEN source IN ({placeholders}) THEN source ELSE target END
+             WHERE deleted_at IS NULL
+               AND (source IN ({placeholders}) OR target IN ({placeholders}));"
+        ),
+        std::iter::once(batch_id.into()).chain(
+            values
+                .iter()
+                .cloned()
+                .chain(values.iter().cloned())
+                .chain(values.iter().cloned()),
+        ),
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
 
-    db.execute(Statement::from_sql_and_values(
+    txn.execute(Statement::from_sql_and_values(
         DatabaseBackend::Sqlite,
-        sql,
-        values,
+        format!(
+            "UPDATE nodes
+             SET deleted_at = unixepoch(), deleted_batch = ?
+             WHERE id IN ({placeholders}) AND deleted_at IS NULL;"
+        ),
+        std::iter::once(batch_id.into()).chain(values),
     ))
     .await
     .map_err(|err| err.to_string())?;
@@ -290,63 +911,1205 @@ async fn delete_nodes_internal(db: &DatabaseConnection, ids: Vec<String>) -> Res
     Ok(())
 }
 
-#[tauri::command]
-async fn get_nodes(state: State<'_, AppState>) -> Result<Vec<NodeModel>, String> {
-    list_nodes_internal(&state.db).await
-}
+async fn list_edges_internal(db: &DatabaseConnection) -> Result<Vec<EdgeModel>, String> {
+    let rows = db
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, source, target, kind, label, created_at, updated_at
+             FROM edges
+             WHERE deleted_at IS NULL
+             ORDER BY updated_at ASC, id ASC;"
+                .to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
 
-#[tauri::command]
-async fn upsert_nodes(state: State<'_, AppState>, nodes: Vec<NodePayload>) -> Result<(), String> {
-    upsert_nodes_internal(&state.db, nodes).await
+    Ok(rows.into_iter().map(EdgeModel::from_row).collect())
 }
 
-#[tauri::command]
-async fn delete_nodes(state: State<'_, AppState>, ids: Vec<String>) -> Result<(), String> {
-    delete_nodes_internal(&state.db, ids).await
-}
+async fn list_edges_by_ids<C: ConnectionTrait>(
+    conn: &C,
+    ids: &[String],
+) -> Result<Vec<EdgeModel>, String> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .setup(|app| {
-            let db = tauri::async_runtime::block_on(async {
-                let db = connect_database(app.handle()).await?;
-                init_schema(&db).await.map_err(|err| err.to_string())?;
-                Ok::<DatabaseConnection, String>(db)
-            })?;
+    let (placeholders, values) = id_placeholders(ids);
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT id, source, target, kind, label, created_at, updated_at
+                 FROM edges
+                 WHERE id IN ({placeholders}) AND deleted_at IS NULL
+                 ORDER BY updated_at ASC, id ASC;"
+            ),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
 
-            app.manage(AppState { db });
-            Ok(())
-        })
-        .invoke_handler(tauri::generate_handler![
-            get_nodes,
-            upsert_nodes,
-            delete_nodes
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+    Ok(rows.into_iter().map(EdgeModel::from_row).collect())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+async fn list_edges_touching<C: ConnectionTrait>(
+    conn: &C,
+    node_ids: &[String],
+) -> Result<Vec<EdgeModel>, String> {
+    if node_ids.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    async fn create_test_db() -> DatabaseConnection {
-        let db = Database::connect("sqlite::memory:")
-            .await
-            .expect("failed to connect sqlite");
+    let (placeholders, values) = id_placeholders(node_ids);
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT id, source, target, kind, label, created_at, updated_at
+                 FROM edges
+                 WHERE (source IN ({placeholders}) OR target IN ({placeholders}))
+                   AND deleted_at IS NULL
+                 ORDER BY updated_at ASC, id ASC;"
+            ),
+            values.iter().cloned().chain(values.iter().cloned()),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
 
-        init_schema(&db).await.expect("failed to init schema");
-        db
+    Ok(rows.into_iter().map(EdgeModel::from_row).collect())
+}
+
+/// Normalizes and dedups node ids, failing on any that are not in the open
+/// case.
+async fn existing_node_ids(
+    db: &DatabaseConnection,
+    ids: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let ids = normalize_delete_ids(ids);
+    let found = list_nodes_by_ids(db, &ids).await?;
+
+    match ids
+        .iter()
+        .find(|id| !found.iter().any(|node| &node.id == *id))
+    {
+        Some(missing) => Err(format!("missing node: {missing}")),
+        None => Ok(ids),
     }
+}
 
-    #[tokio::test]
-    async fn upsert_and_get_nodes_roundtrip() {
+async fn existing_edge_ids(
+    db: &DatabaseConnection,
+    ids: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let ids = ids
+        .into_iter()
+        .map(|id| id.trim().to_owned())
+        .filter(|id| !id.is_empty())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let found = list_edges_by_ids(db, &ids).await?;
+
+    match ids
+        .iter()
+        .find(|id| !found.iter().any(|edge| &edge.id == *id))
+    {
+        Some(missing) => Err(format!("missing edge: {missing}")),
+        None => Ok(ids),
+    }
+}
+
+async fn upsert_edges_internal(
+    db: &DatabaseConnection,
+    actor: &str,
+    edges: Vec<EdgePayload>,
+) -> Result<Vec<String>, String> {
+    if edges.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let written = upsert_edge_rows(&txn, actor, edges).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(written)
+}
+
+/// Validates and writes edges inside `txn`, with their audit entry.
+async fn upsert_edge_rows(
+    txn: &DatabaseTransaction,
+    actor: &str,
+    edges: Vec<EdgePayload>,
+) -> Result<Vec<String>, String> {
+    for edge in &edges {
+        validate_edge_payload(edge)?;
+    }
+
+    let written = write_edges(txn, edges).await?;
+    let after = list_edges_by_ids(txn, &written).await?;
+    audit::append(txn, actor, "edges.upsert", &after).await?;
+
+    Ok(written)
+}
+
+/// Writes already validated edges inside `txn`, failing on any whose
+/// endpoints are missing.
+async fn write_edges(
+    txn: &DatabaseTransaction,
+    edges: Vec<EdgePayload>,
+) -> Result<Vec<String>, String> {
+    let mut written = Vec::with_capacity(edges.len());
+
+    for edge in edges {
+        let edge_id = edge.id.trim().to_owned();
+        let normalized_kind = normalize_relation_kind(&edge.kind)
+            .ok_or_else(|| format!("unsupported relation kind: {}", edge.kind))?;
+        let source = normalize_shape_id(&edge.source);
+        let target = normalize_shape_id(&edge.target);
+
+        let endpoints = txn
+            .query_one(Statement::from_sql_and_values(
+                DatabaseBackend::Sqlite,
+                "SELECT COUNT(*) AS count FROM nodes WHERE id IN (?, ?) AND deleted_at IS NULL;"
+                    .to_owned(),
+                vec![source.clone().into(), target.clone().into()],
+            ))
+            .await
+            .map_err(|err| err.to_string())?
+            .and_then(|row| row.try_get::<i64>("", "count").ok())
+            .unwrap_or(0);
+
+        if endpoints != 2 {
+            return Err(format!(
+                "edge {} references a missing node ({source} -> {target})",
+                edge_id
+            ));
+        }
+
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "INSERT INTO edges (id, source, target, kind, label, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, unixepoch(), unixepoch())
+             ON CONFLICT(id) DO UPDATE SET
+               source = excluded.source,
+               target = excluded.target,
+               kind = excluded.kind,
+               label = excluded.label,
+               updated_at = unixepoch(),
+               deleted_at = NULL,
+               deleted_batch = NULL,
+               deleted_with = NULL;"
+                .to_owned(),
+            vec![
+                edge_id.clone().into(),
+                source.into(),
+                target.into(),
+                normalized_kind.into(),
+                edge.label.into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+        written.push(edge_id);
+    }
+
+    Ok(written)
+}
+
+fn normalize_edge_ids(ids: Vec<String>) -> Vec<String> {
+    ids.into_iter()
+        .map(|id| id.trim().to_owned())
+        .filter(|id| !id.is_empty())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Moves the edges to the trash, returning the normalized ids that were
+/// requested.
+async fn delete_edges_internal(
+    db: &DatabaseConnection,
+    actor: &str,
+    ids: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let normalized_ids = normalize_edge_ids(ids);
+
+    if normalized_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    delete_edge_rows(&txn, &history::new_batch_id(), &normalized_ids).await?;
+    audit::append(&txn, actor, "edges.delete", &normalized_ids).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(normalized_ids)
+}
+
+/// Moves edges deleted on their own, not along with a node, to the trash
+/// and records them in the history batch. Returns the ids that were live.
+async fn delete_edge_rows(
+    txn: &DatabaseTransaction,
+    batch_id: &str,
+    normalized_ids: &[String],
+) -> Result<Vec<String>, String> {
+    let live = list_edges_by_ids(txn, normalized_ids).await?;
+    if live.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids = live.iter().map(|edge| edge.id.clone()).collect::<Vec<_>>();
+    let (placeholders, values) = id_placeholders(&ids);
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        format!(
+            "UPDATE edges
+             SET deleted_at = unixepoch(), deleted_batch = ?, deleted_with = NULL
+             WHERE id IN ({placeholders});"
+        ),
+        std::iter::once(batch_id.into()).chain(values),
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+    history::append_edges(txn, batch_id, HistoryAction::Delete, &live).await?;
+
+    Ok(ids)
+}
+
+#[tauri::command]
+async fn get_nodes(state: State<'_, AppState>) -> Result<Vec<NodeModel>, String> {
+    list_nodes_internal(&state.db().await?).await
+}
+
+#[tauri::command]
+async fn upsert_nodes(state: State<'_, AppState>, nodes: Vec<NodePayload>) -> Result<(), String> {
+    state.upsert_nodes(ChangeOrigin::Webview, nodes).await
+}
+
+#[tauri::command]
+async fn delete_nodes(state: State<'_, AppState>, ids: Vec<String>) -> Result<(), String> {
+    state.delete_nodes(ChangeOrigin::Webview, ids).await
+}
+
+#[tauri::command]
+async fn get_entities(
+    state: State<'_, AppState>,
+    kind: String,
+    attribute_key: Option<String>,
+    attribute_value: Option<String>,
+) -> Result<Vec<NodeModel>, String> {
+    let attribute = attribute_key.as_deref().zip(attribute_value.as_deref());
+    list_entities_internal(&state.db().await?, &kind, attribute).await
+}
+
+async fn extract_observables_internal(
+    db: &DatabaseConnection,
+    ids: Option<Vec<String>>,
+) -> Result<Vec<ObservableModel>, String> {
+    let ids = ids.map(normalize_delete_ids);
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+
+    match ids.as_deref() {
+        Some(ids) => observables::reindex(&txn, Some(ids)).await,
+        None => observables::reindex_live(&txn).await,
+    }
+    .map_err(|err| err.to_string())?;
+    let extracted = observables::list(&txn, ids.as_deref())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(extracted)
+}
+
+#[tauri::command]
+async fn extract_observables(
+    state: State<'_, AppState>,
+    ids: Option<Vec<String>>,
+) -> Result<Vec<ObservableModel>, String> {
+    extract_observables_internal(&state.db().await?, ids).await
+}
+
+#[tauri::command]
+async fn get_observables(
+    state: State<'_, AppState>,
+    ids: Option<Vec<String>>,
+) -> Result<Vec<ObservableModel>, String> {
+    let ids = ids.map(normalize_delete_ids);
+    observables::list(&state.db().await?, ids.as_deref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn find_nodes_by_observable(
+    state: State<'_, AppState>,
+    value: String,
+    kind: Option<String>,
+) -> Result<Vec<NodeModel>, String> {
+    observables::nodes_mentioning(&state.db().await?, &value, kind.as_deref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn search_nodes(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<u32>,
+) -> Result<Vec<SearchHit>, String> {
+    search::search(&state.db().await?, &query, limit).await
+}
+
+#[tauri::command]
+async fn get_nodes_in_bounds(
+    state: State<'_, AppState>,
+    bounds: Bounds,
+    after: Option<i64>,
+    limit: Option<u32>,
+) -> Result<NodePage, String> {
+    spatial::nodes_in_bounds(&state.db().await?, bounds, after, limit).await
+}
+
+async fn compute_layout_internal(
+    db: &DatabaseConnection,
+    algorithm: LayoutAlgorithm,
+    ids: Option<Vec<String>>,
+) -> Result<Vec<NodePosition>, String> {
+    let nodes = match ids {
+        Some(ids) => {
+            let ids = existing_node_ids(db, ids).await?;
+            list_nodes_by_ids(db, &ids).await?
+        }
+        None => list_nodes_internal(db).await?,
+    };
+    let edges = list_edges_internal(db).await?;
+
+    tauri::async_runtime::spawn_blocking(move || layout::compute(algorithm, &nodes, &edges))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Lays out the given nodes (all nodes when `ids` is omitted) and, when
+/// `persist` is set, saves the new positions.
+#[tauri::command]
+async fn compute_layout(
+    state: State<'_, AppState>,
+    algorithm: LayoutAlgorithm,
+    ids: Option<Vec<String>>,
+    persist: Option<bool>,
+) -> Result<Vec<NodePosition>, String> {
+    let positions = compute_layout_internal(&state.db().await?, algorithm, ids).await?;
+
+    if persist.unwrap_or(false) {
+        state.move_nodes(ChangeOrigin::Webview, &positions).await?;
+    }
+
+    Ok(positions)
+}
+
+#[tauri::command]
+async fn get_edges(state: State<'_, AppState>) -> Result<Vec<EdgeModel>, String> {
+    list_edges_internal(&state.db().await?).await
+}
+
+#[tauri::command]
+async fn upsert_edges(state: State<'_, AppState>, edges: Vec<EdgePayload>) -> Result<(), String> {
+    state.upsert_edges(ChangeOrigin::Webview, edges).await
+}
+
+#[tauri::command]
+async fn delete_edges(state: State<'_, AppState>, ids: Vec<String>) -> Result<(), String> {
+    state.delete_edges(ChangeOrigin::Webview, ids).await
+}
+
+#[tauri::command]
+async fn list_node_history(
+    state: State<'_, AppState>,
+    node_id: String,
+) -> Result<Vec<HistoryEntry>, String> {
+    history::node_history(&state.db().await?, &normalize_shape_id(&node_id)).await
+}
+
+/// Most recent write batches first, for an undo list.
+#[tauri::command]
+async fn list_history_batches(
+    state: State<'_, AppState>,
+    limit: Option<u32>,
+) -> Result<Vec<HistoryBatch>, String> {
+    history::list_batches(&state.db().await?, limit.unwrap_or(50)).await
+}
+
+#[tauri::command]
+async fn restore_node(
+    state: State<'_, AppState>,
+    node_id: String,
+) -> Result<HistoryRevert, String> {
+    let revert = trash::restore(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        vec![node_id],
+        Vec::new(),
+    )
+    .await?;
+    state.publish_revert(ChangeOrigin::Backend, &revert).await?;
+    Ok(revert)
+}
+
+#[tauri::command]
+async fn revert_history_batch(
+    state: State<'_, AppState>,
+    batch_id: String,
+) -> Result<HistoryRevert, String> {
+    let revert = history::revert_batch(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        batch_id.trim(),
+    )
+    .await?;
+    state.publish_revert(ChangeOrigin::Backend, &revert).await?;
+    Ok(revert)
+}
+
+#[tauri::command]
+async fn list_snapshots(state: State<'_, AppState>) -> Result<Vec<Snapshot>, String> {
+    snapshots::list(&state.db().await?).await
+}
+
+/// Saves the live nodes and relations under `name`, e.g. before an import.
+#[tauri::command]
+async fn create_snapshot(state: State<'_, AppState>, name: String) -> Result<Snapshot, String> {
+    snapshots::create(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        &name,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn delete_snapshot(state: State<'_, AppState>, snapshot_id: String) -> Result<(), String> {
+    snapshots::delete(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        &snapshot_id,
+    )
+    .await
+}
+
+/// Compares two snapshots, or a snapshot with the current case when `to` is
+/// left out.
+#[tauri::command]
+async fn diff_snapshots(
+    state: State<'_, AppState>,
+    from: String,
+    to: Option<String>,
+) -> Result<SnapshotDiff, String> {
+    snapshots::diff(&state.db().await?, &from, to.as_deref()).await
+}
+
+#[tauri::command]
+async fn rollback_snapshot(
+    state: State<'_, AppState>,
+    snapshot_id: String,
+) -> Result<HistoryRevert, String> {
+    let revert = snapshots::rollback(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        &snapshot_id,
+    )
+    .await?;
+    state.publish_revert(ChangeOrigin::Backend, &revert).await?;
+    Ok(revert)
+}
+
+/// Writes the open case to a single archive for handing it over.
+#[tauri::command]
+async fn export_case(
+    state: State<'_, AppState>,
+    output_path: String,
+) -> Result<ArchiveSummary, String> {
+    let case = state
+        .cases
+        .active_case()
+        .await?
+        .ok_or_else(|| "no case is open".to_owned())?;
+    archive::export(
+        &state.db().await?,
+        &case,
+        &state.cases.case_dir(&case.id),
+        &audit::actor(ChangeOrigin::Webview),
+        &output_path,
+    )
+    .await
+}
+
+/// Adds an exported case as a new case without opening it.
+#[tauri::command]
+async fn import_case(
+    state: State<'_, AppState>,
+    archive_path: String,
+    name: Option<String>,
+) -> Result<CaseModel, String> {
+    archive::import(
+        &state.cases,
+        &audit::actor(ChangeOrigin::Webview),
+        &archive_path,
+        name,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn attach_file(
+    state: State<'_, AppState>,
+    node_id: String,
+    path: String,
+) -> Result<Attachment, String> {
+    let store = state.attachment_store().await?;
+    attachments::attach(
+        &state.db().await?,
+        store,
+        &audit::actor(ChangeOrigin::Webview),
+        &node_id,
+        &path,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn list_attachments(
+    state: State<'_, AppState>,
+    node_id: Option<String>,
+) -> Result<Vec<Attachment>, String> {
+    attachments::list(&state.db().await?, node_id.as_deref()).await
+}
+
+#[tauri::command]
+async fn export_attachment(
+    state: State<'_, AppState>,
+    sha256: String,
+    output_path: String,
+    overwrite: Option<bool>,
+) -> Result<(), String> {
+    let store = state.attachment_store().await?;
+    attachments::export(
+        &state.db().await?,
+        store,
+        &audit::actor(ChangeOrigin::Webview),
+        &sha256,
+        &output_path,
+        overwrite.unwrap_or(false),
+    )
+    .await
+}
+
+/// Re-hashes every stored attachment, or only `sha256`.
+#[tauri::command]
+async fn verify_attachments(
+    state: State<'_, AppState>,
+    sha256: Option<String>,
+) -> Result<Vec<IntegrityCheck>, String> {
+    let store = state.attachment_store().await?;
+    attachments::verify(&state.db().await?, store, sha256.as_deref()).await
+}
+
+/// Chain-of-custody entries, oldest first.
+#[tauri::command]
+async fn list_audit_log(
+    state: State<'_, AppState>,
+    after: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Vec<AuditEntry>, String> {
+    audit::list(&state.db().await?, after, limit.unwrap_or(200)).await
+}
+
+#[tauri::command]
+async fn verify_audit_chain(state: State<'_, AppState>) -> Result<AuditVerification, String> {
+    audit::verify(&state.db().await?).await
+}
+
+#[tauri::command]
+async fn list_trash(state: State<'_, AppState>) -> Result<Trash, String> {
+    trash::list_trash(&state.db().await?).await
+}
+
+#[tauri::command]
+async fn restore_from_trash(
+    state: State<'_, AppState>,
+    node_ids: Vec<String>,
+    edge_ids: Vec<String>,
+) -> Result<HistoryRevert, String> {
+    let revert = trash::restore(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        node_ids,
+        edge_ids,
+    )
+    .await?;
+    state.publish_revert(ChangeOrigin::Backend, &revert).await?;
+    Ok(revert)
+}
+
+#[tauri::command]
+async fn preview_trash_purge(
+    state: State<'_, AppState>,
+    node_ids: Vec<String>,
+    edge_ids: Vec<String>,
+) -> Result<PurgePreview, String> {
+    trash::preview_purge(&state.db().await?, node_ids, edge_ids).await
+}
+
+/// Purging is irreversible, so it takes the token from
+/// `preview_trash_purge` for the same selection.
+#[tauri::command]
+async fn purge_trash(
+    state: State<'_, AppState>,
+    node_ids: Vec<String>,
+    edge_ids: Vec<String>,
+    token: String,
+) -> Result<PurgeReport, String> {
+    let store = state.attachment_store().await?;
+    trash::purge(
+        &state.db().await?,
+        &store,
+        &audit::actor(ChangeOrigin::Webview),
+        node_ids,
+        edge_ids,
+        &token,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn list_plugins(state: State<'_, AppState>) -> Result<Vec<PluginManifest>, String> {
+    state.plugins.list()
+}
+
+#[tauri::command]
+async fn run_plugin(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    request: PluginRunRequest,
+) -> Result<PluginRunReport, String> {
+    plugins::run(state.inner(), request, |log| {
+        let _ = app.emit(plugins::LOG_EVENT, log);
+    })
+    .await
+}
+
+#[tauri::command]
+async fn cancel_plugin(state: State<'_, AppState>, run_id: String) -> Result<(), String> {
+    state.plugins.cancel(&run_id)
+}
+
+/// Renders the open case as a report. A user template file replaces the
+/// built-in one; with `output_path` the report is also written to disk.
+#[tauri::command]
+async fn export_report(
+    state: State<'_, AppState>,
+    format: ReportFormat,
+    template_path: Option<String>,
+    output_path: Option<String>,
+) -> Result<String, String> {
+    let case = state
+        .cases
+        .active_case()
+        .await?
+        .ok_or_else(|| "no case is open".to_owned())?;
+    let template = template_path
+        .map(|path| {
+            std::fs::read_to_string(&path)
+                .map_err(|err| format!("failed to read report template {path}: {err}"))
+        })
+        .transpose()?;
+
+    let context = report::build_context(&state.db().await?, &case, timestamps::unix_now()).await?;
+    let rendered = report::render(&context, format, template.as_deref())?;
+
+    if let Some(path) = output_path {
+        std::fs::write(&path, &rendered)
+            .map_err(|err| format!("failed to write report {path}: {err}"))?;
+    }
+
+    Ok(rendered)
+}
+
+/// Re-scores every node of the open case with the rules in `scoring.json`
+/// and returns the non-zero scores, highest first.
+#[tauri::command]
+async fn score_nodes(state: State<'_, AppState>) -> Result<Vec<NodeScore>, String> {
+    let config = ScoringConfig::load(&state.cases.root().join(scoring::CONFIG_FILE_NAME))?;
+    scoring::score_case(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        &config,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn get_scores(
+    state: State<'_, AppState>,
+    min_score: Option<f64>,
+) -> Result<Vec<NodeScore>, String> {
+    scoring::list_scores(&state.db().await?, min_score).await
+}
+
+/// Paths through the relation graph as node and edge id lists, ready for
+/// `highlight-path`. Ranking uses the scores stored by `score_nodes`.
+#[tauri::command]
+async fn find_attack_paths(
+    state: State<'_, AppState>,
+    query: AttackPathQuery,
+) -> Result<Vec<AttackPath>, String> {
+    let db = state.db().await?;
+    let nodes = list_nodes_internal(&db).await?;
+    let edges = list_edges_internal(&db).await?;
+    let scores = scoring::list_scores(&db, None)
+        .await?
+        .into_iter()
+        .map(|score| (score.node_id, score.score))
+        .collect();
+    let tagged_tactics = attack::node_tactics(&db).await?;
+
+    attack_paths::find(&nodes, &edges, &scores, &tagged_tactics, &query)
+}
+
+/// Replaces the open case's ATT&CK dataset with a STIX bundle, or with the
+/// bundled subset when no path is given.
+#[tauri::command]
+async fn load_attack_dataset(
+    state: State<'_, AppState>,
+    path: Option<String>,
+) -> Result<AttackDatasetSummary, String> {
+    attack::load(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        path.as_deref().map(Path::new),
+    )
+    .await
+}
+
+#[tauri::command]
+async fn list_attack_techniques(
+    state: State<'_, AppState>,
+    query: Option<String>,
+) -> Result<Vec<Technique>, String> {
+    attack::list_techniques(&state.db().await?, query.as_deref()).await
+}
+
+#[tauri::command]
+async fn get_node_techniques(
+    state: State<'_, AppState>,
+    ids: Option<Vec<String>>,
+) -> Result<Vec<NodeTechnique>, String> {
+    let ids = ids.map(normalize_delete_ids);
+
+    attack::node_techniques(&state.db().await?, ids.as_deref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn set_node_techniques(
+    state: State<'_, AppState>,
+    node_id: String,
+    technique_ids: Vec<String>,
+) -> Result<Vec<NodeTechnique>, String> {
+    attack::set_node_techniques(
+        &state.db().await?,
+        &audit::actor(ChangeOrigin::Webview),
+        &node_id,
+        technique_ids,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn suggest_techniques(
+    state: State<'_, AppState>,
+    node_id: String,
+    limit: Option<usize>,
+) -> Result<Vec<TechniqueSuggestion>, String> {
+    attack::suggest(&state.db().await?, &node_id, limit).await
+}
+
+#[tauri::command]
+async fn get_attack_coverage(state: State<'_, AppState>) -> Result<Vec<TacticCoverage>, String> {
+    attack::coverage(&state.db().await?).await
+}
+
+/// Imports a STIX 2.1 bundle file into the open case.
+#[tauri::command]
+async fn import_stix_bundle(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<ImportReport, String> {
+    let raw = std::fs::read_to_string(&path)
+        .map_err(|err| format!("failed to read STIX bundle {path}: {err}"))?;
+
+    stix::import(state.inner(), &raw).await
+}
+
+/// Exports the open case as a STIX 2.1 bundle, optionally writing it to disk.
+#[tauri::command]
+async fn export_stix_bundle(
+    state: State<'_, AppState>,
+    output_path: Option<String>,
+) -> Result<String, String> {
+    let case_id = state
+        .cases
+        .active_case_id()
+        .await
+        .ok_or_else(|| "no case is open".to_owned())?;
+    let bundle = stix::export(&state.db().await?, &case_id).await?;
+    let rendered = serde_json::to_string_pretty(&bundle).map_err(|err| err.to_string())?;
+
+    if let Some(path) = output_path {
+        std::fs::write(&path, &rendered)
+            .map_err(|err| format!("failed to write STIX bundle {path}: {err}"))?;
+    }
+
+    Ok(rendered)
+}
+
+/// Imports a MISP event JSON export into the open case.
+#[tauri::command]
+async fn import_misp_event(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<ImportReport, String> {
+    let raw = std::fs::read_to_string(&path)
+        .map_err(|err| format!("failed to read MISP event {path}: {err}"))?;
+
+    misp::import(state.inner(), &raw).await
+}
+
+/// Exports the open case as a MISP event, optionally writing it to disk.
+#[tauri::command]
+async fn export_misp_event(
+    state: State<'_, AppState>,
+    output_path: Option<String>,
+) -> Result<String, String> {
+    let case = state
+        .cases
+        .active_case()
+        .await?
+        .ok_or_else(|| "no case is open".to_owned())?;
+    let event = misp::export(&state.db().await?, &case).await?;
+    let rendered = serde_json::to_string_pretty(&event).map_err(|err| err.to_string())?;
+
+    if let Some(path) = output_path {
+        std::fs::write(&path, &rendered)
+            .map_err(|err| format!("failed to write MISP event {path}: {err}"))?;
+    }
+
+    Ok(rendered)
+}
+
+/// Imports a Sysmon export, as wevtutil XML or winlogbeat JSON, into the
+/// open case.
+#[tauri::command]
+async fn import_sysmon_events(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<ImportReport, String> {
+    let raw = std::fs::read_to_string(&path)
+        .map_err(|err| format!("failed to read Sysmon export {path}: {err}"))?;
+
+    sysmon::import(state.inner(), &raw).await
+}
+
+/// Starts importing a Zeek log or Suricata `eve.json` as a background job and
+/// returns its id. Progress arrives as `jobs::JOB_EVENT` events.
+#[tauri::command]
+async fn start_network_log_import(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    request: NetworkLogImport,
+) -> Result<String, String> {
+    netlogs::start(state.inner().clone(), request, move |update| {
+        let _ = app.emit(jobs::JOB_EVENT, update);
+    })
+}
+
+#[tauri::command]
+async fn cancel_job(state: State<'_, AppState>, job_id: String) -> Result<(), String> {
+    state.jobs.cancel(&job_id)
+}
+
+#[tauri::command]
+async fn list_cases(
+    state: State<'_, AppState>,
+    include_archived: Option<bool>,
+) -> Result<Vec<CaseModel>, String> {
+    state.cases.list(include_archived.unwrap_or(false)).await
+}
+
+#[tauri::command]
+async fn get_active_case(state: State<'_, AppState>) -> Result<Option<CaseModel>, String> {
+    state.cases.active_case().await
+}
+
+#[tauri::command]
+async fn create_case(state: State<'_, AppState>, case: CasePayload) -> Result<CaseModel, String> {
+    state.cases.create(case).await
+}
+
+#[tauri::command]
+async fn rename_case(
+    state: State<'_, AppState>,
+    id: String,
+    name: String,
+) -> Result<CaseModel, String> {
+    state.cases.rename(&id, &name).await
+}
+
+#[tauri::command]
+async fn archive_case(state: State<'_, AppState>, id: String) -> Result<CaseModel, String> {
+    let was_active = state.cases.active_case_id().await;
+    let case = state.cases.archive(&id).await?;
+
+    if was_active.as_deref() == Some(case.id.as_str()) {
+        state.publish_case_changed(ChangeOrigin::Webview).await;
+    }
+
+    Ok(case)
+}
+
+#[tauri::command]
+async fn unarchive_case(state: State<'_, AppState>, id: String) -> Result<CaseModel, String> {
+    state.cases.unarchive(&id).await
+}
+
+#[tauri::command]
+async fn open_case(state: State<'_, AppState>, id: String) -> Result<CaseModel, String> {
+    let case = state.cases.open(&id).await?;
+    state.publish_case_changed(ChangeOrigin::Webview).await;
+    Ok(case)
+}
+
+#[tauri::command]
+async fn close_case(state: State<'_, AppState>) -> Result<(), String> {
+    state.cases.close().await?;
+    state.publish_case_changed(ChangeOrigin::Webview).await;
+    Ok(())
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            let app_data_dir = app_data_dir(app.handle())?;
+            let cases = tauri::async_runtime::block_on(async {
+                let cases = CaseManager::load(&app_data_dir).await?;
+                cases
+                    .bootstrap(Some(&app_data_dir.join(LEGACY_DB_FILE_NAME)))
+                    .await?;
+                Ok::<CaseManager, String>(cases)
+            })?;
+
+            let state = AppState::new(cases);
+
+            if let Some(config) = sync_server::SyncServerConfig::from_env()? {
+                let server_state = state.clone();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(err) = sync_server::serve(server_state, config).await {
+                        eprintln!("sync server stopped: {err}");
+                    }
+                });
+            }
+
+            changes::forward_to_webview(app.handle().clone(), &state.changes);
+            app.manage(state);
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_nodes,
+            upsert_nodes,
+            delete_nodes,
+            get_entities,
+            extract_observables,
+            get_observables,
+            find_nodes_by_observable,
+            search_nodes,
+            get_nodes_in_bounds,
+            compute_layout,
+            get_edges,
+            upsert_edges,
+            delete_edges,
+            list_plugins,
+            run_plugin,
+            cancel_plugin,
+            export_report,
+            score_nodes,
+            get_scores,
+            find_attack_paths,
+            load_attack_dataset,
+            list_attack_techniques,
+            get_node_techniques,
+            set_node_techniques,
+            suggest_techniques,
+            get_attack_coverage,
+            import_stix_bundle,
+            export_stix_bundle,
+            import_misp_event,
+            export_misp_event,
+            import_sysmon_events,
+            start_network_log_import,
+            cancel_job,
+            list_node_history,
+            list_history_batches,
+            restore_node,
+            revert_history_batch,
+            list_trash,
+            restore_from_trash,
+            preview_trash_purge,
+            purge_trash,
+            list_audit_log,
+            verify_audit_chain,
+            attach_file,
+            list_attachments,
+            export_attachment,
+            verify_attachments,
+            export_case,
+            import_case,
+            list_snapshots,
+            create_snapshot,
+            delete_snapshot,
+            diff_snapshots,
+            rollback_snapshot,
+            list_cases,
+            get_active_case,
+            create_case,
+            rename_case,
+            archive_case,
+            unarchive_case,
+            open_case,
+            close_case
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
+
+// Fixtures shared by the tests of every module.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+
+    pub(crate) async fn create_test_db() -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:")
+            .await
+            .expect("failed to connect sqlite");
+
+        init_schema(&db).await.expect("failed to init schema");
+        db
+    }
+
+    pub(crate) fn edge(id: &str, source: &str, target: &str) -> EdgePayload {
+        EdgePayload {
+            id: id.to_owned(),
+            source: source.to_owned(),
+            target: target.to_owned(),
+            kind: "related_to".to_owned(),
+            label: None,
+        }
+    }
+
+    pub(crate) fn note(id: &str, content: &str) -> NodePayload {
+        NodePayload {
+            id: id.to_owned(),
+            node_type: "note".to_owned(),
+            x: 0.0,
+            y: 0.0,
+            content: content.to_owned(),
+            width: None,
+            height: None,
+            kind: None,
+            attributes: None,
+        }
+    }
+
+    /// A fresh directory under the system temp dir, removed again on drop.
+    pub(crate) struct TempDir(PathBuf);
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    pub(crate) fn temp_dir(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!(
+            "cyberweaver-{name}-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    /// App state over a case root of its own, removed again on drop.
+    pub(crate) struct TestState {
+        state: AppState,
+        root: TempDir,
+    }
+
+    impl TestState {
+        pub(crate) fn root(&self) -> &Path {
+            &self.root
+        }
+    }
+
+    impl std::ops::Deref for TestState {
+        type Target = AppState;
+
+        fn deref(&self) -> &AppState {
+            &self.state
+        }
+    }
+
+    pub(crate) async fn test_state(name: &str) -> TestState {
+        let root = temp_dir(name);
+        let cases = CaseManager::load(&root).await.unwrap();
+        cases.bootstrap(None).await.unwrap();
+        TestState {
+            state: AppState::new(cases),
+            root,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_support::{create_test_db, edge};
+
+    #[tokio::test]
+    async fn upsert_and_get_nodes_roundtrip() {
         let db = create_test_db().await;
 
         upsert_nodes_internal(
             &db,
+            "test",
             vec![NodePayload {
                 id: "artifact-1".to_owned(),
                 node_type: "text".to_owned(),
@@ -355,6 +2118,8 @@ mod tests {
                 content: "IOC discovered".to_owned(),
                 width: Some(200.0),
                 height: None,
+                kind: None,
+                attributes: None,
             }],
         )
         .await
@@ -377,6 +2142,7 @@ mod tests {
 
         upsert_nodes_internal(
             &db,
+            "test",
             vec![NodePayload {
                 id: "shape:artifact-2".to_owned(),
                 node_type: "note".to_owned(),
@@ -385,12 +2151,14 @@ mod tests {
                 content: "temporary".to_owned(),
                 width: None,
                 height: None,
+                kind: None,
+                attributes: None,
             }],
         )
         .await
         .expect("upsert should succeed");
 
-        delete_nodes_internal(&db, vec!["artifact-2".to_owned()])
+        delete_nodes_internal(&db, "test", vec!["artifact-2".to_owned()])
             .await
             .expect("delete should succeed");
 
@@ -400,6 +2168,374 @@ mod tests {
         assert!(rows.is_empty());
     }
 
+    fn node(id: &str, content: &str) -> NodePayload {
+        NodePayload {
+            id: id.to_owned(),
+            node_type: "geo".to_owned(),
+            x: 0.0,
+            y: 0.0,
+            content: content.to_owned(),
+            width: None,
+            height: None,
+            kind: None,
+            attributes: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn upsert_and_get_edges_roundtrip() {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![node("proc-1", "powershell.exe"), node("ip-1", "10.0.0.8")],
+        )
+        .await
+        .expect("upsert should succeed");
+
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![EdgePayload {
+                kind: "connected_to".to_owned(),
+                label: Some("tcp/443".to_owned()),
+                ..edge("edge-1", "proc-1", "shape:ip-1")
+            }],
+        )
+        .await
+        .expect("edge upsert should succeed");
+
+        let edges = list_edges_internal(&db)
+            .await
+            .expect("query should succeed");
+
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].source, "shape:proc-1");
+        assert_eq!(edges[0].target, "shape:ip-1");
+        assert_eq!(edges[0].kind, "connected_to");
+        assert_eq!(edges[0].label.as_deref(), Some("tcp/443"));
+    }
+
+    #[tokio::test]
+    async fn upsert_edges_rejects_missing_endpoints_atomically() {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![node("proc-1", "cmd.exe"), node("file-1", "a.dll")],
+        )
+        .await
+        .expect("upsert should succeed");
+
+        let result = upsert_edges_internal(
+            &db,
+            "test",
+            vec![
+                edge("edge-1", "proc-1", "file-1"),
+                edge("edge-2", "proc-1", "missing"),
+            ],
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(list_edges_internal(&db)
+            .await
+            .expect("query should succeed")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn delete_nodes_cascades_to_edges() {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                node("proc-1", "winword.exe"),
+                node("proc-2", "powershell.exe"),
+                node("file-1", "payload.ps1"),
+            ],
+        )
+        .await
+        .expect("upsert should succeed");
+
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![
+                edge("edge-1", "proc-1", "proc-2"),
+                edge("edge-2", "proc-2", "file-1"),
+            ],
+        )
+        .await
+        .expect("edge upsert should succeed");
+
+        delete_nodes_internal(&db, "test", vec!["proc-1".to_owned()])
+            .await
+            .expect("delete should succeed");
+
+        let edges = list_edges_internal(&db)
+            .await
+            .expect("query should succeed");
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].id, "edge-2");
+    }
+
+    #[tokio::test]
+    async fn entity_typing_survives_canvas_updates_and_is_queryable() {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                NodePayload {
+                    kind: Some("ip".to_owned()),
+                    attributes: Some(serde_json::json!({ "address": "45.33.32.156" })),
+                    ..node("ip-1", "C2 server")
+                },
+                NodePayload {
+                    kind: Some("ip".to_owned()),
+                    attributes: Some(serde_json::json!({ "address": "10.0.0.8" })),
+                    ..node("ip-2", "workstation")
+                },
+            ],
+        )
+        .await
+        .expect("upsert should succeed");
+
+        // The canvas does not know about entity typing and must not erase it.
+        upsert_nodes_internal(&db, "test", vec![node("ip-1", "C2 server (confirmed)")])
+            .await
+            .expect("upsert should succeed");
+
+        let matches = list_entities_internal(&db, "ip", Some(("address", "45.33.32.156")))
+            .await
+            .expect("query should succeed");
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].content, "C2 server (confirmed)");
+        assert_eq!(matches[0].kind.as_deref(), Some("ip"));
+        assert_eq!(
+            list_entities_internal(&db, "ip", None).await.unwrap().len(),
+            2
+        );
+        assert!(
+            list_entities_internal(&db, "ip", Some(("a') OR 1=1 --", "x")))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn upsert_tracks_observables_per_node() {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                node(
+                    "note-1",
+                    "proxy log: GET hxxp://evil[.]example/stage2 from 10.1.2.3",
+                ),
+                node("note-2", "EDR alert on 10.1.2.3"),
+            ],
+        )
+        .await
+        .expect("upsert should succeed");
+
+        let mentioning = observables::nodes_mentioning(&db, "10[.]1[.]2[.]3", None)
+            .await
+            .expect("query should succeed");
+        assert_eq!(mentioning.len(), 2);
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![node("note-2", "EDR alert, host isolated")],
+        )
+        .await
+        .expect("upsert should succeed");
+        delete_nodes_internal(&db, "test", vec!["note-1".to_owned()])
+            .await
+            .expect("delete should succeed");
+
+        assert!(observables::nodes_mentioning(&db, "10.1.2.3", Some("ipv4"))
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(extract_observables_internal(&db, None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn search_nodes_ranks_and_highlights_matches() {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                node("note-1", "powershell -enc spawned by winword.exe"),
+                node(
+                    "note-2",
+                    "<p>Lateral movement via <b>psexec</b> to DC01</p>",
+                ),
+                node("note-3", "powershell <script>alert(1)</script> cleanup"),
+            ],
+        )
+        .await
+        .expect("upsert should succeed");
+
+        let hits = search::search(&db, "power* NOT cleanup", None)
+            .await
+            .expect("search should succeed");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].node.id, "shape:note-1");
+        assert!(hits[0].snippet.contains("<mark>powershell</mark>"));
+
+        let hits = search::search(&db, "\"lateral movement\"", None)
+            .await
+            .expect("search should succeed");
+        assert_eq!(hits.len(), 1);
+        assert!(!search::search(&db, "\"p b\"", None)
+            .await
+            .unwrap()
+            .iter()
+            .any(|hit| hit.node.id == "shape:note-2"));
+
+        let hits = search::search(&db, "alert", None).await.unwrap();
+        assert!(!hits[0].snippet.contains("<script>"));
+
+        delete_nodes_internal(&db, "test", vec!["note-1".to_owned()])
+            .await
+            .expect("delete should succeed");
+        assert!(search::search(&db, "winword", None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn compute_layout_arranges_requested_nodes_only() {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                node("parent", "a"),
+                node("child", "b"),
+                node("bystander", "c"),
+            ],
+        )
+        .await
+        .expect("upsert should succeed");
+        upsert_edges_internal(&db, "test", vec![edge("e1", "parent", "child")])
+            .await
+            .expect("edge upsert should succeed");
+
+        let positions = compute_layout_internal(
+            &db,
+            LayoutAlgorithm::Layered,
+            Some(vec!["parent".to_owned(), "child".to_owned()]),
+        )
+        .await
+        .expect("layout should succeed");
+
+        assert_eq!(positions.len(), 2);
+        let parent = positions.iter().find(|p| p.id == "shape:parent").unwrap();
+        let child = positions.iter().find(|p| p.id == "shape:child").unwrap();
+        assert!(child.y > parent.y);
+
+        assert!(compute_layout_internal(
+            &db,
+            LayoutAlgorithm::Layered,
+            Some(vec!["missing".to_owned()])
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    fn validate_payload_rejects_invalid_entity_attributes() {
+        let payload = NodePayload {
+            kind: Some("hash".to_owned()),
+            attributes: Some(serde_json::json!({ "algorithm": "sha256", "value": "deadbeef" })),
+            ..node("shape:x", "")
+        };
+        assert!(validate_node_payload(&payload).is_err());
+
+        let untyped = NodePayload {
+            attributes: Some(serde_json::json!({})),
+            ..node("shape:y", "")
+        };
+        assert!(validate_node_payload(&untyped).is_err());
+    }
+
+    #[tokio::test]
+    async fn upsert_validates_the_stored_kind_and_attributes() {
+        let db = create_test_db().await;
+
+        let untyped_ip = NodePayload {
+            kind: Some("ip".to_owned()),
+            ..node("ip-1", "C2 server")
+        };
+        assert!(upsert_nodes_internal(&db, "test", vec![untyped_ip])
+            .await
+            .unwrap_err()
+            .contains("ip.address"));
+        assert!(list_nodes_internal(&db).await.unwrap().is_empty());
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![NodePayload {
+                kind: Some("ip".to_owned()),
+                attributes: Some(serde_json::json!({ "address": "45.33.32.156" })),
+                ..node("ip-1", "C2 server")
+            }],
+        )
+        .await
+        .expect("upsert should succeed");
+
+        // The stored address is no hash, so the kind cannot change on its own.
+        let retyped = NodePayload {
+            kind: Some("hash".to_owned()),
+            ..node("ip-1", "C2 server")
+        };
+        assert!(upsert_nodes_internal(&db, "test", vec![retyped])
+            .await
+            .is_err());
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![NodePayload {
+                kind: Some("hash".to_owned()),
+                attributes: Some(serde_json::json!({
+                    "algorithm": "md5",
+                    "value": "d41d8cd98f00b204e9800998ecf8427e"
+                })),
+                ..node("ip-1", "empty file")
+            }],
+        )
+        .await
+        .expect("upsert should succeed");
+
+        let nodes = list_nodes_internal(&db).await.unwrap();
+        assert_eq!(nodes[0].kind.as_deref(), Some("hash"));
+        assert_eq!(
+            nodes[0].attributes.as_ref().unwrap()["algorithm"],
+            serde_json::json!("md5")
+        );
+    }
+
     #[test]
     fn validate_payload_rejects_unknown_shape_types() {
         let payload = NodePayload {
@@ -410,6 +2546,8 @@ mod tests {
             content: String::new(),
             width: None,
             height: None,
+            kind: None,
+            attributes: None,
         };
 
         let result = validate_node_payload(&payload);
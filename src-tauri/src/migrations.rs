@@ -0,0 +1,646 @@
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, Statement, TransactionTrait,
+};
+
+/// Data rewrites that need Rust-side logic rather than plain SQL.
+pub(crate) enum Backfill {
+    Observables,
+    SearchIndex,
+    SpatialIndex,
+}
+
+/// A single schema change applied as one step of a numbered migration.
+pub(crate) enum Step {
+    Sql(&'static str),
+    /// Adds a column unless it is already present. Databases created before the
+    /// registry existed may have been patched column by column, so the initial
+    /// migrations have to tolerate partially upgraded tables.
+    AddColumn {
+        table: &'static str,
+        column: &'static str,
+        definition: &'static str,
+    },
+    Backfill(Backfill),
+}
+
+pub(crate) struct Migration {
+    pub(crate) version: i64,
+    pub(crate) name: &'static str,
+    pub(crate) steps: &'static [Step],
+}
+
+/// Ordered registry of every schema version. Append new entries; never edit or
+/// reorder an entry that has shipped.
+pub(crate) const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_nodes",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS nodes (
+                    id TEXT PRIMARY KEY,
+                    type TEXT NOT NULL,
+                    x REAL NOT NULL,
+                    y REAL NOT NULL,
+                    content TEXT NOT NULL,
+                    width REAL,
+                    height REAL,
+                    updated_at INTEGER NOT NULL DEFAULT 0
+                );",
+            ),
+            Step::AddColumn {
+                table: "nodes",
+                column: "width",
+                definition: "REAL",
+            },
+            Step::AddColumn {
+                table: "nodes",
+                column: "height",
+                definition: "REAL",
+            },
+            Step::AddColumn {
+                table: "nodes",
+                column: "updated_at",
+                definition: "INTEGER NOT NULL DEFAULT 0",
+            },
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_nodes_type_updated_at ON nodes(type, updated_at);",
+            ),
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "create_edges",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS edges (
+                    id TEXT PRIMARY KEY,
+                    source TEXT NOT NULL,
+                    target TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    label TEXT,
+                    created_at INTEGER NOT NULL DEFAULT 0,
+                    updated_at INTEGER NOT NULL DEFAULT 0
+                );",
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_edges_source ON edges(source);"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_edges_target ON edges(target);"),
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "add_node_entities",
+        steps: &[
+            Step::AddColumn {
+                table: "nodes",
+                column: "kind",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "nodes",
+                column: "attributes",
+                definition: "TEXT",
+            },
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_nodes_kind ON nodes(kind);"),
+        ],
+    },
+    Migration {
+        version: 4,
+        name: "create_observables",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS observables (
+                    node_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    value TEXT NOT NULL,
+                    created_at INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (node_id, kind, value)
+                );",
+            ),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_observables_value ON observables(value);"),
+            Step::Backfill(Backfill::Observables),
+        ],
+    },
+    Migration {
+        version: 5,
+        name: "create_search_index",
+        steps: &[
+            // Stable integer keys for nodes; virtual index tables need integer
+            // rowids and the implicit rowid of `nodes` may change on VACUUM.
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS node_keys (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    node_id TEXT NOT NULL UNIQUE
+                );",
+            ),
+            Step::Sql(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS nodes_fts USING fts5(
+                    node_id UNINDEXED,
+                    body,
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );",
+            ),
+            Step::Backfill(Backfill::SearchIndex),
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "create_spatial_index",
+        steps: &[
+            Step::Sql(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS nodes_rtree USING rtree(
+                    seq,
+                    min_x, max_x,
+                    min_y, max_y
+                );",
+            ),
+            Step::Backfill(Backfill::SpatialIndex),
+        ],
+    },
+    Migration {
+        version: 7,
+        name: "add_threat_scores",
+        steps: &[
+            Step::AddColumn {
+                table: "nodes",
+                column: "threat_score",
+                definition: "REAL",
+            },
+            Step::AddColumn {
+                table: "nodes",
+                column: "threat_reasons",
+                definition: "TEXT",
+            },
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_nodes_threat_score ON nodes(threat_score);"),
+        ],
+    },
+    Migration {
+        version: 8,
+        name: "create_attack_tables",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS attack_tactics (
+                    id TEXT PRIMARY KEY,
+                    shortname TEXT NOT NULL UNIQUE,
+                    name TEXT NOT NULL,
+                    position INTEGER NOT NULL
+                );",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS attack_techniques (
+                    id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    description TEXT NOT NULL DEFAULT '',
+                    is_subtechnique INTEGER NOT NULL DEFAULT 0
+                );",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS attack_technique_tactics (
+                    technique_id TEXT NOT NULL,
+                    tactic TEXT NOT NULL,
+                    PRIMARY KEY (technique_id, tactic)
+                );",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS node_techniques (
+                    node_id TEXT NOT NULL,
+                    technique_id TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    PRIMARY KEY (node_id, technique_id)
+                );",
+            ),
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_node_techniques_technique
+                 ON node_techniques(technique_id);",
+            ),
+        ],
+    },
+    Migration {
+        version: 9,
+        name: "create_node_history",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS node_history (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    batch_id TEXT NOT NULL,
+                    node_id TEXT NOT NULL,
+                    action TEXT NOT NULL CHECK (action IN ('create', 'update', 'delete')),
+                    before TEXT,
+                    after TEXT,
+                    edges TEXT,
+                    created_at INTEGER NOT NULL
+                );",
+            ),
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_node_history_node
+                 ON node_history(node_id, seq);",
+            ),
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_node_history_batch
+                 ON node_history(batch_id);",
+            ),
+        ],
+    },
+    Migration {
+        version: 10,
+        name: "add_soft_delete",
+        steps: &[
+            Step::AddColumn {
+                table: "nodes",
+                column: "deleted_at",
+                definition: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "nodes",
+                column: "deleted_batch",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "edges",
+                column: "deleted_at",
+                definition: "INTEGER",
+            },
+            Step::AddColumn {
+                table: "edges",
+                column: "deleted_batch",
+                definition: "TEXT",
+            },
+            Step::AddColumn {
+                table: "edges",
+                column: "deleted_with",
+                definition: "TEXT",
+            },
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_nodes_deleted_at ON nodes(deleted_at);"),
+            Step::Sql("CREATE INDEX IF NOT EXISTS idx_edges_deleted_at ON edges(deleted_at);"),
+            // SQLite cannot alter a CHECK constraint, so the history table is
+            // rebuilt to allow the trash actions.
+            Step::Sql(
+                "CREATE TABLE node_history_next (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    batch_id TEXT NOT NULL,
+                    node_id TEXT NOT NULL,
+                    action TEXT NOT NULL
+                        CHECK (action IN ('create', 'update', 'delete', 'restore', 'purge')),
+                    before TEXT,
+                    after TEXT,
+                    edges TEXT,
+                    created_at INTEGER NOT NULL
+                );",
+            ),
+            Step::Sql(
+                "INSERT INTO node_history_next
+                 SELECT seq, batch_id, node_id, action, before, after, edges, created_at
+                 FROM node_history;",
+            ),
+            Step::Sql("DROP TABLE node_history;"),
+            Step::Sql("ALTER TABLE node_history_next RENAME TO node_history;"),
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_node_history_node
+                 ON node_history(node_id, seq);",
+            ),
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_node_history_batch
+                 ON node_history(batch_id);",
+            ),
+        ],
+    },
+    Migration {
+        version: 11,
+        name: "create_audit_log",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS audit_log (
+                    seq INTEGER PRIMARY KEY,
+                    actor TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    operation TEXT NOT NULL,
+                    payload_digest TEXT NOT NULL,
+                    prev_hash TEXT NOT NULL,
+                    hash TEXT NOT NULL
+                );",
+            ),
+            // Only a guard against accidents; `audit::verify` is what
+            // catches edits made with the triggers dropped.
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS audit_log_no_update
+                 BEFORE UPDATE ON audit_log
+                 BEGIN SELECT RAISE(ABORT, 'audit log is append-only'); END;",
+            ),
+            Step::Sql(
+                "CREATE TRIGGER IF NOT EXISTS audit_log_no_delete
+                 BEFORE DELETE ON audit_log
+                 BEGIN SELECT RAISE(ABORT, 'audit log is append-only'); END;",
+            ),
+        ],
+    },
+    Migration {
+        version: 12,
+        name: "create_attachments",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS attachments (
+                    sha256 TEXT PRIMARY KEY,
+                    sha1 TEXT NOT NULL,
+                    md5 TEXT NOT NULL,
+                    size INTEGER NOT NULL,
+                    mime TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                );",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS node_attachments (
+                    node_id TEXT NOT NULL,
+                    sha256 TEXT NOT NULL REFERENCES attachments(sha256),
+                    file_name TEXT NOT NULL,
+                    attached_at INTEGER NOT NULL,
+                    PRIMARY KEY (node_id, sha256)
+                );",
+            ),
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_node_attachments_sha256
+                 ON node_attachments(sha256);",
+            ),
+        ],
+    },
+    Migration {
+        version: 13,
+        name: "create_snapshots",
+        steps: &[
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS snapshots (
+                    id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                );",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS snapshot_nodes (
+                    snapshot_id TEXT NOT NULL REFERENCES snapshots(id),
+                    node_id TEXT NOT NULL,
+                    type TEXT NOT NULL,
+                    x REAL NOT NULL,
+                    y REAL NOT NULL,
+                    content TEXT NOT NULL,
+                    width REAL,
+                    height REAL,
+                    kind TEXT,
+                    attributes TEXT,
+                    updated_at INTEGER NOT NULL,
+                    PRIMARY KEY (snapshot_id, node_id)
+                );",
+            ),
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS snapshot_edges (
+                    snapshot_id TEXT NOT NULL REFERENCES snapshots(id),
+                    edge_id TEXT NOT NULL,
+                    source TEXT NOT NULL,
+                    target TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    label TEXT,
+                    created_at INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    PRIMARY KEY (snapshot_id, edge_id)
+                );",
+            ),
+        ],
+    },
+    Migration {
+        version: 14,
+        name: "create_edge_history",
+        steps: &[
+            // Relations deleted or restored on their own, not along with a
+            // node. `seq` continues the one of `node_history`.
+            Step::Sql(
+                "CREATE TABLE IF NOT EXISTS edge_history (
+                    seq INTEGER PRIMARY KEY,
+                    batch_id TEXT NOT NULL,
+                    edge_id TEXT NOT NULL,
+                    action TEXT NOT NULL CHECK (action IN ('delete', 'restore')),
+                    edge TEXT NOT NULL,
+                    created_at INTEGER NOT NULL
+                );",
+            ),
+            Step::Sql(
+                "CREATE INDEX IF NOT EXISTS idx_edge_history_batch
+                 ON edge_history(batch_id);",
+            ),
+        ],
+    },
+];
+
+pub(crate) fn latest_version() -> i64 {
+    MIGRATIONS
+        .iter()
+        .map(|migration| migration.version)
+        .max()
+        .unwrap_or(0)
+}
+
+async fn column_exists<C: ConnectionTrait>(
+    conn: &C,
+    table: &str,
+    column: &str,
+) -> Result<bool, DbErr> {
+    let rows = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            format!("PRAGMA table_info({table});"),
+        ))
+        .await?;
+
+    Ok(rows.into_iter().any(|row| {
+        row.try_get::<String>("", "name")
+            .map(|name| name == column)
+            .unwrap_or(false)
+    }))
+}
+
+async fn apply_step<C: ConnectionTrait>(conn: &C, step: &Step) -> Result<(), DbErr> {
+    match step {
+        Step::Sql(sql) => {
+            conn.execute(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                (*sql).to_owned(),
+            ))
+            .await?;
+        }
+        Step::AddColumn {
+            table,
+            column,
+            definition,
+        } => {
+            if !column_exists(conn, table, column).await? {
+                conn.execute(Statement::from_string(
+                    DatabaseBackend::Sqlite,
+                    format!("ALTER TABLE {table} ADD COLUMN {column} {definition};"),
+                ))
+                .await?;
+            }
+        }
+        Step::Backfill(Backfill::Observables) => {
+            crate::observables::reindex(conn, None).await?;
+        }
+        Step::Backfill(Backfill::SearchIndex) => {
+            crate::search::reindex_all(conn).await?;
+        }
+        Step::Backfill(Backfill::SpatialIndex) => {
+            crate::spatial::reindex_all(conn).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the highest migration version recorded in the database, or 0 for a
+/// database that predates the registry.
+pub(crate) async fn current_version(db: &DatabaseConnection) -> Result<i64, DbErr> {
+    let registry = db
+        .query_one(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations';"
+                .to_owned(),
+        ))
+        .await?;
+    if registry.is_none() {
+        return Ok(0);
+    }
+
+    let row = db
+        .query_one(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT COALESCE(MAX(version), 0) AS version FROM schema_migrations;".to_owned(),
+        ))
+        .await?;
+
+    Ok(row
+        .and_then(|row| row.try_get::<i64>("", "version").ok())
+        .unwrap_or(0))
+}
+
+/// Brings the database up to [`latest_version`], applying each pending
+/// migration in its own transaction.
+pub(crate) async fn migrate(db: &DatabaseConnection) -> Result<(), DbErr> {
+    db.execute(Statement::from_string(
+        DatabaseBackend::Sqlite,
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        );"
+        .to_owned(),
+    ))
+    .await?;
+
+    let current = current_version(db).await?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(DbErr::Custom(format!(
+            "database schema version {current} is newer than the supported version {latest}; \
+             please upgrade CyberWeaver to open it"
+        )));
+    }
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current)
+    {
+        let txn = db.begin().await?;
+
+        for step in migration.steps {
+            apply_step(&txn, step).await?;
+        }
+
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "INSERT INTO schema_migrations (version, name, applied_at)
+             VALUES (?, ?, unixepoch());"
+                .to_owned(),
+            vec![migration.version.into(), migration.name.into()],
+        ))
+        .await?;
+
+        txn.commit().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{connect_database_from_path, list_nodes_internal, test_support::temp_dir};
+    use sea_orm::Database;
+    use std::path::{Path, PathBuf};
+
+    fn fixture_copy(dir: &Path) -> PathBuf {
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/legacy-cyberweaver.db");
+        let target = dir.join("legacy.db");
+
+        std::fs::copy(fixture, &target).expect("failed to copy fixture");
+        target
+    }
+
+    #[test]
+    fn registry_versions_are_strictly_increasing() {
+        let versions = MIGRATIONS
+            .iter()
+            .map(|migration| migration.version)
+            .collect::<Vec<_>>();
+
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(versions.first(), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn migrates_legacy_database_forward() {
+        let dir = temp_dir("migrations-forward");
+        let path = fixture_copy(&dir);
+        let db = connect_database_from_path(&path)
+            .await
+            .expect("failed to open fixture");
+
+        migrate(&db).await.expect("migration should succeed");
+        migrate(&db).await.expect("migration should be idempotent");
+
+        assert_eq!(current_version(&db).await.unwrap(), latest_version());
+        assert!(column_exists(&db, "nodes", "updated_at").await.unwrap());
+        assert!(column_exists(&db, "edges", "kind").await.unwrap());
+
+        let observables = crate::observables::list(&db, None).await.unwrap();
+        assert!(observables
+            .iter()
+            .any(|observable| observable.value == "45.33.32.156"));
+
+        let hits = crate::search::search(&db, "beacon*", None).await.unwrap();
+        assert_eq!(hits.len(), 1);
+
+        let rows = list_nodes_internal(&db)
+            .await
+            .expect("query should succeed");
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].width, None);
+
+        db.close().await.ok();
+    }
+
+    #[tokio::test]
+    async fn refuses_databases_from_newer_versions() {
+        let db = Database::connect("sqlite::memory:")
+            .await
+            .expect("failed to connect sqlite");
+
+        migrate(&db).await.expect("migration should succeed");
+        db.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, 'future', 0);"
+                .to_owned(),
+            vec![(latest_version() + 1).into()],
+        ))
+        .await
+        .unwrap();
+
+        let result = migrate(&db).await;
+        assert!(matches!(result, Err(DbErr::Custom(message)) if message.contains("newer")));
+    }
+}
@@ -0,0 +1,1194 @@
+use sea_orm::DatabaseConnection;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeSet, HashMap};
+use uuid::Uuid;
+
+use crate::{
+    attack,
+    cases::CaseModel,
+    entities::is_technique_id,
+    ingest::{self, ImportPlan, ImportReport},
+    list_edges_internal, list_nodes_internal, normalize_shape_id,
+    search::plain_text_from_content,
+    stix::{node_updated_at, relation_from_stix, relation_to_stix, CYBERWEAVER_NAMESPACE},
+    timestamps::format_timestamp,
+    validate_node_payload, AppState, EdgeModel, EdgePayload, NodeModel, NodePayload,
+};
+
+const ATTACK_GALAXY: &str = "misp-galaxy:mitre-attack-pattern=";
+// MISP metadata kept on imported entities and written back on export.
+const CATEGORY_KEY: &str = "mispCategory";
+const TO_IDS_KEY: &str = "toIds";
+const TAGS_KEY: &str = "tags";
+
+/// Standalone attribute types and the entity attribute their value fills.
+/// Hashes get their algorithm from the type; `text`-like types become notes.
+const ATTRIBUTE_TYPES: &[(&str, &str, &str)] = &[
+    ("ip-src", "ip", "address"),
+    ("ip-dst", "ip", "address"),
+    ("domain", "domain", "name"),
+    ("hostname", "host", "hostname"),
+    ("url", "url", "url"),
+    ("link", "url", "url"),
+    ("uri", "url", "url"),
+    ("md5", "hash", "value"),
+    ("sha1", "hash", "value"),
+    ("sha256", "hash", "value"),
+    ("filename", "file", "name"),
+    ("regkey", "registry_key", "path"),
+    ("email-src", "email", "from"),
+    ("email-dst", "email", "to"),
+    ("email-subject", "email", "subject"),
+    ("target-user", "user", "name"),
+    ("malware-type", "malware", "name"),
+    ("stix2-pattern", "indicator", "pattern"),
+    ("yara", "indicator", "pattern"),
+    ("sigma", "indicator", "pattern"),
+    ("snort", "indicator", "pattern"),
+];
+
+/// Second halves of composite `a|b` attribute types.
+const COMPOSITE_PARTS: &[(&str, &str)] = &[
+    ("port", "port"),
+    ("ip", "ip"),
+    ("md5", "md5"),
+    ("sha1", "sha1"),
+    ("sha256", "sha256"),
+    ("value", "value"),
+];
+
+const NOTE_TYPES: &[&str] = &["comment", "text", "other"];
+
+/// Object templates per entity kind. `domain-ip` objects without a domain
+/// describe a host.
+const TEMPLATES: &[(&str, &str)] = &[
+    ("ip", "ip-port"),
+    ("domain", "domain-ip"),
+    ("host", "domain-ip"),
+    ("url", "url"),
+    ("file", "file"),
+    ("hash", "file"),
+    ("process", "process"),
+    ("user", "user-account"),
+    ("registry_key", "registry-key"),
+    ("email", "email"),
+    ("indicator", "stix2-pattern"),
+    ("malware", "malware"),
+    ("attack_pattern", "attack-pattern"),
+];
+const NOTE_TEMPLATE: &str = "annotation";
+
+/// `(entity kind, attribute key, object relation, MISP type)`. Import and
+/// export both read this table, so objects round-trip onto the same fields.
+const FIELDS: &[(&str, &str, &str, &str)] = &[
+    ("ip", "address", "ip", "ip-dst"),
+    ("ip", "port", "dst-port", "port"),
+    ("domain", "name", "domain", "domain"),
+    ("host", "hostname", "hostname", "hostname"),
+    ("host", "ip", "ip", "ip-dst"),
+    ("url", "url", "url", "url"),
+    ("file", "name", "filename", "filename"),
+    ("file", "path", "fullpath", "text"),
+    ("file", "md5", "md5", "md5"),
+    ("file", "sha1", "sha1", "sha1"),
+    ("file", "sha256", "sha256", "sha256"),
+    ("file", "size", "size-in-bytes", "size-in-bytes"),
+    ("process", "pid", "pid", "text"),
+    ("process", "parentPid", "parent-pid", "text"),
+    ("process", "commandLine", "command-line", "text"),
+    ("process", "image", "image", "filename"),
+    ("user", "name", "username", "text"),
+    ("user", "sid", "user-id", "text"),
+    ("registry_key", "path", "key", "regkey"),
+    ("email", "from", "from", "email-src"),
+    ("email", "to", "to", "email-dst"),
+    ("email", "subject", "subject", "email-subject"),
+    ("indicator", "pattern", "pattern", "stix2-pattern"),
+    ("indicator", "name", "name", "text"),
+    ("malware", "name", "name", "malware-type"),
+    ("attack_pattern", "name", "name", "text"),
+    ("attack_pattern", "externalId", "id", "text"),
+];
+
+const NUMERIC_KEYS: &[&str] = &["port", "pid", "parentPid", "size"];
+const HASH_KEYS: &[&str] = &["md5", "sha1", "sha256"];
+const LABEL_KEYS: &[&str] = &[
+    "name",
+    "address",
+    "hostname",
+    "url",
+    "path",
+    "commandLine",
+    "image",
+    "subject",
+    "pattern",
+    "sha256",
+    "sha1",
+    "md5",
+    "value",
+    "from",
+];
+
+/// One imported attribute or object, before validation.
+struct Entry {
+    uuid: String,
+    kind: Option<&'static str>,
+    attributes: Map<String, Value>,
+    content: String,
+    techniques: Vec<String>,
+}
+
+fn str_field<'a>(object: &'a Value, key: &str) -> Option<&'a str> {
+    object
+        .get(key)
+        .and_then(|value| value.as_str())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn list<'a>(object: &'a Value, key: &str) -> &'a [Value] {
+    object
+        .get(key)
+        .and_then(|value| value.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+fn is_deleted(attribute: &Value) -> bool {
+    attribute.get("deleted").and_then(Value::as_bool) == Some(true)
+}
+
+fn to_ids(attribute: &Value) -> bool {
+    match attribute.get("to_ids") {
+        Some(Value::Bool(flag)) => *flag,
+        Some(Value::String(flag)) => matches!(flag.as_str(), "1" | "true"),
+        Some(Value::Number(flag)) => flag.as_u64() == Some(1),
+        _ => false,
+    }
+}
+
+fn tag_names(attribute: &Value) -> Vec<String> {
+    list(attribute, "Tag")
+        .iter()
+        .filter_map(|tag| str_field(tag, "name").map(str::to_owned))
+        .collect()
+}
+
+/// Technique id of an ATT&CK galaxy tag such as
+/// `misp-galaxy:mitre-attack-pattern="PowerShell - T1059.001"`.
+fn galaxy_technique(tag: &str) -> Option<String> {
+    let cluster = tag.strip_prefix(ATTACK_GALAXY)?.trim_matches('"');
+    let id = cluster.rsplit(" - ").next()?.trim().to_ascii_uppercase();
+
+    is_technique_id(&id).then_some(id)
+}
+
+fn entity_for_type(misp_type: &str) -> Option<(&'static str, &'static str)> {
+    ATTRIBUTE_TYPES
+        .iter()
+        .find(|(candidate, _, _)| *candidate == misp_type)
+        .map(|(_, kind, key)| (*kind, *key))
+}
+
+fn pattern_type(misp_type: &str) -> &str {
+    match misp_type {
+        "stix2-pattern" => "stix",
+        other => other,
+    }
+}
+
+/// Stores an attribute value under `key`, parsing counters and collecting
+/// repeated email recipients into a list.
+fn set_field(attributes: &mut Map<String, Value>, key: &str, value: &str) {
+    let parsed = if NUMERIC_KEYS.contains(&key) {
+        value
+            .parse::<u64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| value.into())
+    } else if HASH_KEYS.contains(&key) {
+        value.to_ascii_lowercase().into()
+    } else {
+        value.into()
+    };
+
+    match attributes.get_mut(key) {
+        Some(Value::Array(values)) if matches!(key, "from" | "to") => values.push(parsed),
+        Some(existing) if matches!(key, "from" | "to") => {
+            *existing = Value::Array(vec![existing.take(), parsed]);
+        }
+        _ => {
+            attributes.insert(key.to_owned(), parsed);
+        }
+    }
+}
+
+fn label(attributes: &Map<String, Value>, fallback: &str) -> String {
+    LABEL_KEYS
+        .iter()
+        .find_map(|key| attributes.get(*key)?.as_str())
+        .unwrap_or(fallback)
+        .to_owned()
+}
+
+fn entry_uuid(object: &Value, fallback: &str) -> String {
+    str_field(object, "uuid")
+        .map(str::to_owned)
+        .unwrap_or_else(|| Uuid::new_v5(&CYBERWEAVER_NAMESPACE, fallback.as_bytes()).to_string())
+}
+
+/// Records category, `to_ids` and tags, pulling ATT&CK galaxy tags out as
+/// techniques.
+fn annotate(entry: &mut Entry, category: Option<&str>, flagged: bool, tags: Vec<String>) {
+    let mut kept = Vec::new();
+
+    for tag in tags {
+        match galaxy_technique(&tag) {
+            Some(technique) => entry.techniques.push(technique),
+            None => kept.push(Value::from(tag)),
+        }
+    }
+
+    if entry.kind.is_none() {
+        return;
+    }
+
+    if let Some(category) = category {
+        entry
+            .attributes
+            .insert(CATEGORY_KEY.to_owned(), category.into());
+    }
+    entry
+        .attributes
+        .insert(TO_IDS_KEY.to_owned(), flagged.into());
+    if !kept.is_empty() {
+        entry
+            .attributes
+            .insert(TAGS_KEY.to_owned(), Value::Array(kept));
+    }
+}
+
+/// Maps a standalone attribute. `Ok(None)` means the type has no entity.
+fn map_attribute(attribute: &Value) -> Result<Option<Entry>, String> {
+    let misp_type = str_field(attribute, "type").ok_or("missing type")?;
+    let value = str_field(attribute, "value").ok_or("missing value")?;
+    let uuid = entry_uuid(attribute, &format!("{misp_type}|{value}"));
+    let mut attributes = Map::new();
+
+    let kind = if NOTE_TYPES.contains(&misp_type) {
+        None
+    } else {
+        let (head, tail) = misp_type.split_once('|').unwrap_or((misp_type, ""));
+        let Some((kind, key)) = entity_for_type(head) else {
+            return Ok(None);
+        };
+        let (value, rest) = if tail.is_empty() {
+            (value, None)
+        } else {
+            let (value, rest) = value
+                .split_once('|')
+                .ok_or_else(|| format!("{misp_type} value must be `a|b`"))?;
+            (value, Some(rest))
+        };
+
+        if kind == "hash" {
+            set_field(&mut attributes, key, &value.to_ascii_lowercase());
+            attributes.insert("algorithm".to_owned(), head.into());
+        } else {
+            set_field(&mut attributes, key, value);
+        }
+        if kind == "indicator" {
+            attributes.insert("patternType".to_owned(), pattern_type(head).into());
+        }
+
+        if let Some(rest) = rest {
+            let key = COMPOSITE_PARTS
+                .iter()
+                .find(|(part, _)| *part == tail)
+                .map(|(_, key)| *key)
+                .ok_or_else(|| format!("unsupported composite type: {misp_type}"))?;
+            set_field(&mut attributes, key, rest);
+        }
+
+        Some(kind)
+    };
+
+    let content = label(&attributes, value);
+    let mut entry = Entry {
+        uuid,
+        kind,
+        attributes,
+        content,
+        techniques: Vec::new(),
+    };
+    annotate(
+        &mut entry,
+        str_field(attribute, "category"),
+        to_ids(attribute),
+        tag_names(attribute),
+    );
+
+    Ok(Some(entry))
+}
+
+fn template_kind(template: &str, attributes: &[&Value]) -> Option<&'static str> {
+    let has_relation = |relation: &str| {
+        attributes
+            .iter()
+            .any(|attribute| str_field(attribute, "object_relation") == Some(relation))
+    };
+
+    match template {
+        "domain-ip" if !has_relation("domain") && has_relation("hostname") => Some("host"),
+        NOTE_TEMPLATE => None,
+        template => TEMPLATES
+            .iter()
+            .find(|(_, candidate)| *candidate == template)
+            .map(|(kind, _)| *kind),
+    }
+}
+
+/// Maps an object to one entry, plus standalone entries for attributes the
+/// template does not cover but which are entities of their own (the IP of a
+/// `domain-ip` object, say). Unknown templates become notes listing their
+/// remaining values.
+fn map_object(object: &Value) -> Result<(Entry, Vec<Entry>), String> {
+    let template = str_field(object, "name").ok_or("missing name")?;
+    let uuid = entry_uuid(object, template);
+    let attributes = list(object, "Attribute")
+        .iter()
+        .filter(|attribute| !is_deleted(attribute))
+        .collect::<Vec<_>>();
+    let kind = template_kind(template, &attributes);
+
+    let mut fields = Map::new();
+    let mut extra = Vec::new();
+    let mut text = None;
+    let mut lines = Vec::new();
+    let mut flagged = false;
+    let mut tags = Vec::new();
+
+    for attribute in attributes {
+        let (Some(relation), Some(value)) = (
+            str_field(attribute, "object_relation"),
+            str_field(attribute, "value"),
+        ) else {
+            continue;
+        };
+        let key = kind.and_then(|kind| {
+            FIELDS
+                .iter()
+                .find(|(candidate, _, field, _)| *candidate == kind && *field == relation)
+                .map(|(_, key, _, _)| *key)
+        });
+
+        if let Some(key) = key {
+            set_field(&mut fields, key, value);
+            flagged |= to_ids(attribute);
+            tags.extend(tag_names(attribute));
+            if kind == Some("indicator") && key == "pattern" {
+                let misp_type = str_field(attribute, "type").unwrap_or("stix2-pattern");
+                fields.insert("patternType".to_owned(), pattern_type(misp_type).into());
+            }
+        } else if let Some(entry) = map_attribute(attribute)
+            .ok()
+            .flatten()
+            .filter(|entry| entry.kind.is_some())
+        {
+            extra.push(entry);
+        } else if kind.is_some() {
+            fields.insert(relation.to_owned(), value.into());
+        } else if relation == "text" {
+            text = Some(value.to_owned());
+        } else {
+            lines.push(format!("{relation}: {value}"));
+        }
+    }
+
+    let content = match (kind, text) {
+        (Some(_), _) => label(&fields, template),
+        (None, Some(text)) => text,
+        (None, None) => {
+            std::iter::once(str_field(object, "comment").unwrap_or(template).to_owned())
+                .chain(lines)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    };
+    let mut entry = Entry {
+        uuid,
+        kind,
+        attributes: fields,
+        content,
+        techniques: Vec::new(),
+    };
+
+    if let Some(technique) = entry
+        .attributes
+        .get("externalId")
+        .and_then(Value::as_str)
+        .filter(|_| kind == Some("attack_pattern"))
+    {
+        entry.techniques.push(technique.to_ascii_uppercase());
+    }
+    annotate(
+        &mut entry,
+        str_field(object, "meta-category"),
+        flagged,
+        tags,
+    );
+
+    Ok((entry, extra))
+}
+
+fn node_payload(entry: Entry) -> NodePayload {
+    NodePayload {
+        id: normalize_shape_id(&entry.uuid),
+        node_type: if entry.kind.is_some() { "geo" } else { "note" }.to_owned(),
+        x: 0.0,
+        y: 0.0,
+        content: entry.content,
+        width: None,
+        height: None,
+        kind: entry.kind.map(str::to_owned),
+        attributes: entry.kind.map(|_| Value::Object(entry.attributes)),
+    }
+}
+
+/// Validates an entry and queues it. Returns whether it was accepted.
+fn push_entry(plan: &mut ImportPlan, entry: Entry) -> bool {
+    let uuid = entry.uuid.clone();
+    let techniques = entry.techniques.clone();
+    let node = node_payload(entry);
+
+    if let Err(reason) = validate_node_payload(&node) {
+        plan.skipped.push(format!("{uuid}: {reason}"));
+        return false;
+    }
+
+    for technique in techniques {
+        plan.techniques.push((node.id.clone(), technique));
+    }
+    plan.nodes.push(node);
+    true
+}
+
+fn edge_payload(
+    id: String,
+    source: &str,
+    target: &str,
+    kind: &str,
+    label: Option<String>,
+) -> EdgePayload {
+    EdgePayload {
+        id,
+        source: normalize_shape_id(source),
+        target: normalize_shape_id(target),
+        kind: kind.to_owned(),
+        label,
+    }
+}
+
+/// Turns a MISP event into node and edge payloads. Positions are filled in
+/// by the caller.
+fn plan_import(raw: &str) -> Result<ImportPlan, String> {
+    let document =
+        serde_json::from_str::<Value>(raw).map_err(|err| format!("invalid MISP event: {err}"))?;
+    let event = document.get("Event").unwrap_or(&document);
+
+    if !["info", "Attribute", "Object"]
+        .iter()
+        .any(|key| event.get(*key).is_some())
+    {
+        return Err("MISP document has no event".to_owned());
+    }
+
+    let mut plan = ImportPlan::default();
+    // MISP uuid -> uuid of the node it ended up in.
+    let mut owners = HashMap::<String, String>::new();
+
+    for attribute in list(event, "Attribute") {
+        if is_deleted(attribute) {
+            continue;
+        }
+
+        let name = str_field(attribute, "uuid").unwrap_or("(attribute without uuid)");
+        match map_attribute(attribute) {
+            Ok(Some(entry)) => {
+                let uuid = entry.uuid.clone();
+                if push_entry(&mut plan, entry) {
+                    owners.insert(uuid.clone(), uuid);
+                }
+            }
+            Ok(None) => plan.skipped.push(format!(
+                "{name}: unsupported attribute type {}",
+                str_field(attribute, "type").unwrap_or_default()
+            )),
+            Err(reason) => plan.skipped.push(format!("{name}: {reason}")),
+        }
+    }
+
+    let mut edges = Vec::new();
+
+    for object in list(event, "Object") {
+        let name = str_field(object, "uuid").unwrap_or("(object without uuid)");
+        let (entry, extra) = match map_object(object) {
+            Ok(mapped) => mapped,
+            Err(reason) => {
+                plan.skipped.push(format!("{name}: {reason}"));
+                continue;
+            }
+        };
+
+        let uuid = entry.uuid.clone();
+        let kind = entry.kind;
+        if !push_entry(&mut plan, entry) {
+            continue;
+        }
+
+        for attribute in list(object, "Attribute") {
+            if let Some(member) = str_field(attribute, "uuid") {
+                owners.insert(member.to_owned(), uuid.clone());
+            }
+        }
+        owners.insert(uuid.clone(), uuid.clone());
+
+        for entry in extra {
+            let member = entry.uuid.clone();
+            let relation = match (kind, entry.kind) {
+                (Some("domain" | "host"), Some("ip")) => "resolved_to",
+                _ => "related_to",
+            };
+
+            if push_entry(&mut plan, entry) {
+                owners.insert(member.clone(), member.clone());
+                let id = Uuid::new_v5(
+                    &CYBERWEAVER_NAMESPACE,
+                    format!("{uuid}|{relation}|{member}").as_bytes(),
+                );
+                edges.push(edge_payload(id.to_string(), &uuid, &member, relation, None));
+            }
+        }
+    }
+
+    for object in list(event, "Object") {
+        let Some(source) = str_field(object, "uuid").and_then(|uuid| owners.get(uuid)) else {
+            continue;
+        };
+
+        for reference in list(object, "ObjectReference") {
+            if is_deleted(reference) {
+                continue;
+            }
+
+            let referenced = str_field(reference, "referenced_uuid").unwrap_or_default();
+            let id = entry_uuid(reference, &format!("{source}|{referenced}"));
+            let Some(target) = owners.get(referenced) else {
+                plan.skipped
+                    .push(format!("{id}: references something that was not imported"));
+                continue;
+            };
+
+            let (kind, fallback_label) =
+                relation_from_stix(str_field(reference, "relationship_type").unwrap_or_default());
+            let label = str_field(reference, "comment")
+                .map(str::to_owned)
+                .or(fallback_label);
+            edges.push(edge_payload(id, source, target, kind, label));
+        }
+    }
+
+    plan.edges = edges;
+    Ok(plan)
+}
+
+/// Imports a MISP event export into the open case. Attributes and objects
+/// keep their MISP uuids as node ids, so importing the same event twice
+/// updates in place.
+pub(crate) async fn import(state: &AppState, raw: &str) -> Result<ImportReport, String> {
+    ingest::apply(state, plan_import(raw)?).await
+}
+
+/// One value of an exported attribute or object.
+struct Field {
+    relation: &'static str,
+    misp_type: String,
+    value: String,
+}
+
+fn value_strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(value) if !value.trim().is_empty() => vec![value.trim().to_owned()],
+        Value::Number(value) => vec![value.to_string()],
+        Value::Array(values) => values.iter().flat_map(value_strings).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn note_text(node: &NodeModel) -> String {
+    let text = plain_text_from_content(&node.content);
+    let text = text.trim();
+
+    if text.is_empty() {
+        node.id
+            .strip_prefix("shape:")
+            .unwrap_or(&node.id)
+            .to_owned()
+    } else {
+        text.to_owned()
+    }
+}
+
+fn fields(node: &NodeModel) -> Vec<Field> {
+    let attributes = node.attributes.clone().unwrap_or(Value::Null);
+    let kind = node.kind.as_deref().unwrap_or_default();
+
+    let mut fields = match kind {
+        "hash" => {
+            let algorithm = attributes["algorithm"]
+                .as_str()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            let relation = HASH_KEYS
+                .iter()
+                .find(|key| **key == algorithm)
+                .copied()
+                .unwrap_or("sha256");
+
+            value_strings(&attributes["value"])
+                .into_iter()
+                .map(|value| Field {
+                    relation,
+                    misp_type: relation.to_owned(),
+                    value: value.to_ascii_lowercase(),
+                })
+                .collect()
+        }
+        _ => FIELDS
+            .iter()
+            .filter(|(candidate, _, _, _)| *candidate == kind)
+            .flat_map(|(_, key, relation, misp_type)| {
+                let misp_type = match (kind, *key, attributes["patternType"].as_str()) {
+                    ("indicator", "pattern", Some("stix") | None) => "stix2-pattern",
+                    ("indicator", "pattern", Some(other @ ("yara" | "sigma" | "snort"))) => other,
+                    ("indicator", "pattern", Some(_)) => "text",
+                    _ => misp_type,
+                };
+
+                value_strings(&attributes[*key])
+                    .into_iter()
+                    .map(move |value| Field {
+                        relation,
+                        misp_type: misp_type.to_owned(),
+                        value,
+                    })
+            })
+            .collect::<Vec<_>>(),
+    };
+
+    if fields.is_empty() {
+        fields.push(Field {
+            relation: "text",
+            misp_type: "text".to_owned(),
+            value: note_text(node),
+        });
+    }
+
+    fields
+}
+
+fn template(node: &NodeModel, fields: &[Field]) -> &'static str {
+    let representable = fields.iter().all(|field| field.relation != "text");
+
+    node.kind
+        .as_deref()
+        .filter(|_| representable)
+        .and_then(|kind| TEMPLATES.iter().find(|(candidate, _)| *candidate == kind))
+        .map_or(NOTE_TEMPLATE, |(_, template)| *template)
+}
+
+/// Whether a single-valued node can be written as a bare attribute and still
+/// come back as the same kind of entity.
+fn standalone_type(node: &NodeModel, fields: &[Field]) -> Option<String> {
+    let [field] = fields else {
+        return None;
+    };
+
+    match node.kind.as_deref() {
+        None => Some("comment".to_owned()),
+        Some(kind) => (entity_for_type(&field.misp_type).map(|(candidate, _)| candidate)
+            == Some(kind))
+        .then(|| field.misp_type.clone()),
+    }
+}
+
+fn default_category(misp_type: &str) -> &'static str {
+    match misp_type {
+        "ip-src" | "ip-dst" | "port" | "domain" | "hostname" | "url" => "Network activity",
+        "md5" | "sha1" | "sha256" | "filename" | "size-in-bytes" | "email-src" | "email-dst"
+        | "email-subject" => "Payload delivery",
+        "regkey" => "Persistence mechanism",
+        "stix2-pattern" | "yara" | "sigma" | "snort" => "Artifacts dropped",
+        "malware-type" => "Payload type",
+        _ => "Other",
+    }
+}
+
+fn default_to_ids(misp_type: &str) -> bool {
+    matches!(
+        misp_type,
+        "ip-src"
+            | "ip-dst"
+            | "domain"
+            | "hostname"
+            | "url"
+            | "md5"
+            | "sha1"
+            | "sha256"
+            | "stix2-pattern"
+            | "yara"
+            | "sigma"
+            | "snort"
+    )
+}
+
+fn node_uuid(node: &NodeModel) -> String {
+    let own = node.id.strip_prefix("shape:").unwrap_or(&node.id);
+
+    match Uuid::parse_str(own) {
+        Ok(uuid) => uuid.to_string(),
+        Err(_) => Uuid::new_v5(&CYBERWEAVER_NAMESPACE, node.id.as_bytes()).to_string(),
+    }
+}
+
+fn attribute_json(
+    uuid: String,
+    misp_type: &str,
+    value: &str,
+    node: &NodeModel,
+    timestamp: i64,
+    tags: &[String],
+) -> Map<String, Value> {
+    let meta = node.attributes.as_ref();
+    let category = meta
+        .and_then(|attributes| attributes.get(CATEGORY_KEY)?.as_str())
+        .unwrap_or_else(|| default_category(misp_type));
+    let flagged = meta
+        .and_then(|attributes| attributes.get(TO_IDS_KEY)?.as_bool())
+        .unwrap_or_else(|| default_to_ids(misp_type));
+
+    let mut attribute = Map::new();
+    attribute.insert("uuid".to_owned(), uuid.into());
+    attribute.insert("type".to_owned(), misp_type.into());
+    attribute.insert("category".to_owned(), category.into());
+    attribute.insert("value".to_owned(), value.into());
+    attribute.insert("to_ids".to_owned(), flagged.into());
+    attribute.insert("distribution".to_owned(), "5".into());
+    attribute.insert("timestamp".to_owned(), timestamp.to_string().into());
+
+    if !tags.is_empty() {
+        attribute.insert(
+            "Tag".to_owned(),
+            tags.iter().map(|name| json!({ "name": name })).collect(),
+        );
+    }
+
+    attribute
+}
+
+/// Builds an event from the case. Nodes with a single value and no outgoing
+/// relation become attributes; everything else becomes an object so its
+/// relations can be written as object references. Uuids are derived from
+/// node and edge ids, so exports are stable and re-import in place.
+fn build_event(
+    case: &CaseModel,
+    nodes: &[NodeModel],
+    edges: &[EdgeModel],
+    updated_at: &HashMap<String, i64>,
+    techniques: &HashMap<String, Vec<String>>,
+) -> Value {
+    let sources = edges
+        .iter()
+        .map(|edge| edge.source.as_str())
+        .collect::<BTreeSet<_>>();
+    let uuids = nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node_uuid(node)))
+        .collect::<HashMap<_, _>>();
+
+    let mut attributes = Vec::new();
+    let mut objects = Vec::new();
+
+    for node in nodes {
+        let uuid = uuids[node.id.as_str()].clone();
+        let timestamp = updated_at.get(&node.id).copied().unwrap_or(0);
+        let fields = fields(node);
+        let mut tags = node
+            .attributes
+            .as_ref()
+            .and_then(|attributes| attributes.get(TAGS_KEY))
+            .map(value_strings)
+            .unwrap_or_default();
+        tags.extend(techniques.get(&node.id).into_iter().flatten().cloned());
+
+        if !sources.contains(node.id.as_str()) {
+            if let Some(misp_type) = standalone_type(node, &fields) {
+                attributes.push(Value::Object(attribute_json(
+                    uuid,
+                    &misp_type,
+                    &fields[0].value,
+                    node,
+                    timestamp,
+                    &tags,
+                )));
+                continue;
+            }
+        }
+
+        let template = template(node, &fields);
+        let members = fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                let member = Uuid::new_v5(
+                    &CYBERWEAVER_NAMESPACE,
+                    format!("{}|{}|{index}", node.id, field.relation).as_bytes(),
+                );
+                let member_tags: &[String] = if index == 0 { &tags } else { &[] };
+                let mut attribute = attribute_json(
+                    member.to_string(),
+                    &field.misp_type,
+                    &field.value,
+                    node,
+                    timestamp,
+                    member_tags,
+                );
+                attribute.insert("object_relation".to_owned(), field.relation.into());
+                Value::Object(attribute)
+            })
+            .collect::<Vec<_>>();
+
+        let mut references = edges
+            .iter()
+            .filter(|edge| edge.source == node.id)
+            .filter_map(|edge| {
+                let target = uuids.get(edge.target.as_str())?;
+                let id = Uuid::parse_str(&edge.id)
+                    .unwrap_or_else(|_| Uuid::new_v5(&CYBERWEAVER_NAMESPACE, edge.id.as_bytes()));
+                let mut reference = json!({
+                    "uuid": id.to_string(),
+                    "object_uuid": uuid,
+                    "referenced_uuid": target,
+                    "relationship_type": relation_to_stix(&edge.kind),
+                    "timestamp": edge.updated_at.to_string(),
+                });
+
+                if let Some(label) = edge
+                    .label
+                    .as_deref()
+                    .filter(|label| !label.trim().is_empty())
+                {
+                    reference["comment"] = label.into();
+                }
+
+                Some(reference)
+            })
+            .collect::<Vec<_>>();
+        references.sort_by(|left, right| left["uuid"].as_str().cmp(&right["uuid"].as_str()));
+
+        let object = json!({
+            "uuid": uuid,
+            "name": template,
+            "meta-category": node
+                .attributes
+                .as_ref()
+                .and_then(|attributes| attributes.get(CATEGORY_KEY)?.as_str())
+                .unwrap_or("misc"),
+            "distribution": "5",
+            "timestamp": timestamp.to_string(),
+            "Attribute": members,
+            "ObjectReference": references,
+        });
+
+        objects.push(object);
+    }
+
+    let by_uuid = |left: &Value, right: &Value| left["uuid"].as_str().cmp(&right["uuid"].as_str());
+    attributes.sort_by(by_uuid);
+    objects.sort_by(by_uuid);
+
+    let latest = updated_at
+        .values()
+        .copied()
+        .max()
+        .unwrap_or(case.updated_at);
+
+    json!({
+        "Event": {
+            "uuid": Uuid::new_v5(&CYBERWEAVER_NAMESPACE, case.id.as_bytes()).to_string(),
+            "info": case.name,
+            "date": format_timestamp(case.created_at)[..10].to_owned(),
+            "timestamp": latest.to_string(),
+            "published": false,
+            "analysis": "1",
+            "threat_level_id": "4",
+            "distribution": "0",
+            "Attribute": attributes,
+            "Object": objects,
+        }
+    })
+}
+
+/// ATT&CK galaxy tags per node, from the techniques tagged in the case.
+async fn technique_tags(db: &DatabaseConnection) -> Result<HashMap<String, Vec<String>>, String> {
+    let tagged = attack::node_techniques(db, None)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if tagged.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let names = attack::list_techniques(db, None)
+        .await?
+        .into_iter()
+        .map(|technique| (technique.id, technique.name))
+        .collect::<HashMap<_, _>>();
+    let mut tags = HashMap::<String, Vec<String>>::new();
+
+    for tag in tagged {
+        let name = names
+            .get(&tag.technique_id)
+            .map(|name| name.rsplit(": ").next().unwrap_or(name))
+            .unwrap_or(&tag.technique_id);
+        tags.entry(tag.node_id)
+            .or_default()
+            .push(format!("{ATTACK_GALAXY}\"{name} - {}\"", tag.technique_id));
+    }
+
+    Ok(tags)
+}
+
+pub(crate) async fn export(db: &DatabaseConnection, case: &CaseModel) -> Result<Value, String> {
+    let nodes = list_nodes_internal(db).await?;
+    let edges = list_edges_internal(db).await?;
+    let updated_at = node_updated_at(db).await?;
+    let techniques = technique_tags(db).await?;
+
+    Ok(build_event(case, &nodes, &edges, &updated_at, &techniques))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{changes::ChangeOrigin, test_support::test_state};
+
+    const FIXTURE: &str = include_str!("../fixtures/misp/phishing-event.json");
+
+    #[test]
+    fn reads_attack_galaxy_tags() {
+        assert_eq!(
+            galaxy_technique(r#"misp-galaxy:mitre-attack-pattern="PowerShell - T1059.001""#),
+            Some("T1059.001".to_owned())
+        );
+        assert_eq!(galaxy_technique("tlp:amber"), None);
+        assert_eq!(
+            galaxy_technique(r#"misp-galaxy:mitre-attack-pattern="Phishing""#),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn imports_fixture_event() {
+        let state = test_state("import").await;
+        let report = import(&state, FIXTURE).await.unwrap();
+
+        assert_eq!(report.nodes, 10);
+        assert_eq!(report.edges, 4);
+        assert_eq!(report.techniques_tagged, 2);
+        assert_eq!(
+            report.skipped,
+            vec![
+                "a1f0c2d4-6e8a-4b0c-8d2e-3f4a5b6c7d05: unsupported attribute type x509-fingerprint-sha1",
+                "a1f0c2d4-6e8a-4b0c-8d2e-3f4a5b6c7d06: ip.address is not a valid IP address: not-an-address",
+                "c3f2e4d6-8a0c-4d2e-8f4a-5b6c7d8e9f03: references something that was not imported",
+            ]
+        );
+
+        let db = state.db().await.unwrap();
+        let nodes = list_nodes_internal(&db).await.unwrap();
+        let node = |uuid: &str| {
+            nodes
+                .iter()
+                .find(|node| node.id == format!("shape:{uuid}"))
+                .unwrap()
+        };
+
+        let ip = node("a1f0c2d4-6e8a-4b0c-8d2e-3f4a5b6c7d02");
+        assert_eq!(
+            ip.attributes,
+            Some(json!({
+                "address": "203.0.113.50",
+                "port": 443,
+                "mispCategory": "Network activity",
+                "toIds": true,
+            }))
+        );
+        let domain = node("a1f0c2d4-6e8a-4b0c-8d2e-3f4a5b6c7d01");
+        assert_eq!(
+            domain.attributes.as_ref().unwrap()["tags"],
+            json!(["kill-chain:delivery"])
+        );
+
+        let file = node("b2e1d3c5-7f9b-4c1d-9e3f-4a5b6c7d8e01");
+        let attributes = file.attributes.as_ref().unwrap();
+        assert_eq!(file.kind.as_deref(), Some("file"));
+        assert_eq!(attributes["name"], "invoice_0318.docm");
+        assert_eq!(
+            attributes["sha256"],
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+        assert_eq!(attributes["toIds"], true);
+        assert!(attributes.get("tags").is_none());
+
+        let process = node("b2e1d3c5-7f9b-4c1d-9e3f-4a5b6c7d8e02");
+        assert_eq!(process.attributes.as_ref().unwrap()["pid"], 4120);
+
+        let phone = node("b2e1d3c5-7f9b-4c1d-9e3f-4a5b6c7d8e04");
+        assert_eq!(phone.kind, None);
+        assert_eq!(phone.content, "phone\nphone-number: +1-555-0100");
+
+        let edges = list_edges_internal(&db).await.unwrap();
+        let kinds = edges
+            .iter()
+            .map(|edge| edge.kind.as_str())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            kinds,
+            BTreeSet::from(["connected_to", "related_to", "resolved_to", "wrote"])
+        );
+        assert!(edges
+            .iter()
+            .any(|edge| edge.label.as_deref() == Some("Beacon over HTTPS")));
+
+        let techniques = attack::node_techniques(&db, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.technique_id)
+            .collect::<Vec<_>>();
+        assert_eq!(techniques, vec!["T1566.001", "T1059.001"]);
+
+        // Importing the same event again updates in place.
+        import(&state, FIXTURE).await.unwrap();
+        assert_eq!(list_nodes_internal(&db).await.unwrap().len(), nodes.len());
+        assert_eq!(list_edges_internal(&db).await.unwrap().len(), edges.len());
+    }
+
+    #[tokio::test]
+    async fn failed_import_leaves_the_case_untouched() {
+        let state = test_state("atomic").await;
+        let plan = ImportPlan {
+            nodes: vec![NodePayload {
+                id: "shape:misp-note".to_owned(),
+                node_type: "note".to_owned(),
+                x: 0.0,
+                y: 0.0,
+                content: "phishing mail".to_owned(),
+                width: None,
+                height: None,
+                kind: None,
+                attributes: None,
+            }],
+            edges: vec![EdgePayload {
+                id: "misp-edge".to_owned(),
+                source: "shape:misp-note".to_owned(),
+                target: "shape:missing".to_owned(),
+                kind: "related_to".to_owned(),
+                label: None,
+            }],
+            techniques: vec![("shape:misp-note".to_owned(), "T1566.001".to_owned())],
+            skipped: Vec::new(),
+        };
+
+        assert!(ingest::apply(&state, plan).await.is_err());
+        let db = state.db().await.unwrap();
+        assert!(list_nodes_internal(&db).await.unwrap().is_empty());
+        assert!(crate::audit::list(&db, None, 10)
+            .await
+            .unwrap()
+            .iter()
+            .all(|entry| entry.operation == "attack.dataset.load"));
+    }
+
+    #[tokio::test]
+    async fn export_round_trips_through_import() {
+        let state = test_state("round-trip").await;
+        import(&state, FIXTURE).await.unwrap();
+        state
+            .upsert_nodes(
+                ChangeOrigin::Webview,
+                vec![NodePayload {
+                    id: "analyst-note".to_owned(),
+                    node_type: "note".to_owned(),
+                    x: 0.0,
+                    y: 0.0,
+                    content: "Check the proxy logs".to_owned(),
+                    width: None,
+                    height: None,
+                    kind: None,
+                    attributes: None,
+                }],
+            )
+            .await
+            .unwrap();
+
+        let case = state.cases.active_case().await.unwrap().unwrap();
+        let db = state.db().await.unwrap();
+        let exported = export(&db, &case).await.unwrap();
+        let event = &exported["Event"];
+        assert_eq!(event["info"], case.name);
+
+        let standalone = list(event, "Attribute")
+            .iter()
+            .map(|attribute| attribute["type"].as_str().unwrap())
+            .collect::<BTreeSet<_>>();
+        assert!(standalone.contains("comment"));
+        let process = list(event, "Object")
+            .iter()
+            .find(|object| object["uuid"] == "b2e1d3c5-7f9b-4c1d-9e3f-4a5b6c7d8e02")
+            .unwrap();
+        assert_eq!(process["name"], "process");
+        assert_eq!(list(process, "ObjectReference").len(), 1);
+        assert_eq!(export(&db, &case).await.unwrap(), exported);
+
+        let copy = test_state("round-trip-copy").await;
+        let report = import(&copy, &exported.to_string()).await.unwrap();
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+
+        let copy_db = copy.db().await.unwrap();
+        let expected = list_nodes_internal(&db)
+            .await
+            .unwrap()
+            .iter()
+            .map(|node| (format!("shape:{}", node_uuid(node)), node.kind.clone()))
+            .collect::<BTreeSet<_>>();
+        let imported = list_nodes_internal(&copy_db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|node| (node.id, node.kind))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(imported, expected);
+
+        let copy_case = copy.cases.active_case().await.unwrap().unwrap();
+        let reexported = export(&copy_db, &copy_case).await.unwrap();
+        assert_eq!(
+            list(&reexported["Event"], "Object").len(),
+            list(event, "Object").len()
+        );
+        assert_eq!(
+            list_edges_internal(&copy_db).await.unwrap().len(),
+            list_edges_internal(&db).await.unwrap().len()
+        );
+    }
+}
@@ -0,0 +1,1139 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    entities::is_domain_name,
+    ingest::{self, EntityGraph, ImportPlan, RecordBatch},
+    jobs::{JobGuard, JobStatus, JobUpdate},
+    list_nodes_internal,
+    timestamps::{format_timestamp, parse_timestamp},
+    AppState, NodeModel,
+};
+
+const JOB_KIND: &str = "network-log-import";
+
+/// Records between two progress updates.
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+/// Lines listed individually in the report; the rest are only counted.
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// Distinct entities and relations one import may hold. The log is read as a
+/// stream, but what it maps to is merged in memory and written at the end in
+/// one transaction, so sighting counts add up across the whole log. Larger
+/// imports fail before writing anything and have to be narrowed by time
+/// window or hosts.
+const MAX_GRAPH_SIZE: usize = 100_000;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NetworkLogImport {
+    pub(crate) path: String,
+    pub(crate) job_id: Option<String>,
+    /// Earliest record timestamp to import, as unix seconds or ISO 8601.
+    pub(crate) since: Option<Value>,
+    pub(crate) until: Option<Value>,
+    /// Only records with one of these addresses at either end are imported.
+    #[serde(default)]
+    pub(crate) hosts: Vec<String>,
+}
+
+/// Which records of a log make it into the case.
+#[derive(Debug, Default)]
+pub(crate) struct LogFilter {
+    since: Option<f64>,
+    until: Option<f64>,
+    hosts: HashSet<IpAddr>,
+}
+
+impl LogFilter {
+    pub(crate) fn new(request: &NetworkLogImport) -> Result<Self, String> {
+        let bound = |value: &Option<Value>, name: &str| {
+            value
+                .as_ref()
+                .map(|value| {
+                    parse_timestamp(value)
+                        .ok_or_else(|| format!("invalid {name} timestamp: {value}"))
+                })
+                .transpose()
+        };
+        let hosts = request
+            .hosts
+            .iter()
+            .map(|host| {
+                host.trim()
+                    .parse::<IpAddr>()
+                    .map_err(|_| format!("invalid host address: {host}"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            since: bound(&request.since, "since")?,
+            until: bound(&request.until, "until")?,
+            hosts,
+        })
+    }
+
+    /// Records without a timestamp only pass when no time window is set.
+    fn matches(&self, record: &LogRecord) -> bool {
+        let in_window = match record.ts {
+            Some(ts) => {
+                self.since.is_none_or(|since| ts >= since)
+                    && self.until.is_none_or(|until| ts <= until)
+            }
+            None => self.since.is_none() && self.until.is_none(),
+        };
+        let for_host = self.hosts.is_empty()
+            || [record.src, record.dst]
+                .into_iter()
+                .flatten()
+                .any(|address| self.hosts.contains(&address));
+
+        in_window && for_host
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordKind {
+    Conn,
+    Dns,
+    Http,
+    Tls,
+    Files,
+    Alert,
+}
+
+/// One log line, from whichever format, with the fields every record
+/// kind shares pulled out for filtering.
+struct LogRecord {
+    kind: RecordKind,
+    fields: Map<String, Value>,
+    ts: Option<f64>,
+    src: Option<IpAddr>,
+    dst: Option<IpAddr>,
+}
+
+/// Layout of a Zeek TSV log, from its `#` header lines.
+struct ZeekHeader {
+    separator: String,
+    set_separator: String,
+    unset: String,
+    empty: String,
+    path: String,
+    fields: Vec<String>,
+    types: Vec<String>,
+}
+
+impl Default for ZeekHeader {
+    fn default() -> Self {
+        Self {
+            separator: "\t".to_owned(),
+            set_separator: ",".to_owned(),
+            unset: "-".to_owned(),
+            empty: "(empty)".to_owned(),
+            path: String::new(),
+            fields: Vec::new(),
+            types: Vec::new(),
+        }
+    }
+}
+
+/// Decodes the `\x09` escapes Zeek writes separators with.
+fn unescape(raw: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = raw;
+
+    while let Some(start) = rest.find("\\x") {
+        let code = rest
+            .get(start + 2..start + 4)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        decoded.push_str(&rest[..start]);
+        match code {
+            Some(code) => {
+                decoded.push(char::from(code));
+                rest = &rest[start + 4..];
+            }
+            None => {
+                decoded.push_str("\\x");
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+impl ZeekHeader {
+    fn directive(&mut self, line: &str) {
+        // `#separator \x09` is the only directive not itself tab-separated.
+        if let Some(separator) = line.strip_prefix("#separator ") {
+            self.separator = unescape(separator.trim());
+            return;
+        }
+
+        let separator = self.separator.clone();
+        let (name, rest) = line.split_once(&separator).unwrap_or((line, ""));
+        let list = || {
+            rest.split(&separator)
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        match name {
+            "#set_separator" => self.set_separator = rest.to_owned(),
+            "#unset_field" => self.unset = rest.to_owned(),
+            "#empty_field" => self.empty = rest.to_owned(),
+            "#path" => self.path = rest.to_owned(),
+            "#fields" => self.fields = list(),
+            "#types" => self.types = list(),
+            _ => {}
+        }
+    }
+
+    fn row(&self, line: &str) -> Result<Map<String, Value>, String> {
+        let values = line.split(self.separator.as_str()).collect::<Vec<_>>();
+        if values.len() != self.fields.len() {
+            return Err(format!(
+                "expected {} fields, found {}",
+                self.fields.len(),
+                values.len()
+            ));
+        }
+
+        let mut fields = Map::new();
+        for (index, (name, value)) in self.fields.iter().zip(values).enumerate() {
+            if value == self.unset {
+                continue;
+            }
+
+            let container = self
+                .types
+                .get(index)
+                .is_some_and(|kind| kind.starts_with("set[") || kind.starts_with("vector["));
+            let value = if container {
+                let items = if value == self.empty {
+                    Vec::new()
+                } else {
+                    value
+                        .split(self.set_separator.as_str())
+                        .map(|item| Value::String(item.to_owned()))
+                        .collect()
+                };
+                Value::Array(items)
+            } else if value == self.empty {
+                continue;
+            } else {
+                Value::String(value.to_owned())
+            };
+            fields.insert(name.clone(), value);
+        }
+
+        Ok(fields)
+    }
+}
+
+/// Finds `path` as a literal key (Zeek's `id.orig_h`) or as nested objects
+/// (Suricata's `alert.signature`).
+fn lookup<'a>(fields: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    if let Some(value) = fields.get(path) {
+        return Some(value);
+    }
+
+    let (head, rest) = path.split_once('.')?;
+    match fields.get(head)? {
+        Value::Object(inner) => lookup(inner, rest),
+        _ => None,
+    }
+}
+
+/// Every value under the first of `keys` that is set, with lists flattened.
+fn values(fields: &Map<String, Value>, keys: &[&str]) -> Vec<String> {
+    let scalar = |value: &Value| match value {
+        Value::String(text) => Some(text.trim().to_owned()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    };
+
+    keys.iter()
+        .filter_map(|key| lookup(fields, key))
+        .map(|value| match value {
+            Value::Array(items) => items.iter().filter_map(scalar).collect(),
+            value => scalar(value).into_iter().collect::<Vec<_>>(),
+        })
+        .map(|found| {
+            found
+                .into_iter()
+                .filter(|value| !value.is_empty() && value != "-")
+                .collect::<Vec<_>>()
+        })
+        .find(|found| !found.is_empty())
+        .unwrap_or_default()
+}
+
+fn text(fields: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    values(fields, keys).into_iter().next()
+}
+
+fn number(fields: &Map<String, Value>, key: &str) -> Option<u64> {
+    text(fields, &[key]).and_then(|value| value.parse().ok())
+}
+
+fn address(fields: &Map<String, Value>, keys: &[&str]) -> Option<IpAddr> {
+    text(fields, keys).and_then(|value| value.parse().ok())
+}
+
+/// Zeek writes unix seconds, as text in TSV logs; Suricata writes ISO 8601
+/// with a `+0000` offset.
+fn timestamp(fields: &Map<String, Value>) -> Option<f64> {
+    ["ts", "timestamp"]
+        .into_iter()
+        .filter_map(|key| fields.get(key))
+        .find_map(|value| {
+            parse_timestamp(value)
+                .or_else(|| value.as_str().and_then(|raw| raw.trim().parse().ok()))
+        })
+}
+
+/// Zeek JSON logs only carry `_path` when the writer is told to add it, so
+/// the log is otherwise recognised by its fields.
+fn zeek_path(fields: &Map<String, Value>) -> Option<&str> {
+    if let Some(path) = fields.get("_path").and_then(Value::as_str) {
+        return Some(path);
+    }
+
+    let has = |key: &str| fields.contains_key(key);
+    if has("fuid") && (has("tx_hosts") || has("mime_type") || has("seen_bytes")) {
+        Some("files")
+    } else if has("query") && (has("qtype") || has("rcode") || has("answers")) {
+        Some("dns")
+    } else if has("method") || has("status_code") {
+        Some("http")
+    } else if has("server_name") || has("cipher") {
+        Some("ssl")
+    } else if has("conn_state") || has("orig_bytes") {
+        Some("conn")
+    } else {
+        None
+    }
+}
+
+enum Source {
+    Supported(RecordKind),
+    /// A log or event type with nothing to map, e.g. `zeek weird`.
+    Unsupported(String),
+}
+
+fn classify(fields: &Map<String, Value>, tsv_path: Option<&str>) -> Result<Source, String> {
+    let kind = match fields.get("event_type") {
+        Some(event_type) => match event_type.as_str().unwrap_or_default() {
+            "flow" => RecordKind::Conn,
+            "dns" => RecordKind::Dns,
+            "http" => RecordKind::Http,
+            "tls" => RecordKind::Tls,
+            "fileinfo" => RecordKind::Files,
+            "alert" => RecordKind::Alert,
+            other => return Ok(Source::Unsupported(format!("suricata {other}"))),
+        },
+        None => match tsv_path.or_else(|| zeek_path(fields)) {
+            Some("conn") => RecordKind::Conn,
+            Some("dns") => RecordKind::Dns,
+            Some("http") => RecordKind::Http,
+            Some("ssl") => RecordKind::Tls,
+            Some("files") => RecordKind::Files,
+            Some(other) => return Ok(Source::Unsupported(format!("zeek {other}"))),
+            None => return Err("not a Zeek or Suricata record".to_owned()),
+        },
+    };
+
+    Ok(Source::Supported(kind))
+}
+
+/// Parses one record line, counting it under `unsupported` when its type has
+/// nothing to map.
+fn parse_line(
+    line: &str,
+    header: Option<&ZeekHeader>,
+    unsupported: &mut BTreeMap<String, usize>,
+) -> Result<Option<LogRecord>, String> {
+    let (fields, tsv_path) = if line.starts_with('{') {
+        let fields = serde_json::from_str::<Map<String, Value>>(line)
+            .map_err(|err| format!("invalid JSON: {err}"))?;
+        (fields, None)
+    } else {
+        let header = header
+            .filter(|header| !header.fields.is_empty())
+            .ok_or_else(|| "record before a Zeek #fields header".to_owned())?;
+        let path = Some(header.path.as_str()).filter(|path| !path.is_empty());
+        (header.row(line)?, path)
+    };
+
+    let kind = match classify(&fields, tsv_path)? {
+        Source::Supported(kind) => kind,
+        Source::Unsupported(name) => {
+            *unsupported.entry(name).or_default() += 1;
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(LogRecord {
+        kind,
+        ts: timestamp(&fields),
+        src: address(&fields, &["id.orig_h", "src_ip", "tx_hosts"]),
+        dst: address(&fields, &["id.resp_h", "dest_ip", "rx_hosts"]),
+        fields,
+    }))
+}
+
+fn ip(batch: &mut RecordBatch, address: IpAddr) -> Result<String, String> {
+    let mut attributes = Map::new();
+    attributes.insert("address".to_owned(), address.to_string().into());
+    batch.entity("ip", attributes, address.to_string())
+}
+
+/// Names that are not domains, such as a `Host: 10.0.0.1:8080` header, map
+/// to no node.
+fn domain(batch: &mut RecordBatch, name: &str) -> Result<Option<String>, String> {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let name = match name.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host.to_owned(),
+        _ => name,
+    };
+    if name.parse::<IpAddr>().is_ok() || !is_domain_name(&name) {
+        return Ok(None);
+    }
+
+    let mut attributes = Map::new();
+    attributes.insert("name".to_owned(), name.clone().into());
+    batch.entity("domain", attributes, name).map(Some)
+}
+
+fn endpoints(record: &LogRecord) -> Result<(IpAddr, IpAddr), String> {
+    Ok((
+        record.src.ok_or("missing source address")?,
+        record.dst.ok_or("missing destination address")?,
+    ))
+}
+
+/// Counts one sighting at the record's time; merging adds the counts up and
+/// widens the seen window.
+fn sighting(attributes: &mut Map<String, Value>, record: &LogRecord) {
+    attributes.insert("count".to_owned(), 1.into());
+    if let Some(ts) = record.ts {
+        let seen = format_timestamp(ts.floor() as i64);
+        attributes.insert("firstSeen".to_owned(), seen.clone().into());
+        attributes.insert("lastSeen".to_owned(), seen.into());
+    }
+}
+
+fn insert(attributes: &mut Map<String, Value>, key: &str, value: Option<impl Into<Value>>) {
+    if let Some(value) = value {
+        attributes.insert(key.to_owned(), value.into());
+    }
+}
+
+/// Zeek `conn` and Suricata `flow` records, aggregated per protocol, client,
+/// server and server port.
+fn conn(batch: &mut RecordBatch, record: &LogRecord) -> Result<(), String> {
+    let fields = &record.fields;
+    let (src, dst) = endpoints(record)?;
+    let proto = text(fields, &["proto"]).map(|proto| proto.to_ascii_lowercase());
+    let port = number(fields, "id.resp_p").or_else(|| number(fields, "dest_port"));
+    let bytes = [
+        "orig_bytes",
+        "resp_bytes",
+        "flow.bytes_toserver",
+        "flow.bytes_toclient",
+    ]
+    .into_iter()
+    .filter_map(|key| number(fields, key))
+    .reduce(|left, right| left + right);
+
+    let mut attributes = Map::new();
+    insert(&mut attributes, "proto", proto.clone());
+    attributes.insert("srcIp".to_owned(), src.to_string().into());
+    attributes.insert("dstIp".to_owned(), dst.to_string().into());
+    insert(&mut attributes, "dstPort", port);
+    insert(
+        &mut attributes,
+        "service",
+        text(fields, &["service", "app_proto"]),
+    );
+    insert(&mut attributes, "bytes", bytes);
+    sighting(&mut attributes, record);
+
+    let mut content = format!("{src} → {dst}");
+    if let Some(port) = port {
+        content.push_str(&format!(":{port}"));
+    }
+    if let Some(proto) = proto {
+        content.push_str(&format!("/{proto}"));
+    }
+
+    let connection = batch.entity("connection", attributes, content)?;
+    let src = ip(batch, src)?;
+    let dst = ip(batch, dst)?;
+    batch.relate(&src, "connected_to", &connection, None);
+    batch.relate(&connection, "connected_to", &dst, None);
+
+    Ok(())
+}
+
+fn dns(batch: &mut RecordBatch, record: &LogRecord) -> Result<(), String> {
+    let fields = &record.fields;
+    // Suricata logs answers in the resolver's direction.
+    let client = if lookup(fields, "dns.type").and_then(Value::as_str) == Some("answer") {
+        record.dst
+    } else {
+        record.src
+    }
+    .ok_or("missing client address")?;
+    let query = text(fields, &["query", "dns.rrname"])
+        .or_else(|| {
+            lookup(fields, "dns.queries")?
+                .get(0)?
+                .get("rrname")?
+                .as_str()
+                .map(str::to_owned)
+        })
+        .ok_or("missing query")?;
+    let Some(domain) = domain(batch, &query)? else {
+        return Err(format!("query is not a domain: {query}"));
+    };
+
+    let client = ip(batch, client)?;
+    batch.relate(&client, "related_to", &domain, Some("DNS query".to_owned()));
+
+    // Suricata lists answers as objects, or in older versions one per event.
+    let mut answers = values(fields, &["answers", "dns.rdata", "dns.grouped.A"]);
+    if let Some(Value::Array(items)) = lookup(fields, "dns.answers") {
+        answers.extend(
+            items
+                .iter()
+                .filter_map(|item| item.get("rdata")?.as_str().map(str::to_owned)),
+        );
+    }
+    for answer in answers {
+        if let Ok(address) = answer.parse::<IpAddr>() {
+            let address = ip(batch, address)?;
+            batch.relate(&domain, "resolved_to", &address, None);
+        }
+    }
+
+    Ok(())
+}
+
+/// The domain a client asked for by name, served from the record's server
+/// address; without a name the client links to the address itself.
+fn named_server(
+    batch: &mut RecordBatch,
+    record: &LogRecord,
+    name: Option<String>,
+    label: String,
+) -> Result<Option<String>, String> {
+    let (client, server) = endpoints(record)?;
+    let client = ip(batch, client)?;
+    let server = ip(batch, server)?;
+
+    match name.map(|name| domain(batch, &name)).transpose()?.flatten() {
+        Some(domain) => {
+            batch.relate(&client, "connected_to", &domain, Some(label));
+            batch.relate(&domain, "related_to", &server, Some("served by".to_owned()));
+            Ok(Some(domain))
+        }
+        None => {
+            batch.relate(&client, "connected_to", &server, Some(label));
+            Ok(None)
+        }
+    }
+}
+
+fn http(batch: &mut RecordBatch, record: &LogRecord) -> Result<(), String> {
+    let fields = &record.fields;
+    let label = match text(fields, &["method", "http.http_method"]) {
+        Some(method) => format!("HTTP {method}"),
+        None => "HTTP".to_owned(),
+    };
+    named_server(
+        batch,
+        record,
+        text(fields, &["host", "http.hostname"]),
+        label,
+    )?;
+
+    Ok(())
+}
+
+fn tls(batch: &mut RecordBatch, record: &LogRecord) -> Result<(), String> {
+    let fields = &record.fields;
+    let domain = named_server(
+        batch,
+        record,
+        text(fields, &["server_name", "tls.sni"]),
+        "TLS".to_owned(),
+    )?;
+
+    let fingerprint = text(fields, &["cert_chain_fps", "tls.fingerprint"])
+        .map(|fingerprint| fingerprint.to_ascii_lowercase());
+    let subject = text(fields, &["subject", "tls.subject"]);
+    let content = match (&subject, &fingerprint) {
+        (Some(subject), _) => subject.clone(),
+        (None, Some(fingerprint)) => fingerprint.clone(),
+        (None, None) => return Ok(()),
+    };
+
+    let mut attributes = Map::new();
+    insert(&mut attributes, "fingerprint", fingerprint);
+    insert(&mut attributes, "subject", subject);
+    insert(
+        &mut attributes,
+        "issuer",
+        text(fields, &["issuer", "tls.issuerdn"]),
+    );
+    let certificate = batch.entity("certificate", attributes, content)?;
+
+    let server = ip(batch, endpoints(record)?.1)?;
+    let label = Some("TLS certificate".to_owned());
+    batch.relate(&server, "related_to", &certificate, label.clone());
+    if let Some(domain) = domain {
+        batch.relate(&domain, "related_to", &certificate, label);
+    }
+
+    Ok(())
+}
+
+/// Zeek names the receiving hosts; Suricata logs a file in the direction it
+/// travelled, so the receiver is the destination.
+fn files(batch: &mut RecordBatch, record: &LogRecord) -> Result<(), String> {
+    let fields = &record.fields;
+    let receiver = if fields.contains_key("event_type") {
+        address(fields, &["dest_ip"])
+    } else {
+        address(fields, &["rx_hosts", "id.orig_h"])
+    }
+    .ok_or("missing receiving host")?;
+
+    let name = text(fields, &["filename", "fileinfo.filename"]);
+    let mut attributes = Map::new();
+    for algorithm in ["md5", "sha1", "sha256"] {
+        let digest = text(fields, &[algorithm, &format!("fileinfo.{algorithm}")]);
+        insert(
+            &mut attributes,
+            algorithm,
+            digest.map(|digest| digest.to_ascii_lowercase()),
+        );
+    }
+    if attributes.is_empty() {
+        return Err(format!(
+            "file {} has no hashes",
+            name.as_deref().unwrap_or("without a name")
+        ));
+    }
+
+    let name = name.map(|name| name.rsplit('/').next().unwrap_or(&name).to_owned());
+    let size = ["total_bytes", "seen_bytes", "fileinfo.size"]
+        .into_iter()
+        .find_map(|key| number(fields, key));
+    insert(&mut attributes, "name", name.clone());
+    insert(&mut attributes, "size", size);
+    insert(
+        &mut attributes,
+        "mimeType",
+        text(fields, &["mime_type", "fileinfo.magic"]),
+    );
+    let content = name
+        .or_else(|| {
+            attributes
+                .get("sha256")
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+        })
+        .unwrap_or_else(|| "file".to_owned());
+
+    let file = batch.entity("file", attributes, content)?;
+    let receiver = ip(batch, receiver)?;
+    let label = text(fields, &["source", "app_proto"]).map(|source| source.to_ascii_uppercase());
+    batch.relate(&receiver, "downloaded", &file, label);
+
+    Ok(())
+}
+
+fn alert(batch: &mut RecordBatch, record: &LogRecord) -> Result<(), String> {
+    let fields = &record.fields;
+    let signature = text(fields, &["alert.signature"]).ok_or("missing alert.signature")?;
+
+    let mut attributes = Map::new();
+    attributes.insert("signature".to_owned(), signature.clone().into());
+    insert(
+        &mut attributes,
+        "signatureId",
+        number(fields, "alert.signature_id"),
+    );
+    insert(
+        &mut attributes,
+        "severity",
+        number(fields, "alert.severity"),
+    );
+    insert(
+        &mut attributes,
+        "category",
+        text(fields, &["alert.category"]),
+    );
+    sighting(&mut attributes, record);
+    let alert = batch.entity("alert", attributes, signature)?;
+
+    let (src, dst) = endpoints(record)?;
+    let src = ip(batch, src)?;
+    let dst = ip(batch, dst)?;
+    batch.relate(&alert, "related_to", &src, Some("source".to_owned()));
+    batch.relate(&alert, "related_to", &dst, Some("destination".to_owned()));
+
+    Ok(())
+}
+
+/// How far a scan has read.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ScanProgress {
+    processed: u64,
+    bytes_read: u64,
+}
+
+impl ScanProgress {
+    fn update(self, job_id: &str, status: JobStatus, total_bytes: Option<u64>) -> JobUpdate {
+        JobUpdate {
+            job_id: job_id.to_owned(),
+            kind: JOB_KIND,
+            status,
+            processed: self.processed,
+            bytes_read: self.bytes_read,
+            total_bytes,
+            report: None,
+            error: None,
+        }
+    }
+}
+
+/// Reads a Zeek or Suricata log line by line into a plan. Formats may be
+/// mixed, e.g. several Zeek logs concatenated with their headers. Lines that
+/// fail to parse or map are reported and skipped. Returns `None` once
+/// `cancelled` is set, and fails once the plan outgrows `max_graph_size`.
+fn scan(
+    mut reader: impl BufRead,
+    filter: &LogFilter,
+    existing: Vec<NodeModel>,
+    max_graph_size: usize,
+    cancelled: &AtomicBool,
+    progress: &mut ScanProgress,
+    mut on_progress: impl FnMut(ScanProgress),
+) -> Result<Option<ImportPlan>, String> {
+    let mut graph = EntityGraph::new(existing);
+    let mut header: Option<ZeekHeader> = None;
+    let mut skipped = Vec::new();
+    let mut failed = 0;
+    let mut unsupported = BTreeMap::<String, usize>::new();
+    let mut buffer = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        buffer.clear();
+        let read = reader
+            .read_until(b'\n', &mut buffer)
+            .map_err(|err| format!("failed to read log: {err}"))?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+        progress.bytes_read += read as u64;
+
+        let line = String::from_utf8_lossy(&buffer);
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            if line.starts_with("#separator") {
+                header = Some(ZeekHeader::default());
+            }
+            header.get_or_insert_with(Default::default).directive(line);
+            continue;
+        }
+
+        progress.processed += 1;
+        if progress.processed.is_multiple_of(PROGRESS_INTERVAL) {
+            on_progress(*progress);
+        }
+
+        let outcome = match parse_line(line, header.as_ref(), &mut unsupported) {
+            Ok(Some(record)) if filter.matches(&record) => graph.record(|batch| {
+                let map = match record.kind {
+                    RecordKind::Conn => conn,
+                    RecordKind::Dns => dns,
+                    RecordKind::Http => http,
+                    RecordKind::Tls => tls,
+                    RecordKind::Files => files,
+                    RecordKind::Alert => alert,
+                };
+                map(batch, &record)
+            }),
+            Ok(_) => Ok(()),
+            Err(reason) => Err(reason),
+        };
+        if let Err(reason) = outcome {
+            failed += 1;
+            if skipped.len() < MAX_REPORTED_ERRORS {
+                skipped.push(format!("line {line_number}: {reason}"));
+            }
+        }
+        if graph.len() > max_graph_size {
+            return Err(format!(
+                "line {line_number}: the log maps to more than {max_graph_size} entities and \
+                 relations; narrow the import by time window or hosts"
+            ));
+        }
+    }
+
+    if failed > skipped.len() {
+        skipped.push(format!("{} more lines skipped", failed - skipped.len()));
+    }
+    if !unsupported.is_empty() {
+        let total = unsupported.values().sum::<usize>();
+        let kinds = unsupported.into_keys().collect::<Vec<_>>().join(", ");
+        skipped.push(format!("{total} records of unsupported types: {kinds}"));
+    }
+
+    Ok(Some(graph.into_plan(skipped)))
+}
+
+/// Reads the log off the async runtime and imports what it maps to into the
+/// case that was open when the job started. Returns the job's last update.
+async fn run(
+    state: &AppState,
+    guard: JobGuard,
+    file: File,
+    filter: LogFilter,
+    emit: Arc<dyn Fn(&JobUpdate) + Send + Sync>,
+) -> JobUpdate {
+    let job_id = guard.job_id().to_owned();
+    let total_bytes = file.metadata().ok().map(|metadata| metadata.len());
+    let case_id = state.cases.active_case_id().await;
+    let finish = |progress: ScanProgress, outcome: Result<_, String>| {
+        let status = match &outcome {
+            Ok(Some(_)) => JobStatus::Completed,
+            Ok(None) => JobStatus::Cancelled,
+            Err(_) => JobStatus::Failed,
+        };
+        let mut update = progress.update(&job_id, status, total_bytes);
+        match outcome {
+            Ok(report) => update.report = report,
+            Err(err) => update.error = Some(err),
+        }
+        update
+    };
+
+    let existing = match state.db().await {
+        Ok(db) => list_nodes_internal(&db).await,
+        Err(err) => Err(err),
+    };
+    let existing = match existing {
+        Ok(existing) => existing,
+        Err(err) => return finish(ScanProgress::default(), Err(err)),
+    };
+
+    let cancelled = guard.cancelled();
+    let scan_job_id = job_id.clone();
+    let scanned = tauri::async_runtime::spawn_blocking(move || {
+        let mut progress = ScanProgress::default();
+        let plan = scan(
+            BufReader::new(file),
+            &filter,
+            existing,
+            MAX_GRAPH_SIZE,
+            &cancelled,
+            &mut progress,
+            |progress| emit(&progress.update(&scan_job_id, JobStatus::Running, total_bytes)),
+        );
+        (progress, plan)
+    })
+    .await;
+
+    let (progress, plan) = match scanned {
+        Ok((progress, Ok(Some(plan)))) => (progress, plan),
+        Ok((progress, Ok(None))) => return finish(progress, Ok(None)),
+        Ok((progress, Err(err))) => return finish(progress, Err(err)),
+        Err(err) => return finish(ScanProgress::default(), Err(err.to_string())),
+    };
+    if guard.cancelled().load(Ordering::Relaxed) {
+        return finish(progress, Ok(None));
+    }
+    if state.cases.active_case_id().await != case_id {
+        return finish(
+            progress,
+            Err("the open case changed while the log was read".to_owned()),
+        );
+    }
+
+    finish(progress, ingest::apply(state, plan).await.map(Some))
+}
+
+/// Starts importing a Zeek or Suricata log in the background and returns the
+/// job id. `emit` receives progress updates and the final one.
+pub(crate) fn start(
+    state: AppState,
+    request: NetworkLogImport,
+    emit: impl Fn(&JobUpdate) + Send + Sync + 'static,
+) -> Result<String, String> {
+    let filter = LogFilter::new(&request)?;
+    let file = File::open(&request.path)
+        .map_err(|err| format!("failed to open log {}: {err}", request.path))?;
+    let job_id = request
+        .job_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let guard = state.jobs.start(&job_id)?;
+    let emit: Arc<dyn Fn(&JobUpdate) + Send + Sync> = Arc::new(emit);
+
+    tauri::async_runtime::spawn(async move {
+        let update = run(&state, guard, file, filter, emit.clone()).await;
+        emit(&update);
+    });
+
+    Ok(job_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{list_edges_internal, test_support::test_state};
+    use serde_json::json;
+    use std::{
+        collections::{BTreeSet, HashMap},
+        path::Path,
+    };
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/netlogs");
+
+    fn request(file: &str, filter: Value) -> NetworkLogImport {
+        let mut request = json!({ "path": Path::new(FIXTURES).join(file) });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(filter.as_object().unwrap().clone());
+        serde_json::from_value(request).unwrap()
+    }
+
+    async fn import(state: &AppState, request: NetworkLogImport) -> JobUpdate {
+        let guard = state.jobs.start("test").unwrap();
+        let filter = LogFilter::new(&request).unwrap();
+        let file = File::open(&request.path).unwrap();
+        run(state, guard, file, filter, Arc::new(|_: &JobUpdate| {})).await
+    }
+
+    fn attributes<'a>(nodes: &'a [NodeModel], kind: &str) -> Vec<&'a Value> {
+        nodes
+            .iter()
+            .filter(|node| node.kind.as_deref() == Some(kind))
+            .map(|node| node.attributes.as_ref().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn imports_zeek_tsv_and_json_logs() {
+        let state = test_state("zeek").await;
+        let update = import(&state, request("zeek.log", json!({}))).await;
+
+        assert_eq!(update.status, JobStatus::Completed);
+        assert_eq!(update.processed, 7);
+        assert_eq!(update.total_bytes, Some(update.bytes_read));
+        let report = update.report.unwrap();
+        assert_eq!(
+            report.skipped,
+            vec![
+                "line 12: expected 13 fields, found 4",
+                "line 23: query is not a domain: wpad",
+                "1 records of unsupported types: zeek weird",
+            ]
+        );
+
+        let update = import(&state, request("zeek.json", json!({}))).await;
+        assert_eq!(
+            update.report.unwrap().skipped,
+            vec!["line 4: file index.html has no hashes"]
+        );
+
+        let db = state.db().await.unwrap();
+        let nodes = list_nodes_internal(&db).await.unwrap();
+        let connections = attributes(&nodes, "connection");
+        assert_eq!(connections.len(), 2);
+        let tls = connections
+            .iter()
+            .find(|attributes| attributes["dstPort"] == 8443)
+            .unwrap();
+        assert_eq!(tls["count"], 2);
+        assert_eq!(tls["bytes"], 9400);
+        assert_eq!(tls["firstSeen"], "2024-03-18T09:30:00Z");
+        assert_eq!(tls["lastSeen"], "2024-03-18T09:31:00Z");
+        assert_eq!(attributes(&nodes, "domain").len(), 1);
+        assert_eq!(
+            attributes(&nodes, "certificate")[0]["issuer"],
+            "CN=Update Services CA"
+        );
+        assert_eq!(attributes(&nodes, "file")[0]["size"], 48128);
+
+        let names = nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.content.as_str()))
+            .collect::<HashMap<_, _>>();
+        let edges = list_edges_internal(&db).await.unwrap();
+        let relations = edges
+            .iter()
+            .map(|edge| {
+                (
+                    names[edge.source.as_str()],
+                    edge.kind.as_str(),
+                    names[edge.target.as_str()],
+                )
+            })
+            .collect::<BTreeSet<_>>();
+        for relation in [
+            ("10.0.0.5", "related_to", "cdn-update.example"),
+            ("cdn-update.example", "resolved_to", "203.0.113.50"),
+            (
+                "10.0.0.5",
+                "connected_to",
+                "10.0.0.5 → 203.0.113.50:8443/tcp",
+            ),
+            (
+                "10.0.0.5 → 203.0.113.50:8443/tcp",
+                "connected_to",
+                "203.0.113.50",
+            ),
+            ("cdn-update.example", "related_to", "198.51.100.23"),
+            ("cdn-update.example", "related_to", "CN=cdn-update.example"),
+            ("203.0.113.50", "related_to", "CN=cdn-update.example"),
+            ("10.0.0.5", "downloaded", "updater.exe"),
+        ] {
+            assert!(relations.contains(&relation), "missing {relation:?}");
+        }
+        let client = edges
+            .iter()
+            .find(|edge| {
+                names[edge.source.as_str()] == "10.0.0.5"
+                    && names[edge.target.as_str()] == "cdn-update.example"
+                    && edge.kind == "connected_to"
+            })
+            .unwrap();
+        assert_eq!(client.label.as_deref(), Some("HTTP GET, TLS"));
+    }
+
+    #[tokio::test]
+    async fn filters_suricata_records_and_keeps_counts_on_reimport() {
+        let state = test_state("suricata").await;
+        let filtered = request(
+            "eve.json",
+            json!({ "hosts": ["10.0.0.5"], "until": "2024-03-18T09:31:10Z" }),
+        );
+        let update = import(&state, filtered).await;
+
+        assert_eq!(update.status, JobStatus::Completed);
+        let report = update.report.unwrap();
+        assert_eq!(report.skipped.len(), 2);
+        assert!(report.skipped[0].starts_with("line 7: invalid JSON"));
+        assert_eq!(
+            report.skipped[1],
+            "1 records of unsupported types: suricata stats"
+        );
+
+        let db = state.db().await.unwrap();
+        let nodes = list_nodes_internal(&db).await.unwrap();
+        assert_eq!(attributes(&nodes, "alert")[0]["count"], 1);
+        assert!(!nodes.iter().any(|node| node.content == "10.0.0.9"));
+        let certificate = attributes(&nodes, "certificate")[0];
+        assert_eq!(
+            certificate["fingerprint"],
+            "a1:b2:c3:d4:e5:f6:07:18:29:3a:4b:5c:6d:7e:8f:90:11:22:33:44"
+        );
+        assert_eq!(attributes(&nodes, "file")[0]["name"], "updater.exe");
+
+        for _ in 0..2 {
+            import(&state, request("eve.json", json!({}))).await;
+            let nodes = list_nodes_internal(&db).await.unwrap();
+            let alert = attributes(&nodes, "alert")[0];
+            assert_eq!(alert["count"], 3);
+            assert_eq!(alert["signatureId"], 2027865);
+            assert_eq!(alert["lastSeen"], "2024-03-18T09:45:00Z");
+        }
+
+        assert!(LogFilter::new(&request("eve.json", json!({ "since": "yesterday" }))).is_err());
+        assert!(LogFilter::new(&request("eve.json", json!({ "hosts": ["ws-42"] }))).is_err());
+    }
+
+    #[tokio::test]
+    async fn cancelled_jobs_import_nothing() {
+        let state = test_state("cancel").await;
+        let request = request("eve.json", json!({}));
+        let guard = state.jobs.start("job-1").unwrap();
+
+        assert!(state.jobs.start("job-1").is_err());
+        assert!(state.jobs.cancel("job-2").is_err());
+        state.jobs.cancel("job-1").unwrap();
+
+        let file = File::open(&request.path).unwrap();
+        let update = run(
+            &state,
+            guard,
+            file,
+            LogFilter::default(),
+            Arc::new(|_: &JobUpdate| {}),
+        )
+        .await;
+        assert_eq!(update.status, JobStatus::Cancelled);
+        assert!(update.report.is_none());
+
+        let db = state.db().await.unwrap();
+        assert!(list_nodes_internal(&db).await.unwrap().is_empty());
+        // The guard is gone, so the id is free again.
+        assert!(state.jobs.start("job-1").is_ok());
+    }
+
+    #[test]
+    fn refuses_logs_that_outgrow_the_graph_limit() {
+        let raw = std::fs::read(Path::new(FIXTURES).join("eve.json")).unwrap();
+        let mut progress = ScanProgress::default();
+        let err = scan(
+            raw.as_slice(),
+            &LogFilter::default(),
+            Vec::new(),
+            3,
+            &AtomicBool::new(false),
+            &mut progress,
+            |_| {},
+        )
+        .unwrap_err();
+        assert!(err.contains("more than 3 entities and relations"), "{err}");
+
+        let mut progress = ScanProgress::default();
+        assert!(scan(
+            raw.as_slice(),
+            &LogFilter::default(),
+            Vec::new(),
+            MAX_GRAPH_SIZE,
+            &AtomicBool::new(false),
+            &mut progress,
+            |_| {},
+        )
+        .unwrap()
+        .is_some());
+    }
+}
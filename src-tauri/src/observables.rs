@@ -0,0 +1,397 @@
+use regex::Regex;
+use sea_orm::{ConnectionTrait, DatabaseBackend, DbErr, QueryResult, Statement};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    net::{Ipv4Addr, Ipv6Addr},
+    sync::LazyLock,
+};
+
+use crate::{entities::is_domain_name, NodeModel, NODE_COLUMNS};
+
+static DEFANG_SCHEME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bh(?:xx|\*\*)p(s?)\b").unwrap());
+static DEFANG_DOT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\s?(?:\[\.\]|\(\.\)|\{\.\}|\[dot\]|\(dot\))\s?").unwrap());
+static DEFANG_AT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\s?(?:\[@\]|\(@\)|\[at\]|\(at\))\s?").unwrap());
+static DEFANG_COLON: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[:\]|\[://\]").unwrap());
+
+static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\b(?:https?|ftp)://[^\s<>"'`]+"#).unwrap());
+static EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,63}\b").unwrap()
+});
+static IPV4: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}\b").unwrap());
+static IPV6: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:[0-9a-f]{1,4}|:)(?::[0-9a-f]{0,4}){2,7}(?:%[0-9a-z]+)?").unwrap()
+});
+static DOMAIN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:[a-z0-9](?:[a-z0-9_-]{0,61}[a-z0-9])?\.)+[a-z]{2,63}\b").unwrap()
+});
+static SHA256: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[a-fA-F0-9]{64}\b").unwrap());
+static SHA1: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[a-fA-F0-9]{40}\b").unwrap());
+static MD5: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\b[a-fA-F0-9]{32}\b").unwrap());
+static CVE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)\bCVE-\d{4}-\d{4,}\b").unwrap());
+static WINDOWS_PATH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(?:\b[a-z]:|%[a-z_]+%|\\\\[a-z0-9._-]+)\\[^\s"'<>|*?]*"#).unwrap()
+});
+static REGISTRY_KEY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)\b(?:HKEY_LOCAL_MACHINE|HKEY_CURRENT_USER|HKEY_USERS|HKEY_CLASSES_ROOT|HKEY_CURRENT_CONFIG|HKLM|HKCU|HKU|HKCR|HKCC)\\[^\s"'<>|]*"#,
+    )
+    .unwrap()
+});
+
+/// Extensions that look like top-level domains but almost always name files
+/// in pasted log lines (`svchost.exe`, `invoice.docx`).
+const FILE_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "sys", "bat", "cmd", "ps", "vbs", "js", "jse", "wsf", "hta", "scr", "lnk", "msi",
+    "jar", "py", "sh", "doc", "docx", "docm", "xls", "xlsx", "xlsm", "ppt", "pptx", "pdf", "rtf",
+    "txt", "log", "tmp", "dat", "ini", "cfg", "zip", "rar", "gz", "tar", "iso", "img", "png",
+    "jpg", "jpeg", "gif", "bmp", "json", "xml", "html", "htm", "csv", "evtx", "pcap", "db",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Observable {
+    pub(crate) kind: String,
+    pub(crate) value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ObservableModel {
+    pub(crate) node_id: String,
+    pub(crate) kind: String,
+    pub(crate) value: String,
+}
+
+impl ObservableModel {
+    fn from_row(row: QueryResult) -> Self {
+        Self {
+            node_id: row.try_get("", "node_id").unwrap_or_default(),
+            kind: row.try_get("", "kind").unwrap_or_default(),
+            value: row.try_get("", "value").unwrap_or_default(),
+        }
+    }
+}
+
+/// Restores common defanged notations (`hxxp://`, `1[.]2[.]3[.]4`,
+/// `user[at]example[.]com`) so indicators can be matched.
+pub(crate) fn refang(text: &str) -> String {
+    let text = DEFANG_SCHEME.replace_all(text, "http$1");
+    let text = DEFANG_DOT.replace_all(&text, ".");
+    let text = DEFANG_AT.replace_all(&text, "@");
+    DEFANG_COLON
+        .replace_all(
+            &text,
+            |caps: &regex::Captures| {
+                if &caps[0] == "[://]" {
+                    "://"
+                } else {
+                    ":"
+                }
+            },
+        )
+        .into_owned()
+}
+
+fn trim_trailing_punctuation(value: &str) -> &str {
+    value.trim_end_matches(['.', ',', ';', ':', ')', ']', '}', '>', '!', '?', '\''])
+}
+
+fn is_file_name_like(domain: &str) -> bool {
+    domain
+        .rsplit('.')
+        .next()
+        .is_some_and(|tld| FILE_EXTENSIONS.contains(&tld.to_ascii_lowercase().as_str()))
+}
+
+/// Pulls indicators of compromise out of free-form text.
+pub(crate) fn extract(text: &str) -> Vec<Observable> {
+    let text = refang(text);
+    let mut found = BTreeSet::new();
+    let mut push = |kind: &str, value: String| {
+        found.insert(Observable {
+            kind: kind.to_owned(),
+            value,
+        });
+    };
+
+    for m in URL.find_iter(&text) {
+        push("url", trim_trailing_punctuation(m.as_str()).to_owned());
+    }
+
+    for m in EMAIL.find_iter(&text) {
+        push("email", m.as_str().to_ascii_lowercase());
+    }
+
+    for m in IPV4.find_iter(&text) {
+        if let Ok(address) = m.as_str().parse::<Ipv4Addr>() {
+            push("ipv4", address.to_string());
+        }
+    }
+
+    for m in IPV6.find_iter(&text) {
+        let candidate = m.as_str().split('%').next().unwrap_or_default();
+
+        if let Ok(address) = candidate.parse::<Ipv6Addr>() {
+            push("ipv6", address.to_string());
+        }
+    }
+
+    for m in DOMAIN.find_iter(&text) {
+        let part_of_email = text[..m.start()].ends_with('@') || text[m.end()..].starts_with('@');
+        let domain = m.as_str().to_ascii_lowercase();
+
+        if !part_of_email && !is_file_name_like(&domain) && is_domain_name(&domain) {
+            push("domain", domain);
+        }
+    }
+
+    for (kind, pattern) in [("sha256", &SHA256), ("sha1", &SHA1), ("md5", &MD5)] {
+        for m in pattern.find_iter(&text) {
+            push(kind, m.as_str().to_ascii_lowercase());
+        }
+    }
+
+    for m in CVE.find_iter(&text) {
+        push("cve", m.as_str().to_ascii_uppercase());
+    }
+
+    for m in WINDOWS_PATH.find_iter(&text) {
+        push(
+            "file_path",
+            trim_trailing_punctuation(m.as_str()).to_owned(),
+        );
+    }
+
+    for m in REGISTRY_KEY.find_iter(&text) {
+        push(
+            "registry_key",
+            trim_trailing_punctuation(m.as_str())
+                .trim_end_matches('\\')
+                .to_owned(),
+        );
+    }
+
+    found.into_iter().collect()
+}
+
+/// Canonical form of a user-supplied indicator, matching what [`extract`]
+/// stores. Falls back to the trimmed, refanged input.
+pub(crate) fn normalize_indicator(raw: &str) -> String {
+    let refanged = refang(raw.trim());
+
+    match extract(&refanged).into_iter().next() {
+        Some(observable) if observable.value.len() == refanged.len() => observable.value,
+        _ => refanged,
+    }
+}
+
+/// Replaces the observables recorded for one node with those found in its
+/// current content.
+pub(crate) async fn refresh_for_node<C: ConnectionTrait>(
+    conn: &C,
+    node_id: &str,
+    content: &str,
+) -> Result<(), DbErr> {
+    conn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "DELETE FROM observables WHERE node_id = ?;".to_owned(),
+        vec![node_id.into()],
+    ))
+    .await?;
+
+    for observable in extract(content) {
+        conn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "INSERT OR IGNORE INTO observables (node_id, kind, value, created_at)
+             VALUES (?, ?, ?, unixepoch());"
+                .to_owned(),
+            vec![
+                node_id.into(),
+                observable.kind.into(),
+                observable.value.into(),
+            ],
+        ))
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Re-extracts observables for the given nodes, or for every node row when
+/// `ids` is `None`; the backfill runs that before the trash existed.
+pub(crate) async fn reindex<C: ConnectionTrait>(
+    conn: &C,
+    ids: Option<&[String]>,
+) -> Result<(), DbErr> {
+    let statement = match ids {
+        Some([]) => return Ok(()),
+        Some(ids) => Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT id, content FROM nodes WHERE id IN ({}) AND deleted_at IS NULL;",
+                vec!["?"; ids.len()].join(", ")
+            ),
+            ids.iter().cloned().map(Into::into).collect::<Vec<_>>(),
+        ),
+        None => Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, content FROM nodes;".to_owned(),
+        ),
+    };
+
+    refresh_rows(conn, statement).await
+}
+
+/// Re-extracts observables for every node outside the trash.
+pub(crate) async fn reindex_live<C: ConnectionTrait>(conn: &C) -> Result<(), DbErr> {
+    refresh_rows(
+        conn,
+        Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, content FROM nodes WHERE deleted_at IS NULL;".to_owned(),
+        ),
+    )
+    .await
+}
+
+async fn refresh_rows<C: ConnectionTrait>(conn: &C, statement: Statement) -> Result<(), DbErr> {
+    for row in conn.query_all(statement).await? {
+        let id: String = row.try_get("", "id")?;
+        let content: String = row.try_get("", "content").unwrap_or_default();
+        refresh_for_node(conn, &id, &content).await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn list<C: ConnectionTrait>(
+    conn: &C,
+    ids: Option<&[String]>,
+) -> Result<Vec<ObservableModel>, DbErr> {
+    let statement = match ids {
+        Some([]) => return Ok(Vec::new()),
+        Some(ids) => Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT node_id, kind, value FROM observables
+                 WHERE node_id IN ({})
+                 ORDER BY kind ASC, value ASC, node_id ASC;",
+                vec!["?"; ids.len()].join(", ")
+            ),
+            ids.iter().cloned().map(Into::into).collect::<Vec<_>>(),
+        ),
+        None => Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT node_id, kind, value FROM observables
+             ORDER BY kind ASC, value ASC, node_id ASC;"
+                .to_owned(),
+        ),
+    };
+
+    Ok(conn
+        .query_all(statement)
+        .await?
+        .into_iter()
+        .map(ObservableModel::from_row)
+        .collect())
+}
+
+/// Nodes whose content mentions the indicator, optionally restricted to one
+/// observable kind.
+pub(crate) async fn nodes_mentioning<C: ConnectionTrait>(
+    conn: &C,
+    indicator: &str,
+    kind: Option<&str>,
+) -> Result<Vec<NodeModel>, DbErr> {
+    let value = normalize_indicator(indicator);
+    let kind = kind.map(str::trim).filter(|kind| !kind.is_empty());
+
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {NODE_COLUMNS}
+                 FROM nodes
+                 WHERE id IN (
+                   SELECT node_id FROM observables
+                   WHERE value = ? AND (? IS NULL OR kind = ?)
+                 )
+                   AND deleted_at IS NULL
+                 ORDER BY updated_at ASC, id ASC;"
+            ),
+            vec![value.into(), kind.into(), kind.into()],
+        ))
+        .await?;
+
+    Ok(rows.into_iter().map(NodeModel::from_row).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str, kind: &str) -> Vec<String> {
+        extract(text)
+            .into_iter()
+            .filter(|observable| observable.kind == kind)
+            .map(|observable| observable.value)
+            .collect()
+    }
+
+    #[test]
+    fn extracts_defanged_network_indicators() {
+        let text = "beacon to hxxps://update[.]evil-cdn[.]com/a.ps1 from 10[.]0[.]0[.]8, \
+                    fallback 45.33.32.156:443 and fe80::1; mail ops[at]evil-cdn[.]com";
+
+        assert_eq!(
+            values(text, "url"),
+            vec!["https://update.evil-cdn.com/a.ps1"]
+        );
+        assert_eq!(values(text, "ipv4"), vec!["10.0.0.8", "45.33.32.156"]);
+        assert_eq!(values(text, "ipv6"), vec!["fe80::1"]);
+        assert_eq!(values(text, "email"), vec!["ops@evil-cdn.com"]);
+        assert_eq!(values(text, "domain"), vec!["update.evil-cdn.com"]);
+        assert!(values("999.1.1.1 12:30:45", "ipv4").is_empty());
+        assert!(values("12:30:45", "ipv6").is_empty());
+    }
+
+    #[test]
+    fn extracts_host_indicators() {
+        let sha256 = "E3B0C44298FC1C149AFBF4C8996FB92427AE41E4649B934CA495991B7852B855";
+        let text = format!(
+            "C:\\Users\\bob\\AppData\\Local\\Temp\\svchost.exe (sha256 {sha256}, md5 \
+             d41d8cd98f00b204e9800998ecf8427e) persisted via \
+             HKCU\\Software\\Microsoft\\Windows\\CurrentVersion\\Run\\updater. cve-2021-44228"
+        );
+
+        assert_eq!(values(&text, "sha256"), vec![sha256.to_ascii_lowercase()]);
+        assert_eq!(
+            values(&text, "md5"),
+            vec!["d41d8cd98f00b204e9800998ecf8427e"]
+        );
+        assert!(values(&text, "sha1").is_empty());
+        assert_eq!(
+            values(&text, "file_path"),
+            vec!["C:\\Users\\bob\\AppData\\Local\\Temp\\svchost.exe"]
+        );
+        assert_eq!(
+            values(&text, "registry_key"),
+            vec!["HKCU\\Software\\Microsoft\\Windows\\CurrentVersion\\Run\\updater"]
+        );
+        assert_eq!(values(&text, "cve"), vec!["CVE-2021-44228"]);
+        assert!(values(&text, "domain").is_empty());
+    }
+
+    #[test]
+    fn normalizes_lookup_indicators() {
+        assert_eq!(normalize_indicator(" Evil-CDN[.]com "), "evil-cdn.com");
+        assert_eq!(normalize_indicator("1[.]2[.]3[.]4"), "1.2.3.4");
+        assert_eq!(normalize_indicator("free text"), "free text");
+    }
+}
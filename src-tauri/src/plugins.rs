@@ -0,0 +1,801 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{ChildStdin, Command},
+    sync::oneshot,
+};
+
+use crate::{
+    changes::ChangeOrigin, list_edges_internal, list_nodes_by_ids, list_nodes_internal,
+    normalize_delete_ids, AppState, EdgePayload, NodePayload,
+};
+
+pub(crate) const PLUGINS_DIR_NAME: &str = "plugins";
+/// Tauri event carrying each `PluginLog` while a plugin runs.
+pub(crate) const LOG_EVENT: &str = "plugin-log";
+
+const MANIFEST_FILE_NAME: &str = "plugin.json";
+const DEFAULT_TIMEOUT_SECS: u64 = 300;
+const MAX_TIMEOUT_SECS: u64 = 3600;
+const STDERR_DRAIN: Duration = Duration::from_secs(1);
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const PERMISSION_DENIED: i64 = -32001;
+const WRITE_FAILED: i64 = -32002;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PluginPermission {
+    #[serde(rename = "nodes:read")]
+    NodesRead,
+    #[serde(rename = "nodes:write")]
+    NodesWrite,
+    #[serde(rename = "edges:read")]
+    EdgesRead,
+    #[serde(rename = "edges:write")]
+    EdgesWrite,
+}
+
+impl PluginPermission {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::NodesRead => "nodes:read",
+            Self::NodesWrite => "nodes:write",
+            Self::EdgesRead => "edges:read",
+            Self::EdgesWrite => "edges:write",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PluginInputType {
+    String,
+    Number,
+    Boolean,
+    /// A list of node ids, typically the canvas selection.
+    NodeIds,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PluginInput {
+    #[serde(rename = "type")]
+    input_type: PluginInputType,
+    #[serde(default)]
+    required: bool,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// Contents of `plugins/<name>/plugin.json`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PluginManifest {
+    name: String,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    /// Program and arguments. A program containing a path separator is
+    /// resolved against the plugin directory, anything else through `PATH`.
+    command: Vec<String>,
+    #[serde(default)]
+    inputs: BTreeMap<String, PluginInput>,
+    #[serde(default)]
+    permissions: Vec<PluginPermission>,
+    #[serde(default)]
+    timeout_secs: Option<u64>,
+}
+
+impl PluginManifest {
+    fn validate(&self, dir_name: &str) -> Result<(), String> {
+        if self.name != dir_name {
+            return Err(format!(
+                "plugin manifest name {} does not match its directory {dir_name}",
+                self.name
+            ));
+        }
+
+        if self
+            .command
+            .first()
+            .is_none_or(|program| program.trim().is_empty())
+        {
+            return Err(format!("plugin {} has no command", self.name));
+        }
+
+        if self
+            .timeout_secs
+            .is_some_and(|secs| secs == 0 || secs > MAX_TIMEOUT_SECS)
+        {
+            return Err(format!(
+                "plugin {} timeoutSecs must be between 1 and {MAX_TIMEOUT_SECS}",
+                self.name
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn validate_inputs(&self, inputs: &Map<String, Value>) -> Result<(), String> {
+        if let Some(unknown) = inputs.keys().find(|key| !self.inputs.contains_key(*key)) {
+            return Err(format!("plugin {} has no input {unknown}", self.name));
+        }
+
+        for (name, input) in &self.inputs {
+            let valid = match (inputs.get(name), input.input_type) {
+                (None | Some(Value::Null), _) => !input.required,
+                (Some(Value::String(_)), PluginInputType::String) => true,
+                (Some(Value::Number(_)), PluginInputType::Number) => true,
+                (Some(Value::Bool(_)), PluginInputType::Boolean) => true,
+                (Some(Value::Array(items)), PluginInputType::NodeIds) => {
+                    items.iter().all(Value::is_string)
+                }
+                _ => false,
+            };
+
+            if !valid {
+                return Err(format!(
+                    "plugin input {name} must be a {:?} value",
+                    input.input_type
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn allows(&self, permission: PluginPermission) -> bool {
+        self.permissions.contains(&permission)
+    }
+}
+
+fn is_plugin_name(raw: &str) -> bool {
+    !raw.is_empty()
+        && raw
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+}
+
+/// Installed plugins and the runs currently in flight.
+pub(crate) struct PluginHost {
+    dir: PathBuf,
+    running: Mutex<HashMap<String, oneshot::Sender<()>>>,
+}
+
+impl PluginHost {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            running: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn load(&self, name: &str) -> Result<(PathBuf, PluginManifest), String> {
+        if !is_plugin_name(name) {
+            return Err(format!("invalid plugin name: {name}"));
+        }
+
+        let dir = self.dir.join(name);
+        let raw = std::fs::read_to_string(dir.join(MANIFEST_FILE_NAME))
+            .map_err(|err| format!("plugin {name} not found: {err}"))?;
+        let manifest = serde_json::from_str::<PluginManifest>(&raw)
+            .map_err(|err| format!("invalid manifest for plugin {name}: {err}"))?;
+        manifest.validate(name)?;
+
+        Ok((dir, manifest))
+    }
+
+    /// Manifests of every installed plugin; broken ones are reported and skipped.
+    pub(crate) fn list(&self) -> Result<Vec<PluginManifest>, String> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let mut names = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().join(MANIFEST_FILE_NAME).is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect::<Vec<_>>();
+        names.sort();
+
+        Ok(names
+            .iter()
+            .filter_map(|name| match self.load(name) {
+                Ok((_, manifest)) => Some(manifest),
+                Err(err) => {
+                    eprintln!("skipping plugin: {err}");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    pub(crate) fn cancel(&self, run_id: &str) -> Result<(), String> {
+        let sender = self
+            .running
+            .lock()
+            .map_err(|err| err.to_string())?
+            .remove(run_id)
+            .ok_or_else(|| format!("no running plugin with run id {run_id}"))?;
+
+        let _ = sender.send(());
+        Ok(())
+    }
+}
+
+/// Unregisters a run from `PluginHost::running` however the run ends.
+struct RunGuard<'a> {
+    host: &'a PluginHost,
+    run_id: String,
+}
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.host.running.lock() {
+            running.remove(&self.run_id);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PluginRunRequest {
+    pub(crate) name: String,
+    /// Caller-chosen id so the run can be cancelled while it is awaited.
+    #[serde(default)]
+    pub(crate) run_id: Option<String>,
+    #[serde(default)]
+    pub(crate) inputs: Map<String, Value>,
+    #[serde(default)]
+    pub(crate) selection: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PluginLog {
+    pub(crate) run_id: String,
+    /// `debug`, `info`, `warn`, `error`, or `stderr` for raw stderr lines.
+    pub(crate) level: String,
+    pub(crate) message: String,
+    pub(crate) progress: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PluginRunStatus {
+    Completed,
+    Failed,
+    TimedOut,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PluginRunReport {
+    pub(crate) run_id: String,
+    pub(crate) status: PluginRunStatus,
+    pub(crate) exit_code: Option<i32>,
+    /// Params of the last `result` notification the plugin sent.
+    pub(crate) result: Option<Value>,
+    pub(crate) logs: Vec<PluginLog>,
+    pub(crate) nodes_written: usize,
+    pub(crate) edges_written: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcMessage {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct ListNodesParams {
+    #[serde(default)]
+    ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpsertNodesParams {
+    nodes: Vec<NodePayload>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpsertEdgesParams {
+    edges: Vec<EdgePayload>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogParams {
+    #[serde(default)]
+    level: Option<String>,
+    message: String,
+    #[serde(default)]
+    progress: Option<f64>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+/// Host side of one plugin run: answers the plugin's requests against the
+/// case that was open when the run started.
+struct Session<'a> {
+    state: &'a AppState,
+    manifest: &'a PluginManifest,
+    run_id: String,
+    case_id: Option<String>,
+    logs: Vec<PluginLog>,
+    result: Option<Value>,
+    nodes_written: usize,
+    edges_written: usize,
+}
+
+impl Session<'_> {
+    fn require(&self, permission: PluginPermission) -> Result<(), RpcError> {
+        if self.manifest.allows(permission) {
+            Ok(())
+        } else {
+            Err(RpcError::new(
+                PERMISSION_DENIED,
+                format!(
+                    "plugin {} lacks permission {}",
+                    self.manifest.name,
+                    permission.as_str()
+                ),
+            ))
+        }
+    }
+
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value, RpcError> {
+        if self.state.cases.active_case_id().await != self.case_id {
+            return Err(RpcError::new(
+                WRITE_FAILED,
+                "the active case changed while the plugin was running",
+            ));
+        }
+
+        let failed = |err: String| RpcError::new(WRITE_FAILED, err);
+
+        match method {
+            "nodes.list" => {
+                self.require(PluginPermission::NodesRead)?;
+                let params = parse_params::<ListNodesParams>(params)?;
+                let db = self.state.db().await.map_err(failed)?;
+                let nodes = match params.ids {
+                    Some(ids) => list_nodes_by_ids(&db, &normalize_delete_ids(ids)).await,
+                    None => list_nodes_internal(&db).await,
+                }
+                .map_err(failed)?;
+
+                Ok(json!({ "nodes": nodes }))
+            }
+            "nodes.upsert" => {
+                self.require(PluginPermission::NodesWrite)?;
+                let params = parse_params::<UpsertNodesParams>(params)?;
+                let count = params.nodes.len();
+                self.state
+                    .upsert_nodes(ChangeOrigin::Backend, params.nodes)
+                    .await
+                    .map_err(failed)?;
+                self.nodes_written += count;
+
+                Ok(json!({ "written": count }))
+            }
+            "edges.list" => {
+                self.require(PluginPermission::EdgesRead)?;
+                let db = self.state.db().await.map_err(failed)?;
+                let edges = list_edges_internal(&db).await.map_err(failed)?;
+
+                Ok(json!({ "edges": edges }))
+            }
+            "edges.upsert" => {
+                self.require(PluginPermission::EdgesWrite)?;
+                let params = parse_params::<UpsertEdgesParams>(params)?;
+                let count = params.edges.len();
+                self.state
+                    .upsert_edges(ChangeOrigin::Backend, params.edges)
+                    .await
+                    .map_err(failed)?;
+                self.edges_written += count;
+
+                Ok(json!({ "written": count }))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method: {method}"),
+            )),
+        }
+    }
+
+    fn log(&mut self, level: &str, message: String, progress: Option<f64>) -> PluginLog {
+        let entry = PluginLog {
+            run_id: self.run_id.clone(),
+            level: level.to_owned(),
+            message,
+            progress: progress.filter(|value| value.is_finite()),
+        };
+        self.logs.push(entry.clone());
+        entry
+    }
+
+    /// Handles one line of plugin output, returning the reply to send back
+    /// (requests only) and any log entry it produced.
+    async fn handle_line(&mut self, line: &str) -> (Option<Value>, Option<PluginLog>) {
+        if line.trim().is_empty() {
+            return (None, None);
+        }
+
+        let message = match serde_json::from_str::<RpcMessage>(line) {
+            Ok(message) => message,
+            Err(err) => {
+                return (
+                    Some(rpc_error(Value::Null, PARSE_ERROR, &err.to_string())),
+                    None,
+                )
+            }
+        };
+
+        match (message.id, message.method.as_str()) {
+            (None, "log") => match parse_params::<LogParams>(message.params) {
+                Ok(params) => {
+                    let level = params.level.unwrap_or_else(|| "info".to_owned());
+                    (
+                        None,
+                        Some(self.log(&level, params.message, params.progress)),
+                    )
+                }
+                Err(err) => (None, Some(self.log("warn", err.message, None))),
+            },
+            (None, "result") => {
+                self.result = Some(message.params);
+                (None, None)
+            }
+            (None, method) => (
+                None,
+                Some(self.log("warn", format!("ignored notification: {method}"), None)),
+            ),
+            (Some(id), method) => {
+                let reply = match self.call(method, message.params).await {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(err) => rpc_error(id, err.code, &err.message),
+                };
+                (Some(reply), None)
+            }
+        }
+    }
+}
+
+fn rpc_error(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+async fn write_line(stdin: &mut ChildStdin, message: &Value) -> std::io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    stdin.write_all(line.as_bytes()).await?;
+    stdin.flush().await
+}
+
+fn resolve_program(dir: &Path, program: &str) -> PathBuf {
+    if program.contains('/') || program.contains('\\') {
+        dir.join(program)
+    } else {
+        PathBuf::from(program)
+    }
+}
+
+/// Runs an installed plugin to completion, timeout or cancellation. The
+/// plugin reads a `run` notification from stdin and then exchanges
+/// line-delimited JSON-RPC 2.0 messages with the host over stdin/stdout.
+pub(crate) async fn run(
+    state: &AppState,
+    request: PluginRunRequest,
+    on_log: impl Fn(&PluginLog),
+) -> Result<PluginRunReport, String> {
+    let host = state.plugins.as_ref();
+    let (dir, manifest) = host.load(&request.name)?;
+    manifest.validate_inputs(&request.inputs)?;
+
+    let run_id = request
+        .run_id
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let (cancel_sender, mut cancelled) = oneshot::channel();
+    {
+        let mut running = host.running.lock().map_err(|err| err.to_string())?;
+
+        if running.contains_key(&run_id) {
+            return Err(format!("plugin run {run_id} is already in progress"));
+        }
+
+        running.insert(run_id.clone(), cancel_sender);
+    }
+    let _guard = RunGuard {
+        host,
+        run_id: run_id.clone(),
+    };
+
+    let case_id = state.cases.active_case_id().await;
+    let mut child = Command::new(resolve_program(&dir, &manifest.command[0]))
+        .args(&manifest.command[1..])
+        .current_dir(&dir)
+        .env("CYBERWEAVER_PLUGIN_RUN_ID", &run_id)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| format!("failed to start plugin {}: {err}", manifest.name))?;
+
+    let (Some(mut stdin), Some(stdout), Some(stderr)) =
+        (child.stdin.take(), child.stdout.take(), child.stderr.take())
+    else {
+        return Err("plugin stdio was not captured".to_owned());
+    };
+    let mut stdout = BufReader::new(stdout).lines();
+    let mut stderr = BufReader::new(stderr).lines();
+
+    let mut session = Session {
+        state,
+        manifest: &manifest,
+        run_id: run_id.clone(),
+        case_id: case_id.clone(),
+        logs: Vec::new(),
+        result: None,
+        nodes_written: 0,
+        edges_written: 0,
+    };
+
+    let start = json!({
+        "jsonrpc": "2.0",
+        "method": "run",
+        "params": {
+            "runId": run_id,
+            "caseId": case_id,
+            "inputs": request.inputs,
+            "selection": normalize_delete_ids(request.selection),
+        },
+    });
+    // A plugin that exits without reading stdin is reported through its exit
+    // status below.
+    let _ = write_line(&mut stdin, &start).await;
+
+    let timeout = Duration::from_secs(manifest.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+    let deadline = tokio::time::sleep(timeout);
+    tokio::pin!(deadline);
+    let mut stderr_open = true;
+
+    let interrupted = loop {
+        tokio::select! {
+            line = stdout.next_line() => match line {
+                Ok(Some(line)) => {
+                    let (reply, log) = session.handle_line(&line).await;
+
+                    if let Some(log) = log {
+                        on_log(&log);
+                    }
+                    if let Some(reply) = reply {
+                        let _ = write_line(&mut stdin, &reply).await;
+                    }
+                }
+                Ok(None) | Err(_) => break None,
+            },
+            line = stderr.next_line(), if stderr_open => match line {
+                Ok(Some(line)) => on_log(&session.log("stderr", line, None)),
+                Ok(None) | Err(_) => stderr_open = false,
+            },
+            _ = &mut deadline => break Some(PluginRunStatus::TimedOut),
+            _ = &mut cancelled => break Some(PluginRunStatus::Cancelled),
+        }
+    };
+    drop(stdin);
+
+    let mut exit_code = None;
+    let status = match interrupted {
+        Some(status) => status,
+        None => tokio::select! {
+            exit = child.wait() => match exit {
+                Ok(exit) => {
+                    exit_code = exit.code();
+                    if exit.success() {
+                        PluginRunStatus::Completed
+                    } else {
+                        PluginRunStatus::Failed
+                    }
+                }
+                Err(_) => PluginRunStatus::Failed,
+            },
+            _ = &mut deadline => PluginRunStatus::TimedOut,
+            _ = &mut cancelled => PluginRunStatus::Cancelled,
+        },
+    };
+
+    if exit_code.is_none() {
+        let _ = child.kill().await;
+    }
+
+    if stderr_open {
+        let drain = async {
+            while let Ok(Some(line)) = stderr.next_line().await {
+                on_log(&session.log("stderr", line, None));
+            }
+        };
+        let _ = tokio::time::timeout(STDERR_DRAIN, drain).await;
+    }
+
+    Ok(PluginRunReport {
+        run_id,
+        status,
+        exit_code,
+        result: session.result,
+        logs: session.logs,
+        nodes_written: session.nodes_written,
+        edges_written: session.edges_written,
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_support::test_state;
+
+    fn install(root: &Path, name: &str, manifest: Value, script: &str) {
+        let dir = root.join(PLUGINS_DIR_NAME).join(name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(MANIFEST_FILE_NAME), manifest.to_string()).unwrap();
+        std::fs::write(dir.join("main.sh"), script).unwrap();
+    }
+
+    fn request(name: &str, inputs: Value) -> PluginRunRequest {
+        PluginRunRequest {
+            name: name.to_owned(),
+            inputs: inputs.as_object().cloned().unwrap_or_default(),
+            ..PluginRunRequest::default()
+        }
+    }
+
+    const TRIAGE_SCRIPT: &str = r#"
+read -r run
+printf '%s\n' '{"jsonrpc":"2.0","method":"log","params":{"message":"starting","progress":0.5}}'
+printf '%s\n' '{"jsonrpc":"2.0","id":1,"method":"nodes.upsert","params":{"nodes":[{"id":"from-plugin","type":"note","x":0,"y":0,"content":"45.33.32.156"}]}}'
+read -r created
+printf '%s\n' '{"jsonrpc":"2.0","id":2,"method":"nodes.upsert","params":{"nodes":[{"id":"bad","type":"draw","x":0,"y":0,"content":""}]}}'
+read -r invalid
+printf '%s\n' '{"jsonrpc":"2.0","id":3,"method":"edges.upsert","params":{"edges":[]}}'
+read -r denied
+echo "$invalid" >&2
+echo "$denied" >&2
+printf '{"jsonrpc":"2.0","method":"result","params":{"run":%s,"created":%s}}\n' "$run" "$created"
+"#;
+
+    #[tokio::test]
+    async fn runs_plugin_through_json_rpc() {
+        let state = test_state("plugins-rpc").await;
+        install(
+            state.root(),
+            "triage",
+            json!({
+                "name": "triage",
+                "command": ["sh", "main.sh"],
+                "inputs": { "target": { "type": "string", "required": true } },
+                "permissions": ["nodes:read", "nodes:write"],
+            }),
+            TRIAGE_SCRIPT,
+        );
+        let mut changes = state.changes.subscribe();
+
+        assert!(run(&state, request("triage", json!({})), |_| {})
+            .await
+            .is_err());
+        assert!(run(&state, request("../triage", json!({})), |_| {})
+            .await
+            .is_err());
+
+        let streamed = Mutex::new(Vec::new());
+        let report = run(
+            &state,
+            request("triage", json!({ "target": "host-1" })),
+            |log| streamed.lock().unwrap().push(log.message.clone()),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(report.status, PluginRunStatus::Completed);
+        assert_eq!(report.exit_code, Some(0));
+        assert_eq!(report.nodes_written, 1);
+        assert_eq!(report.logs.len(), streamed.lock().unwrap().len());
+        assert_eq!(report.logs[0].progress, Some(0.5));
+
+        let result = report.result.unwrap();
+        assert_eq!(result["run"]["params"]["inputs"]["target"], "host-1");
+        assert_eq!(result["created"]["result"]["written"], 1);
+
+        let stderr = report
+            .logs
+            .iter()
+            .filter(|log| log.level == "stderr")
+            .map(|log| log.message.as_str())
+            .collect::<Vec<_>>();
+        assert!(stderr[0].contains("unsupported node type"));
+        assert!(stderr[1].contains("lacks permission edges:write"));
+
+        let change = changes.recv().await.unwrap();
+        assert_eq!(change.origin, ChangeOrigin::Backend);
+
+        let nodes = list_nodes_internal(&state.db().await.unwrap())
+            .await
+            .unwrap();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].id, "shape:from-plugin");
+    }
+
+    #[tokio::test]
+    async fn stops_plugins_on_timeout_and_cancel() {
+        let state = test_state("plugins-stop").await;
+        let manifest = json!({ "name": "slow", "command": ["sh", "main.sh"], "timeoutSecs": 1 });
+        install(state.root(), "slow", manifest, "sleep 30\n");
+
+        let report = run(&state, request("slow", json!({})), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(report.status, PluginRunStatus::TimedOut);
+
+        let background = state.clone();
+        let running = tokio::spawn(async move {
+            let request = PluginRunRequest {
+                run_id: Some("run-1".to_owned()),
+                ..request("slow", json!({}))
+            };
+            run(&background, request, |_| {}).await
+        });
+
+        let mut cancelled = false;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            if state.plugins.cancel("run-1").is_ok() {
+                cancelled = true;
+                break;
+            }
+        }
+        assert!(cancelled);
+
+        let report = running.await.unwrap().unwrap();
+        assert_eq!(report.status, PluginRunStatus::Cancelled);
+        assert!(state.plugins.cancel("run-1").is_err());
+    }
+}
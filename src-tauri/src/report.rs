@@ -0,0 +1,566 @@
+use minijinja::{AutoEscape, Environment};
+use sea_orm::DatabaseConnection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    attack,
+    cases::CaseModel,
+    list_edges_internal, list_nodes_internal, observables,
+    search::plain_text_from_content,
+    timestamps::{format_timestamp, parse_timestamp},
+    NodeModel,
+};
+
+const MARKDOWN_TEMPLATE: &str = include_str!("../templates/report.md.j2");
+const HTML_TEMPLATE: &str = include_str!("../templates/report.html.j2");
+const MAX_LABEL_CHARS: usize = 120;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ReportFormat {
+    Markdown,
+    /// A single self-contained HTML document with inline styles.
+    Html,
+}
+
+// The context handed to templates. Field names are snake_case because that
+// is what template authors write, unlike the camelCase used over IPC.
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ReportContext {
+    case: ReportCase,
+    generated_at: String,
+    summary: ReportSummary,
+    timeline: Vec<TimelineEntry>,
+    observables: Vec<ObservableRow>,
+    attack_coverage: Vec<CoverageRow>,
+    entity_groups: Vec<EntityGroup>,
+    relations: Vec<RelationRow>,
+    notes: Vec<NoteRow>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportCase {
+    name: String,
+    case_number: Option<String>,
+    analyst: Option<String>,
+    status: String,
+    created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportSummary {
+    nodes: usize,
+    entities: usize,
+    relations: usize,
+    observables: usize,
+    events: usize,
+    notes: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct TimelineEntry {
+    node_id: String,
+    timestamp: Option<String>,
+    label: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ObservableRow {
+    kind: String,
+    value: String,
+    nodes: Vec<String>,
+}
+
+/// A tactic with at least one tagged technique.
+#[derive(Debug, Serialize)]
+struct CoverageRow {
+    name: String,
+    techniques: Vec<CoverageTechnique>,
+}
+
+#[derive(Debug, Serialize)]
+struct CoverageTechnique {
+    id: String,
+    name: String,
+    nodes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct EntityGroup {
+    kind: String,
+    entities: Vec<EntityRow>,
+}
+
+#[derive(Debug, Serialize)]
+struct EntityRow {
+    node_id: String,
+    label: String,
+    attributes: Vec<AttributeRow>,
+}
+
+#[derive(Debug, Serialize)]
+struct AttributeRow {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RelationRow {
+    source: String,
+    kind: String,
+    target: String,
+    label: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct NoteRow {
+    node_id: String,
+    text: String,
+}
+
+/// Short display name for a node: the first line of its text, or its id.
+fn node_label(node: &NodeModel) -> String {
+    let text = plain_text_from_content(&node.content);
+    let first_line = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+
+    if first_line.is_empty() {
+        return node
+            .id
+            .strip_prefix("shape:")
+            .unwrap_or(&node.id)
+            .to_owned();
+    }
+
+    if first_line.chars().count() > MAX_LABEL_CHARS {
+        let truncated = first_line.chars().take(MAX_LABEL_CHARS).collect::<String>();
+        return format!("{truncated}…");
+    }
+
+    first_line.to_owned()
+}
+
+fn attribute_rows(attributes: Option<&serde_json::Value>) -> Vec<AttributeRow> {
+    let Some(serde_json::Value::Object(map)) = attributes else {
+        return Vec::new();
+    };
+
+    map.iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(name, value)| AttributeRow {
+            name: name.clone(),
+            value: match value {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            },
+        })
+        .collect()
+}
+
+fn event_time(node: &NodeModel) -> Option<f64> {
+    node.attributes
+        .as_ref()
+        .and_then(|attributes| attributes.get("timestamp"))
+        .and_then(parse_timestamp)
+}
+
+pub(crate) async fn build_context(
+    conn: &DatabaseConnection,
+    case: &CaseModel,
+    generated_at: i64,
+) -> Result<ReportContext, String> {
+    let nodes = list_nodes_internal(conn).await?;
+    let edges = list_edges_internal(conn).await?;
+    let extracted = observables::list(conn, None)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let labels = nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node_label(node)))
+        .collect::<HashMap<_, _>>();
+    let label_of = |id: &str| {
+        labels
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| id.strip_prefix("shape:").unwrap_or(id).to_owned())
+    };
+
+    let mut timeline = nodes
+        .iter()
+        .filter(|node| node.kind.as_deref() == Some("event"))
+        .map(|node| (event_time(node), node))
+        .collect::<Vec<_>>();
+    timeline.sort_by(|left, right| match (left.0, right.0) {
+        (Some(left_time), Some(right_time)) => left_time.total_cmp(&right_time),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    let timeline = timeline
+        .into_iter()
+        .map(|(time, node)| TimelineEntry {
+            node_id: node.id.clone(),
+            timestamp: time.map(|seconds| format_timestamp(seconds.floor() as i64)),
+            label: label_of(&node.id),
+        })
+        .collect::<Vec<_>>();
+
+    let mut grouped_observables = BTreeMap::<(String, String), Vec<String>>::new();
+    for observable in extracted {
+        let seen_in = grouped_observables
+            .entry((observable.kind, observable.value))
+            .or_default();
+        let label = label_of(&observable.node_id);
+
+        if !seen_in.contains(&label) {
+            seen_in.push(label);
+        }
+    }
+    let observables = grouped_observables
+        .into_iter()
+        .map(|((kind, value), mut nodes)| {
+            nodes.sort();
+            ObservableRow { kind, value, nodes }
+        })
+        .collect::<Vec<_>>();
+
+    let attack_coverage = attack::coverage(conn)
+        .await?
+        .into_iter()
+        .filter(|tactic| !tactic.techniques.is_empty())
+        .map(|tactic| CoverageRow {
+            name: tactic.name,
+            techniques: tactic
+                .techniques
+                .into_iter()
+                .map(|technique| {
+                    let mut nodes = technique
+                        .node_ids
+                        .iter()
+                        .map(|id| label_of(id))
+                        .collect::<Vec<_>>();
+                    nodes.sort();
+
+                    CoverageTechnique {
+                        id: technique.id,
+                        name: technique.name,
+                        nodes,
+                    }
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut entity_groups = BTreeMap::<String, Vec<EntityRow>>::new();
+    for node in &nodes {
+        if let Some(kind) = &node.kind {
+            entity_groups
+                .entry(kind.clone())
+                .or_default()
+                .push(EntityRow {
+                    node_id: node.id.clone(),
+                    label: label_of(&node.id),
+                    attributes: attribute_rows(node.attributes.as_ref()),
+                });
+        }
+    }
+    let entity_groups = entity_groups
+        .into_iter()
+        .map(|(kind, mut entities)| {
+            entities.sort_by(|left, right| {
+                (&left.label, &left.node_id).cmp(&(&right.label, &right.node_id))
+            });
+            EntityGroup { kind, entities }
+        })
+        .collect::<Vec<_>>();
+
+    let mut relations = edges
+        .iter()
+        .map(|edge| RelationRow {
+            source: label_of(&edge.source),
+            kind: edge.kind.clone(),
+            target: label_of(&edge.target),
+            label: edge.label.clone().filter(|label| !label.trim().is_empty()),
+        })
+        .collect::<Vec<_>>();
+    relations.sort_by(|left, right| {
+        (&left.source, &left.kind, &left.target).cmp(&(&right.source, &right.kind, &right.target))
+    });
+
+    // Notes read top to bottom, left to right, as they sit on the canvas.
+    let mut note_nodes = nodes
+        .iter()
+        .filter(|node| node.node_type == "note")
+        .collect::<Vec<_>>();
+    note_nodes.sort_by(|left, right| {
+        left.y
+            .total_cmp(&right.y)
+            .then(left.x.total_cmp(&right.x))
+            .then(left.id.cmp(&right.id))
+    });
+    let notes = note_nodes
+        .into_iter()
+        .map(|node| NoteRow {
+            node_id: node.id.clone(),
+            text: plain_text_from_content(&node.content).trim().to_owned(),
+        })
+        .filter(|note| !note.text.is_empty())
+        .collect::<Vec<_>>();
+
+    Ok(ReportContext {
+        case: ReportCase {
+            name: case.name.clone(),
+            case_number: case.case_number.clone(),
+            analyst: case.analyst.clone(),
+            status: case.status.clone(),
+            created_at: format_timestamp(case.created_at),
+        },
+        generated_at: format_timestamp(generated_at),
+        summary: ReportSummary {
+            nodes: nodes.len(),
+            entities: entity_groups.iter().map(|group| group.entities.len()).sum(),
+            relations: relations.len(),
+            observables: observables.len(),
+            events: timeline.len(),
+            notes: notes.len(),
+        },
+        timeline,
+        observables,
+        attack_coverage,
+        entity_groups,
+        relations,
+        notes,
+    })
+}
+
+/// Escapes text for a Markdown table cell or list item.
+fn md_cell(value: String) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for ch in value.chars() {
+        match ch {
+            '\\' | '|' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '\r' => {}
+            '\n' => escaped.push(' '),
+            _ => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
+
+/// Makes text safe inside a single-backtick code span in a table.
+fn md_code(value: String) -> String {
+    value
+        .replace('`', "'")
+        .replace('|', "\\|")
+        .replace(['\r', '\n'], " ")
+}
+
+fn md_quote(value: String) -> String {
+    value
+        .lines()
+        .map(|line| {
+            if line.trim().is_empty() {
+                ">".to_owned()
+            } else {
+                format!("> {}", md_cell(line.to_owned()))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Renders the report with the built-in template for `format`, or with
+/// `template` when the analyst supplies their own. HTML output is always
+/// auto-escaped.
+pub(crate) fn render(
+    context: &ReportContext,
+    format: ReportFormat,
+    template: Option<&str>,
+) -> Result<String, String> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_auto_escape_callback(move |_| match format {
+        ReportFormat::Markdown => AutoEscape::None,
+        ReportFormat::Html => AutoEscape::Html,
+    });
+    env.add_filter("md_cell", md_cell);
+    env.add_filter("md_code", md_code);
+    env.add_filter("md_quote", md_quote);
+
+    let source = template.unwrap_or(match format {
+        ReportFormat::Markdown => MARKDOWN_TEMPLATE,
+        ReportFormat::Html => HTML_TEMPLATE,
+    });
+
+    env.add_template("report", source)
+        .map_err(|err| format!("invalid report template: {err}"))?;
+    env.get_template("report")
+        .and_then(|template| template.render(context))
+        .map_err(|err| format!("failed to render report: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_support::{create_test_db, edge},
+        upsert_edges_internal, upsert_nodes_internal, EdgePayload, NodePayload,
+    };
+    use serde_json::json;
+
+    fn node(
+        id: &str,
+        node_type: &str,
+        y: f64,
+        content: &str,
+        kind: Option<&str>,
+        attributes: Option<serde_json::Value>,
+    ) -> NodePayload {
+        NodePayload {
+            id: id.to_owned(),
+            node_type: node_type.to_owned(),
+            x: 0.0,
+            y,
+            content: content.to_owned(),
+            width: None,
+            height: None,
+            kind: kind.map(str::to_owned),
+            attributes,
+        }
+    }
+
+    async fn sample_case() -> (DatabaseConnection, CaseModel) {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                node(
+                    "proc-1",
+                    "geo",
+                    0.0,
+                    "powershell.exe -enc SQBFAFgA",
+                    Some("process"),
+                    Some(json!({ "pid": 4242, "image": "C:\\Windows\\powershell.exe" })),
+                ),
+                node(
+                    "ip-1",
+                    "geo",
+                    0.0,
+                    "45.33.32.156",
+                    Some("ip"),
+                    Some(json!({ "address": "45.33.32.156" })),
+                ),
+                node(
+                    "evt-2",
+                    "geo",
+                    0.0,
+                    "Outbound beacon | every 60s",
+                    Some("event"),
+                    Some(json!({ "timestamp": "2024-03-01T10:20:00Z" })),
+                ),
+                node(
+                    "evt-1",
+                    "geo",
+                    0.0,
+                    "Macro dropped payload",
+                    Some("event"),
+                    Some(json!({ "timestamp": 1709287800 })),
+                ),
+                node(
+                    "note-2",
+                    "note",
+                    200.0,
+                    "Contained at 11:00.",
+                    None,
+                    None,
+                ),
+                node(
+                    "note-1",
+                    "note",
+                    100.0,
+                    r#"{"type":"doc","content":[{"type":"paragraph","content":[{"type":"text","text":"Beacon to 45.33.32.156"}]},{"type":"paragraph","content":[{"type":"text","text":"<script>alert(1)</script>"}]}]}"#,
+                    None,
+                    None,
+                ),
+            ],
+        )
+        .await
+        .unwrap();
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![
+                EdgePayload {
+                    kind: "connected_to".to_owned(),
+                    label: Some("TLS 443".to_owned()),
+                    ..edge("e1", "proc-1", "ip-1")
+                },
+                edge("e2", "evt-1", "proc-1"),
+            ],
+        )
+        .await
+        .unwrap();
+        crate::attack::set_node_techniques(&db, "test", "proc-1", vec!["T1059.001".to_owned()])
+            .await
+            .unwrap();
+
+        let case = CaseModel {
+            id: "case-1".to_owned(),
+            name: "Operation Nightjar".to_owned(),
+            case_number: Some("IR-2024-017".to_owned()),
+            analyst: Some("J. Doe".to_owned()),
+            status: "open".to_owned(),
+            created_at: 1_709_251_200,
+            updated_at: 1_709_251_200,
+            last_opened_at: None,
+        };
+
+        (db, case)
+    }
+
+    #[tokio::test]
+    async fn renders_markdown_report() {
+        let (db, case) = sample_case().await;
+        let context = build_context(&db, &case, 1_709_300_000).await.unwrap();
+
+        insta::assert_snapshot!(render(&context, ReportFormat::Markdown, None).unwrap());
+    }
+
+    #[tokio::test]
+    async fn renders_self_contained_html_report() {
+        let (db, case) = sample_case().await;
+        let context = build_context(&db, &case, 1_709_300_000).await.unwrap();
+        let html = render(&context, ReportFormat::Html, None).unwrap();
+
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("src=") && !html.contains("href="));
+        insta::assert_snapshot!(html);
+    }
+
+    #[tokio::test]
+    async fn renders_user_templates() {
+        let (db, case) = sample_case().await;
+        let context = build_context(&db, &case, 1_709_300_000).await.unwrap();
+
+        let custom = "{{ case.name }}: {% for entry in timeline %}{{ entry.label }}; {% endfor %}";
+        assert_eq!(
+            render(&context, ReportFormat::Html, Some(custom)).unwrap(),
+            "Operation Nightjar: Macro dropped payload; Outbound beacon | every 60s; "
+        );
+        assert!(render(&context, ReportFormat::Markdown, Some("{% for %}")).is_err());
+    }
+}
@@ -0,0 +1,681 @@
+use regex::Regex;
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, Statement, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::IpAddr,
+    path::Path,
+    sync::OnceLock,
+};
+
+use crate::{
+    audit, list_edges_internal, list_nodes_internal, observables, observables::ObservableModel,
+    search::plain_text_from_content, EdgeModel, NodeModel,
+};
+
+/// File in the app data directory overriding the default scoring rules.
+pub(crate) const CONFIG_FILE_NAME: &str = "scoring.json";
+
+const MAX_SCORE: f64 = 100.0;
+
+pub(crate) const RULE_KNOWN_BAD_HASH: &str = "known_bad_hash";
+pub(crate) const RULE_SUSPICIOUS_PROCESS_PAIR: &str = "suspicious_process_pair";
+pub(crate) const RULE_RARE_EXTERNAL_IP: &str = "rare_external_ip";
+pub(crate) const RULE_ENCODED_POWERSHELL: &str = "encoded_powershell";
+pub(crate) const RULE_PROPAGATED: &str = "propagated";
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct PropagationConfig {
+    /// Share of a node's score passed to each neighbour per hop.
+    pub(crate) decay: f64,
+    pub(crate) max_hops: usize,
+}
+
+impl Default for PropagationConfig {
+    fn default() -> Self {
+        Self {
+            decay: 0.5,
+            max_hops: 2,
+        }
+    }
+}
+
+/// Rule weights and reference data. Every field is optional in
+/// `scoring.json`; missing ones keep their defaults.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub(crate) struct ScoringConfig {
+    pub(crate) weights: BTreeMap<String, f64>,
+    pub(crate) known_bad_hashes: Vec<String>,
+    /// `[parent, child]` image names, compared case-insensitively.
+    pub(crate) suspicious_process_pairs: Vec<(String, String)>,
+    /// A public IP mentioned by at most this many nodes counts as rare.
+    pub(crate) rare_ip_max_mentions: usize,
+    pub(crate) propagation: PropagationConfig,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        let pairs = [
+            ("winword.exe", "powershell.exe"),
+            ("winword.exe", "cmd.exe"),
+            ("excel.exe", "powershell.exe"),
+            ("excel.exe", "cmd.exe"),
+            ("outlook.exe", "powershell.exe"),
+            ("mshta.exe", "powershell.exe"),
+            ("wmiprvse.exe", "powershell.exe"),
+            ("w3wp.exe", "cmd.exe"),
+            ("sqlservr.exe", "cmd.exe"),
+        ];
+
+        Self {
+            weights: default_weights(),
+            known_bad_hashes: Vec::new(),
+            suspicious_process_pairs: pairs
+                .iter()
+                .map(|(parent, child)| ((*parent).to_owned(), (*child).to_owned()))
+                .collect(),
+            rare_ip_max_mentions: 1,
+            propagation: PropagationConfig::default(),
+        }
+    }
+}
+
+fn default_weights() -> BTreeMap<String, f64> {
+    [
+        (RULE_KNOWN_BAD_HASH, 80.0),
+        (RULE_SUSPICIOUS_PROCESS_PAIR, 50.0),
+        (RULE_RARE_EXTERNAL_IP, 25.0),
+        (RULE_ENCODED_POWERSHELL, 60.0),
+    ]
+    .into_iter()
+    .map(|(rule, weight)| (rule.to_owned(), weight))
+    .collect()
+}
+
+impl ScoringConfig {
+    /// Reads `path`, falling back to the defaults when it does not exist.
+    pub(crate) fn load(path: &Path) -> Result<Self, String> {
+        let raw = match std::fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let mut config = serde_json::from_str::<Self>(&raw)
+            .map_err(|err| format!("invalid scoring config {}: {err}", path.display()))?;
+
+        // Partial weight tables only override the rules they name.
+        let mut weights = default_weights();
+        weights.append(&mut config.weights);
+        config.weights = weights;
+
+        if !(0.0..=1.0).contains(&config.propagation.decay) {
+            return Err("scoring propagation.decay must be between 0 and 1".to_owned());
+        }
+
+        if config.weights.values().any(|weight| !weight.is_finite()) {
+            return Err("scoring weights must be finite numbers".to_owned());
+        }
+
+        Ok(config)
+    }
+
+    fn weight(&self, rule: &str) -> f64 {
+        self.weights.get(rule).copied().unwrap_or(0.0)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ScoreReason {
+    pub(crate) rule: String,
+    pub(crate) weight: f64,
+    pub(crate) detail: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NodeScore {
+    pub(crate) node_id: String,
+    pub(crate) score: f64,
+    pub(crate) reasons: Vec<ScoreReason>,
+}
+
+fn encoded_powershell_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(?:powershell|pwsh)(?:\.exe)?\b.*?\s[-/]e(?:c|nc|nco|ncod|ncode|ncoded|ncodedcommand)?\s+[A-Za-z0-9+/=]{16,}",
+        )
+        .expect("valid encoded powershell regex")
+    })
+}
+
+fn is_public_ip(raw: &str) -> bool {
+    match raw.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            let [first, second, ..] = ip.octets();
+            let shared = first == 100 && (64..128).contains(&second);
+
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || shared)
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let first = ip.segments()[0];
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+        Err(_) => false,
+    }
+}
+
+/// Lower-cased image name of a process node, from `attributes.image` or the
+/// first word of its text.
+fn process_image(node: &NodeModel) -> Option<String> {
+    if node.kind.as_deref() != Some("process") {
+        return None;
+    }
+
+    let image = node
+        .attributes
+        .as_ref()
+        .and_then(|attributes| attributes.get("image"))
+        .and_then(|image| image.as_str())
+        .map(str::to_owned)
+        .or_else(|| {
+            plain_text_from_content(&node.content)
+                .split_whitespace()
+                .next()
+                .map(str::to_owned)
+        })?;
+
+    image
+        .rsplit(['\\', '/'])
+        .next()
+        .map(|name| name.trim_matches('"').to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+}
+
+fn short_id(id: &str) -> &str {
+    id.strip_prefix("shape:").unwrap_or(id)
+}
+
+/// Scores every node from its own content and attributes, then spreads risk
+/// to related nodes. A node keeps the strongest propagated contribution
+/// rather than the sum, so dense neighbourhoods do not saturate.
+pub(crate) fn score(
+    config: &ScoringConfig,
+    nodes: &[NodeModel],
+    edges: &[EdgeModel],
+    observables: &[ObservableModel],
+) -> Vec<NodeScore> {
+    let known_bad = config
+        .known_bad_hashes
+        .iter()
+        .map(|hash| hash.trim().to_ascii_lowercase())
+        .collect::<HashSet<_>>();
+    let suspicious_pairs = config
+        .suspicious_process_pairs
+        .iter()
+        .map(|(parent, child)| (parent.to_ascii_lowercase(), child.to_ascii_lowercase()))
+        .collect::<HashSet<_>>();
+    let index = nodes
+        .iter()
+        .enumerate()
+        .map(|(position, node)| (node.id.as_str(), position))
+        .collect::<HashMap<_, _>>();
+
+    let mut reasons = vec![Vec::<ScoreReason>::new(); nodes.len()];
+    let mut add_reason = |node: usize, rule: &str, detail: String| {
+        let weight = config.weight(rule);
+
+        if weight > 0.0 && !reasons[node].iter().any(|reason| reason.rule == rule) {
+            reasons[node].push(ScoreReason {
+                rule: rule.to_owned(),
+                weight,
+                detail,
+            });
+        }
+    };
+
+    let mut ip_mentions = HashMap::<&str, HashSet<&str>>::new();
+    for observable in observables {
+        if matches!(observable.kind.as_str(), "ipv4" | "ipv6") {
+            ip_mentions
+                .entry(observable.value.as_str())
+                .or_default()
+                .insert(observable.node_id.as_str());
+        }
+    }
+
+    for observable in observables {
+        let Some(&node) = index.get(observable.node_id.as_str()) else {
+            continue;
+        };
+
+        match observable.kind.as_str() {
+            "md5" | "sha1" | "sha256"
+                if known_bad.contains(&observable.value.to_ascii_lowercase()) =>
+            {
+                add_reason(
+                    node,
+                    RULE_KNOWN_BAD_HASH,
+                    format!(
+                        "{} {} is on the known-bad list",
+                        observable.kind, observable.value
+                    ),
+                );
+            }
+            "ipv4" | "ipv6"
+                if is_public_ip(&observable.value)
+                    && ip_mentions[observable.value.as_str()].len()
+                        <= config.rare_ip_max_mentions =>
+            {
+                add_reason(
+                    node,
+                    RULE_RARE_EXTERNAL_IP,
+                    format!(
+                        "external IP {} appears nowhere else in the case",
+                        observable.value
+                    ),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    for (position, node) in nodes.iter().enumerate() {
+        let attribute_hashes = node
+            .attributes
+            .as_ref()
+            .and_then(|attributes| attributes.as_object())
+            .into_iter()
+            .flat_map(|attributes| {
+                ["md5", "sha1", "sha256", "value"]
+                    .into_iter()
+                    .filter_map(|key| attributes.get(key)?.as_str())
+            });
+
+        for hash in attribute_hashes {
+            if known_bad.contains(&hash.trim().to_ascii_lowercase()) {
+                add_reason(
+                    position,
+                    RULE_KNOWN_BAD_HASH,
+                    format!("hash {hash} is on the known-bad list"),
+                );
+            }
+        }
+
+        let command_line = node
+            .attributes
+            .as_ref()
+            .and_then(|attributes| attributes.get("commandLine"))
+            .and_then(|value| value.as_str())
+            .unwrap_or_default();
+
+        if encoded_powershell_pattern().is_match(command_line)
+            || encoded_powershell_pattern().is_match(&plain_text_from_content(&node.content))
+        {
+            add_reason(
+                position,
+                RULE_ENCODED_POWERSHELL,
+                "PowerShell launched with an encoded command".to_owned(),
+            );
+        }
+    }
+
+    for edge in edges.iter().filter(|edge| edge.kind == "spawned") {
+        let (Some(&parent), Some(&child)) = (
+            index.get(edge.source.as_str()),
+            index.get(edge.target.as_str()),
+        ) else {
+            continue;
+        };
+
+        if let (Some(parent_image), Some(child_image)) =
+            (process_image(&nodes[parent]), process_image(&nodes[child]))
+        {
+            if suspicious_pairs.contains(&(parent_image.clone(), child_image.clone())) {
+                add_reason(
+                    child,
+                    RULE_SUSPICIOUS_PROCESS_PAIR,
+                    format!("{parent_image} spawned {child_image}"),
+                );
+            }
+        }
+    }
+
+    let base = reasons
+        .iter()
+        .map(|node_reasons| {
+            node_reasons
+                .iter()
+                .map(|reason| reason.weight)
+                .sum::<f64>()
+                .min(MAX_SCORE)
+        })
+        .collect::<Vec<_>>();
+
+    let mut neighbours = vec![Vec::new(); nodes.len()];
+    for edge in edges {
+        if let (Some(&source), Some(&target)) = (
+            index.get(edge.source.as_str()),
+            index.get(edge.target.as_str()),
+        ) {
+            neighbours[source].push((target, edge.kind.as_str()));
+            neighbours[target].push((source, edge.kind.as_str()));
+        }
+    }
+
+    // Strongest contribution reaching each node: (amount, origin, relation).
+    let mut propagated = vec![None::<(f64, usize, &str)>; nodes.len()];
+    let mut frontier = (0..nodes.len())
+        .filter(|&node| base[node] > 0.0)
+        .map(|node| (node, base[node], node))
+        .collect::<Vec<_>>();
+
+    for _ in 0..config.propagation.max_hops {
+        let mut next = Vec::new();
+
+        for &(node, amount, origin) in &frontier {
+            let passed = amount * config.propagation.decay;
+
+            if passed < 1.0 {
+                continue;
+            }
+
+            for &(neighbour, relation) in &neighbours[node] {
+                if neighbour == origin {
+                    continue;
+                }
+
+                let stronger = propagated[neighbour].is_none_or(|(current, _, _)| passed > current);
+
+                if stronger {
+                    propagated[neighbour] = Some((passed, origin, relation));
+                    next.push((neighbour, passed, origin));
+                }
+            }
+        }
+
+        frontier = next;
+    }
+
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(position, node)| {
+            let mut node_reasons = reasons[position].clone();
+
+            if let Some((amount, origin, relation)) = propagated[position] {
+                node_reasons.push(ScoreReason {
+                    rule: RULE_PROPAGATED.to_owned(),
+                    weight: amount.round(),
+                    detail: format!(
+                        "related to {} ({relation}) scoring {}",
+                        short_id(&nodes[origin].id),
+                        base[origin].round()
+                    ),
+                });
+            }
+
+            let total = node_reasons.iter().map(|reason| reason.weight).sum::<f64>();
+
+            NodeScore {
+                node_id: node.id.clone(),
+                score: total.min(MAX_SCORE).round(),
+                reasons: node_reasons,
+            }
+        })
+        .collect()
+}
+
+/// Scores the whole case and stores the results on the nodes.
+pub(crate) async fn score_case(
+    db: &DatabaseConnection,
+    actor: &str,
+    config: &ScoringConfig,
+) -> Result<Vec<NodeScore>, String> {
+    let nodes = list_nodes_internal(db).await?;
+    let edges = list_edges_internal(db).await?;
+    let extracted = observables::list(db, None)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut scores = score(config, &nodes, &edges, &extracted);
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+
+    for node_score in &scores {
+        let reasons = serde_json::to_string(&node_score.reasons).map_err(|err| err.to_string())?;
+
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "UPDATE nodes SET threat_score = ?, threat_reasons = ? WHERE id = ?;".to_owned(),
+            vec![
+                node_score.score.into(),
+                reasons.into(),
+                node_score.node_id.clone().into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+
+    audit::append(&txn, actor, "scores.update", &scores).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    scores.retain(|node_score| node_score.score > 0.0);
+    sort_scores(&mut scores);
+    Ok(scores)
+}
+
+fn sort_scores(scores: &mut [NodeScore]) {
+    scores.sort_by(|left, right| {
+        right
+            .score
+            .total_cmp(&left.score)
+            .then(left.node_id.cmp(&right.node_id))
+    });
+}
+
+/// Stored scores at or above `min_score`, highest first.
+pub(crate) async fn list_scores<C: ConnectionTrait>(
+    conn: &C,
+    min_score: Option<f64>,
+) -> Result<Vec<NodeScore>, String> {
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            "SELECT id, threat_score, threat_reasons
+             FROM nodes
+             WHERE threat_score IS NOT NULL AND threat_score >= ? AND deleted_at IS NULL;"
+                .to_owned(),
+            vec![min_score.unwrap_or(f64::MIN_POSITIVE).into()],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut scores = rows
+        .into_iter()
+        .map(|row| NodeScore {
+            node_id: row.try_get("", "id").unwrap_or_default(),
+            score: row.try_get("", "threat_score").unwrap_or(0.0),
+            reasons: row
+                .try_get::<String>("", "threat_reasons")
+                .ok()
+                .and_then(|raw| serde_json::from_str(&raw).ok())
+                .unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
+
+    sort_scores(&mut scores);
+    Ok(scores)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_support::{create_test_db, edge, temp_dir},
+        upsert_edges_internal, upsert_nodes_internal, EdgePayload, NodePayload,
+    };
+    use serde_json::json;
+
+    fn entity(id: &str, content: &str, kind: &str, attributes: serde_json::Value) -> NodePayload {
+        NodePayload {
+            id: id.to_owned(),
+            node_type: "geo".to_owned(),
+            x: 0.0,
+            y: 0.0,
+            content: content.to_owned(),
+            width: None,
+            height: None,
+            kind: Some(kind.to_owned()),
+            attributes: Some(attributes),
+        }
+    }
+
+    const BAD_HASH: &str = "44d88612fea8a8f36de82e1278abb02f";
+
+    async fn sample_db() -> DatabaseConnection {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                entity("word", "WINWORD.EXE", "process", json!({ "image": "C:\\Program Files\\Office\\WINWORD.EXE" })),
+                entity(
+                    "ps",
+                    "powershell",
+                    "process",
+                    json!({
+                        "image": "C:\\Windows\\System32\\WindowsPowerShell\\v1.0\\powershell.exe",
+                        "commandLine": "powershell.exe -nop -w hidden -enc SQBFAFgAIAAoAE4AZQB3AC0ATwBiAGoAZQBjAHQAKQA="
+                    }),
+                ),
+                entity("drop", &format!("dropper {BAD_HASH}"), "file", json!({ "md5": BAD_HASH })),
+                entity("c2", "45.33.32.156", "ip", json!({ "address": "45.33.32.156" })),
+                entity("lan", "10.0.0.8", "ip", json!({ "address": "10.0.0.8" })),
+                entity("far", "unrelated.example", "domain", json!({ "name": "unrelated.example" })),
+            ],
+        )
+        .await
+        .unwrap();
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![
+                EdgePayload {
+                    kind: "spawned".to_owned(),
+                    ..edge("e1", "word", "ps")
+                },
+                EdgePayload {
+                    kind: "wrote".to_owned(),
+                    ..edge("e2", "ps", "drop")
+                },
+                EdgePayload {
+                    kind: "connected_to".to_owned(),
+                    ..edge("e3", "ps", "c2")
+                },
+                EdgePayload {
+                    kind: "connected_to".to_owned(),
+                    ..edge("e4", "word", "lan")
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        db
+    }
+
+    fn config() -> ScoringConfig {
+        ScoringConfig {
+            known_bad_hashes: vec![BAD_HASH.to_uppercase()],
+            ..ScoringConfig::default()
+        }
+    }
+
+    fn rules(score: &NodeScore) -> Vec<&str> {
+        score
+            .reasons
+            .iter()
+            .map(|reason| reason.rule.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn scores_rules_and_propagates_risk() {
+        let db = sample_db().await;
+        let scores = score_case(&db, "test", &config()).await.unwrap();
+        let find = |id: &str| scores.iter().find(|score| score.node_id == id);
+
+        let ps = find("shape:ps").unwrap();
+        assert_eq!(
+            rules(ps),
+            vec![
+                RULE_ENCODED_POWERSHELL,
+                RULE_SUSPICIOUS_PROCESS_PAIR,
+                RULE_PROPAGATED
+            ]
+        );
+        assert_eq!(ps.score, 100.0);
+        assert_eq!(scores[0].node_id, "shape:drop");
+
+        let drop = find("shape:drop").unwrap();
+        assert_eq!(rules(drop)[0], RULE_KNOWN_BAD_HASH);
+
+        let c2 = find("shape:c2").unwrap();
+        assert!(rules(c2).contains(&RULE_RARE_EXTERNAL_IP));
+        assert!(rules(c2).contains(&RULE_PROPAGATED));
+
+        // Private addresses are never rare-external, but risk still reaches
+        // them through relations.
+        let lan = find("shape:lan").unwrap();
+        assert_eq!(rules(lan), vec![RULE_PROPAGATED]);
+        assert!(find("shape:far").is_none());
+
+        let stored = list_scores(&db, Some(50.0)).await.unwrap();
+        assert!(stored.iter().all(|score| score.score >= 50.0));
+        assert_eq!(stored[0].reasons, scores[0].reasons);
+    }
+
+    #[test]
+    fn loads_partial_config_over_defaults() {
+        let dir = temp_dir("scoring-config");
+        let path = dir.join("scoring.json");
+
+        std::fs::write(
+            &path,
+            r#"{ "weights": { "rare_external_ip": 5 }, "knownBadHashes": ["abc"] }"#,
+        )
+        .unwrap();
+        let config = ScoringConfig::load(&path).unwrap();
+        assert_eq!(config.weight(RULE_RARE_EXTERNAL_IP), 5.0);
+        assert_eq!(config.weight(RULE_KNOWN_BAD_HASH), 80.0);
+        assert_eq!(config.known_bad_hashes, vec!["abc".to_owned()]);
+        assert!(!config.suspicious_process_pairs.is_empty());
+
+        std::fs::write(&path, r#"{ "propagation": { "decay": 3 } }"#).unwrap();
+        assert!(ScoringConfig::load(&path).is_err());
+
+        std::fs::remove_file(&path).ok();
+        assert_eq!(
+            ScoringConfig::load(&path).unwrap(),
+            ScoringConfig::default()
+        );
+    }
+}
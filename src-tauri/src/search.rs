@@ -0,0 +1,376 @@
+use sea_orm::{ConnectionTrait, DatabaseBackend, DbErr, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::{node_index_key, NodeModel, NODE_COLUMNS};
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 500;
+
+// Private-use code points mark match boundaries inside FTS5 snippets so the
+// surrounding text can be HTML-escaped before `<mark>` tags are inserted.
+const MATCH_START: char = '\u{E000}';
+const MATCH_END: char = '\u{E001}';
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SearchHit {
+    pub(crate) node: NodeModel,
+    /// HTML-escaped excerpt with matches wrapped in `<mark>`.
+    pub(crate) snippet: String,
+    /// BM25 relevance; lower is better.
+    pub(crate) score: f64,
+}
+
+fn collect_rich_text(value: &serde_json::Value, blocks: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            if let Some(serde_json::Value::String(text)) = map.get("text") {
+                match blocks.last_mut() {
+                    Some(current) => current.push_str(text),
+                    None => blocks.push(text.clone()),
+                }
+            }
+
+            if let Some(children) = map.get("content") {
+                let is_block = map.get("type").and_then(|kind| kind.as_str()) != Some("text");
+
+                if is_block {
+                    blocks.push(String::new());
+                }
+
+                collect_rich_text(children, blocks);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_rich_text(item, blocks);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" | "#39" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => entity
+            .strip_prefix("#x")
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+            .and_then(char::from_u32),
+    }
+}
+
+fn strip_html(markup: &str) -> String {
+    let mut text = String::with_capacity(markup.len());
+    let mut chars = markup.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '<' => {
+                for inner in chars.by_ref() {
+                    if inner == '>' {
+                        break;
+                    }
+                }
+                text.push(' ');
+            }
+            '&' => {
+                let mut entity = String::new();
+
+                while let Some(&next) = chars.peek() {
+                    if next == ';' || entity.len() > 8 || next.is_whitespace() {
+                        break;
+                    }
+                    entity.push(next);
+                    chars.next();
+                }
+
+                match (chars.peek(), decode_entity(&entity)) {
+                    (Some(';'), Some(decoded)) => {
+                        chars.next();
+                        text.push(decoded);
+                    }
+                    _ => {
+                        text.push('&');
+                        text.push_str(&entity);
+                    }
+                }
+            }
+            _ => text.push(ch),
+        }
+    }
+
+    text
+}
+
+/// Plain text of a node's content. tldraw rich text (ProseMirror JSON) and
+/// HTML fragments are reduced to their visible text so markup does not
+/// pollute search matches.
+pub(crate) fn plain_text_from_content(content: &str) -> String {
+    let trimmed = content.trim_start();
+
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(trimmed) {
+            let mut blocks = Vec::new();
+            collect_rich_text(&value, &mut blocks);
+
+            return blocks
+                .iter()
+                .map(|block| block.trim())
+                .filter(|block| !block.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+    }
+
+    if trimmed.contains('<') && trimmed.contains('>') || trimmed.contains('&') {
+        return strip_html(content);
+    }
+
+    content.to_owned()
+}
+
+/// Translates an analyst query into FTS5 syntax. Quoted phrases, `term*`
+/// prefixes, `AND` / `OR` / `NOT` and parentheses are passed through; every
+/// other token is quoted so punctuation in indicators (`10.0.0.8`,
+/// `evil-cdn.com`) cannot break the parser.
+pub(crate) fn build_match_query(raw: &str) -> Result<String, String> {
+    let mut parts = Vec::new();
+    let mut chars = raw.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                parts.push(ch.to_string());
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                let mut phrase = String::new();
+
+                for inner in chars.by_ref() {
+                    if inner == '"' {
+                        break;
+                    }
+                    phrase.push(inner);
+                }
+
+                if !phrase.trim().is_empty() {
+                    parts.push(format!("\"{}\"", phrase.replace('"', "\"\"")));
+                }
+            }
+            _ => {
+                let mut token = String::new();
+
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, '(' | ')' | '"') {
+                        break;
+                    }
+                    token.push(next);
+                    chars.next();
+                }
+
+                match token.as_str() {
+                    "AND" | "OR" | "NOT" => parts.push(token),
+                    _ => {
+                        let (stem, prefix) = match token.strip_suffix('*') {
+                            Some(stem) => (stem, "*"),
+                            None => (token.as_str(), ""),
+                        };
+
+                        if !stem.is_empty() {
+                            parts.push(format!("\"{}\"{prefix}", stem.replace('"', "\"\"")));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if parts.is_empty() {
+        return Err("search query must not be empty".to_owned());
+    }
+
+    Ok(parts.join(" "))
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            MATCH_START => escaped.push_str("<mark>"),
+            MATCH_END => escaped.push_str("</mark>"),
+            _ => escaped.push(ch),
+        }
+    }
+
+    escaped
+}
+
+/// Rewrites the search document for one node.
+pub(crate) async fn index_node<C: ConnectionTrait>(
+    conn: &C,
+    node_id: &str,
+    content: &str,
+) -> Result<(), DbErr> {
+    let key = node_index_key(conn, node_id).await?;
+
+    conn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "DELETE FROM nodes_fts WHERE rowid = ?;".to_owned(),
+        vec![key.into()],
+    ))
+    .await?;
+
+    conn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT INTO nodes_fts (rowid, node_id, body) VALUES (?, ?, ?);".to_owned(),
+        vec![
+            key.into(),
+            node_id.into(),
+            plain_text_from_content(content).into(),
+        ],
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Drops the search documents of the given nodes. Must run before their index
+/// keys are released.
+pub(crate) async fn remove_nodes<C: ConnectionTrait>(
+    conn: &C,
+    ids: &[String],
+) -> Result<(), DbErr> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    conn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        format!(
+            "DELETE FROM nodes_fts WHERE rowid IN (
+               SELECT seq FROM node_keys WHERE node_id IN ({})
+             );",
+            vec!["?"; ids.len()].join(", ")
+        ),
+        ids.iter().cloned().map(Into::into).collect::<Vec<Value>>(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn reindex_all<C: ConnectionTrait>(conn: &C) -> Result<(), DbErr> {
+    let rows = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, content FROM nodes;".to_owned(),
+        ))
+        .await?;
+
+    for row in rows {
+        let id: String = row.try_get("", "id")?;
+        let content: String = row.try_get("", "content").unwrap_or_default();
+        index_node(conn, &id, &content).await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn search<C: ConnectionTrait>(
+    conn: &C,
+    query: &str,
+    limit: Option<u32>,
+) -> Result<Vec<SearchHit>, String> {
+    let match_query = build_match_query(query)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {NODE_COLUMNS},
+                        snippet(nodes_fts, 1, ?, ?, '…', 16) AS snippet,
+                        bm25(nodes_fts) AS score
+                 FROM nodes_fts
+                 JOIN nodes ON nodes.id = nodes_fts.node_id
+                 WHERE nodes_fts MATCH ?
+                 ORDER BY score ASC, nodes.updated_at DESC
+                 LIMIT ?;"
+            ),
+            vec![
+                MATCH_START.to_string().into(),
+                MATCH_END.to_string().into(),
+                match_query.into(),
+                i64::from(limit).into(),
+            ],
+        ))
+        .await
+        .map_err(|err| format!("invalid search query: {err}"))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let snippet: String = row.try_get("", "snippet").unwrap_or_default();
+            let score: f64 = row.try_get("", "score").unwrap_or(0.0);
+
+            SearchHit {
+                node: NodeModel::from_row(row),
+                snippet: escape_html(&snippet),
+                score,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_rich_text_and_html_markup() {
+        let rich_text = r#"{"type":"doc","content":[
+            {"type":"paragraph","content":[{"type":"text","text":"Beacon to "},
+                {"type":"text","text":"evil.example","marks":[{"type":"bold"}]}]},
+            {"type":"paragraph","content":[{"type":"text","text":"every 60s"}]}]}"#;
+
+        assert_eq!(
+            plain_text_from_content(rich_text),
+            "Beacon to evil.example\nevery 60s"
+        );
+        assert_eq!(
+            plain_text_from_content("<p>cmd.exe &amp; <b>whoami</b></p>"),
+            " cmd.exe &  whoami  "
+        );
+        assert_eq!(plain_text_from_content("a < b"), "a < b");
+    }
+
+    #[test]
+    fn builds_fts5_queries() {
+        assert_eq!(
+            build_match_query("10.0.0.8 AND power*").unwrap(),
+            "\"10.0.0.8\" AND \"power\"*"
+        );
+        assert_eq!(
+            build_match_query("\"lateral movement\" NOT (psexec OR wmic)").unwrap(),
+            "\"lateral movement\" NOT ( \"psexec\" OR \"wmic\" )"
+        );
+        assert!(build_match_query("  \"\" ").is_err());
+    }
+}
@@ -0,0 +1,677 @@
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, QueryResult, Statement, TransactionTrait,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    audit, delete_edge_rows,
+    history::{self, HistoryAction, HistoryRevert},
+    EdgeModel, NodeModel, NODE_COLUMNS,
+};
+
+/// A named copy of the live nodes and relations of a case.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Snapshot {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) created_at: i64,
+    pub(crate) nodes: i64,
+    pub(crate) edges: i64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FieldChange {
+    /// A node column, or `attributes.<key>` for one top-level attribute.
+    pub(crate) field: String,
+    pub(crate) before: Value,
+    pub(crate) after: Value,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NodeChange {
+    pub(crate) id: String,
+    pub(crate) before_updated_at: i64,
+    pub(crate) after_updated_at: i64,
+    pub(crate) fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EdgeChange {
+    pub(crate) id: String,
+    pub(crate) fields: Vec<FieldChange>,
+}
+
+/// What changed going from `from` to `to`; `to` is `None` for the current
+/// state of the case.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SnapshotDiff {
+    pub(crate) from: String,
+    pub(crate) to: Option<String>,
+    pub(crate) added_nodes: Vec<NodeModel>,
+    pub(crate) removed_nodes: Vec<NodeModel>,
+    pub(crate) modified_nodes: Vec<NodeChange>,
+    pub(crate) added_edges: Vec<EdgeModel>,
+    pub(crate) removed_edges: Vec<EdgeModel>,
+    pub(crate) modified_edges: Vec<EdgeChange>,
+}
+
+impl Snapshot {
+    fn from_row(row: QueryResult) -> Self {
+        Self {
+            id: row.try_get("", "id").unwrap_or_default(),
+            name: row.try_get("", "name").unwrap_or_default(),
+            created_at: row.try_get("", "created_at").unwrap_or(0),
+            nodes: row.try_get("", "nodes").unwrap_or(0),
+            edges: row.try_get("", "edges").unwrap_or(0),
+        }
+    }
+}
+
+/// Nodes with their `updated_at` and edges, keyed by id.
+struct CaseState {
+    nodes: BTreeMap<String, (NodeModel, i64)>,
+    edges: BTreeMap<String, EdgeModel>,
+}
+
+const EDGE_COLUMNS: &str = "source, target, kind, label, created_at, updated_at";
+
+async fn load_state<C: ConnectionTrait>(
+    conn: &C,
+    snapshot_id: Option<&str>,
+) -> Result<CaseState, String> {
+    let (node_sql, edge_sql, values) = match snapshot_id {
+        Some(id) => (
+            "SELECT node_id AS id, type, x, y, content, width, height, kind, attributes,
+                    updated_at
+             FROM snapshot_nodes WHERE snapshot_id = ?;"
+                .to_owned(),
+            format!(
+                "SELECT edge_id AS id, {EDGE_COLUMNS}
+                 FROM snapshot_edges WHERE snapshot_id = ?;"
+            ),
+            vec![id.into()],
+        ),
+        None => (
+            format!("SELECT {NODE_COLUMNS}, updated_at FROM nodes WHERE deleted_at IS NULL;"),
+            format!("SELECT id, {EDGE_COLUMNS} FROM edges WHERE deleted_at IS NULL;"),
+            Vec::new(),
+        ),
+    };
+
+    let nodes = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            node_sql,
+            values.clone(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|row| {
+            let updated_at = row.try_get("", "updated_at").unwrap_or(0);
+            let node = NodeModel::from_row(row);
+            (node.id.clone(), (node, updated_at))
+        })
+        .collect();
+    let edges = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            edge_sql,
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|row| {
+            let edge = EdgeModel::from_row(row);
+            (edge.id.clone(), edge)
+        })
+        .collect();
+
+    Ok(CaseState { nodes, edges })
+}
+
+async fn get<C: ConnectionTrait>(conn: &C, id: &str) -> Result<Snapshot, String> {
+    conn.query_one(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "SELECT id, name, created_at,
+                (SELECT COUNT(*) FROM snapshot_nodes WHERE snapshot_id = snapshots.id) AS nodes,
+                (SELECT COUNT(*) FROM snapshot_edges WHERE snapshot_id = snapshots.id) AS edges
+         FROM snapshots WHERE id = ?;"
+            .to_owned(),
+        vec![id.trim().into()],
+    ))
+    .await
+    .map_err(|err| err.to_string())?
+    .map(Snapshot::from_row)
+    .ok_or_else(|| format!("snapshot not found: {}", id.trim()))
+}
+
+pub(crate) async fn list(db: &DatabaseConnection) -> Result<Vec<Snapshot>, String> {
+    let rows = db
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, name, created_at,
+                    (SELECT COUNT(*) FROM snapshot_nodes WHERE snapshot_id = snapshots.id) AS nodes,
+                    (SELECT COUNT(*) FROM snapshot_edges WHERE snapshot_id = snapshots.id) AS edges
+             FROM snapshots
+             ORDER BY created_at DESC, rowid DESC;"
+                .to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(Snapshot::from_row).collect())
+}
+
+/// Copies the live nodes and relations under `name`.
+pub(crate) async fn create(
+    db: &DatabaseConnection,
+    actor: &str,
+    name: &str,
+) -> Result<Snapshot, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("snapshot.name must not be empty".to_owned());
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    txn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT INTO snapshots (id, name, created_at) VALUES (?, ?, unixepoch());".to_owned(),
+        vec![id.clone().into(), name.into()],
+    ))
+    .await
+    .map_err(|err| err.to_string())?;
+    for sql in [
+        "INSERT INTO snapshot_nodes
+             (snapshot_id, node_id, type, x, y, content, width, height, kind, attributes,
+              updated_at)
+         SELECT ?, id, type, x, y, content, width, height, kind, attributes, updated_at
+         FROM nodes WHERE deleted_at IS NULL;",
+        "INSERT INTO snapshot_edges
+             (snapshot_id, edge_id, source, target, kind, label, created_at, updated_at)
+         SELECT ?, id, source, target, kind, label, created_at, updated_at
+         FROM edges WHERE deleted_at IS NULL;",
+    ] {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            sql.to_owned(),
+            vec![id.clone().into()],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+    let snapshot = get(&txn, &id).await?;
+    audit::append(&txn, actor, "snapshot.create", &snapshot).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(snapshot)
+}
+
+pub(crate) async fn delete(db: &DatabaseConnection, actor: &str, id: &str) -> Result<(), String> {
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let snapshot = get(&txn, id).await?;
+    for sql in [
+        "DELETE FROM snapshot_nodes WHERE snapshot_id = ?;",
+        "DELETE FROM snapshot_edges WHERE snapshot_id = ?;",
+        "DELETE FROM snapshots WHERE id = ?;",
+    ] {
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            sql.to_owned(),
+            vec![snapshot.id.clone().into()],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+    audit::append(&txn, actor, "snapshot.delete", &snapshot).await?;
+    txn.commit().await.map_err(|err| err.to_string())
+}
+
+fn push_change(changes: &mut Vec<FieldChange>, field: &str, before: Value, after: Value) {
+    if before != after {
+        changes.push(FieldChange {
+            field: field.to_owned(),
+            before,
+            after,
+        });
+    }
+}
+
+fn node_changes(before: &NodeModel, after: &NodeModel) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    push_change(
+        &mut changes,
+        "type",
+        json!(before.node_type),
+        json!(after.node_type),
+    );
+    push_change(&mut changes, "x", json!(before.x), json!(after.x));
+    push_change(&mut changes, "y", json!(before.y), json!(after.y));
+    push_change(
+        &mut changes,
+        "content",
+        json!(before.content),
+        json!(after.content),
+    );
+    push_change(
+        &mut changes,
+        "width",
+        json!(before.width),
+        json!(after.width),
+    );
+    push_change(
+        &mut changes,
+        "height",
+        json!(before.height),
+        json!(after.height),
+    );
+    push_change(&mut changes, "kind", json!(before.kind), json!(after.kind));
+
+    match (&before.attributes, &after.attributes) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                push_change(
+                    &mut changes,
+                    &format!("attributes.{key}"),
+                    old.get(key).cloned().unwrap_or(Value::Null),
+                    new.get(key).cloned().unwrap_or(Value::Null),
+                );
+            }
+        }
+        (old, new) => push_change(
+            &mut changes,
+            "attributes",
+            old.clone().unwrap_or(Value::Null),
+            new.clone().unwrap_or(Value::Null),
+        ),
+    }
+
+    changes
+}
+
+fn edge_changes(before: &EdgeModel, after: &EdgeModel) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    push_change(
+        &mut changes,
+        "source",
+        json!(before.source),
+        json!(after.source),
+    );
+    push_change(
+        &mut changes,
+        "target",
+        json!(before.target),
+        json!(after.target),
+    );
+    push_change(&mut changes, "kind", json!(before.kind), json!(after.kind));
+    push_change(
+        &mut changes,
+        "label",
+        json!(before.label),
+        json!(after.label),
+    );
+    changes
+}
+
+fn compare(from_id: &str, to_id: Option<&str>, from: &CaseState, to: &CaseState) -> SnapshotDiff {
+    let mut diff = SnapshotDiff {
+        from: from_id.to_owned(),
+        to: to_id.map(str::to_owned),
+        added_nodes: Vec::new(),
+        removed_nodes: Vec::new(),
+        modified_nodes: Vec::new(),
+        added_edges: Vec::new(),
+        removed_edges: Vec::new(),
+        modified_edges: Vec::new(),
+    };
+
+    for (id, (node, updated_at)) in &from.nodes {
+        match to.nodes.get(id) {
+            None => diff.removed_nodes.push(node.clone()),
+            Some((current, current_updated_at)) => {
+                let fields = node_changes(node, current);
+                if !fields.is_empty() {
+                    diff.modified_nodes.push(NodeChange {
+                        id: id.clone(),
+                        before_updated_at: *updated_at,
+                        after_updated_at: *current_updated_at,
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    diff.added_nodes = to
+        .nodes
+        .iter()
+        .filter(|(id, _)| !from.nodes.contains_key(*id))
+        .map(|(_, (node, _))| node.clone())
+        .collect();
+
+    for (id, edge) in &from.edges {
+        match to.edges.get(id) {
+            None => diff.removed_edges.push(edge.clone()),
+            Some(current) => {
+                let fields = edge_changes(edge, current);
+                if !fields.is_empty() {
+                    diff.modified_edges.push(EdgeChange {
+                        id: id.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    diff.added_edges = to
+        .edges
+        .iter()
+        .filter(|(id, _)| !from.edges.contains_key(*id))
+        .map(|(_, edge)| edge.clone())
+        .collect();
+
+    diff
+}
+
+/// Field-level differences from snapshot `from` to snapshot `to`, or to the
+/// current state when `to` is `None`.
+pub(crate) async fn diff(
+    db: &DatabaseConnection,
+    from: &str,
+    to: Option<&str>,
+) -> Result<SnapshotDiff, String> {
+    let from = get(db, from).await?;
+    let to = match to {
+        Some(to) => Some(get(db, to).await?),
+        None => None,
+    };
+    let before = load_state(db, Some(&from.id)).await?;
+    let after = load_state(db, to.as_ref().map(|snapshot| snapshot.id.as_str())).await?;
+
+    Ok(compare(
+        &from.id,
+        to.as_ref().map(|snapshot| snapshot.id.as_str()),
+        &before,
+        &after,
+    ))
+}
+
+/// Puts the case back into the state of a snapshot. Only what differs is
+/// written, as one history batch that can itself be reverted; nodes and
+/// relations added since go to the trash. Edits to relations that still
+/// exist are not part of the history, so a revert leaves those as rolled back.
+pub(crate) async fn rollback(
+    db: &DatabaseConnection,
+    actor: &str,
+    id: &str,
+) -> Result<HistoryRevert, String> {
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let snapshot = get(&txn, id).await?;
+    let saved = load_state(&txn, Some(&snapshot.id)).await?;
+    let current = load_state(&txn, None).await?;
+    let changes = compare(&snapshot.id, None, &saved, &current);
+
+    let mut targets = changes
+        .added_nodes
+        .iter()
+        .map(|node| (node.id.clone(), None))
+        .collect::<BTreeMap<_, _>>();
+    for id in changes
+        .removed_nodes
+        .iter()
+        .map(|node| &node.id)
+        .chain(changes.modified_nodes.iter().map(|change| &change.id))
+    {
+        targets.insert(
+            id.clone(),
+            saved.nodes.get(id).map(|(node, _)| node.clone()),
+        );
+    }
+    let edges = changes
+        .removed_edges
+        .iter()
+        .map(|edge| edge.id.clone())
+        .chain(
+            changes
+                .modified_edges
+                .iter()
+                .map(|change| change.id.clone()),
+        )
+        .filter_map(|id| saved.edges.get(&id).cloned())
+        .collect::<Vec<_>>();
+
+    let mut revert = history::apply_states(&txn, targets, edges).await?;
+    let restored_edges = changes
+        .removed_edges
+        .into_iter()
+        .filter(|edge| revert.edges.contains(&edge.id))
+        .collect::<Vec<_>>();
+    history::append_edges(
+        &txn,
+        &revert.batch_id,
+        HistoryAction::Restore,
+        &restored_edges,
+    )
+    .await?;
+    // Edges of nodes trashed above are already gone with them.
+    let added_edges = changes
+        .added_edges
+        .into_iter()
+        .map(|edge| edge.id)
+        .collect::<Vec<_>>();
+    revert.deleted_edges = delete_edge_rows(&txn, &revert.batch_id, &added_edges).await?;
+
+    audit::append(
+        &txn,
+        actor,
+        "snapshot.rollback",
+        &json!({ "snapshotId": snapshot.id, "rollback": revert }),
+    )
+    .await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(revert)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        delete_edges_internal, delete_nodes_internal, list_edges_internal,
+        test_support::{create_test_db, edge},
+        upsert_edges_internal, upsert_nodes_internal, NodePayload,
+    };
+
+    fn node(id: &str, content: &str, attributes: Option<Value>) -> NodePayload {
+        NodePayload {
+            id: id.to_owned(),
+            node_type: "geo".to_owned(),
+            x: 0.0,
+            y: 0.0,
+            content: content.to_owned(),
+            width: None,
+            height: None,
+            kind: attributes.as_ref().map(|_| "process".to_owned()),
+            attributes,
+        }
+    }
+
+    fn ids<T>(items: &[T], id: impl Fn(&T) -> &str) -> Vec<String> {
+        items.iter().map(|item| id(item).to_owned()).collect()
+    }
+
+    #[tokio::test]
+    async fn diffs_snapshots_and_rolls_back() {
+        let db = create_test_db().await;
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                node(
+                    "proc",
+                    "powershell.exe",
+                    Some(json!({ "pid": 4120, "user": "svc" })),
+                ),
+                node("child", "whoami.exe", None),
+                node("gone", "beacon", None),
+            ],
+        )
+        .await
+        .unwrap();
+        upsert_edges_internal(&db, "test", vec![edge("e1", "proc", "child")])
+            .await
+            .unwrap();
+        assert!(create(&db, "test", "  ").await.is_err());
+        let before = create(&db, "test", "before import").await.unwrap();
+        assert_eq!((before.nodes, before.edges), (3, 1));
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                node(
+                    "proc",
+                    "powershell.exe -enc",
+                    Some(json!({ "pid": 4120, "cmd": "-enc" })),
+                ),
+                node("new", "dropped.dll", None),
+            ],
+        )
+        .await
+        .unwrap();
+        delete_nodes_internal(&db, "test", vec!["gone".to_owned()])
+            .await
+            .unwrap();
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![edge("e2", "child", "new"), edge("e3", "child", "proc")],
+        )
+        .await
+        .unwrap();
+        delete_edges_internal(&db, "test", vec!["e1".to_owned()])
+            .await
+            .unwrap();
+
+        let changes = diff(&db, &before.id, None).await.unwrap();
+        assert_eq!(ids(&changes.added_nodes, |n| &n.id), vec!["shape:new"]);
+        assert_eq!(ids(&changes.removed_nodes, |n| &n.id), vec!["shape:gone"]);
+        assert_eq!(changes.modified_nodes.len(), 1);
+        let fields = changes.modified_nodes[0]
+            .fields
+            .iter()
+            .map(|change| {
+                (
+                    change.field.as_str(),
+                    change.before.clone(),
+                    change.after.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec![
+                (
+                    "content",
+                    json!("powershell.exe"),
+                    json!("powershell.exe -enc")
+                ),
+                ("attributes.cmd", Value::Null, json!("-enc")),
+                ("attributes.user", json!("svc"), Value::Null),
+            ]
+        );
+        assert_eq!(ids(&changes.added_edges, |e| &e.id), vec!["e2", "e3"]);
+        assert_eq!(ids(&changes.removed_edges, |e| &e.id), vec!["e1"]);
+
+        // Two snapshots compare the same way as a snapshot and the case.
+        let after = create(&db, "test", "after import").await.unwrap();
+        let between = diff(&db, &before.id, Some(&after.id)).await.unwrap();
+        assert_eq!(between.to.as_deref(), Some(after.id.as_str()));
+        assert_eq!(between.modified_nodes, changes.modified_nodes);
+        assert_eq!(between.added_edges, changes.added_edges);
+        assert!(diff(&db, &before.id, Some("missing")).await.is_err());
+
+        let rolled_back = rollback(&db, "test", &before.id).await.unwrap();
+        assert_eq!(rolled_back.deleted, vec!["shape:new"]);
+        assert_eq!(rolled_back.upserted, vec!["shape:gone", "shape:proc"]);
+        assert_eq!(rolled_back.edges, vec!["e1"]);
+        // `e2` went to the trash along with `new`.
+        assert_eq!(rolled_back.deleted_edges, vec!["e3"]);
+        let unchanged = diff(&db, &before.id, None).await.unwrap();
+        assert!(unchanged.added_nodes.is_empty() && unchanged.removed_nodes.is_empty());
+        assert!(unchanged.modified_nodes.is_empty() && unchanged.added_edges.is_empty());
+        assert!(unchanged.removed_edges.is_empty());
+        assert_eq!(
+            ids(&list_edges_internal(&db).await.unwrap(), |e| &e.id),
+            vec!["e1"]
+        );
+
+        // The rollback is an ordinary history batch.
+        let undone = history::revert_batch(&db, "test", &rolled_back.batch_id)
+            .await
+            .unwrap();
+        assert_eq!(undone.deleted_edges, vec!["e1"]);
+        let redone = diff(&db, &after.id, None).await.unwrap();
+        assert!(redone.modified_nodes.is_empty() && redone.removed_nodes.is_empty());
+        assert!(redone.added_edges.is_empty() && redone.removed_edges.is_empty());
+
+        assert_eq!(list(&db).await.unwrap().len(), 2);
+        delete(&db, "test", &after.id).await.unwrap();
+        assert_eq!(
+            ids(&list(&db).await.unwrap(), |s| &s.id),
+            vec![before.id.clone()]
+        );
+        assert!(rollback(&db, "test", &after.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rollback_of_relations_alone_can_be_reverted() {
+        let db = create_test_db().await;
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![node("a", "loader", None), node("b", "c2", None)],
+        )
+        .await
+        .unwrap();
+        let snapshot = create(&db, "test", "nodes only").await.unwrap();
+        upsert_edges_internal(&db, "test", vec![edge("e1", "a", "b")])
+            .await
+            .unwrap();
+
+        let rolled_back = rollback(&db, "test", &snapshot.id).await.unwrap();
+        assert!(rolled_back.upserted.is_empty() && rolled_back.deleted.is_empty());
+        assert_eq!(rolled_back.deleted_edges, vec!["e1"]);
+        assert!(list_edges_internal(&db).await.unwrap().is_empty());
+
+        let batches = history::list_batches(&db, 1).await.unwrap();
+        assert_eq!(batches[0].batch_id, rolled_back.batch_id);
+        assert_eq!(batches[0].deleted_edges, 1);
+        let undone = history::revert_batch(&db, "test", &rolled_back.batch_id)
+            .await
+            .unwrap();
+        assert_eq!(undone.edges, vec!["e1"]);
+        assert_eq!(
+            ids(&list_edges_internal(&db).await.unwrap(), |e| &e.id),
+            vec!["e1"]
+        );
+
+        // Restoring it again is refused once the relation is back.
+        assert_eq!(
+            history::revert_batch(&db, "test", &rolled_back.batch_id)
+                .await
+                .unwrap_err(),
+            format!("edge e1 changed after batch {}", rolled_back.batch_id)
+        );
+    }
+}
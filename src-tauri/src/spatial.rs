@@ -0,0 +1,281 @@
+use sea_orm::{ConnectionTrait, DatabaseBackend, DbErr, Statement, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::{node_index_key, NodeModel, NODE_COLUMNS};
+
+const DEFAULT_PAGE_SIZE: u32 = 500;
+const MAX_PAGE_SIZE: u32 = 5000;
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Bounds {
+    pub(crate) min_x: f64,
+    pub(crate) min_y: f64,
+    pub(crate) max_x: f64,
+    pub(crate) max_y: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NodePage {
+    pub(crate) nodes: Vec<NodeModel>,
+    /// Pass back as `after` to fetch the next page; `None` on the last page.
+    pub(crate) next_cursor: Option<i64>,
+}
+
+fn validate_bounds(bounds: &Bounds) -> Result<(), String> {
+    let values = [bounds.min_x, bounds.min_y, bounds.max_x, bounds.max_y];
+
+    if values.iter().any(|value| !value.is_finite()) {
+        return Err("bounds must be finite numbers".to_owned());
+    }
+
+    if bounds.min_x > bounds.max_x || bounds.min_y > bounds.max_y {
+        return Err("bounds minimum must not exceed maximum".to_owned());
+    }
+
+    Ok(())
+}
+
+/// Rewrites the bounding box of one node. Nodes without a recorded size are
+/// indexed as points at their origin.
+pub(crate) async fn index_node<C: ConnectionTrait>(
+    conn: &C,
+    node_id: &str,
+    x: f64,
+    y: f64,
+    width: Option<f64>,
+    height: Option<f64>,
+) -> Result<(), DbErr> {
+    let key = node_index_key(conn, node_id).await?;
+
+    conn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        "INSERT OR REPLACE INTO nodes_rtree (seq, min_x, max_x, min_y, max_y)
+         VALUES (?, ?, ?, ?, ?);"
+            .to_owned(),
+        vec![
+            key.into(),
+            x.into(),
+            (x + width.unwrap_or(0.0)).into(),
+            y.into(),
+            (y + height.unwrap_or(0.0)).into(),
+        ],
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Drops the bounding boxes of the given nodes. Must run before their index
+/// keys are released.
+pub(crate) async fn remove_nodes<C: ConnectionTrait>(
+    conn: &C,
+    ids: &[String],
+) -> Result<(), DbErr> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    conn.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Sqlite,
+        format!(
+            "DELETE FROM nodes_rtree WHERE seq IN (
+               SELECT seq FROM node_keys WHERE node_id IN ({})
+             );",
+            vec!["?"; ids.len()].join(", ")
+        ),
+        ids.iter().cloned().map(Into::into).collect::<Vec<Value>>(),
+    ))
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn reindex_all<C: ConnectionTrait>(conn: &C) -> Result<(), DbErr> {
+    let rows = conn
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, x, y, width, height FROM nodes;".to_owned(),
+        ))
+        .await?;
+
+    for row in rows {
+        let id: String = row.try_get("", "id")?;
+
+        index_node(
+            conn,
+            &id,
+            row.try_get("", "x").unwrap_or(0.0),
+            row.try_get("", "y").unwrap_or(0.0),
+            row.try_get("", "width").ok(),
+            row.try_get("", "height").ok(),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Nodes whose bounding box intersects `bounds`, in stable key order so the
+/// canvas can page through a viewport with `after`.
+pub(crate) async fn nodes_in_bounds<C: ConnectionTrait>(
+    conn: &C,
+    bounds: Bounds,
+    after: Option<i64>,
+    limit: Option<u32>,
+) -> Result<NodePage, String> {
+    validate_bounds(&bounds)?;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {NODE_COLUMNS}, nodes_rtree.seq AS cursor
+                 FROM nodes_rtree
+                 JOIN node_keys ON node_keys.seq = nodes_rtree.seq
+                 JOIN nodes ON nodes.id = node_keys.node_id
+                 WHERE nodes_rtree.max_x >= ? AND nodes_rtree.min_x <= ?
+                   AND nodes_rtree.max_y >= ? AND nodes_rtree.min_y <= ?
+                   AND nodes_rtree.seq > ?
+                 ORDER BY nodes_rtree.seq ASC
+                 LIMIT ?;"
+            ),
+            vec![
+                bounds.min_x.into(),
+                bounds.max_x.into(),
+                bounds.min_y.into(),
+                bounds.max_y.into(),
+                after.unwrap_or(0).into(),
+                (i64::from(limit) + 1).into(),
+            ],
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let has_more = rows.len() > limit as usize;
+    let mut nodes = Vec::with_capacity(rows.len().min(limit as usize));
+    let mut last_cursor = None;
+
+    for row in rows.into_iter().take(limit as usize) {
+        last_cursor = row.try_get::<i64>("", "cursor").ok();
+        nodes.push(NodeModel::from_row(row));
+    }
+
+    Ok(NodePage {
+        nodes,
+        next_cursor: if has_more { last_cursor } else { None },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        delete_nodes_internal, test_support::create_test_db, upsert_nodes_internal, NodePayload,
+    };
+
+    fn placed(id: &str, x: f64, y: f64, size: Option<f64>) -> NodePayload {
+        NodePayload {
+            id: id.to_owned(),
+            node_type: "geo".to_owned(),
+            x,
+            y,
+            content: id.to_owned(),
+            width: size,
+            height: size,
+            kind: None,
+            attributes: None,
+        }
+    }
+
+    fn viewport(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Bounds {
+        Bounds {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+
+    fn ids(page: &NodePage) -> Vec<&str> {
+        page.nodes.iter().map(|node| node.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn returns_nodes_intersecting_viewport() {
+        let db = create_test_db().await;
+
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![
+                placed("inside", 10.0, 10.0, Some(50.0)),
+                placed("overlapping", -40.0, -40.0, Some(60.0)),
+                placed("point", 99.0, 99.0, None),
+                placed("outside", 500.0, 500.0, Some(10.0)),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let page = nodes_in_bounds(&db, viewport(0.0, 0.0, 100.0, 100.0), None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&page),
+            vec!["shape:inside", "shape:overlapping", "shape:point"]
+        );
+        assert_eq!(page.next_cursor, None);
+
+        upsert_nodes_internal(&db, "test", vec![placed("outside", 20.0, 20.0, Some(10.0))])
+            .await
+            .unwrap();
+        delete_nodes_internal(&db, "test", vec!["inside".to_owned()])
+            .await
+            .unwrap();
+
+        let page = nodes_in_bounds(&db, viewport(0.0, 0.0, 100.0, 100.0), None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            ids(&page),
+            vec!["shape:overlapping", "shape:point", "shape:outside"]
+        );
+        assert!(
+            nodes_in_bounds(&db, viewport(10.0, 0.0, 0.0, 5.0), None, None)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn pages_through_large_viewports() {
+        let db = create_test_db().await;
+
+        let nodes = (0..25)
+            .map(|index| placed(&format!("n{index:02}"), index as f64 * 10.0, 0.0, Some(5.0)))
+            .collect();
+        upsert_nodes_internal(&db, "test", nodes).await.unwrap();
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let page = nodes_in_bounds(&db, viewport(0.0, 0.0, 1000.0, 10.0), cursor, Some(10))
+                .await
+                .unwrap();
+            seen.extend(page.nodes.into_iter().map(|node| node.id));
+
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        assert_eq!(seen.len(), 25);
+        assert_eq!(seen.first().map(String::as_str), Some("shape:n00"));
+        assert_eq!(seen.last().map(String::as_str), Some("shape:n24"));
+    }
+}
@@ -0,0 +1,1118 @@
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, Statement};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+
+use crate::{
+    ingest::{self, ImportPlan, ImportReport},
+    list_edges_internal, list_nodes_internal, normalize_relation_kind, normalize_shape_id,
+    search::plain_text_from_content,
+    timestamps::{format_timestamp, parse_timestamp},
+    validate_node_payload, AppState, EdgeModel, EdgePayload, NodeModel, NodePayload,
+};
+
+/// Namespace the STIX 2.1 specification fixes for deterministic SCO ids.
+const SCO_NAMESPACE: Uuid = Uuid::from_u128(0x00abedb4_aa42_466c_9c01_fed23315a9b7);
+/// Namespace for ids derived from CyberWeaver node and edge ids.
+pub(crate) const CYBERWEAVER_NAMESPACE: Uuid =
+    Uuid::from_u128(0x6f1f3a52_3c1e_4d8e_9d0e_6b8a6f3c2a10);
+
+/// Custom object for clues STIX has no type for, such as a free-standing
+/// note or an event without observables.
+const CLUE_TYPE: &str = "x-cyberweaver-clue";
+// Custom properties that let our own exports re-import without loss.
+const X_CONTENT: &str = "x_cyberweaver_content";
+const X_NODE_TYPE: &str = "x_cyberweaver_node_type";
+const X_KIND: &str = "x_cyberweaver_kind";
+const X_ATTRIBUTES: &str = "x_cyberweaver_attributes";
+
+/// Relation kinds with a standard STIX relationship type; the rest are
+/// exported with underscores turned into hyphens.
+const RELATIONSHIP_TYPES: &[(&str, &str)] = &[
+    ("connected_to", "communicates-with"),
+    ("resolved_to", "resolves-to"),
+    ("downloaded", "downloads"),
+    ("wrote", "drops"),
+    ("related_to", "related-to"),
+];
+
+struct MappedObject {
+    node_type: &'static str,
+    kind: Option<&'static str>,
+    attributes: Map<String, Value>,
+    content: String,
+    technique: Option<String>,
+}
+
+fn stix_timestamp(seconds: i64) -> String {
+    let formatted = format_timestamp(seconds);
+    format!("{}.000Z", formatted.trim_end_matches('Z'))
+}
+
+/// The object type of a well-formed STIX id, `<type>--<uuid>`.
+fn stix_id_type(id: &str) -> Option<&str> {
+    let (kind, uuid) = id.split_once("--")?;
+    let valid_type = !kind.is_empty()
+        && kind
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+
+    (valid_type && Uuid::parse_str(uuid).is_ok()).then_some(kind)
+}
+
+fn relationship_id(source: &str, kind: &str, target: &str) -> String {
+    let name = format!("{source}|{kind}|{target}");
+    format!(
+        "relationship--{}",
+        Uuid::new_v5(&CYBERWEAVER_NAMESPACE, name.as_bytes())
+    )
+}
+
+/// JSON with object keys sorted, as the spec requires for id hashing.
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let fields = map
+                .iter()
+                .collect::<BTreeMap<_, _>>()
+                .into_iter()
+                .map(|(key, value)| {
+                    format!("{}:{}", Value::from(key.as_str()), canonical_json(value))
+                })
+                .collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(canonical_json)
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        other => other.to_string(),
+    }
+}
+
+fn str_field<'a>(object: &'a Value, key: &str) -> Option<&'a str> {
+    object
+        .get(key)
+        .and_then(|value| value.as_str())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn ref_list<'a>(object: &'a Value, key: &str) -> Vec<&'a str> {
+    match object.get(key) {
+        Some(Value::Array(refs)) => refs.iter().filter_map(|id| id.as_str()).collect(),
+        Some(Value::String(id)) => vec![id.as_str()],
+        _ => Vec::new(),
+    }
+}
+
+fn insert_str(attributes: &mut Map<String, Value>, key: &str, value: Option<&str>) {
+    if let Some(value) = value {
+        attributes.insert(key.to_owned(), value.into());
+    }
+}
+
+fn mitre_external_id(object: &Value) -> Option<String> {
+    object
+        .get("external_references")?
+        .as_array()?
+        .iter()
+        .find(|reference| str_field(reference, "source_name") == Some("mitre-attack"))
+        .and_then(|reference| str_field(reference, "external_id"))
+        .map(str::to_ascii_uppercase)
+}
+
+/// Maps one SDO or SCO to clue fields. `Ok(None)` means the type is not
+/// something CyberWeaver models.
+fn map_object(
+    object: &Value,
+    index: &HashMap<&str, &Value>,
+) -> Result<Option<MappedObject>, String> {
+    let kind = str_field(object, "type").unwrap_or_default();
+    let mut attributes = Map::new();
+    let mut technique = None;
+
+    let (entity_kind, content) = match kind {
+        "ipv4-addr" | "ipv6-addr" => {
+            let value = str_field(object, "value").ok_or("missing value")?;
+            insert_str(&mut attributes, "address", Some(value));
+            (Some("ip"), value.to_owned())
+        }
+        "domain-name" => {
+            let value = str_field(object, "value").ok_or("missing value")?;
+            insert_str(&mut attributes, "name", Some(value));
+            (Some("domain"), value.to_owned())
+        }
+        "url" => {
+            let value = str_field(object, "value").ok_or("missing value")?;
+            insert_str(&mut attributes, "url", Some(value));
+            (Some("url"), value.to_owned())
+        }
+        "file" => {
+            let name = str_field(object, "name");
+            insert_str(&mut attributes, "name", name);
+
+            if let Some(size) = object.get("size").and_then(|size| size.as_u64()) {
+                attributes.insert("size".to_owned(), size.into());
+            }
+
+            if let Some(Value::Object(hashes)) = object.get("hashes") {
+                for (algorithm, value) in hashes {
+                    let key = match algorithm.to_ascii_uppercase().replace('-', "").as_str() {
+                        "MD5" => "md5",
+                        "SHA1" => "sha1",
+                        "SHA256" => "sha256",
+                        _ => continue,
+                    };
+                    if let Some(digest) = value.as_str() {
+                        attributes
+                            .insert(key.to_owned(), digest.trim().to_ascii_lowercase().into());
+                    }
+                }
+            }
+
+            let label = name
+                .map(str::to_owned)
+                .or_else(|| {
+                    ["sha256", "sha1", "md5"]
+                        .iter()
+                        .find_map(|key| attributes.get(*key)?.as_str().map(str::to_owned))
+                })
+                .unwrap_or_else(|| "file".to_owned());
+            (Some("file"), label)
+        }
+        "process" => {
+            if let Some(pid) = object.get("pid").and_then(|pid| pid.as_u64()) {
+                attributes.insert("pid".to_owned(), pid.into());
+            }
+
+            let command_line = str_field(object, "command_line");
+            let image = str_field(object, "image_ref")
+                .and_then(|id| index.get(id))
+                .and_then(|file| str_field(file, "name"));
+            insert_str(&mut attributes, "commandLine", command_line);
+            insert_str(&mut attributes, "image", image);
+
+            let label = command_line
+                .or(image)
+                .map(str::to_owned)
+                .or_else(|| attributes.get("pid").map(|pid| format!("pid {pid}")))
+                .unwrap_or_else(|| "process".to_owned());
+            (Some("process"), label)
+        }
+        "user-account" => {
+            let user_id = str_field(object, "user_id");
+            let name = str_field(object, "account_login")
+                .or_else(|| str_field(object, "display_name"))
+                .or(user_id)
+                .ok_or("user account has no login, display name or user id")?;
+            insert_str(&mut attributes, "name", Some(name));
+            insert_str(
+                &mut attributes,
+                "sid",
+                user_id.filter(|id| id.starts_with("S-")),
+            );
+            (Some("user"), name.to_owned())
+        }
+        "windows-registry-key" => {
+            let key = str_field(object, "key").ok_or("missing key")?;
+            insert_str(&mut attributes, "path", Some(key));
+            (Some("registry_key"), key.to_owned())
+        }
+        "email-message" => {
+            let subject = str_field(object, "subject");
+            insert_str(&mut attributes, "subject", subject);
+            (Some("email"), subject.unwrap_or("email").to_owned())
+        }
+        "infrastructure" => {
+            let name = str_field(object, "name").ok_or("missing name")?;
+            insert_str(&mut attributes, "hostname", Some(name));
+            (Some("host"), name.to_owned())
+        }
+        "indicator" => {
+            let pattern = str_field(object, "pattern").ok_or("missing pattern")?;
+            let name = str_field(object, "name");
+            insert_str(&mut attributes, "pattern", Some(pattern));
+            insert_str(
+                &mut attributes,
+                "patternType",
+                str_field(object, "pattern_type"),
+            );
+            insert_str(&mut attributes, "name", name);
+            insert_str(
+                &mut attributes,
+                "validFrom",
+                str_field(object, "valid_from"),
+            );
+            (Some("indicator"), name.unwrap_or(pattern).to_owned())
+        }
+        "malware" => {
+            let name = str_field(object, "name")
+                .map(str::to_owned)
+                .or_else(|| {
+                    let types = ref_list(object, "malware_types");
+                    (!types.is_empty()).then(|| types.join(", "))
+                })
+                .ok_or("malware has no name or malware types")?;
+            attributes.insert("name".to_owned(), name.clone().into());
+            (Some("malware"), name)
+        }
+        "attack-pattern" => {
+            let name = str_field(object, "name").ok_or("missing name")?;
+            let external_id = mitre_external_id(object);
+            insert_str(&mut attributes, "name", Some(name));
+            insert_str(&mut attributes, "externalId", external_id.as_deref());
+            technique = external_id.clone();
+
+            let label = match &external_id {
+                Some(id) => format!("{id} {name}"),
+                None => name.to_owned(),
+            };
+            (Some("attack_pattern"), label)
+        }
+        "observed-data" => {
+            insert_str(
+                &mut attributes,
+                "timestamp",
+                str_field(object, "first_observed"),
+            );
+            (Some("event"), "Observed data".to_owned())
+        }
+        "note" => (
+            None,
+            str_field(object, "content").unwrap_or_default().to_owned(),
+        ),
+        CLUE_TYPE => {
+            let kind = str_field(object, X_KIND)
+                .map(|kind| {
+                    crate::entities::normalize_entity_kind(kind)
+                        .ok_or_else(|| format!("unsupported entity kind: {kind}"))
+                })
+                .transpose()?;
+            (
+                kind,
+                str_field(object, "name").unwrap_or_default().to_owned(),
+            )
+        }
+        _ => return Ok(None),
+    };
+
+    if let (Some(_), Some(Value::Object(original))) = (entity_kind, object.get(X_ATTRIBUTES)) {
+        attributes = original.clone();
+    }
+
+    let node_type = match str_field(object, X_NODE_TYPE) {
+        Some("text") => "text",
+        Some("note") => "note",
+        Some(_) => "geo",
+        None if kind == "note" => "note",
+        None => "geo",
+    };
+
+    Ok(Some(MappedObject {
+        node_type,
+        kind: entity_kind,
+        attributes,
+        content: object
+            .get(X_CONTENT)
+            .and_then(|content| content.as_str())
+            .map(str::to_owned)
+            .unwrap_or(content),
+        technique,
+    }))
+}
+
+pub(crate) fn relation_from_stix(relationship_type: &str) -> (&'static str, Option<String>) {
+    let relationship_type = relationship_type.trim().to_ascii_lowercase();
+
+    if let Some((kind, _)) = RELATIONSHIP_TYPES
+        .iter()
+        .find(|(_, stix)| *stix == relationship_type)
+    {
+        return (kind, None);
+    }
+
+    match normalize_relation_kind(&relationship_type.replace('-', "_")) {
+        Some(kind) => (kind, None),
+        None => ("related_to", Some(relationship_type)),
+    }
+}
+
+pub(crate) fn relation_to_stix(kind: &str) -> String {
+    RELATIONSHIP_TYPES
+        .iter()
+        .find(|(ours, _)| *ours == kind)
+        .map(|(_, stix)| (*stix).to_owned())
+        .unwrap_or_else(|| kind.replace('_', "-"))
+}
+
+fn edge_payload(
+    id: &str,
+    source: &str,
+    target: &str,
+    kind: &str,
+    label: Option<String>,
+) -> EdgePayload {
+    EdgePayload {
+        id: id.to_owned(),
+        source: normalize_shape_id(source),
+        target: normalize_shape_id(target),
+        kind: kind.to_owned(),
+        label,
+    }
+}
+
+/// Turns a bundle into node and edge payloads. Positions are filled in by
+/// the caller.
+fn plan_import(raw: &str) -> Result<ImportPlan, String> {
+    let bundle =
+        serde_json::from_str::<Value>(raw).map_err(|err| format!("invalid STIX bundle: {err}"))?;
+
+    if str_field(&bundle, "type") != Some("bundle") {
+        return Err("STIX document is not a bundle".to_owned());
+    }
+
+    let objects = bundle
+        .get("objects")
+        .and_then(|objects| objects.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let index = objects
+        .iter()
+        .filter_map(|object| Some((str_field(object, "id")?, object)))
+        .collect::<HashMap<_, _>>();
+
+    let mut plan = ImportPlan::default();
+    let mut imported = BTreeSet::new();
+
+    for object in objects {
+        let Some(id) = str_field(object, "id").filter(|id| stix_id_type(id).is_some()) else {
+            plan.skipped
+                .push("(object without a valid id): skipped".to_owned());
+            continue;
+        };
+
+        if str_field(object, "type") == Some("relationship") {
+            continue;
+        }
+
+        let mapped = match map_object(object, &index) {
+            Ok(Some(mapped)) => mapped,
+            Ok(None) => {
+                plan.skipped.push(format!("{id}: unsupported type"));
+                continue;
+            }
+            Err(reason) => {
+                plan.skipped.push(format!("{id}: {reason}"));
+                continue;
+            }
+        };
+
+        let node = NodePayload {
+            id: normalize_shape_id(id),
+            node_type: mapped.node_type.to_owned(),
+            x: 0.0,
+            y: 0.0,
+            content: mapped.content,
+            width: None,
+            height: None,
+            kind: mapped.kind.map(str::to_owned),
+            attributes: mapped.kind.map(|_| Value::Object(mapped.attributes)),
+        };
+
+        if let Err(reason) = validate_node_payload(&node) {
+            plan.skipped.push(format!("{id}: {reason}"));
+            continue;
+        }
+
+        if let Some(technique) = mapped.technique {
+            plan.techniques.push((node.id.clone(), technique));
+        }
+
+        imported.insert(id);
+        plan.nodes.push(node);
+    }
+
+    let mut edges = BTreeMap::new();
+    let mut linked = BTreeSet::new();
+
+    for object in objects
+        .iter()
+        .filter(|object| str_field(object, "type") == Some("relationship"))
+    {
+        let Some(id) = str_field(object, "id").filter(|id| stix_id_type(id).is_some()) else {
+            continue;
+        };
+        let (Some(source), Some(target)) = (
+            str_field(object, "source_ref"),
+            str_field(object, "target_ref"),
+        ) else {
+            plan.skipped
+                .push(format!("{id}: missing source_ref or target_ref"));
+            continue;
+        };
+
+        if !imported.contains(source) || !imported.contains(target) {
+            plan.skipped
+                .push(format!("{id}: references an object that was not imported"));
+            continue;
+        }
+
+        let (kind, fallback_label) =
+            relation_from_stix(str_field(object, "relationship_type").unwrap_or_default());
+        let label = str_field(object, "description")
+            .map(str::to_owned)
+            .or(fallback_label);
+
+        linked.insert((source.min(target), source.max(target)));
+        edges.insert(id.to_owned(), edge_payload(id, source, target, kind, label));
+    }
+
+    // Embedded references become relations with ids derived from both ends,
+    // unless a relationship object already links the pair. Our own exports
+    // carry both, and must not come back as duplicate relations.
+    let embedded = [
+        ("parent_ref", "spawned", true),
+        ("child_refs", "spawned", false),
+        ("resolves_to_refs", "resolved_to", false),
+        ("object_refs", "related_to", false),
+    ];
+
+    for object in objects {
+        let Some(id) = str_field(object, "id").filter(|id| imported.contains(id)) else {
+            continue;
+        };
+
+        for (key, kind, reversed) in embedded {
+            for target in ref_list(object, key)
+                .into_iter()
+                .filter(|target| imported.contains(target))
+            {
+                let (source, target) = if reversed { (target, id) } else { (id, target) };
+
+                if !linked.contains(&(source.min(target), source.max(target))) {
+                    let edge_id = relationship_id(source, kind, target);
+                    edges.insert(
+                        edge_id.clone(),
+                        edge_payload(&edge_id, source, target, kind, None),
+                    );
+                }
+            }
+        }
+    }
+
+    plan.edges = edges.into_values().collect();
+    Ok(plan)
+}
+
+/// Imports a STIX 2.1 bundle into the open case. Objects keep their STIX
+/// ids as node ids, so importing the same bundle twice updates in place.
+pub(crate) async fn import(state: &AppState, raw: &str) -> Result<ImportReport, String> {
+    ingest::apply(state, plan_import(raw)?).await
+}
+
+fn attribute<'a>(node: &'a NodeModel, key: &str) -> Option<&'a str> {
+    node.attributes
+        .as_ref()?
+        .get(key)?
+        .as_str()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn file_hashes(node: &NodeModel) -> Map<String, Value> {
+    let mut hashes = Map::new();
+
+    if node.kind.as_deref() == Some("hash") {
+        if let (Some(algorithm), Some(value)) =
+            (attribute(node, "algorithm"), attribute(node, "value"))
+        {
+            let name = match algorithm.to_ascii_lowercase().as_str() {
+                "md5" => "MD5",
+                "sha1" => "SHA-1",
+                _ => "SHA-256",
+            };
+            hashes.insert(name.to_owned(), value.to_ascii_lowercase().into());
+        }
+    }
+
+    for (key, name) in [("md5", "MD5"), ("sha1", "SHA-1"), ("sha256", "SHA-256")] {
+        if let Some(value) = attribute(node, key) {
+            hashes.insert(name.to_owned(), value.to_ascii_lowercase().into());
+        }
+    }
+
+    hashes
+}
+
+fn file_name(node: &NodeModel) -> Option<&str> {
+    attribute(node, "name")
+        .or_else(|| attribute(node, "path").and_then(|path| path.rsplit(['\\', '/']).next()))
+}
+
+/// STIX Cyber-observable type for entity kinds that have one.
+fn sco_type(node: &NodeModel) -> Option<&'static str> {
+    match node.kind.as_deref()? {
+        "ip" => Some(
+            match attribute(node, "address")?
+                .parse::<std::net::IpAddr>()
+                .ok()?
+            {
+                std::net::IpAddr::V4(_) => "ipv4-addr",
+                std::net::IpAddr::V6(_) => "ipv6-addr",
+            },
+        ),
+        "domain" => attribute(node, "name").map(|_| "domain-name"),
+        "url" => attribute(node, "url").map(|_| "url"),
+        "file" | "hash" => {
+            (file_name(node).is_some() || !file_hashes(node).is_empty()).then_some("file")
+        }
+        "process" => Some("process"),
+        "user" => attribute(node, "name").map(|_| "user-account"),
+        "registry_key" => attribute(node, "path").map(|_| "windows-registry-key"),
+        "email" => Some("email-message"),
+        _ => None,
+    }
+}
+
+fn is_sco(object_type: &str) -> bool {
+    matches!(
+        object_type,
+        "ipv4-addr"
+            | "ipv6-addr"
+            | "domain-name"
+            | "url"
+            | "file"
+            | "process"
+            | "user-account"
+            | "windows-registry-key"
+            | "email-message"
+    )
+}
+
+fn object_type(node: &NodeModel, has_neighbours: bool, has_observables: bool) -> &'static str {
+    if let Some(sco) = sco_type(node) {
+        return sco;
+    }
+
+    match node.kind.as_deref() {
+        Some("host") if attribute(node, "hostname").is_some() => "infrastructure",
+        Some("indicator") if attribute(node, "pattern").is_some() => "indicator",
+        Some("malware") if attribute(node, "name").is_some() => "malware",
+        Some("attack_pattern") if attribute(node, "name").is_some() => "attack-pattern",
+        Some("event") if has_observables => "observed-data",
+        None if has_neighbours => "note",
+        _ => CLUE_TYPE,
+    }
+}
+
+/// Properties the spec hashes into an SCO id; empty when the type has none.
+fn contributing_properties(object_type: &str, node: &NodeModel) -> Map<String, Value> {
+    let mut properties = Map::new();
+
+    match object_type {
+        "ipv4-addr" | "ipv6-addr" => {
+            insert_str(&mut properties, "value", attribute(node, "address"))
+        }
+        "domain-name" => insert_str(&mut properties, "value", attribute(node, "name")),
+        "url" => insert_str(&mut properties, "value", attribute(node, "url")),
+        "file" => {
+            let hashes = file_hashes(node);
+
+            // Only the most preferred hash contributes to the id.
+            if let Some((name, value)) = ["MD5", "SHA-1", "SHA-256"]
+                .iter()
+                .find_map(|name| Some((*name, hashes.get(*name)?.clone())))
+            {
+                properties.insert("hashes".to_owned(), json!({ name: value }));
+            }
+
+            insert_str(&mut properties, "name", file_name(node));
+        }
+        "user-account" => {
+            insert_str(&mut properties, "account_login", attribute(node, "name"));
+            insert_str(&mut properties, "user_id", attribute(node, "sid"));
+        }
+        "windows-registry-key" => insert_str(&mut properties, "key", attribute(node, "path")),
+        "email-message" => insert_str(&mut properties, "subject", attribute(node, "subject")),
+        _ => {}
+    }
+
+    properties
+}
+
+/// Reuses the node's own id when it already is a STIX id of the right type,
+/// so imported objects export under their original ids.
+fn stix_id(object_type: &str, node: &NodeModel) -> String {
+    let own = node.id.strip_prefix("shape:").unwrap_or(&node.id);
+
+    if stix_id_type(own) == Some(object_type) {
+        return own.to_owned();
+    }
+
+    let properties = contributing_properties(object_type, node);
+    let uuid = if is_sco(object_type) && !properties.is_empty() {
+        Uuid::new_v5(
+            &SCO_NAMESPACE,
+            canonical_json(&Value::Object(properties)).as_bytes(),
+        )
+    } else {
+        Uuid::new_v5(&CYBERWEAVER_NAMESPACE, node.id.as_bytes())
+    };
+
+    format!("{object_type}--{uuid}")
+}
+
+fn label(node: &NodeModel) -> String {
+    plain_text_from_content(&node.content)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_owned)
+        .unwrap_or_else(|| {
+            node.id
+                .strip_prefix("shape:")
+                .unwrap_or(&node.id)
+                .to_owned()
+        })
+}
+
+fn export_object(
+    node: &NodeModel,
+    object_type: &str,
+    id: String,
+    refs: Vec<String>,
+    updated_at: i64,
+) -> Value {
+    let mut object = Map::new();
+    object.insert("type".to_owned(), object_type.into());
+    object.insert("spec_version".to_owned(), "2.1".into());
+    object.insert("id".to_owned(), id.into());
+
+    let modified = stix_timestamp(updated_at);
+
+    if !is_sco(object_type) {
+        object.insert("created".to_owned(), modified.clone().into());
+        object.insert("modified".to_owned(), modified.clone().into());
+    }
+
+    match object_type {
+        "ipv4-addr"
+        | "ipv6-addr"
+        | "domain-name"
+        | "url"
+        | "windows-registry-key"
+        | "email-message" => {
+            object.extend(contributing_properties(object_type, node));
+
+            if object_type == "email-message" {
+                object.insert("is_multipart".to_owned(), false.into());
+            }
+        }
+        "file" => {
+            insert_str(&mut object, "name", file_name(node));
+
+            if let Some(size) = node
+                .attributes
+                .as_ref()
+                .and_then(|attributes| attributes.get("size")?.as_u64())
+            {
+                object.insert("size".to_owned(), size.into());
+            }
+
+            let hashes = file_hashes(node);
+            if !hashes.is_empty() {
+                object.insert("hashes".to_owned(), Value::Object(hashes));
+            }
+        }
+        "process" => {
+            if let Some(pid) = node
+                .attributes
+                .as_ref()
+                .and_then(|attributes| attributes.get("pid")?.as_u64())
+            {
+                object.insert("pid".to_owned(), pid.into());
+            }
+
+            insert_str(&mut object, "command_line", attribute(node, "commandLine"));
+        }
+        "user-account" => object.extend(contributing_properties(object_type, node)),
+        "infrastructure" => insert_str(&mut object, "name", attribute(node, "hostname")),
+        "indicator" => {
+            insert_str(&mut object, "name", attribute(node, "name"));
+            insert_str(&mut object, "pattern", attribute(node, "pattern"));
+            object.insert(
+                "pattern_type".to_owned(),
+                attribute(node, "patternType").unwrap_or("stix").into(),
+            );
+            object.insert(
+                "valid_from".to_owned(),
+                attribute(node, "validFrom")
+                    .map(str::to_owned)
+                    .unwrap_or_else(|| modified.clone())
+                    .into(),
+            );
+        }
+        "malware" => {
+            insert_str(&mut object, "name", attribute(node, "name"));
+            object.insert("is_family".to_owned(), false.into());
+        }
+        "attack-pattern" => {
+            insert_str(&mut object, "name", attribute(node, "name"));
+
+            if let Some(external_id) = attribute(node, "externalId") {
+                object.insert(
+                    "external_references".to_owned(),
+                    json!([{ "source_name": "mitre-attack", "external_id": external_id }]),
+                );
+            }
+        }
+        "observed-data" => {
+            let observed = node
+                .attributes
+                .as_ref()
+                .and_then(|attributes| attributes.get("timestamp"))
+                .and_then(parse_timestamp)
+                .map(|seconds| stix_timestamp(seconds.floor() as i64))
+                .unwrap_or_else(|| modified.clone());
+            object.insert("first_observed".to_owned(), observed.clone().into());
+            object.insert("last_observed".to_owned(), observed.into());
+            object.insert("number_observed".to_owned(), 1.into());
+            object.insert("object_refs".to_owned(), refs.into());
+        }
+        "note" => {
+            object.insert("content".to_owned(), label(node).into());
+            object.insert("object_refs".to_owned(), refs.into());
+        }
+        _ => {
+            object.insert("name".to_owned(), label(node).into());
+            object.insert(X_KIND.to_owned(), node.kind.clone().into());
+        }
+    }
+
+    object.insert(X_CONTENT.to_owned(), node.content.clone().into());
+    object.insert(X_NODE_TYPE.to_owned(), node.node_type.clone().into());
+
+    if let Some(attributes) = &node.attributes {
+        object.insert(X_ATTRIBUTES.to_owned(), attributes.clone());
+    }
+
+    Value::Object(object)
+}
+
+/// Builds a bundle from the case. Every id is derived from the clue it
+/// describes, so exporting an unchanged case yields the same ids and an
+/// exported bundle re-imports onto the same nodes and relations.
+fn build_bundle(
+    case_id: &str,
+    nodes: &[NodeModel],
+    edges: &[EdgeModel],
+    updated_at: &HashMap<String, i64>,
+) -> Value {
+    let mut neighbours = HashMap::<&str, BTreeSet<&str>>::new();
+    for edge in edges {
+        neighbours
+            .entry(edge.source.as_str())
+            .or_default()
+            .insert(edge.target.as_str());
+        neighbours
+            .entry(edge.target.as_str())
+            .or_default()
+            .insert(edge.source.as_str());
+    }
+
+    let by_id = nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node))
+        .collect::<HashMap<_, _>>();
+    let observable_ids = nodes
+        .iter()
+        .filter_map(|node| {
+            let object_type = sco_type(node)?;
+            Some((node.id.as_str(), stix_id(object_type, node)))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut types = HashMap::new();
+    let mut ids = HashMap::new();
+    for node in nodes {
+        let linked = neighbours
+            .get(node.id.as_str())
+            .map(|linked| {
+                linked
+                    .iter()
+                    .filter(|id| by_id.contains_key(*id))
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let has_observables = linked.iter().any(|id| observable_ids.contains_key(id));
+        let object_type = object_type(node, !linked.is_empty(), has_observables);
+
+        types.insert(node.id.as_str(), (object_type, linked));
+        ids.insert(node.id.as_str(), stix_id(object_type, node));
+    }
+
+    let mut objects = nodes
+        .iter()
+        .map(|node| {
+            let (object_type, linked) = &types[node.id.as_str()];
+            let mut refs = match *object_type {
+                "observed-data" => linked
+                    .iter()
+                    .filter_map(|id| observable_ids.get(id).cloned())
+                    .collect::<Vec<_>>(),
+                "note" => linked.iter().map(|id| ids[id].clone()).collect(),
+                _ => Vec::new(),
+            };
+            refs.sort();
+
+            export_object(
+                node,
+                object_type,
+                ids[node.id.as_str()].clone(),
+                refs,
+                updated_at.get(&node.id).copied().unwrap_or(0),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    for edge in edges {
+        let (Some(source), Some(target)) =
+            (ids.get(edge.source.as_str()), ids.get(edge.target.as_str()))
+        else {
+            continue;
+        };
+        let id = if stix_id_type(&edge.id) == Some("relationship") {
+            edge.id.clone()
+        } else {
+            format!(
+                "relationship--{}",
+                Uuid::new_v5(&CYBERWEAVER_NAMESPACE, edge.id.as_bytes())
+            )
+        };
+
+        let mut relationship = json!({
+            "type": "relationship",
+            "spec_version": "2.1",
+            "id": id,
+            "created": stix_timestamp(edge.created_at),
+            "modified": stix_timestamp(edge.updated_at),
+            "relationship_type": relation_to_stix(&edge.kind),
+            "source_ref": source,
+            "target_ref": target,
+        });
+
+        if let Some(label) = edge
+            .label
+            .as_deref()
+            .filter(|label| !label.trim().is_empty())
+        {
+            relationship["description"] = label.into();
+        }
+
+        objects.push(relationship);
+    }
+
+    objects.sort_by(|left, right| {
+        str_field(left, "id")
+            .unwrap_or_default()
+            .cmp(str_field(right, "id").unwrap_or_default())
+    });
+
+    json!({
+        "type": "bundle",
+        "id": format!("bundle--{}", Uuid::new_v5(&CYBERWEAVER_NAMESPACE, case_id.as_bytes())),
+        "objects": objects,
+    })
+}
+
+pub(crate) async fn node_updated_at(
+    db: &DatabaseConnection,
+) -> Result<HashMap<String, i64>, String> {
+    let rows = db
+        .query_all(Statement::from_string(
+            DatabaseBackend::Sqlite,
+            "SELECT id, updated_at FROM nodes WHERE deleted_at IS NULL;".to_owned(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            (
+                row.try_get::<String>("", "id").unwrap_or_default(),
+                row.try_get::<i64>("", "updated_at").unwrap_or(0),
+            )
+        })
+        .collect())
+}
+
+pub(crate) async fn export(db: &DatabaseConnection, case_id: &str) -> Result<Value, String> {
+    let nodes = list_nodes_internal(db).await?;
+    let edges = list_edges_internal(db).await?;
+    let updated_at = node_updated_at(db).await?;
+
+    Ok(build_bundle(case_id, &nodes, &edges, &updated_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{attack, changes::ChangeOrigin, test_support::test_state};
+
+    const FIXTURE: &str = include_str!("../fixtures/stix/intrusion-bundle.json");
+
+    /// Drops timestamps, which follow the clues' update times.
+    fn without_timestamps(mut bundle: Value) -> Value {
+        for object in bundle["objects"].as_array_mut().unwrap() {
+            let object = object.as_object_mut().unwrap();
+            object.remove("created");
+            object.remove("modified");
+        }
+
+        bundle
+    }
+
+    #[test]
+    fn derives_spec_sco_ids() {
+        let node = NodeModel {
+            id: "shape:ip".to_owned(),
+            node_type: "geo".to_owned(),
+            x: 0.0,
+            y: 0.0,
+            content: String::new(),
+            width: None,
+            height: None,
+            kind: Some("ip".to_owned()),
+            attributes: Some(json!({ "address": "198.51.100.3" })),
+        };
+
+        // UUIDv5 of `{"value":"198.51.100.3"}` in the SCO namespace.
+        assert_eq!(
+            stix_id("ipv4-addr", &node),
+            "ipv4-addr--28bb3599-77cd-5a82-a950-b5bc3caf07c4"
+        );
+    }
+
+    #[tokio::test]
+    async fn imports_fixture_bundle() {
+        let state = test_state("import").await;
+        let report = import(&state, FIXTURE).await.unwrap();
+
+        assert_eq!(report.nodes, 9);
+        assert_eq!(report.edges, 8);
+        assert_eq!(report.techniques_tagged, 1);
+        assert_eq!(
+            report.skipped,
+            vec![
+                "identity--3b1e8f1a-0d5c-4c43-9a0f-6b5cc1a7f2b1: unsupported type",
+                "relationship--9c0a3c7b-1f9e-4a5e-8f61-2d8d7a0b5e42: references an object that was not imported",
+            ]
+        );
+
+        let db = state.db().await.unwrap();
+        let nodes = list_nodes_internal(&db).await.unwrap();
+        let node = |id: &str| {
+            nodes
+                .iter()
+                .find(|node| node.id == format!("shape:{id}"))
+                .unwrap()
+        };
+
+        let process = node("process--f52a906a-0dfc-40bd-92f1-e7778ead38a9");
+        assert_eq!(process.kind.as_deref(), Some("process"));
+        assert_eq!(
+            process.attributes.as_ref().unwrap()["image"],
+            "powershell.exe"
+        );
+        let file = node("file--364fe3e5-b1f4-5ba3-b951-ee5983b3538d");
+        assert_eq!(
+            file.attributes.as_ref().unwrap()["sha256"],
+            "fe90a7e910cb3a4739bed9180e807e93fa70c90f25a8915476f5e4bfbac681db"
+        );
+        assert!(nodes
+            .iter()
+            .all(|node| node.x.is_finite() && node.y.is_finite()));
+
+        let edges = list_edges_internal(&db).await.unwrap();
+        let kinds = edges
+            .iter()
+            .map(|edge| edge.kind.as_str())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            kinds,
+            BTreeSet::from(["connected_to", "related_to", "resolved_to", "spawned"])
+        );
+        let indicates = edges
+            .iter()
+            .find(|edge| edge.label.as_deref() == Some("indicates"))
+            .unwrap();
+        assert_eq!(indicates.kind, "related_to");
+
+        let tags = attack::node_techniques(&db, None).await.unwrap();
+        assert_eq!(tags[0].technique_id, "T1059.001");
+
+        // Importing the same bundle again updates in place.
+        let positions = nodes
+            .iter()
+            .map(|node| (node.id.clone(), (node.x, node.y)))
+            .collect::<HashMap<_, _>>();
+        import(&state, FIXTURE).await.unwrap();
+        let again = list_nodes_internal(&db).await.unwrap();
+        assert_eq!(again.len(), nodes.len());
+        assert!(again
+            .iter()
+            .all(|node| positions[&node.id] == (node.x, node.y)));
+        assert_eq!(list_edges_internal(&db).await.unwrap().len(), edges.len());
+    }
+
+    #[tokio::test]
+    async fn export_round_trips_through_import() {
+        let state = test_state("round-trip").await;
+        import(&state, FIXTURE).await.unwrap();
+        state
+            .upsert_nodes(
+                ChangeOrigin::Webview,
+                vec![NodePayload {
+                    id: "analyst-note".to_owned(),
+                    node_type: "note".to_owned(),
+                    x: 0.0,
+                    y: 0.0,
+                    content: "Check the proxy logs".to_owned(),
+                    width: None,
+                    height: None,
+                    kind: None,
+                    attributes: None,
+                }],
+            )
+            .await
+            .unwrap();
+
+        let exported = export(&state.db().await.unwrap(), "case-a").await.unwrap();
+        let objects = exported["objects"].as_array().unwrap();
+        assert!(objects
+            .iter()
+            .all(|object| stix_id_type(object["id"].as_str().unwrap()).is_some()));
+        assert!(objects
+            .iter()
+            .any(|object| object["type"] == "observed-data"));
+        assert!(objects.iter().any(|object| object["type"] == CLUE_TYPE));
+        assert_eq!(
+            export(&state.db().await.unwrap(), "case-a").await.unwrap(),
+            exported
+        );
+
+        let copy = test_state("round-trip-copy").await;
+        let report = import(&copy, &exported.to_string()).await.unwrap();
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+
+        let reexported = export(&copy.db().await.unwrap(), "case-a").await.unwrap();
+        assert_eq!(without_timestamps(reexported), without_timestamps(exported));
+    }
+}
@@ -0,0 +1,451 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{header::ORIGIN, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{net::TcpListener, sync::broadcast::error::RecvError};
+
+use crate::{
+    changes::{Change, ChangeOrigin},
+    list_edges_internal, list_nodes_internal, AppState, EdgeModel, EdgePayload, NodeModel,
+    NodePayload,
+};
+
+/// Version of the JSON message protocol; every message carries it as `v`.
+pub(crate) const PROTOCOL_VERSION: u32 = 1;
+
+const CHANGE_ORIGIN: ChangeOrigin = ChangeOrigin::SyncClient;
+
+const PORT_ENV: &str = "CYBERWEAVER_SYNC_PORT";
+const ALLOWED_ORIGINS_ENV: &str = "CYBERWEAVER_SYNC_ALLOWED_ORIGINS";
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SyncServerConfig {
+    pub(crate) port: u16,
+    /// Browser origins allowed to connect. Requests without an `Origin`
+    /// header (scripts, CLI tools) are always accepted; any other origin is
+    /// rejected so web pages cannot reach the case through localhost.
+    pub(crate) allowed_origins: Vec<String>,
+}
+
+impl SyncServerConfig {
+    /// Reads the configuration from the environment. The server is disabled
+    /// unless `CYBERWEAVER_SYNC_PORT` is set.
+    pub(crate) fn from_env() -> Result<Option<Self>, String> {
+        let Ok(raw_port) = std::env::var(PORT_ENV) else {
+            return Ok(None);
+        };
+
+        let port = raw_port
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| format!("{PORT_ENV} must be a TCP port number: {raw_port}"))?;
+
+        let allowed_origins = std::env::var(ALLOWED_ORIGINS_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .map(|origin| origin.trim().to_owned())
+            .filter(|origin| !origin.is_empty())
+            .collect();
+
+        Ok(Some(Self {
+            port,
+            allowed_origins,
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op")]
+enum ClientOp {
+    #[serde(rename = "ping")]
+    Ping,
+    #[serde(rename = "nodes.list")]
+    ListNodes,
+    #[serde(rename = "nodes.upsert")]
+    UpsertNodes { nodes: Vec<NodePayload> },
+    #[serde(rename = "nodes.delete")]
+    DeleteNodes { ids: Vec<String> },
+    #[serde(rename = "edges.list")]
+    ListEdges,
+    #[serde(rename = "edges.upsert")]
+    UpsertEdges { edges: Vec<EdgePayload> },
+    #[serde(rename = "edges.delete")]
+    DeleteEdges { ids: Vec<String> },
+    #[serde(rename = "canvas.focus")]
+    FocusNode { id: String },
+    #[serde(rename = "canvas.select")]
+    SelectNodes { ids: Vec<String> },
+    #[serde(rename = "canvas.highlight", rename_all = "camelCase")]
+    HighlightPath {
+        #[serde(default)]
+        node_ids: Vec<String>,
+        #[serde(default)]
+        edge_ids: Vec<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientMessage {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(flatten)]
+    op: ClientOp,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum ServerMessage {
+    Hello {
+        protocol: u32,
+        #[serde(rename = "caseId")]
+        case_id: Option<String>,
+    },
+    Ack {
+        id: Option<String>,
+    },
+    Nodes {
+        id: Option<String>,
+        nodes: Vec<NodeModel>,
+    },
+    Edges {
+        id: Option<String>,
+        edges: Vec<EdgeModel>,
+    },
+    Error {
+        id: Option<String>,
+        message: String,
+    },
+    Event {
+        event: Change,
+    },
+    /// Events were dropped because the client fell behind; reload everything.
+    Resync,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    v: u32,
+    #[serde(flatten)]
+    message: &'a ServerMessage,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    app: AppState,
+    allowed_origins: Arc<Vec<String>>,
+}
+
+fn encode(message: &ServerMessage) -> String {
+    serde_json::to_string(&Envelope {
+        v: PROTOCOL_VERSION,
+        message,
+    })
+    .unwrap_or_default()
+}
+
+async fn handle_request(state: &AppState, raw: &str) -> ServerMessage {
+    let value = match serde_json::from_str::<serde_json::Value>(raw) {
+        Ok(value) => value,
+        Err(err) => {
+            return ServerMessage::Error {
+                id: None,
+                message: format!("invalid JSON: {err}"),
+            }
+        }
+    };
+
+    let id = value
+        .get("id")
+        .and_then(|id| id.as_str())
+        .map(str::to_owned);
+
+    if value.get("v").and_then(|v| v.as_u64()) != Some(u64::from(PROTOCOL_VERSION)) {
+        return ServerMessage::Error {
+            id,
+            message: format!("unsupported protocol version; expected v={PROTOCOL_VERSION}"),
+        };
+    }
+
+    let message = match serde_json::from_value::<ClientMessage>(value) {
+        Ok(message) => message,
+        Err(err) => {
+            return ServerMessage::Error {
+                id,
+                message: format!("invalid message: {err}"),
+            }
+        }
+    };
+
+    let id = message.id;
+    let result = match message.op {
+        ClientOp::Ping => Ok(ServerMessage::Ack { id: id.clone() }),
+        ClientOp::ListNodes => match state.db().await {
+            Ok(db) => list_nodes_internal(&db)
+                .await
+                .map(|nodes| ServerMessage::Nodes {
+                    id: id.clone(),
+                    nodes,
+                }),
+            Err(err) => Err(err),
+        },
+        ClientOp::ListEdges => match state.db().await {
+            Ok(db) => list_edges_internal(&db)
+                .await
+                .map(|edges| ServerMessage::Edges {
+                    id: id.clone(),
+                    edges,
+                }),
+            Err(err) => Err(err),
+        },
+        ClientOp::UpsertNodes { nodes } => state
+            .upsert_nodes(CHANGE_ORIGIN, nodes)
+            .await
+            .map(|_| ServerMessage::Ack { id: id.clone() }),
+        ClientOp::DeleteNodes { ids } => state
+            .delete_nodes(CHANGE_ORIGIN, ids)
+            .await
+            .map(|_| ServerMessage::Ack { id: id.clone() }),
+        ClientOp::UpsertEdges { edges } => state
+            .upsert_edges(CHANGE_ORIGIN, edges)
+            .await
+            .map(|_| ServerMessage::Ack { id: id.clone() }),
+        ClientOp::DeleteEdges { ids } => state
+            .delete_edges(CHANGE_ORIGIN, ids)
+            .await
+            .map(|_| ServerMessage::Ack { id: id.clone() }),
+        ClientOp::FocusNode { id: node_id } => state
+            .focus_node(CHANGE_ORIGIN, node_id)
+            .await
+            .map(|_| ServerMessage::Ack { id: id.clone() }),
+        ClientOp::SelectNodes { ids } => state
+            .select_nodes(CHANGE_ORIGIN, ids)
+            .await
+            .map(|_| ServerMessage::Ack { id: id.clone() }),
+        ClientOp::HighlightPath { node_ids, edge_ids } => state
+            .highlight_path(CHANGE_ORIGIN, node_ids, edge_ids)
+            .await
+            .map(|_| ServerMessage::Ack { id: id.clone() }),
+    };
+
+    result.unwrap_or_else(|message| ServerMessage::Error { id, message })
+}
+
+async fn handle_socket(socket: WebSocket, state: AppState) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut changes = state.changes.subscribe();
+
+    let hello = ServerMessage::Hello {
+        protocol: PROTOCOL_VERSION,
+        case_id: state.cases.active_case_id().await,
+    };
+
+    if sender
+        .send(Message::Text(encode(&hello).into()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    loop {
+        let outgoing = tokio::select! {
+            incoming = receiver.next() => match incoming {
+                Some(Ok(Message::Text(text))) => handle_request(&state, text.as_str()).await,
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            event = changes.recv() => match event {
+                Ok(event) => ServerMessage::Event { event },
+                Err(RecvError::Lagged(_)) => ServerMessage::Resync,
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        if sender
+            .send(Message::Text(encode(&outgoing).into()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn upgrade(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    State(state): State<ServerState>,
+) -> Response {
+    if let Some(origin) = headers.get(ORIGIN) {
+        let allowed = origin.to_str().is_ok_and(|origin| {
+            state
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed == origin)
+        });
+
+        if !allowed {
+            return StatusCode::FORBIDDEN.into_response();
+        }
+    }
+
+    ws.on_upgrade(move |socket| handle_socket(socket, state.app))
+}
+
+pub(crate) async fn serve_listener(
+    app: AppState,
+    listener: TcpListener,
+    allowed_origins: Vec<String>,
+) -> Result<(), String> {
+    let router = Router::new()
+        .route("/ws", get(upgrade))
+        .with_state(ServerState {
+            app,
+            allowed_origins: Arc::new(allowed_origins),
+        });
+
+    axum::serve(listener, router)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Serves the sync WebSocket on `ws://127.0.0.1:<port>/ws` until the app exits.
+pub(crate) async fn serve(app: AppState, config: SyncServerConfig) -> Result<(), String> {
+    let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, config.port)))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    serve_listener(app, listener, config.allowed_origins).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_state, TestState};
+    use serde_json::{json, Value};
+    use tokio_tungstenite::{
+        connect_async, tungstenite::client::IntoClientRequest, tungstenite::Message as WsMessage,
+        MaybeTlsStream, WebSocketStream,
+    };
+
+    type Client = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+    /// The state is handed back so its case root outlives the server.
+    async fn start_server(name: &str) -> (SocketAddr, TestState) {
+        let state = test_state(name).await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_listener(
+            AppState::clone(&state),
+            listener,
+            vec!["tauri://localhost".to_owned()],
+        ));
+
+        (addr, state)
+    }
+
+    async fn connect(addr: SocketAddr) -> Client {
+        let (mut client, _) = connect_async(format!("ws://{addr}/ws")).await.unwrap();
+        let hello = next_json(&mut client).await;
+        assert_eq!(hello["type"], "hello");
+        assert_eq!(hello["v"], 1);
+        client
+    }
+
+    async fn next_json(client: &mut Client) -> Value {
+        loop {
+            let message = client.next().await.unwrap().unwrap();
+
+            if message.is_text() {
+                return serde_json::from_str(message.to_text().unwrap()).unwrap();
+            }
+        }
+    }
+
+    async fn send_json(client: &mut Client, value: Value) {
+        client
+            .send(WsMessage::text(value.to_string()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn broadcasts_changes_to_every_client() {
+        let (addr, _state) = start_server("sync-broadcast").await;
+        let mut writer = connect(addr).await;
+        let mut watcher = connect(addr).await;
+
+        send_json(
+            &mut writer,
+            json!({
+                "v": 1,
+                "id": "req-1",
+                "op": "nodes.upsert",
+                "nodes": [{ "id": "ip-1", "type": "geo", "x": 1.0, "y": 2.0, "content": "10.0.0.8" }]
+            }),
+        )
+        .await;
+
+        let ack = next_json(&mut writer).await;
+        assert_eq!(ack["type"], "ack");
+        assert_eq!(ack["id"], "req-1");
+
+        for client in [&mut writer, &mut watcher] {
+            let event = next_json(client).await;
+            assert_eq!(event["type"], "event");
+            assert_eq!(event["event"]["kind"], "nodesUpserted");
+            assert_eq!(event["event"]["origin"], "syncClient");
+            assert_eq!(event["event"]["nodes"][0]["id"], "shape:ip-1");
+        }
+
+        send_json(
+            &mut watcher,
+            json!({ "v": 1, "id": "req-2", "op": "nodes.list" }),
+        )
+        .await;
+        let listed = next_json(&mut watcher).await;
+        assert_eq!(listed["type"], "nodes");
+        assert_eq!(listed["nodes"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_requests_and_foreign_origins() {
+        let (addr, _state) = start_server("sync-errors").await;
+        let mut client = connect(addr).await;
+
+        send_json(&mut client, json!({ "v": 2, "id": "a", "op": "ping" })).await;
+        let reply = next_json(&mut client).await;
+        assert_eq!(reply["type"], "error");
+        assert_eq!(reply["id"], "a");
+
+        send_json(
+            &mut client,
+            json!({ "v": 1, "id": "b", "op": "edges.upsert", "edges": [
+                { "id": "e", "source": "missing-1", "target": "missing-2", "kind": "spawned" }
+            ] }),
+        )
+        .await;
+        let reply = next_json(&mut client).await;
+        assert_eq!(reply["type"], "error");
+        assert!(reply["message"].as_str().unwrap().contains("missing node"));
+
+        let mut request = format!("ws://{addr}/ws").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Origin", "https://attacker.example".parse().unwrap());
+        assert!(connect_async(request).await.is_err());
+    }
+}
@@ -0,0 +1,610 @@
+use roxmltree::{Document, Node};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    ingest::{self, EntityGraph, ImportPlan, ImportReport, RecordBatch},
+    list_nodes_internal, AppState, NodeModel,
+};
+
+const SYSMON_PROVIDER: &str = "Microsoft-Windows-Sysmon";
+
+/// One Sysmon record, whichever export format it came from.
+#[derive(Debug, Default)]
+struct SysmonEvent {
+    event_id: u32,
+    provider: Option<String>,
+    computer: Option<String>,
+    data: HashMap<String, String>,
+}
+
+/// Events in an export, each tagged with its 1-based position so errors can
+/// point back at the record.
+type ParsedEvents = Vec<(usize, Result<SysmonEvent, String>)>;
+
+/// Byte offset of the next `<Event>` start tag, skipping `<EventData>` and
+/// the like.
+fn next_event_start(body: &str, from: usize) -> Option<usize> {
+    let mut offset = from;
+
+    while let Some(found) = body[offset..].find("<Event") {
+        let start = offset + found;
+        let after = body[start + "<Event".len()..].chars().next();
+        if after.is_some_and(|ch| ch == '>' || ch == '/' || ch.is_whitespace()) {
+            return Some(start);
+        }
+        offset = start + "<Event".len();
+    }
+
+    None
+}
+
+/// Cuts an export into its `<Event>` elements, with or without an `<Events>`
+/// root as `wevtutil qe /f:xml` prints them, so one malformed event does not
+/// take the rest down with it. An event runs to its closing tag, or up to the
+/// next event when the closing tag is missing.
+fn split_events(body: &str) -> Vec<&str> {
+    let mut events = Vec::new();
+    let mut next = next_event_start(body, 0);
+
+    while let Some(start) = next {
+        let following = next_event_start(body, start + 1);
+        let limit = following.unwrap_or(body.len());
+        let end = body[start..limit]
+            .find("</Event>")
+            .map_or(limit, |close| start + close + "</Event>".len());
+
+        events.push(&body[start..end]);
+        next = following;
+    }
+
+    events
+}
+
+fn parse_xml(raw: &str) -> Result<ParsedEvents, String> {
+    let events = split_events(raw);
+    if events.is_empty() {
+        return Err("invalid Sysmon XML: no Event elements".to_owned());
+    }
+
+    Ok(events
+        .into_iter()
+        .enumerate()
+        .map(|(index, xml)| {
+            let event = Document::parse(xml)
+                .map_err(|err| format!("invalid XML: {err}"))
+                .and_then(|document| event_from_xml(document.root_element()));
+            (index + 1, event)
+        })
+        .collect())
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn event_from_xml(event: Node) -> Result<SysmonEvent, String> {
+    let system = child(event, "System").ok_or("missing System element")?;
+    let event_id = child(system, "EventID")
+        .and_then(|id| id.text())
+        .ok_or("missing EventID")?;
+
+    Ok(SysmonEvent {
+        event_id: event_id
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid EventID: {event_id}"))?,
+        provider: child(system, "Provider")
+            .and_then(|provider| provider.attribute("Name"))
+            .map(str::to_owned),
+        computer: child(system, "Computer")
+            .and_then(|computer| computer.text())
+            .map(|computer| computer.trim().to_owned()),
+        data: child(event, "EventData")
+            .into_iter()
+            .flat_map(|data| data.children())
+            .filter(|data| data.has_tag_name("Data"))
+            .filter_map(|data| {
+                Some((
+                    data.attribute("Name")?.to_owned(),
+                    data.text().unwrap_or_default().trim().to_owned(),
+                ))
+            })
+            .collect(),
+    })
+}
+
+/// Winlogbeat writes either one JSON document per line or, when exported
+/// from a search, a single array.
+fn parse_json(raw: &str) -> ParsedEvents {
+    if let Ok(Value::Array(records)) = serde_json::from_str::<Value>(raw) {
+        return records
+            .iter()
+            .enumerate()
+            .map(|(index, record)| (index + 1, event_from_json(record)))
+            .collect();
+    }
+
+    raw.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let event = serde_json::from_str::<Value>(line)
+                .map_err(|err| format!("invalid JSON: {err}"))
+                .and_then(|record| event_from_json(&record));
+            (index + 1, event)
+        })
+        .collect()
+}
+
+fn json_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.trim().to_owned()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn event_from_json(record: &Value) -> Result<SysmonEvent, String> {
+    let winlog = record.get("winlog").ok_or("missing winlog")?;
+    let event_id = winlog
+        .get("event_id")
+        .and_then(json_string)
+        .ok_or("missing winlog.event_id")?;
+
+    Ok(SysmonEvent {
+        event_id: event_id
+            .parse()
+            .map_err(|_| format!("invalid winlog.event_id: {event_id}"))?,
+        provider: winlog.get("provider_name").and_then(json_string),
+        computer: winlog
+            .get("computer_name")
+            .or_else(|| record.pointer("/host/name"))
+            .and_then(json_string),
+        data: winlog
+            .get("event_data")
+            .and_then(Value::as_object)
+            .map(|data| {
+                data.iter()
+                    .filter_map(|(key, value)| Some((key.clone(), json_string(value)?)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+fn parse_events(raw: &str) -> Result<ParsedEvents, String> {
+    if raw
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('<')
+    {
+        parse_xml(raw)
+    } else {
+        Ok(parse_json(raw))
+    }
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+fn field<'a>(event: &'a SysmonEvent, key: &str) -> Option<&'a str> {
+    event
+        .data
+        .get(key)
+        .map(String::as_str)
+        .filter(|value| !value.is_empty() && *value != "-")
+}
+
+fn required<'a>(event: &'a SysmonEvent, key: &str) -> Result<&'a str, String> {
+    field(event, key).ok_or_else(|| format!("missing {key}"))
+}
+
+fn insert(attributes: &mut Map<String, Value>, key: &str, value: Option<&str>) {
+    if let Some(value) = value {
+        attributes.insert(key.to_owned(), value.into());
+    }
+}
+
+fn insert_u64(attributes: &mut Map<String, Value>, key: &str, value: Option<&str>) {
+    if let Some(value) = value.and_then(|value| value.parse::<u64>().ok()) {
+        attributes.insert(key.to_owned(), value.into());
+    }
+}
+
+/// Adds the process behind `<prefix>ProcessGuid`, e.g. the parent of a
+/// process-create event with prefix `Parent`.
+fn process(batch: &mut RecordBatch, event: &SysmonEvent, prefix: &str) -> Result<String, String> {
+    let guid = required(event, &format!("{prefix}ProcessGuid"))?
+        .trim_matches(['{', '}'])
+        .to_ascii_lowercase();
+    let pid = field(event, &format!("{prefix}ProcessId"));
+    let image = field(event, &format!("{prefix}Image"));
+
+    let mut attributes = Map::new();
+    attributes.insert("guid".to_owned(), guid.into());
+    insert_u64(&mut attributes, "pid", pid);
+    insert(&mut attributes, "image", image);
+    insert(
+        &mut attributes,
+        "commandLine",
+        field(event, &format!("{prefix}CommandLine")),
+    );
+    if prefix.is_empty() {
+        insert(&mut attributes, "user", field(event, "User"));
+        insert_u64(
+            &mut attributes,
+            "parentPid",
+            field(event, "ParentProcessId"),
+        );
+        insert(&mut attributes, "host", event.computer.as_deref());
+    }
+
+    let content = match (image, pid) {
+        (Some(image), Some(pid)) => format!("{} ({pid})", basename(image)),
+        (Some(image), None) => basename(image).to_owned(),
+        (None, Some(pid)) => format!("pid {pid}"),
+        (None, None) => "process".to_owned(),
+    };
+    batch.entity("process", attributes, content)
+}
+
+fn file(batch: &mut RecordBatch, path: &str, hashes: Option<&str>) -> Result<String, String> {
+    let mut attributes = Map::new();
+    attributes.insert("path".to_owned(), path.into());
+    attributes.insert("name".to_owned(), basename(path).into());
+
+    // `SHA1=...,MD5=...,SHA256=...,IMPHASH=...`
+    for (algorithm, digest) in hashes
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+    {
+        let key = match algorithm.trim().to_ascii_uppercase().as_str() {
+            "MD5" => "md5",
+            "SHA1" => "sha1",
+            "SHA256" => "sha256",
+            _ => continue,
+        };
+        attributes.insert(key.to_owned(), digest.trim().to_ascii_lowercase().into());
+    }
+
+    batch.entity("file", attributes, path.to_owned())
+}
+
+fn ip(batch: &mut RecordBatch, address: &str) -> Result<String, String> {
+    let address = address.trim_start_matches("::ffff:");
+    let mut attributes = Map::new();
+    attributes.insert("address".to_owned(), address.into());
+    batch.entity("ip", attributes, address.to_owned())
+}
+
+fn process_create(batch: &mut RecordBatch, event: &SysmonEvent) -> Result<(), String> {
+    let child = process(batch, event, "")?;
+
+    if field(event, "ParentProcessGuid").is_some() {
+        let parent = process(batch, event, "Parent")?;
+        batch.relate(&parent, "spawned", &child, None);
+    }
+
+    if let Some(image) = field(event, "Image") {
+        let image = file(batch, image, field(event, "Hashes"))?;
+        batch.relate(&child, "executed", &image, None);
+    }
+
+    Ok(())
+}
+
+fn network_connect(batch: &mut RecordBatch, event: &SysmonEvent) -> Result<(), String> {
+    let process = process(batch, event, "")?;
+    let remote = ip(batch, required(event, "DestinationIp")?)?;
+    let label = match (field(event, "Protocol"), field(event, "DestinationPort")) {
+        (Some(protocol), Some(port)) => Some(format!("{protocol}/{port}")),
+        (None, Some(port)) => Some(port.to_owned()),
+        (protocol, None) => protocol.map(str::to_owned),
+    };
+
+    if field(event, "Initiated") == Some("false") {
+        batch.relate(&remote, "connected_to", &process, label);
+    } else {
+        batch.relate(&process, "connected_to", &remote, label);
+    }
+
+    Ok(())
+}
+
+fn file_create(batch: &mut RecordBatch, event: &SysmonEvent) -> Result<(), String> {
+    let process = process(batch, event, "")?;
+    let target = file(batch, required(event, "TargetFilename")?, None)?;
+    batch.relate(&process, "wrote", &target, None);
+
+    Ok(())
+}
+
+fn registry_event(batch: &mut RecordBatch, event: &SysmonEvent) -> Result<(), String> {
+    let process = process(batch, event, "")?;
+    let path = required(event, "TargetObject")?;
+    let mut attributes = Map::new();
+    attributes.insert("path".to_owned(), path.into());
+    let key = batch.entity("registry_key", attributes, path.to_owned())?;
+
+    let label = match (field(event, "EventType"), field(event, "Details")) {
+        (Some(kind), Some(details)) => Some(format!("{kind}: {details}")),
+        (Some(kind), None) => Some(kind.to_owned()),
+        (None, details) => details.map(str::to_owned),
+    };
+    batch.relate(&process, "wrote", &key, label);
+
+    Ok(())
+}
+
+fn dns_query(batch: &mut RecordBatch, event: &SysmonEvent) -> Result<(), String> {
+    let process = process(batch, event, "")?;
+    let name = required(event, "QueryName")?.trim_end_matches('.');
+    let mut attributes = Map::new();
+    attributes.insert("name".to_owned(), name.to_ascii_lowercase().into());
+    let domain = batch.entity("domain", attributes, name.to_owned())?;
+    batch.relate(
+        &process,
+        "related_to",
+        &domain,
+        Some("DNS query".to_owned()),
+    );
+
+    // `type:  5 cname.example;::ffff:198.51.100.23;` keeps CNAMEs and
+    // addresses in one list; only the addresses become nodes.
+    for answer in field(event, "QueryResults")
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|answer| {
+            answer
+                .trim_start_matches("::ffff:")
+                .parse::<std::net::IpAddr>()
+                .is_ok()
+        })
+    {
+        let address = ip(batch, answer)?;
+        batch.relate(&domain, "resolved_to", &address, None);
+    }
+
+    Ok(())
+}
+
+fn is_sysmon(event: &SysmonEvent) -> bool {
+    event
+        .provider
+        .as_deref()
+        .is_none_or(|provider| provider.eq_ignore_ascii_case(SYSMON_PROVIDER))
+}
+
+/// Maps an export to node and edge payloads. Events that fail to parse or
+/// map are reported and skipped; the rest still import.
+fn plan_import(raw: &str, existing: Vec<NodeModel>) -> Result<ImportPlan, String> {
+    let mut graph = EntityGraph::new(existing);
+
+    let mut skipped = Vec::new();
+    let mut unsupported = BTreeMap::<String, usize>::new();
+
+    for (position, event) in parse_events(raw)? {
+        let event = match event {
+            Ok(event) => event,
+            Err(reason) => {
+                skipped.push(format!("event {position}: {reason}"));
+                continue;
+            }
+        };
+
+        let handler: fn(&mut RecordBatch, &SysmonEvent) -> Result<(), String> = match event.event_id
+        {
+            1 if is_sysmon(&event) => process_create,
+            3 if is_sysmon(&event) => network_connect,
+            11 if is_sysmon(&event) => file_create,
+            12..=14 if is_sysmon(&event) => registry_event,
+            22 if is_sysmon(&event) => dns_query,
+            id => {
+                let source = match &event.provider {
+                    Some(provider) if !is_sysmon(&event) => format!("{provider} {id}"),
+                    _ => id.to_string(),
+                };
+                *unsupported.entry(source).or_default() += 1;
+                continue;
+            }
+        };
+
+        if let Err(reason) = graph.record(|batch| handler(batch, &event)) {
+            skipped.push(format!("event {position}: {reason}"));
+        }
+    }
+
+    if !unsupported.is_empty() {
+        let total = unsupported.values().sum::<usize>();
+        let ids = unsupported.into_keys().collect::<Vec<_>>().join(", ");
+        skipped.push(format!("{total} events with unsupported ids: {ids}"));
+    }
+
+    Ok(graph.into_plan(skipped))
+}
+
+/// Imports a Sysmon export (wevtutil XML or winlogbeat JSON) into the open
+/// case. Entities are keyed by process GUID, file hash or path, address and
+/// name, so repeated events and repeated imports land on the same nodes.
+pub(crate) async fn import(state: &AppState, raw: &str) -> Result<ImportReport, String> {
+    let db = state.db().await?;
+    let existing = list_nodes_internal(&db).await?;
+
+    ingest::apply(state, plan_import(raw, existing)?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{list_edges_internal, test_support::test_state};
+    use std::collections::BTreeSet;
+
+    const XML_FIXTURE: &str = include_str!("../fixtures/sysmon/intrusion.xml");
+    const JSON_FIXTURE: &str = include_str!("../fixtures/sysmon/winlogbeat.ndjson");
+
+    fn find<'a>(nodes: &'a [NodeModel], kind: &str, key: &str, value: &str) -> &'a NodeModel {
+        nodes
+            .iter()
+            .find(|node| {
+                node.kind.as_deref() == Some(kind)
+                    && node.attributes.as_ref().unwrap()[key] == value
+            })
+            .unwrap_or_else(|| panic!("no {kind} with {key} {value}"))
+    }
+
+    #[test]
+    fn parses_bare_wevtutil_events() {
+        let raw = r#"<Event xmlns="http://schemas.microsoft.com/win/2004/08/events/event"><System><Provider Name="Microsoft-Windows-Sysmon"/><EventID>11</EventID></System><EventData><Data Name="TargetFilename">C:\x.txt</Data></EventData></Event>
+<Event><System><EventID>eleven</EventID></System></Event>"#;
+        let events = parse_events(raw).unwrap();
+
+        let (position, first) = &events[0];
+        let first = first.as_ref().unwrap();
+        assert_eq!((*position, first.event_id), (1, 11));
+        assert_eq!(first.data["TargetFilename"], "C:\\x.txt");
+        assert_eq!(events[1].1.as_ref().unwrap_err(), "invalid EventID: eleven");
+        assert!(parse_events("<Events></Events>").is_err());
+    }
+
+    #[test]
+    fn reports_malformed_xml_events_one_by_one() {
+        let event = |id: u32| {
+            format!(
+                "<Event><System><Provider Name=\"Microsoft-Windows-Sysmon\"/>\
+                 <EventID>{id}</EventID></System></Event>"
+            )
+        };
+        let raw = format!(
+            "<?xml version=\"1.0\"?><Events>{}<Event><System><EventID>3</EventID></System>\
+             <Event><System><EventID>1<EventID></System></Event>{}</Events>",
+            event(11),
+            event(22)
+        );
+        let events = parse_events(&raw).unwrap();
+
+        let outcomes = events
+            .iter()
+            .map(|(position, event)| (*position, event.as_ref().map(|event| event.event_id)))
+            .collect::<Vec<_>>();
+        assert_eq!(outcomes[0], (1, Ok(11)));
+        assert!(outcomes[1]
+            .1
+            .as_ref()
+            .unwrap_err()
+            .starts_with("invalid XML"));
+        assert!(outcomes[2]
+            .1
+            .as_ref()
+            .unwrap_err()
+            .starts_with("invalid XML"));
+        assert_eq!(outcomes[3], (4, Ok(22)));
+    }
+
+    #[tokio::test]
+    async fn imports_xml_and_winlogbeat_exports() {
+        let state = test_state("import").await;
+        let report = import(&state, XML_FIXTURE).await.unwrap();
+
+        assert_eq!(report.nodes, 7);
+        assert_eq!(report.edges, 7);
+        assert_eq!(
+            report.skipped,
+            vec![
+                "event 8: missing ProcessGuid",
+                "event 9: missing DestinationIp",
+                "1 events with unsupported ids: 5",
+            ]
+        );
+
+        let db = state.db().await.unwrap();
+        let nodes = list_nodes_internal(&db).await.unwrap();
+
+        let powershell = find(
+            &nodes,
+            "process",
+            "guid",
+            "b7c1a9e2-1f00-65f8-2a01-000000000a00",
+        );
+        let attributes = powershell.attributes.as_ref().unwrap();
+        assert_eq!(attributes["pid"], 4120);
+        assert_eq!(attributes["parentPid"], 3312);
+        assert_eq!(attributes["user"], "CORP\\alice");
+        assert_eq!(powershell.content, "powershell.exe (4120)");
+        let image = find(
+            &nodes,
+            "file",
+            "sha256",
+            "de96a6e69944335375dc1ac238336066889d9ffc7d73628ef4fe1b1b160ab32c",
+        );
+        assert_eq!(image.attributes.as_ref().unwrap()["name"], "powershell.exe");
+        find(&nodes, "ip", "address", "198.51.100.23");
+        find(&nodes, "domain", "name", "cdn-update.example");
+
+        let edges = list_edges_internal(&db).await.unwrap();
+        let relations = edges
+            .iter()
+            .map(|edge| (edge.kind.as_str(), edge.label.as_deref()))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            relations,
+            BTreeSet::from([
+                ("connected_to", Some("tcp/443")),
+                ("executed", None),
+                ("related_to", Some("DNS query")),
+                ("resolved_to", None),
+                ("spawned", None),
+                ("wrote", None),
+                (
+                    "wrote",
+                    Some("SetValue: C:\\Users\\alice\\AppData\\Roaming\\updater.exe")
+                ),
+            ])
+        );
+
+        // The dropped file is the one the next export runs, found by path.
+        let dropped = find(
+            &nodes,
+            "file",
+            "path",
+            "C:\\Users\\alice\\AppData\\Roaming\\updater.exe",
+        );
+        let report = import(&state, JSON_FIXTURE).await.unwrap();
+        assert_eq!(report.skipped.len(), 2);
+        assert!(report.skipped[0].starts_with("event 4: invalid JSON"));
+        assert_eq!(
+            report.skipped[1],
+            "1 events with unsupported ids: Microsoft-Windows-Security-Auditing 4624"
+        );
+
+        let nodes = list_nodes_internal(&db).await.unwrap();
+        assert_eq!(nodes.len(), 9);
+        let updated = nodes.iter().find(|node| node.id == dropped.id).unwrap();
+        assert_eq!(
+            updated.attributes.as_ref().unwrap()["sha256"],
+            "4a5b6c7d8e9f00112233445566778899aabbccddeeff00112233445566778899"
+        );
+        let updater = find(
+            &nodes,
+            "process",
+            "guid",
+            "b7c1a9e2-2a00-65f8-3b01-000000000a00",
+        );
+        let edges = list_edges_internal(&db).await.unwrap();
+        assert!(edges.iter().any(|edge| edge.source == powershell.id
+            && edge.target == updater.id
+            && edge.kind == "spawned"));
+        assert!(edges.iter().any(|edge| edge.source == updater.id
+            && edge.target == dropped.id
+            && edge.kind == "executed"));
+
+        // Re-importing changes nothing.
+        import(&state, XML_FIXTURE).await.unwrap();
+        assert_eq!(list_nodes_internal(&db).await.unwrap().len(), 9);
+        assert_eq!(list_edges_internal(&db).await.unwrap().len(), edges.len());
+    }
+}
@@ -0,0 +1,144 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Seconds since the Unix epoch from a unix timestamp or an ISO 8601 /
+/// RFC 3339 string such as `2024-03-01T10:15:30.5Z` or `2024-03-01 10:15:30`.
+pub(crate) fn parse_timestamp(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(number) => number.as_f64(),
+        serde_json::Value::String(raw) => parse_iso8601(raw.trim()),
+        _ => None,
+    }
+}
+
+fn parse_iso8601(raw: &str) -> Option<f64> {
+    let (date, rest) = raw.split_at_checked(10)?;
+    let mut fields = date.split('-');
+    let year = fields.next()?.parse::<i64>().ok()?;
+    let month = fields.next()?.parse::<i64>().ok()?;
+    let day = fields.next()?.parse::<i64>().ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut seconds = days_from_civil(year, month, day) as f64 * 86_400.0;
+
+    if rest.is_empty() {
+        return Some(seconds);
+    }
+
+    let rest = rest.strip_prefix(['T', 't', ' '])?;
+    let (time, offset) = match rest.find(['Z', 'z', '+', '-']) {
+        Some(split) => rest.split_at(split),
+        None => (rest, ""),
+    };
+
+    let mut clock = time.split(':');
+    let hours = clock.next()?.parse::<f64>().ok()?;
+    let minutes = clock.next()?.parse::<f64>().ok()?;
+    let secs = clock
+        .next()
+        .map_or(Some(0.0), |raw| raw.parse::<f64>().ok())?;
+
+    if clock.next().is_some() || hours >= 24.0 || minutes >= 60.0 || secs >= 61.0 {
+        return None;
+    }
+
+    seconds += hours * 3600.0 + minutes * 60.0 + secs;
+
+    match offset {
+        "" | "Z" | "z" => Some(seconds),
+        _ => {
+            let sign = if offset.starts_with('-') { -1.0 } else { 1.0 };
+            let digits = offset[1..].replace(':', "");
+
+            if digits.len() != 4 {
+                return None;
+            }
+
+            let offset_hours = digits[..2].parse::<f64>().ok()?;
+            let offset_minutes = digits[2..].parse::<f64>().ok()?;
+            Some(seconds - sign * (offset_hours * 3600.0 + offset_minutes * 60.0))
+        }
+    }
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Inverse of `days_from_civil`: (year, month, day) of a day count.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// RFC 3339 UTC rendering of whole seconds, e.g. `2024-03-01T10:15:30Z`.
+pub(crate) fn format_timestamp(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_iso8601_timestamps() {
+        assert_eq!(parse_iso8601("1970-01-01"), Some(0.0));
+        assert_eq!(parse_iso8601("2024-03-01T00:00:00Z"), Some(1_709_251_200.0));
+        assert_eq!(
+            parse_iso8601("2024-03-01T02:00:00.5+02:00"),
+            Some(1_709_251_200.5)
+        );
+        assert_eq!(parse_iso8601("2024-13-01"), None);
+        assert_eq!(parse_iso8601("yesterday"), None);
+    }
+
+    #[test]
+    fn formats_and_round_trips_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1_709_288_130), "2024-03-01T10:15:30Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59Z");
+
+        for seconds in [951_782_400, 1_709_251_199, 4_102_444_800] {
+            assert_eq!(
+                parse_iso8601(&format_timestamp(seconds)),
+                Some(seconds as f64)
+            );
+        }
+    }
+}
@@ -0,0 +1,703 @@
+use sea_orm::{
+    ConnectionTrait, DatabaseBackend, DatabaseConnection, DatabaseTransaction, QueryResult,
+    Statement, TransactionTrait,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+use uuid::Uuid;
+
+use crate::{
+    attachments, attack, audit,
+    history::{self, HistoryAction, HistoryRevert},
+    id_placeholders, list_nodes_by_ids, normalize_delete_ids, normalize_edge_ids,
+    stix::CYBERWEAVER_NAMESPACE,
+    write_edges, write_nodes, EdgeModel, EdgePayload, NodeModel, NODE_COLUMNS,
+};
+
+/// A deleted node, with how many of its relations went to the trash with it.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrashedNode {
+    #[serde(flatten)]
+    pub(crate) node: NodeModel,
+    pub(crate) deleted_at: i64,
+    pub(crate) deleted_batch: Option<String>,
+    pub(crate) relations: i64,
+}
+
+/// An edge deleted on its own rather than along with one of its nodes.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TrashedEdge {
+    #[serde(flatten)]
+    pub(crate) edge: EdgeModel,
+    pub(crate) deleted_at: i64,
+    pub(crate) deleted_batch: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Trash {
+    pub(crate) nodes: Vec<TrashedNode>,
+    pub(crate) edges: Vec<TrashedEdge>,
+}
+
+/// What a purge would remove; `token` has to be passed back to `purge`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PurgePreview {
+    pub(crate) token: String,
+    pub(crate) nodes: Vec<String>,
+    pub(crate) edges: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PurgeReport {
+    pub(crate) batch_id: String,
+    pub(crate) nodes: Vec<String>,
+    pub(crate) edges: Vec<String>,
+    /// Hashes of attachment blobs no remaining node links to.
+    pub(crate) attachments: Vec<String>,
+}
+
+const EDGE_COLUMNS: &str = "id, source, target, kind, label, created_at, updated_at";
+
+impl TrashedNode {
+    fn from_row(row: QueryResult) -> Self {
+        Self {
+            deleted_at: row.try_get("", "deleted_at").unwrap_or(0),
+            deleted_batch: row.try_get("", "deleted_batch").ok().flatten(),
+            relations: row.try_get("", "relations").unwrap_or(0),
+            node: NodeModel::from_row(row),
+        }
+    }
+}
+
+impl TrashedEdge {
+    fn from_row(row: QueryResult) -> Self {
+        Self {
+            deleted_at: row.try_get("", "deleted_at").unwrap_or(0),
+            deleted_batch: row.try_get("", "deleted_batch").ok().flatten(),
+            edge: EdgeModel::from_row(row),
+        }
+    }
+}
+
+async fn trashed_nodes<C: ConnectionTrait>(
+    conn: &C,
+    ids: Option<&[String]>,
+) -> Result<Vec<TrashedNode>, String> {
+    let (filter, values) = match ids {
+        Some([]) => return Ok(Vec::new()),
+        Some(ids) => {
+            let (placeholders, values) = id_placeholders(ids);
+            (format!("AND id IN ({placeholders})"), values)
+        }
+        None => (String::new(), Vec::new()),
+    };
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {NODE_COLUMNS}, deleted_at, deleted_batch,
+                        (SELECT COUNT(*) FROM edges
+                         WHERE edges.deleted_with = nodes.id
+                           AND edges.deleted_at IS NOT NULL) AS relations
+                 FROM nodes
+                 WHERE deleted_at IS NOT NULL {filter}
+                 ORDER BY deleted_at DESC, id ASC;"
+            ),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(TrashedNode::from_row).collect())
+}
+
+async fn trashed_edges<C: ConnectionTrait>(
+    conn: &C,
+    ids: Option<&[String]>,
+) -> Result<Vec<TrashedEdge>, String> {
+    let (filter, values) = match ids {
+        Some([]) => return Ok(Vec::new()),
+        Some(ids) => {
+            let (placeholders, values) = id_placeholders(ids);
+            (format!("AND id IN ({placeholders})"), values)
+        }
+        None => (String::new(), Vec::new()),
+    };
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {EDGE_COLUMNS}, deleted_at, deleted_batch
+                 FROM edges
+                 WHERE deleted_at IS NOT NULL AND deleted_with IS NULL {filter}
+                 ORDER BY deleted_at DESC, id ASC;"
+            ),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(TrashedEdge::from_row).collect())
+}
+
+/// Trashed edges that went along with one of `node_ids`.
+async fn edges_deleted_with<C: ConnectionTrait>(
+    conn: &C,
+    node_ids: &[String],
+) -> Result<Vec<EdgeModel>, String> {
+    if node_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (placeholders, values) = id_placeholders(node_ids);
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {EDGE_COLUMNS}
+                 FROM edges
+                 WHERE deleted_at IS NOT NULL AND deleted_with IS NOT NULL
+                   AND (source IN ({placeholders}) OR target IN ({placeholders}))
+                 ORDER BY id ASC;"
+            ),
+            [values.clone(), values].concat(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(EdgeModel::from_row).collect())
+}
+
+/// Every edge, live or trashed, touching one of `node_ids`.
+async fn all_edges_touching<C: ConnectionTrait>(
+    conn: &C,
+    node_ids: &[String],
+) -> Result<Vec<EdgeModel>, String> {
+    if node_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (placeholders, values) = id_placeholders(node_ids);
+    let rows = conn
+        .query_all(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!(
+                "SELECT {EDGE_COLUMNS}
+                 FROM edges
+                 WHERE source IN ({placeholders}) OR target IN ({placeholders})
+                 ORDER BY id ASC;"
+            ),
+            [values.clone(), values].concat(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(rows.into_iter().map(EdgeModel::from_row).collect())
+}
+
+pub(crate) async fn list_trash(db: &DatabaseConnection) -> Result<Trash, String> {
+    Ok(Trash {
+        nodes: trashed_nodes(db, None).await?,
+        edges: trashed_edges(db, None).await?,
+    })
+}
+
+/// Looks up the selection in the trash, failing on the first id that is not
+/// there.
+async fn selection(
+    txn: &DatabaseTransaction,
+    node_ids: &[String],
+    edge_ids: &[String],
+) -> Result<(Vec<TrashedNode>, Vec<TrashedEdge>), String> {
+    let nodes = trashed_nodes(txn, Some(node_ids)).await?;
+    if let Some(id) = node_ids
+        .iter()
+        .find(|id| !nodes.iter().any(|trashed| &trashed.node.id == *id))
+    {
+        return Err(format!("node {id} is not in the trash"));
+    }
+
+    let edges = trashed_edges(txn, Some(edge_ids)).await?;
+    if let Some(id) = edge_ids
+        .iter()
+        .find(|id| !edges.iter().any(|trashed| &trashed.edge.id == *id))
+    {
+        return Err(format!("edge {id} is not in the trash"));
+    }
+
+    Ok((nodes, edges))
+}
+
+/// Takes nodes and edges back out of the trash as one history batch. Edges
+/// that went along with a restored node come back once both ends are live;
+/// a selected edge whose end is still trashed fails the whole restore.
+pub(crate) async fn restore(
+    db: &DatabaseConnection,
+    actor: &str,
+    node_ids: Vec<String>,
+    edge_ids: Vec<String>,
+) -> Result<HistoryRevert, String> {
+    let node_ids = normalize_delete_ids(node_ids);
+    let edge_ids = normalize_edge_ids(edge_ids);
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let (nodes, selected_edges) = selection(&txn, &node_ids, &edge_ids).await?;
+
+    let upserted = write_nodes(
+        &txn,
+        nodes
+            .iter()
+            .map(|trashed| history::payload(&trashed.node))
+            .collect(),
+    )
+    .await?;
+
+    let selected_edges = selected_edges
+        .into_iter()
+        .map(|trashed| trashed.edge)
+        .collect::<Vec<_>>();
+    let mut edges = edges_deleted_with(&txn, &node_ids).await?;
+    edges.extend(selected_edges.iter().cloned());
+    let endpoints = edges
+        .iter()
+        .flat_map(|edge| [edge.source.clone(), edge.target.clone()])
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let live = list_nodes_by_ids(&txn, &endpoints)
+        .await?
+        .into_iter()
+        .map(|node| node.id)
+        .collect::<BTreeSet<_>>();
+    if let Some(edge) = edges.iter().find(|edge| {
+        edge_ids.contains(&edge.id) && !(live.contains(&edge.source) && live.contains(&edge.target))
+    }) {
+        return Err(format!(
+            "edge {} connects a node that is still in the trash",
+            edge.id
+        ));
+    }
+    let edges = edges
+        .into_iter()
+        .filter(|edge| live.contains(&edge.source) && live.contains(&edge.target))
+        .map(|edge| (edge.id.clone(), edge))
+        .collect::<BTreeMap<_, _>>();
+    let restored_edges = write_edges(
+        &txn,
+        edges
+            .values()
+            .map(|edge| EdgePayload {
+                id: edge.id.clone(),
+                source: edge.source.clone(),
+                target: edge.target.clone(),
+                kind: edge.kind.clone(),
+                label: edge.label.clone(),
+            })
+            .collect(),
+    )
+    .await?;
+
+    let batch_id = history::new_batch_id();
+    let after = list_nodes_by_ids(&txn, &node_ids).await?;
+    for node in &after {
+        let touching = edges
+            .values()
+            .filter(|edge| edge.source == node.id || edge.target == node.id)
+            .cloned()
+            .collect::<Vec<_>>();
+        history::append(
+            &txn,
+            &batch_id,
+            HistoryAction::Restore,
+            &node.id,
+            None,
+            Some(node),
+            &touching,
+        )
+        .await?;
+    }
+    // Selected edges were all checked to have live ends, so all came back.
+    history::append_edges(&txn, &batch_id, HistoryAction::Restore, &selected_edges).await?;
+    let revert = HistoryRevert {
+        batch_id,
+        upserted,
+        deleted: Vec::new(),
+        edges: restored_edges,
+        deleted_edges: Vec::new(),
+    };
+    audit::append(&txn, actor, "trash.restore", &revert).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+
+    Ok(revert)
+}
+
+/// Derived from the selection and the delete batch of each item, so a token
+/// stops matching once anything in the selection is restored or deleted
+/// again.
+fn purge_token(nodes: &[TrashedNode], edges: &[TrashedEdge]) -> String {
+    let mut name = String::from("purge");
+    for node in nodes {
+        name.push_str(&format!(
+            "|node:{}:{}",
+            node.node.id,
+            node.deleted_batch.as_deref().unwrap_or_default()
+        ));
+    }
+    for edge in edges {
+        name.push_str(&format!(
+            "|edge:{}:{}",
+            edge.edge.id,
+            edge.deleted_batch.as_deref().unwrap_or_default()
+        ));
+    }
+
+    Uuid::new_v5(&CYBERWEAVER_NAMESPACE, name.as_bytes()).to_string()
+}
+
+async fn purge_plan(
+    txn: &DatabaseTransaction,
+    node_ids: &[String],
+    edge_ids: &[String],
+) -> Result<(String, Vec<TrashedNode>, Vec<EdgeModel>), String> {
+    if node_ids.is_empty() && edge_ids.is_empty() {
+        return Err("nothing selected to purge".to_owned());
+    }
+
+    let (mut nodes, mut selected) = selection(txn, node_ids, edge_ids).await?;
+    nodes.sort_by(|a, b| a.node.id.cmp(&b.node.id));
+    selected.sort_by(|a, b| a.edge.id.cmp(&b.edge.id));
+    let token = purge_token(&nodes, &selected);
+
+    let mut edges = all_edges_touching(txn, node_ids)
+        .await?
+        .into_iter()
+        .map(|edge| (edge.id.clone(), edge))
+        .collect::<BTreeMap<_, _>>();
+    for trashed in selected {
+        edges.insert(trashed.edge.id.clone(), trashed.edge);
+    }
+
+    Ok((token, nodes, edges.into_values().collect()))
+}
+
+/// Lists what purging the selection removes for good, edges of purged nodes
+/// included, along with the token `purge` asks for.
+pub(crate) async fn preview_purge(
+    db: &DatabaseConnection,
+    node_ids: Vec<String>,
+    edge_ids: Vec<String>,
+) -> Result<PurgePreview, String> {
+    let node_ids = normalize_delete_ids(node_ids);
+    let edge_ids = normalize_edge_ids(edge_ids);
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let (token, nodes, edges) = purge_plan(&txn, &node_ids, &edge_ids).await?;
+
+    Ok(PurgePreview {
+        token,
+        nodes: nodes.into_iter().map(|trashed| trashed.node.id).collect(),
+        edges: edges.into_iter().map(|edge| edge.id).collect(),
+    })
+}
+
+/// Deletes trashed nodes and edges for good, along with attachment blobs in
+/// `store` that only they linked to. The history keeps a `purge` entry per
+/// node, so an audit still shows what was removed.
+pub(crate) async fn purge(
+    db: &DatabaseConnection,
+    store: &Path,
+    actor: &str,
+    node_ids: Vec<String>,
+    edge_ids: Vec<String>,
+    token: &str,
+) -> Result<PurgeReport, String> {
+    let node_ids = normalize_delete_ids(node_ids);
+    let edge_ids = normalize_edge_ids(edge_ids);
+    let txn = db.begin().await.map_err(|err| err.to_string())?;
+    let (expected, nodes, edges) = purge_plan(&txn, &node_ids, &edge_ids).await?;
+
+    if token.trim() != expected {
+        return Err(
+            "purge confirmation token does not match the selection; preview the purge again"
+                .to_owned(),
+        );
+    }
+
+    let edge_ids = edges.iter().map(|edge| edge.id.clone()).collect::<Vec<_>>();
+    if !edge_ids.is_empty() {
+        let (placeholders, values) = id_placeholders(&edge_ids);
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!("DELETE FROM edges WHERE id IN ({placeholders});"),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+    }
+
+    let mut blobs = Vec::new();
+    if !node_ids.is_empty() {
+        attack::remove_nodes(&txn, &node_ids)
+            .await
+            .map_err(|err| err.to_string())?;
+        let (placeholders, values) = id_placeholders(&node_ids);
+        // The store belongs to this case, so a blob no node here links to
+        // any more is not needed by anything else.
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!("DELETE FROM node_attachments WHERE node_id IN ({placeholders});"),
+            values.clone(),
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+        txn.execute(Statement::from_sql_and_values(
+            DatabaseBackend::Sqlite,
+            format!("DELETE FROM nodes WHERE id IN ({placeholders});"),
+            values,
+        ))
+        .await
+        .map_err(|err| err.to_string())?;
+        blobs = attachments::forget_unreferenced(&txn).await?;
+    }
+
+    let batch_id = history::new_batch_id();
+    for trashed in &nodes {
+        let touching = edges
+            .iter()
+            .filter(|edge| edge.source == trashed.node.id || edge.target == trashed.node.id)
+            .cloned()
+            .collect::<Vec<_>>();
+        history::append(
+            &txn,
+            &batch_id,
+            HistoryAction::Purge,
+            &trashed.node.id,
+            Some(&trashed.node),
+            None,
+            &touching,
+        )
+        .await?;
+    }
+    let report = PurgeReport {
+        batch_id,
+        nodes: node_ids,
+        edges: edge_ids,
+        attachments: blobs,
+    };
+    audit::append(&txn, actor, "trash.purge", &report).await?;
+    txn.commit().await.map_err(|err| err.to_string())?;
+    attachments::remove_blobs(store, &report.attachments);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        attack::set_node_techniques,
+        delete_edges_internal, delete_nodes_internal, list_edges_internal, list_nodes_internal,
+        test_support::{create_test_db, edge, note},
+        upsert_edges_internal, upsert_nodes_internal,
+    };
+    use serde_json::json;
+
+    async fn contents(db: &DatabaseConnection) -> Vec<String> {
+        let mut contents = list_nodes_internal(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|node| node.content)
+            .collect::<Vec<_>>();
+        contents.sort();
+        contents
+    }
+
+    fn ids(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|id| (*id).to_owned()).collect()
+    }
+
+    #[tokio::test]
+    async fn restores_deleted_nodes_with_their_relations() {
+        let db = create_test_db().await;
+        let mut process = note("proc", "powershell.exe");
+        process.node_type = "geo".to_owned();
+        process.kind = Some("process".to_owned());
+        process.attributes = Some(json!({ "pid": 4120 }));
+        upsert_nodes_internal(
+            &db,
+            "test",
+            vec![process, note("b", "beacon"), note("c", "c2")],
+        )
+        .await
+        .unwrap();
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![edge("e1", "proc", "b"), edge("e2", "b", "c")],
+        )
+        .await
+        .unwrap();
+
+        delete_nodes_internal(&db, "test", ids(&["b"]))
+            .await
+            .unwrap();
+        delete_nodes_internal(&db, "test", ids(&["c", "proc"]))
+            .await
+            .unwrap();
+        assert!(contents(&db).await.is_empty());
+        let trash = list_trash(&db).await.unwrap();
+        assert_eq!(trash.nodes.len(), 3);
+        let beacon = trash.nodes.iter().find(|t| t.node.id == "shape:b").unwrap();
+        assert_eq!(beacon.relations, 2);
+        assert!(trash.edges.is_empty());
+
+        let revert = restore(&db, "test", ids(&["proc"]), Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(revert.upserted, vec!["shape:proc"]);
+        assert!(revert.edges.is_empty());
+        let restored = list_nodes_internal(&db).await.unwrap().remove(0);
+        assert_eq!(restored.kind.as_deref(), Some("process"));
+        assert_eq!(restored.attributes, Some(json!({ "pid": 4120 })));
+
+        let revert = restore(&db, "test", ids(&["c"]), Vec::new()).await.unwrap();
+        assert!(revert.edges.is_empty());
+        let revert = restore(&db, "test", ids(&["b"]), Vec::new()).await.unwrap();
+        assert_eq!(revert.edges, vec!["e1", "e2"]);
+        assert_eq!(list_edges_internal(&db).await.unwrap().len(), 2);
+
+        assert_eq!(
+            restore(&db, "test", ids(&["b"]), Vec::new())
+                .await
+                .unwrap_err(),
+            "node shape:b is not in the trash"
+        );
+        assert!(restore(&db, "test", ids(&["never"]), Vec::new())
+            .await
+            .is_err());
+
+        // A restore is a batch like any other.
+        let latest = history::list_batches(&db, 1).await.unwrap().remove(0);
+        assert_eq!(
+            (latest.batch_id.as_str(), latest.restored),
+            (revert.batch_id.as_str(), 1)
+        );
+        history::revert_batch(&db, "test", &latest.batch_id)
+            .await
+            .unwrap();
+        assert_eq!(contents(&db).await, vec!["c2", "powershell.exe"]);
+    }
+
+    #[tokio::test]
+    async fn purges_only_with_a_matching_token() {
+        let db = create_test_db().await;
+        upsert_nodes_internal(&db, "test", vec![note("a", "loader"), note("b", "dropper")])
+            .await
+            .unwrap();
+        upsert_edges_internal(
+            &db,
+            "test",
+            vec![edge("e1", "a", "b"), edge("e2", "b", "a")],
+        )
+        .await
+        .unwrap();
+        set_node_techniques(&db, "test", "a", ids(&["T1003.001"]))
+            .await
+            .unwrap();
+
+        delete_edges_internal(&db, "test", ids(&["e2"]))
+            .await
+            .unwrap();
+        delete_nodes_internal(&db, "test", ids(&["a"]))
+            .await
+            .unwrap();
+        let trash = list_trash(&db).await.unwrap();
+        assert_eq!(trash.edges.len(), 1);
+        assert_eq!(trash.edges[0].edge.id, "e2");
+
+        assert!(preview_purge(&db, Vec::new(), Vec::new()).await.is_err());
+        assert_eq!(
+            preview_purge(&db, ids(&["b"]), Vec::new())
+                .await
+                .unwrap_err(),
+            "node shape:b is not in the trash"
+        );
+
+        let preview = preview_purge(&db, ids(&["a"]), Vec::new()).await.unwrap();
+        assert_eq!(preview.nodes, vec!["shape:a"]);
+        assert_eq!(preview.edges, vec!["e1", "e2"]);
+        assert!(purge(
+            &db,
+            Path::new("attachments"),
+            "test",
+            ids(&["a"]),
+            Vec::new(),
+            "guess"
+        )
+        .await
+        .is_err());
+
+        // Restoring and deleting again invalidates an earlier preview.
+        restore(&db, "test", ids(&["a"]), Vec::new()).await.unwrap();
+        delete_nodes_internal(&db, "test", ids(&["a"]))
+            .await
+            .unwrap();
+        assert!(purge(
+            &db,
+            Path::new("attachments"),
+            "test",
+            ids(&["a"]),
+            Vec::new(),
+            &preview.token
+        )
+        .await
+        .is_err());
+
+        let preview = preview_purge(&db, ids(&["a"]), Vec::new()).await.unwrap();
+        let report = purge(
+            &db,
+            Path::new("attachments"),
+            "test",
+            ids(&["a"]),
+            Vec::new(),
+            &preview.token,
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.edges, vec!["e1", "e2"]);
+        assert_eq!(list_trash(&db).await.unwrap(), Trash::default());
+        assert_eq!(contents(&db).await, vec!["dropper"]);
+        let tags = db
+            .query_one(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "SELECT COUNT(*) AS count FROM node_techniques;".to_owned(),
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(tags.try_get::<i64>("", "count").unwrap(), 0);
+
+        // Neither a restore nor a revert can bring a purged node back.
+        assert!(restore(&db, "test", ids(&["a"]), Vec::new()).await.is_err());
+        let history = history::node_history(&db, "shape:a").await.unwrap();
+        assert_eq!(history.last().unwrap().action, HistoryAction::Purge);
+        let delete = history
+            .iter()
+            .rev()
+            .find(|entry| entry.action == HistoryAction::Delete)
+            .unwrap();
+        assert_eq!(
+            history::revert_batch(&db, "test", &delete.batch_id)
+                .await
+                .unwrap_err(),
+            "node shape:a was purged"
+        );
+    }
+}